@@ -0,0 +1,37 @@
+use std::time::{Duration, Instant};
+
+use monkey_lang_lib::engine::{Backend, Engine};
+use monkey_lang_lib::lexer::token::Tokens;
+use monkey_lang_lib::lexer::Lexer;
+use monkey_lang_lib::parser::Parser;
+
+const RUNS: u32 = 10;
+
+fn main() {
+    let input = "let fib = fn(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } }; fib(20)";
+    let (_, r) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+    let (_, program) = Parser::parse_tokens(Tokens::new(&r)).unwrap();
+
+    for (name, backend) in [("eval", Backend::Eval), ("vm", Backend::Vm)] {
+        let mut total = Duration::ZERO;
+        let mut fastest = Duration::MAX;
+        for _ in 0..RUNS {
+            let mut engine = Engine::new(backend);
+            let program = program.clone();
+            let start = Instant::now();
+            let result = engine.eval_program(program);
+            let elapsed = start.elapsed();
+            assert_eq!(result.to_string(), "6765");
+            total += elapsed;
+            fastest = fastest.min(elapsed);
+        }
+        // fib(20) makes 21891 calls
+        println!(
+            "fib(20) on {}: mean {:?}, fastest {:?}, {:?} per call",
+            name,
+            total / RUNS,
+            fastest,
+            fastest / 21891,
+        );
+    }
+}
@@ -0,0 +1,13 @@
+use std::io;
+use std::process;
+
+use monkey_lang_lib::dap::serve;
+
+fn main() {
+    if let Err(err) = serve(io::stdin().lock(), io::stdout()) {
+        eprintln!("monkey_dap: {}", err);
+        process::exit(1);
+    }
+    // the program may still be running, or stopped
+    process::exit(0);
+}
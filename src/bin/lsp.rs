@@ -0,0 +1,26 @@
+use std::io::{self, BufReader};
+use std::process;
+
+use monkey_lang_lib::lsp::{read_content, write_message, Server};
+
+fn main() {
+    let mut input = BufReader::new(io::stdin());
+    let mut output = io::stdout();
+    let mut server = Server::new();
+    loop {
+        let content = match read_content(&mut input) {
+            Ok(Some(content)) => content,
+            Ok(None) => process::exit(1),
+            Err(err) => {
+                eprintln!("monkey_lsp: {}", err);
+                process::exit(1)
+            }
+        };
+        for reply in server.receive(&content) {
+            write_message(&mut output, &reply).expect("Error writing to stdout");
+        }
+        if let Some(code) = server.exit_code() {
+            process::exit(code);
+        }
+    }
+}
@@ -1,6 +1,10 @@
 use std::borrow::Cow;
 use std::borrow::Cow::{Borrowed, Owned};
-use nom::IResult;
+use std::cell::RefCell;
+use std::fs;
+use std::io;
+use std::rc::Rc;
+use clap::clap_app;
 use rustyline::completion::{Completer, FilenameCompleter, Pair};
 use rustyline::{Cmd, CompletionType, Config, Context, EditMode, Editor, KeyEvent, OutputStreamType};
 use rustyline::error::ReadlineError;
@@ -8,12 +12,25 @@ use rustyline::highlight::{Highlighter, MatchingBracketHighlighter};
 use rustyline::hint::{Hinter, HistoryHinter};
 use rustyline::validate::{MatchingBracketValidator, ValidationContext, ValidationResult, Validator};
 use rustyline_derive::Helper;
-use monkey_lang_lib::evaluator::Evaluator;
+use monkey_lang_lib::debug::console::{self, Breakpoints, HELP};
+use monkey_lang_lib::parser::parse;
+use monkey_lang_lib::engine::{Backend, Engine};
 use monkey_lang_lib::lexer::Lexer;
 use monkey_lang_lib::lexer::token::Tokens;
 use monkey_lang_lib::parser::Parser;
 
 fn main() {
+    let matches = clap_app!(monkey_repl =>
+    (version: "0.0.1")
+        (about: "The Monkey programming language repl")
+        (@arg engine: -e --engine +takes_value {is_backend} "Backend running the code: eval (default) or vm")
+    )
+    .get_matches();
+    let backend = matches
+        .value_of("engine")
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(Backend::Eval);
+
     let config = Config::builder()
         .history_ignore_space(true)
         .completion_type(CompletionType::List)
@@ -27,7 +44,7 @@ fn main() {
         colored_prompt: "".to_owned(),
         validator: MatchingBracketValidator::new(),
     };
-    let mut rl = Editor::with_config(config);
+    let mut rl = Editor::<MyHelper>::with_config(config);
     rl.set_helper(Some(h));
     rl.bind_sequence(KeyEvent::alt('N'), Cmd::HistorySearchForward);
     rl.bind_sequence(KeyEvent::alt('P'), Cmd::HistorySearchBackward);
@@ -38,18 +55,43 @@ fn main() {
     println!();
     println!("This is the monkey language repl v0.0.1");
     println!("Press Ctrl-D or enter \"quit\" to exit.");
+    println!("Enter \":run <file>\" to debug a file, and \":help\" for the debugger commands.");
     println!();
 
-    let mut evaluator = Evaluator::new();
+    let mut engine = Engine::new(backend);
+    let interrupt = engine.interrupt_handle();
+    // while a line is being read the terminal is in raw mode and rustyline reports Ctrl-C itself,
+    // so the signal only reaches this handler during evaluation
+    let handler = interrupt.clone();
+    ctrlc::set_handler(move || handler.interrupt()).expect("Error setting Ctrl-C handler");
     let mut count = 1;
+    let rl = Rc::new(RefCell::new(rl));
+    let breakpoints = Rc::new(RefCell::new(Breakpoints::default()));
 
     loop {
         let p = format!("{}> ", count);
-        rl.helper_mut().expect("No helper").colored_prompt = format!("\x1b[1;32m{}\x1b[0m", p);
-        let readline = rl.readline(&p);
+        let readline = read(&mut rl.borrow_mut(), &p);
+        // a Ctrl-C which came after the last evaluation ended is not meant for the next one
+        interrupt.reset();
         match readline {
+            Ok(line) if line.trim_start().starts_with(':') => {
+                let (command, argument) = line.trim().split_once(' ').unwrap_or((line.trim(), ""));
+                match command {
+                    ":run" => debug(&mut engine, argument.trim(), &rl, &breakpoints),
+                    ":break" | ":delete" => {
+                        let mut breakpoints = breakpoints.borrow_mut();
+                        let changed = match command {
+                            ":break" if argument.trim().is_empty() => Ok(()),
+                            ":break" => breakpoints.set(argument),
+                            _ => breakpoints.delete(argument),
+                        };
+                        println!("{}", changed.map_or_else(|err| err, |_| breakpoints.to_string()));
+                    }
+                    ":help" => println!(":run <file>        runs a file, stopping at the breakpoints\n{}", HELP),
+                    _ => println!("the program is not running, see :help"),
+                }
+            }
             Ok(line) => {
-                rl.add_history_entry(line.as_str());
                 let lex_tokens = Lexer::lex_tokens(line.as_bytes());
                 match lex_tokens {
                     Ok((_, r)) => {
@@ -57,7 +99,7 @@ fn main() {
                         let parsed = Parser::parse_tokens(tokens);
                         match parsed {
                             Ok((_, program)) => {
-                                let eval  =evaluator.eval_program(program);
+                                let eval  =engine.eval_program(program);
                                 println!("{}", eval);
                             },
                             Err(nom::Err::Error(_)) => println!("Parser error"),
@@ -87,6 +129,39 @@ fn main() {
     }
 }
 
+/// Reads a line after a prompt, into the history.
+fn read(rl: &mut Editor<MyHelper>, prompt: &str) -> rustyline::Result<String> {
+    rl.helper_mut().expect("No helper").colored_prompt = format!("\x1b[1;32m{}\x1b[0m", prompt);
+    let line = rl.readline(prompt)?;
+    rl.add_history_entry(line.as_str());
+    Ok(line)
+}
+
+/// Runs a file on the evaluator, reading debugger commands where it stops. What it defines stays
+/// defined.
+fn debug(engine: &mut Engine, path: &str, rl: &Rc<RefCell<Editor<MyHelper>>>, breakpoints: &Rc<RefCell<Breakpoints>>) {
+    let Engine::Eval(evaluator) = engine else {
+        return println!("the debugger runs on the eval engine");
+    };
+    let program = fs::read_to_string(path)
+        .map_err(|err| err.to_string())
+        .and_then(|source| parse(&source).map(|program| (source, program)));
+    let (source, program) = match program {
+        Ok(program) => program,
+        Err(err) => return println!("{}: {}", path, err),
+    };
+    let rl = Rc::clone(rl);
+    let input = Box::new(move |prompt: &str| read(&mut rl.borrow_mut(), prompt).ok());
+    evaluator.set_file(Some(path));
+    let result = console::run(evaluator, &source, program, Rc::clone(breakpoints), input, Box::new(io::stdout()));
+    evaluator.set_file(None);
+    println!("{}", result);
+}
+
+fn is_backend(s: String) -> Result<(), String> {
+    s.parse::<Backend>().map(|_| ())
+}
+
 #[derive(Helper)]
 struct MyHelper {
     complete: FilenameCompleter,
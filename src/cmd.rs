@@ -1,26 +1,145 @@
+use std::path::Path;
+
 use clap::clap_app;
+use monkey_lang_lib::codegen::Target;
+use monkey_lang_lib::engine::Backend;
+use monkey_lang_lib::testing::Format;
 
 pub enum Command {
     FileRead(String),
     RunInlineCode(String),
+    /// compiles a source file to a bytecode file
+    Compile(String, String),
+    /// runs a bytecode file, or a source file
+    Run(String),
+    /// formats source files in place, or only checks that they are formatted
+    Format(Vec<String>, bool),
+    /// analyzes source files without running them, inferring types or not, reporting as JSON
+    /// or not
+    Check(Vec<String>, bool, bool),
+    /// runs the tests of the test files of paths, writing the results in a format
+    Test(Vec<String>, Format),
     Noop,
 }
 
-pub fn read_command() -> Command {
+pub struct Options {
+    pub backend: Backend,
+    /// runs the optimizer on programs before running or compiling them
+    pub optimize: bool,
+    /// prints the syntax tree of programs instead of running them
+    pub dump_ast: bool,
+    /// prints the code translated to another language instead of running it
+    pub emit: Option<Target>,
+    /// prints the calls made by programs to the standard error as they run
+    pub trace: bool,
+    /// prints the time spent in each function to the standard error once programs ran
+    pub profile: bool,
+    /// writes the time spent in each stack of calls to a file, as flame graph tools read them
+    pub folded: Option<String>,
+    /// writes what ran of programs to an lcov file, printing a summary to the standard error
+    pub coverage: Option<String>,
+}
+
+pub fn read_command() -> (Command, Options) {
     let matches = clap_app!(monkey =>
     (version: "0.0.1")
         (about: "The Monkey programming language")
         (@setting ArgRequiredElseHelp)
         (@arg src: -s --src +takes_value "Path of the source file")
         (@arg run: -r --run +takes_value "Code you want to run inline")
+        (@arg engine: -e --engine +takes_value {is_backend} "Backend running the code: eval (default) or vm")
+        (@arg opt: --opt "Optimizes the code before running or compiling it")
+        (@arg dump_ast: --("dump-ast") "Prints the syntax tree of the code, after optimization with --opt, instead of running it")
+        (@arg trace: --trace "Prints each call, and the value it returns, indented under its caller to the standard error as the code runs on the eval engine")
+        (@arg profile: --profile conflicts_with[trace] "Prints the calls of each function, and the time spent in it, to the standard error once the code ran on the eval engine")
+        (@arg folded: --folded +takes_value conflicts_with[trace] "Writes the time spent in each stack of calls to a file, in the folded format of flame graph tools")
+        (@arg coverage: --coverage +takes_value conflicts_with[trace profile folded opt] "Writes the lines, branches and functions of the code which ran on the eval engine to an lcov file, and a summary to the standard error")
+        (@arg emit: --emit +takes_value {is_target} "Prints the code translated to another language instead of running it: c, js or wat")
+        (@subcommand compile =>
+            (about: "Compiles a source file to bytecode")
+            (@arg input: +required "Path of the source file")
+            (@arg output: -o --output +takes_value "Path of the bytecode file, the source's with a .mkc extension by default")
+        )
+        (@subcommand run =>
+            (about: "Runs a bytecode file, or a source file")
+            (@arg input: +required "Path of the file")
+        )
+        (@subcommand fmt =>
+            (about: "Formats source files in place")
+            (@arg check: --check "Lists the files that are not formatted instead, failing if there are any")
+            (@arg files: +required +multiple "Paths of the source files")
+        )
+        (@subcommand check =>
+            (about: "Reports likely mistakes in source files without running them, failing if there are errors")
+            (@arg types: --types "Infers the types of the code too, reporting those that do not match")
+            (@arg json: --json "Prints the findings as a JSON array")
+            (@arg files: +required +multiple "Paths of the source files")
+        )
+        (@subcommand test =>
+            (about: "Runs the functions named test_... of *_test.mk files, each in a fresh evaluator, failing if any fails")
+            (@arg format: --format +takes_value {is_format} "How the results are written: text (default), tap or junit")
+            (@arg paths: +multiple "Paths of test files, or of directories to search for them, the current one by default")
+        )
     )
     .get_matches();
 
+    let options = Options {
+        backend: matches
+            .value_of("engine")
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(Backend::Eval),
+        optimize: matches.is_present("opt"),
+        dump_ast: matches.is_present("dump_ast"),
+        emit: matches.value_of("emit").and_then(|s| s.parse().ok()),
+        trace: matches.is_present("trace"),
+        profile: matches.is_present("profile"),
+        folded: matches.value_of("folded").map(|s| s.to_string()),
+        coverage: matches.value_of("coverage").map(|s| s.to_string()),
+    };
+    if let Some(matches) = matches.subcommand_matches("compile") {
+        let input = matches.value_of("input").expect("required").to_string();
+        let output = matches
+            .value_of("output")
+            .map(|s| s.to_string())
+            .unwrap_or_else(|| Path::new(&input).with_extension("mkc").display().to_string());
+        return (Command::Compile(input, output), options);
+    }
+    if let Some(matches) = matches.subcommand_matches("run") {
+        let input = matches.value_of("input").expect("required").to_string();
+        return (Command::Run(input), options);
+    }
+    if let Some(matches) = matches.subcommand_matches("fmt") {
+        let files = matches.values_of("files").expect("required").map(|s| s.to_string()).collect();
+        return (Command::Format(files, matches.is_present("check")), options);
+    }
+    if let Some(matches) = matches.subcommand_matches("check") {
+        let files = matches.values_of("files").expect("required").map(|s| s.to_string()).collect();
+        return (Command::Check(files, matches.is_present("types"), matches.is_present("json")), options);
+    }
+    if let Some(matches) = matches.subcommand_matches("test") {
+        let paths = matches.values_of("paths").map_or_else(|| vec![".".to_string()], |paths| paths.map(|s| s.to_string()).collect());
+        let format = matches.value_of("format").and_then(|s| s.parse().ok()).unwrap_or(Format::Text);
+        return (Command::Test(paths, format), options);
+    }
+
     let src_path = matches.value_of("src").map(|s| s.to_string());
     let run_string = matches.value_of("run").map(|s| s.to_string());
-    match (src_path, run_string) {
+    let command = match (src_path, run_string) {
         (Some(s), _) => Command::FileRead(s),
         (_, Some(s)) => Command::RunInlineCode(s),
         _ => Command::Noop,
-    }
+    };
+    (command, options)
+}
+
+pub fn is_backend(s: String) -> Result<(), String> {
+    s.parse::<Backend>().map(|_| ())
+}
+
+pub fn is_target(s: String) -> Result<(), String> {
+    s.parse::<Target>().map(|_| ())
+}
+
+pub fn is_format(s: String) -> Result<(), String> {
+    s.parse::<Format>().map(|_| ())
 }
@@ -0,0 +1,461 @@
+//! Ahead-of-time translation of programs to a self-contained C99 file.
+//!
+//! The output starts with a small runtime, `runtime.c`, giving values a tag and a reference
+//! count, and defining the operators and builtins as the evaluator does: a program translates
+//! whatever its values, and its errors are values giving the same messages. Variables live in
+//! scopes laid out by the resolver, one per call, which the functions a call creates keep
+//! alive. Every function literal becomes a C function taking the scope of its call, and the
+//! program becomes `main`, which prints the value of the program.
+
+use super::{supported, CodegenError};
+use crate::evaluator::builtins::BuiltinFunctions;
+use crate::evaluator::object::Object;
+use crate::parser::ast::{Expr, Ident, Infix, Literal, Prefix, Program, Stmt};
+use crate::resolver::{Resolution, Resolver};
+
+const INDENT: &str = "    ";
+
+const RUNTIME: &str = include_str!("runtime.c");
+
+pub fn emit(program: &Program) -> Result<String, CodegenError> {
+    let builtins = BuiltinFunctions::new().get_builtins();
+    let names = builtins.iter().map(|(Ident(name), _)| name.clone()).collect();
+    let mut resolver = Resolver::new(names);
+    let resolution = resolver
+        .resolve(program)
+        .map_err(|mut errors| CodegenError::Resolve(errors.remove(0)))?;
+    supported(program)?;
+    let mut emitter = Emitter { resolution: &resolution, functions: vec![], definitions: vec![], units: vec![] };
+    let main = emitter.unit(None, program);
+
+    let mut out = String::from(RUNTIME);
+    out.push_str("\nstatic Value builtins[] = {\n");
+    for (Ident(name), builtin) in &builtins {
+        if let Object::Builtin(_, params, _) = builtin {
+            out.push_str(&format!("{}BUILTIN_VALUE(\"{}\", {}, builtin_{}),\n", INDENT, name, params, name));
+        }
+    }
+    out.push_str("};\n");
+    if !emitter.definitions.is_empty() {
+        out.push('\n');
+        for i in 0..emitter.definitions.len() {
+            out.push_str(&format!("static Value *fn_{}(Scope *scope);\n", i));
+        }
+        for (i, lines) in emitter.definitions.iter().enumerate() {
+            out.push_str(&format!("\nstatic Value *fn_{}(Scope *scope) {{\n", i));
+            push_lines(&mut out, lines);
+            out.push_str("}\n");
+        }
+    }
+    out.push_str("\nint main(void) {\n");
+    out.push_str(&format!("{}Scope *scope = scope_new(NULL, {});\n", INDENT, resolver.globals().len()));
+    push_lines(&mut out, &main.lines);
+    out.push_str(&format!("{}Value *result = unwrap({});\n", INDENT, main.value));
+    out.push_str(&format!("{}print_value(result);\n", INDENT));
+    out.push_str(&format!("{}release(result);\n", INDENT));
+    out.push_str(&format!("{}scope_release(scope);\n", INDENT));
+    out.push_str(&format!("{}return 0;\n", INDENT));
+    out.push_str("}\n");
+    Ok(out)
+}
+
+fn push_lines(out: &mut String, lines: &[String]) {
+    for line in lines {
+        out.push_str(line);
+        out.push('\n');
+    }
+}
+
+/// the statements of a function, or of the program, and the variable holding their value
+struct Unit {
+    /// the function translated, or `None` for the program
+    function: Option<usize>,
+    lines: Vec<String>,
+    value: String,
+    temps: usize,
+    indent: usize,
+}
+
+struct Emitter<'a> {
+    resolution: &'a Resolution,
+    /// The function literals met, by enclosing function, parameters and body. Equal literals
+    /// of a function share their C function, so that the closures they give compare equal
+    /// when they close over the same scope, as in the evaluator.
+    functions: Vec<(Option<usize>, &'a [Ident], &'a Program)>,
+    /// the statements of each function of `functions`
+    definitions: Vec<Vec<String>>,
+    /// the units being translated, innermost last
+    units: Vec<Unit>,
+}
+
+impl<'a> Emitter<'a> {
+    fn unit(&mut self, function: Option<usize>, body: &'a Program) -> Unit {
+        self.units.push(Unit { function, lines: vec![], value: String::new(), temps: 0, indent: 1 });
+        let value = self.block(body);
+        let mut unit = self.units.pop().expect("no unit");
+        unit.value = value;
+        unit
+    }
+
+    fn current(&mut self) -> &mut Unit {
+        self.units.last_mut().expect("no unit")
+    }
+
+    fn line(&mut self, line: String) {
+        let unit = self.current();
+        let indent = INDENT.repeat(unit.indent);
+        unit.lines.push(format!("{}{}", indent, line));
+    }
+
+    /// a new variable of the current unit
+    fn temp(&mut self) -> String {
+        let unit = self.current();
+        unit.temps += 1;
+        format!("t{}", unit.temps - 1)
+    }
+
+    /// Translates statements run one after the other, the block ending at the first giving a
+    /// returned value, and gives the variable holding the value of the last one run.
+    fn block(&mut self, program: &'a [Stmt]) -> String {
+        let Some((last, init)) = program.split_last() else {
+            let value = self.temp();
+            self.line(format!("Value *{} = mk_null();", value));
+            return value;
+        };
+        if !init.iter().any(returns) {
+            for stmt in init {
+                let value = self.stmt(stmt);
+                self.line(format!("release({});", value));
+            }
+            return self.stmt(last);
+        }
+        let result = self.temp();
+        self.line(format!("Value *{};", result));
+        self.line("do {".to_string());
+        self.current().indent += 1;
+        for stmt in init {
+            let value = self.stmt(stmt);
+            if returns(stmt) {
+                self.line(format!("if (is_return({})) {{", value));
+                self.line(format!("{}{} = {};", INDENT, result, value));
+                self.line(format!("{}break;", INDENT));
+                self.line("}".to_string());
+            }
+            self.line(format!("release({});", value));
+        }
+        let value = self.stmt(last);
+        self.line(format!("{} = {};", result, value));
+        self.current().indent -= 1;
+        self.line("} while (0);".to_string());
+        result
+    }
+
+    fn stmt(&mut self, stmt: &'a Stmt) -> String {
+        match stmt {
+            Stmt::LetStmt(ident, _, expr) => {
+                let value = self.expr(expr);
+                let slot = self.resolution.slot(ident).expect("unresolved binding");
+                self.line(format!("scope_set(scope, {}, {});", slot.index, value));
+                value
+            }
+            Stmt::ReturnStmt(expr) => {
+                let value = self.expr(expr);
+                let returned = self.temp();
+                self.line(format!("Value *{} = mk_return({});", returned, value));
+                returned
+            }
+            Stmt::ExprStmt(expr) => self.expr(expr),
+            Stmt::ImportStmt(..) | Stmt::ExportStmt(_) => unreachable!("modules are rejected before emitting"),
+        }
+    }
+
+    /// Translates an expression, giving the variable holding a reference to its value.
+    fn expr(&mut self, expr: &'a Expr) -> String {
+        match expr {
+            Expr::IdentExpr(ident) => {
+                let slot = self.resolution.slot(ident).expect("unresolved identifier");
+                let nesting = self.units.len() - 1;
+                let value = self.temp();
+                if slot.depth == nesting + 1 {
+                    self.line(format!("Value *{} = retain(&builtins[{}]);", value, slot.index));
+                } else {
+                    self.line(format!(
+                        "Value *{} = scope_get(scope, {}, {}, \"{}\");",
+                        value, slot.depth, slot.index, ident.0
+                    ));
+                }
+                value
+            }
+            Expr::LiteralExpr(l) => {
+                let value = self.temp();
+                self.line(format!("Value *{} = {};", value, literal(l)));
+                value
+            }
+            Expr::PrefixExpr(prefix, e) => {
+                let operand = self.expr(e);
+                let op = match prefix {
+                    Prefix::PrefixPlus => "op_plus",
+                    Prefix::PrefixMinus => "op_minus",
+                    Prefix::Not => "op_not",
+                };
+                let value = self.temp();
+                self.line(format!("Value *{} = {}({});", value, op, operand));
+                self.line(format!("release({});", operand));
+                value
+            }
+            Expr::InfixExpr(infix, e1, e2) => {
+                let left = self.expr(e1);
+                let right = self.expr(e2);
+                let op = match infix {
+                    Infix::Plus => "op_add",
+                    Infix::Minus => "op_sub",
+                    Infix::Divide => "op_div",
+                    Infix::Multiply => "op_mul",
+                    Infix::Equal => "op_eq",
+                    Infix::NotEqual => "op_ne",
+                    Infix::GreaterThanEqual => "op_ge",
+                    Infix::LessThanEqual => "op_le",
+                    Infix::GreaterThan => "op_gt",
+                    Infix::LessThan => "op_lt",
+                };
+                let value = self.temp();
+                self.line(format!("Value *{} = {}({}, {});", value, op, left, right));
+                self.line(format!("release({});", left));
+                self.line(format!("release({});", right));
+                value
+            }
+            Expr::IfExpr { cond, consequence, alternative } => {
+                let cond = self.expr(cond);
+                let (error, truth, value) = (self.temp(), self.temp(), self.temp());
+                self.line(format!("Value *{} = NULL;", error));
+                self.line(format!("int {} = to_bool({}, &{});", truth, cond, error));
+                self.line(format!("release({});", cond));
+                self.line(format!("Value *{};", value));
+                self.line(format!("if ({} < 0) {{", truth));
+                self.line(format!("{}{} = {};", INDENT, value, error));
+                self.line(format!("}} else if ({}) {{", truth));
+                self.branch(consequence, &value);
+                self.line("} else {".to_string());
+                match alternative {
+                    Some(alternative) => self.branch(alternative, &value),
+                    None => self.line(format!("{}{} = mk_null();", INDENT, value)),
+                }
+                self.line("}".to_string());
+                value
+            }
+            Expr::FnExpr { params, body, .. } => {
+                let index = self.function(params, body);
+                let slots = self.resolution.frame(body).map_or(params.len(), |frame| frame.len());
+                let value = self.temp();
+                self.line(format!(
+                    "Value *{} = mk_function(fn_{}, {}, {}, scope);",
+                    value,
+                    index,
+                    params.len(),
+                    slots
+                ));
+                value
+            }
+            Expr::CallExpr { function, arguments } => {
+                let callee = self.expr(function);
+                let (error, value) = (self.temp(), self.temp());
+                self.line(format!("Value *{} = check_call({}, {});", error, callee, arguments.len()));
+                self.line(format!("Value *{};", value));
+                self.line(format!("if ({}) {{", error));
+                self.line(format!("{}{} = {};", INDENT, value, error));
+                self.line("} else {".to_string());
+                self.current().indent += 1;
+                let args = arguments.iter().map(|e| self.expr(e)).collect::<Vec<_>>();
+                if args.is_empty() {
+                    self.line(format!("{} = call({}, NULL);", value, callee));
+                } else {
+                    let array = self.temp();
+                    self.line(format!("Value *{}[] = {{{}}};", array, args.join(", ")));
+                    self.line(format!("{} = call({}, {});", value, callee, array));
+                    for arg in &args {
+                        self.line(format!("release({});", arg));
+                    }
+                }
+                self.current().indent -= 1;
+                self.line("}".to_string());
+                self.line(format!("release({});", callee));
+                value
+            }
+            Expr::ArrayExpr(exprs) => {
+                let items = exprs.iter().map(|e| self.expr(e)).collect::<Vec<_>>();
+                let value = self.temp();
+                if items.is_empty() {
+                    self.line(format!("Value *{} = mk_array(0, NULL);", value));
+                } else {
+                    let array = self.temp();
+                    self.line(format!("Value *{}[] = {{{}}};", array, items.join(", ")));
+                    self.line(format!("Value *{} = mk_array({}, {});", value, items.len(), array));
+                }
+                value
+            }
+            Expr::HashExpr(pairs) => {
+                let hash = self.temp();
+                self.line(format!("Value *{} = mk_hash();", hash));
+                for (key, e) in pairs {
+                    let value = self.expr(e);
+                    self.line(format!("hash_set({}, {}, {});", hash, literal(key), value));
+                }
+                hash
+            }
+            Expr::IndexExpr { array, index } => {
+                let target = self.expr(array);
+                let index = self.expr(index);
+                let value = self.temp();
+                self.line(format!("Value *{} = op_index({}, {});", value, target, index));
+                self.line(format!("release({});", target));
+                self.line(format!("release({});", index));
+                value
+            }
+            Expr::MemberExpr { .. } => unreachable!("modules are rejected before emitting"),
+        }
+    }
+
+    /// translates the block of an `if`, assigning its value to `result`
+    fn branch(&mut self, program: &'a [Stmt], result: &str) {
+        self.current().indent += 1;
+        let value = self.block(program);
+        self.line(format!("{} = {};", result, value));
+        self.current().indent -= 1;
+    }
+
+    /// the index of the C function of a function literal, translating it when first met
+    fn function(&mut self, params: &'a [Ident], body: &'a Program) -> usize {
+        let enclosing = self.current().function;
+        let found = self
+            .functions
+            .iter()
+            .position(|&(f, p, b)| f == enclosing && p == params && b == body);
+        if let Some(index) = found {
+            return index;
+        }
+        let index = self.functions.len();
+        self.functions.push((enclosing, params, body));
+        self.definitions.push(vec![]);
+        let mut unit = self.unit(Some(index), body);
+        unit.lines.push(format!("{}return {};", INDENT, unit.value));
+        self.definitions[index] = unit.lines;
+        index
+    }
+}
+
+/// whether a statement may give a returned value, ending its block
+fn returns(stmt: &Stmt) -> bool {
+    match stmt {
+        Stmt::ReturnStmt(_) => true,
+        Stmt::LetStmt(_, _, expr) | Stmt::ExprStmt(expr) => expr_returns(expr),
+        Stmt::ImportStmt(..) | Stmt::ExportStmt(_) => false,
+    }
+}
+
+fn expr_returns(expr: &Expr) -> bool {
+    match expr {
+        Expr::IdentExpr(_) | Expr::LiteralExpr(_) | Expr::FnExpr { .. } => false,
+        Expr::PrefixExpr(_, e) => expr_returns(e),
+        Expr::InfixExpr(_, e1, e2) => expr_returns(e1) || expr_returns(e2),
+        Expr::IfExpr { cond, consequence, alternative } => {
+            expr_returns(cond)
+                || consequence.iter().any(returns)
+                || alternative.as_deref().unwrap_or_default().iter().any(returns)
+        }
+        Expr::CallExpr { function, arguments } => expr_returns(function) || arguments.iter().any(expr_returns),
+        Expr::ArrayExpr(exprs) => exprs.iter().any(expr_returns),
+        Expr::HashExpr(pairs) => pairs.iter().any(|(_, e)| expr_returns(e)),
+        Expr::IndexExpr { array, index } => expr_returns(array) || expr_returns(index),
+        Expr::MemberExpr { module, .. } => expr_returns(module),
+    }
+}
+
+/// a C expression creating the value of a literal
+fn literal(literal: &Literal) -> String {
+    match literal {
+        Literal::IntLiteral(i) => format!("mk_int(INT64_C({}))", i),
+        Literal::BoolLiteral(b) => format!("mk_bool({})", *b as u8),
+        Literal::StringLiteral(s) => {
+            let mut out = String::from("mk_string(\"");
+            for &b in s.as_bytes() {
+                match b {
+                    b'"' => out.push_str("\\\""),
+                    b'\\' => out.push_str("\\\\"),
+                    // trigraphs start with two question marks
+                    b'?' => out.push_str("\\?"),
+                    b' '..=b'~' => out.push(b as char),
+                    b => out.push_str(&format!("\\{:03o}", b)),
+                }
+            }
+            out.push_str(&format!("\", {})", s.len()));
+            out
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::token::Tokens;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn parse(input: &str) -> Program {
+        let (_, r) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+        let (_, program) = Parser::parse_tokens(Tokens::new(&r)).unwrap();
+        program
+    }
+
+    /// the translation, from the definitions following the runtime
+    fn generated(input: &str) -> String {
+        let c = emit(&parse(input)).unwrap();
+        let builtins = c.find("static Value builtins[]").unwrap();
+        let start = builtins + c[builtins..].find("};\n").unwrap() + 3;
+        c[start..].to_string()
+    }
+
+    #[test]
+    fn functions() {
+        assert_eq!(
+            generated("let f = fn(x) { x }; f"),
+            "\nstatic Value *fn_0(Scope *scope);\n\
+             \nstatic Value *fn_0(Scope *scope) {\
+             \n    Value *t0 = scope_get(scope, 0, 0, \"x\");\
+             \n    return t0;\
+             \n}\n\
+             \nint main(void) {\
+             \n    Scope *scope = scope_new(NULL, 1);\
+             \n    Value *t0 = mk_function(fn_0, 1, 1, scope);\
+             \n    scope_set(scope, 0, t0);\
+             \n    release(t0);\
+             \n    Value *t1 = scope_get(scope, 0, 0, \"f\");\
+             \n    Value *result = unwrap(t1);\
+             \n    print_value(result);\
+             \n    release(result);\
+             \n    scope_release(scope);\
+             \n    return 0;\
+             \n}\n"
+        );
+        // equal literals of the same function share their code, those of others do not
+        let c = generated("let f = fn() { fn(x) { x } }; [fn(x) { x }, fn(x) { x }, f()]");
+        assert_eq!(c.matches("static Value *fn_").count(), 6);
+    }
+
+    #[test]
+    fn literals() {
+        assert_eq!(literal(&Literal::IntLiteral(-1)), "mk_int(INT64_C(-1))");
+        assert_eq!(literal(&Literal::BoolLiteral(true)), "mk_bool(1)");
+        assert_eq!(
+            literal(&Literal::StringLiteral("a\"??/\né".to_string())),
+            "mk_string(\"a\\\"\\?\\?/\\012\\303\\251\", 8)"
+        );
+    }
+
+    #[test]
+    fn builtins() {
+        let c = emit(&parse("len")).unwrap();
+        assert!(c.contains("    BUILTIN_VALUE(\"len\", 1, builtin_len),\n"));
+        assert!(c.contains("Value *t0 = retain(&builtins[1]);"));
+        assert_eq!(emit(&parse("y")).unwrap_err().to_string(), "identifier not found: y");
+    }
+}
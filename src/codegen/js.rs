@@ -0,0 +1,698 @@
+//! Translation of programs to JavaScript (ES2015) meant to be read and reused.
+//!
+//! Functions become arrow functions, closing over the variables of their scope as Monkey
+//! functions do, arrays become arrays and hashes `Map`s. A name bound once at the top of its
+//! scope becomes a `const`; the others are declared with `let` at the start of the scope, as
+//! the functions of a Monkey function see its bindings wherever they are. An `if` becomes an `if`
+//! statement where its value is returned, bound or dropped, and a conditional expression or a
+//! function called in place elsewhere. The builtins, and the helpers giving indexing and
+//! equality their Monkey meaning, are defined at the start of the output when they are used.
+//!
+//! The script completes with the value of the program, as `eval` gives it. Programs are
+//! expected to run without errors, which are not checked for: operators and calls behave as
+//! they do in JavaScript on values of unexpected types or numbers of arguments, integers are
+//! numbers, exact only up to 2^53, and the errors of builtins are thrown, so that no error
+//! would reach `assert_error`. Using `assert_error`, returning from the top level of a program,
+//! returning from an `if` used as an operand, or reading an enclosing binding before the `let`
+//! shadowing it, has no JavaScript equivalent.
+
+use super::{bound_names, expr_bound_names, supported, CodegenError};
+use crate::evaluator::builtins::BuiltinFunctions;
+use crate::parser::ast::{Expr, Ident, Infix, Literal, Prefix, Program, Stmt};
+use crate::resolver::{Resolution, Resolver};
+
+const INDENT: &str = "  ";
+
+/// The definitions the output may need, in the order they are written, and the definitions
+/// they use.
+const PRELUDE: &[(&str, &[&str], &str)] = &[
+    (
+        "$show",
+        &[],
+        r#"const $show = (value) => {
+  if (value === null || value === undefined) {
+    return "null";
+  } else if (Array.isArray(value)) {
+    return "[" + value.map($show).join(", ") + "]";
+  } else if (value instanceof Map) {
+    return "{" + Array.from(value, ([k, v]) => $show(k) + ": " + $show(v)).join(", ") + "}";
+  } else if (typeof value === "function") {
+    return "[function]";
+  }
+  return String(value);
+};"#,
+    ),
+    (
+        "$equal",
+        &[],
+        r#"const $equal = (a, b) => {
+  if (Array.isArray(a) && Array.isArray(b)) {
+    return a.length === b.length && a.every((x, i) => $equal(x, b[i]));
+  } else if (a instanceof Map && b instanceof Map) {
+    return a.size === b.size && Array.from(a).every(([k, v]) => b.has(k) && $equal(v, b.get(k)));
+  }
+  return a === b;
+};"#,
+    ),
+    (
+        "$index",
+        &[],
+        r#"const $index = (target, key) => {
+  if (target instanceof Map) {
+    return target.has(key) ? target.get(key) : null;
+  }
+  return key >= 0 && key < target.length ? target[key] : null;
+};"#,
+    ),
+    (
+        "print",
+        &["$show"],
+        r#"const print = (value) => {
+  console.log($show(value));
+  return null;
+};"#,
+    ),
+    (
+        "len",
+        &[],
+        r#"const len = (value) => (typeof value === "string" ? unescape(encodeURIComponent(value)) : value).length;"#,
+    ),
+    (
+        "head",
+        &[],
+        r#"const head = (array) => {
+  if (array.length === 0) {
+    throw new Error("empty array");
+  }
+  return array[0];
+};"#,
+    ),
+    (
+        "tail",
+        &[],
+        r#"const tail = (array) => {
+  if (array.length === 0) {
+    throw new Error("empty array");
+  }
+  return array.slice(1);
+};"#,
+    ),
+    ("cons", &[], "const cons = (value, array) => [value].concat(array);"),
+    (
+        "$source",
+        &["$show"],
+        r#"const $source = (value) => {
+  if (typeof value === "string") {
+    return "\"" + value.replace(/["\\]/g, "\\$&") + "\"";
+  } else if (Array.isArray(value)) {
+    return "[" + value.map($source).join(", ") + "]";
+  } else if (value instanceof Map) {
+    const pairs = Array.from(value, ([k, v]) => [$source(k), $source(v)]);
+    pairs.sort(([a], [b]) => (a < b ? -1 : a > b ? 1 : 0));
+    return "{" + pairs.map(([k, v]) => k + ": " + v).join(", ") + "}";
+  }
+  return $show(value);
+};"#,
+    ),
+    (
+        "$differ",
+        &["$equal", "$source"],
+        r#"const $differ = (path, left, right, differences) => {
+  if (Array.isArray(left) && Array.isArray(right)) {
+    for (let i = 0; i < left.length || i < right.length; i++) {
+      const at = path + "[" + i + "]";
+      if (i >= right.length) {
+        differences.push(at + ": " + $source(left[i]) + " only on the left");
+      } else if (i >= left.length) {
+        differences.push(at + ": " + $source(right[i]) + " only on the right");
+      } else {
+        $differ(at, left[i], right[i], differences);
+      }
+    }
+  } else if (left instanceof Map && right instanceof Map) {
+    const keys = Array.from(left.keys()).concat(Array.from(right.keys()).filter((k) => !left.has(k)));
+    const shown = keys.map((k) => [$source(k), k]);
+    shown.sort(([a], [b]) => (a < b ? -1 : a > b ? 1 : 0));
+    for (const [source, k] of shown) {
+      const at = path + "[" + source + "]";
+      if (!right.has(k)) {
+        differences.push(at + ": " + $source(left.get(k)) + " only on the left");
+      } else if (!left.has(k)) {
+        differences.push(at + ": " + $source(right.get(k)) + " only on the right");
+      } else {
+        $differ(at, left.get(k), right.get(k), differences);
+      }
+    }
+  } else if (path !== "" && !$equal(left, right)) {
+    differences.push(path + ": " + $source(left) + " is not " + $source(right));
+  }
+};"#,
+    ),
+    (
+        "assert",
+        &["$source"],
+        r#"const assert = (condition) => {
+  if (condition !== true) {
+    throw new Error(condition === false ? "assertion failed" : "assertion failed: " + $source(condition) + " is not a bool");
+  }
+  return null;
+};"#,
+    ),
+    (
+        "assert_eq",
+        &["$equal", "$source", "$differ"],
+        r#"const assert_eq = (left, right) => {
+  if ($equal(left, right)) {
+    return null;
+  }
+  let message = "assertion failed: the values differ\n  left:  " + $source(left) + "\n  right: " + $source(right);
+  const differences = [];
+  $differ("", left, right, differences);
+  for (const difference of differences.slice(0, 5)) {
+    message += "\n  at " + difference;
+  }
+  if (differences.length > 5) {
+    message += "\n  and " + (differences.length - 5) + " more";
+  }
+  throw new Error(message);
+};"#,
+    ),
+];
+
+/// names of JavaScript that Monkey programs may bind, which are given a trailing `_`
+const RESERVED: &[&str] = &[
+    "arguments", "await", "break", "case", "catch", "class", "const", "continue", "debugger",
+    "default", "delete", "do", "enum", "eval", "export", "extends", "finally", "for", "function",
+    "implements", "import", "in", "instanceof", "interface", "new", "null", "package", "private",
+    "protected", "public", "static", "super", "switch", "this", "throw", "try", "typeof", "var",
+    "void", "while", "with", "yield", "undefined", "NaN", "Infinity", "Array", "Map", "Math",
+    "String", "Error", "console", "unescape", "encodeURIComponent",
+];
+
+// precedences of JavaScript expressions, the operands of which are parenthesized when they
+// bind less tightly than needed
+const ARROW: u8 = 2;
+const EQUALITY: u8 = 9;
+const RELATIONAL: u8 = 10;
+const ADDITIVE: u8 = 12;
+const MULTIPLICATIVE: u8 = 13;
+const PREFIX: u8 = 14;
+const PRIMARY: u8 = 20;
+
+pub fn emit(program: &Program) -> Result<String, CodegenError> {
+    let builtins = BuiltinFunctions::new().names();
+    let resolution = Resolver::new(builtins.clone())
+        .resolve(program)
+        .map_err(|mut errors| CodegenError::Resolve(errors.remove(0)))?;
+    supported(program)?;
+    let mut emitter = Emitter { resolution, scopes: vec![], builtins, used: vec![] };
+    let body = emitter.scope(&[], program, Tail::Value, 0)?;
+
+    let mut out = String::from("\"use strict\";\n\n");
+    for (name, _, definition) in PRELUDE {
+        if emitter.used.contains(name) {
+            out.push_str(definition);
+            out.push_str("\n\n");
+        }
+    }
+    for line in body {
+        out.push_str(&line);
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+/// what becomes of the value of a block
+#[derive(Debug, Clone, Copy)]
+enum Tail<'a> {
+    Drop,
+    /// left as the completion value of the script
+    Value,
+    Return,
+    Assign(&'a str),
+}
+
+/// the names of a function scope, and those of them written as constants
+struct Scope {
+    names: Vec<String>,
+    constants: Vec<String>,
+}
+
+struct Emitter {
+    resolution: Resolution,
+    scopes: Vec<Scope>,
+    builtins: Vec<String>,
+    /// the definitions of `PRELUDE` used
+    used: Vec<&'static str>,
+}
+
+impl Emitter {
+    fn scope(
+        &mut self,
+        params: &[Ident],
+        body: &[Stmt],
+        tail: Tail,
+        depth: usize,
+    ) -> Result<Vec<String>, CodegenError> {
+        let params = params.iter().map(|Ident(name)| name.clone()).collect::<Vec<_>>();
+        let mut bound = vec![];
+        bound_names(body, &mut bound);
+        let mut nested = vec![];
+        let mut direct = vec![];
+        for stmt in body {
+            match stmt {
+                Stmt::LetStmt(Ident(name), _, expr) => {
+                    direct.push(name.clone());
+                    expr_bound_names(expr, &mut nested);
+                }
+                Stmt::ReturnStmt(expr) | Stmt::ExprStmt(expr) => expr_bound_names(expr, &mut nested),
+                Stmt::ImportStmt(..) | Stmt::ExportStmt(_) => {}
+            }
+        }
+        let constants = bound
+            .iter()
+            .filter(|name| {
+                direct.iter().filter(|d| d == name).count() == 1 && !nested.contains(name) && !params.contains(name)
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        let declared = bound
+            .iter()
+            .filter(|name| !constants.contains(name) && !params.contains(name))
+            .map(|name| js_name(name))
+            .collect::<Vec<_>>();
+        let mut names = params;
+        names.extend(bound);
+        self.scopes.push(Scope { names, constants });
+
+        let mut lines = vec![];
+        if !declared.is_empty() {
+            lines.push(format!("{}let {};", INDENT.repeat(depth), declared.join(", ")));
+        }
+        let block = self.block(body, tail, depth);
+        self.scopes.pop();
+        lines.extend(block?);
+        Ok(lines)
+    }
+
+    /// the statements of a block up to a `return`, indented by `depth`
+    fn block(&mut self, program: &[Stmt], tail: Tail, depth: usize) -> Result<Vec<String>, CodegenError> {
+        let indent = INDENT.repeat(depth);
+        let mut lines = vec![];
+        if program.is_empty() {
+            lines.extend(self.tail("null".to_string(), tail, depth));
+        }
+        for (i, stmt) in program.iter().enumerate() {
+            let last = i == program.len() - 1;
+            match stmt {
+                Stmt::ReturnStmt(expr) => {
+                    if self.scopes.len() == 1 {
+                        return Err(unsupported("return outside of functions"));
+                    }
+                    match expr {
+                        Expr::IfExpr { cond, consequence, alternative } if !simple(expr) => {
+                            lines.extend(self.if_stmt(cond, consequence, alternative.as_deref(), Tail::Return, depth)?)
+                        }
+                        _ => lines.push(format!("{}return {};", indent, self.expr(expr, ARROW, depth)?)),
+                    }
+                    break;
+                }
+                Stmt::LetStmt(Ident(name), _, expr) => {
+                    let constant = self.scopes.last().unwrap().constants.contains(name);
+                    let js = js_name(name);
+                    match expr {
+                        Expr::IfExpr { cond, consequence, alternative } if !simple(expr) => {
+                            if constant {
+                                lines.push(format!("{}let {};", indent, js));
+                            }
+                            let tail = Tail::Assign(&js);
+                            lines.extend(self.if_stmt(cond, consequence, alternative.as_deref(), tail, depth)?)
+                        }
+                        _ => {
+                            let decl = if constant { "const " } else { "" };
+                            lines.push(format!("{}{}{} = {};", indent, decl, js, self.expr(expr, ARROW, depth)?));
+                        }
+                    }
+                    if last {
+                        lines.extend(self.tail(js, tail, depth));
+                    }
+                }
+                Stmt::ExprStmt(expr) => {
+                    let tail = if last { tail } else { Tail::Drop };
+                    match expr {
+                        Expr::IfExpr { cond, consequence, alternative } => {
+                            lines.extend(self.if_stmt(cond, consequence, alternative.as_deref(), tail, depth)?)
+                        }
+                        _ => {
+                            let value = self.expr(expr, ARROW, depth)?;
+                            lines.extend(self.tail(value, tail, depth))
+                        }
+                    }
+                }
+                Stmt::ImportStmt(..) | Stmt::ExportStmt(_) => return Err(unsupported("modules")),
+            }
+        }
+        Ok(lines)
+    }
+
+    fn tail(&self, value: String, tail: Tail, depth: usize) -> Vec<String> {
+        let indent = INDENT.repeat(depth);
+        let line = match tail {
+            Tail::Drop if value == "null" => return vec![],
+            Tail::Drop | Tail::Value => format!("{}{};", indent, value),
+            Tail::Return => format!("{}return {};", indent, value),
+            Tail::Assign(name) if name == value => return vec![],
+            Tail::Assign(name) => format!("{}{} = {};", indent, name, value),
+        };
+        vec![line]
+    }
+
+    fn if_stmt(
+        &mut self,
+        cond: &Expr,
+        consequence: &[Stmt],
+        alternative: Option<&[Stmt]>,
+        tail: Tail,
+        depth: usize,
+    ) -> Result<Vec<String>, CodegenError> {
+        let indent = INDENT.repeat(depth);
+        let mut lines = vec![format!("{}if ({}) {{", indent, self.expr(cond, ARROW, depth)?)];
+        lines.extend(self.block(consequence, tail, depth + 1)?);
+        match (alternative, tail) {
+            // an `else if`
+            (Some([Stmt::ExprStmt(Expr::IfExpr { cond, consequence, alternative })]), _) => {
+                let mut chained = self.if_stmt(cond, consequence, alternative.as_deref(), tail, depth)?;
+                chained[0] = format!("{}}} else {}", indent, chained[0].trim_start());
+                lines.extend(chained);
+                return Ok(lines);
+            }
+            (Some(alternative), _) => {
+                lines.push(format!("{}}} else {{", indent));
+                lines.extend(self.block(alternative, tail, depth + 1)?);
+            }
+            // the consequence returned otherwise
+            (None, Tail::Return) => {
+                lines.push(format!("{}}}", indent));
+                lines.push(format!("{}return null;", indent));
+                return Ok(lines);
+            }
+            (None, Tail::Assign(_)) => {
+                lines.push(format!("{}}} else {{", indent));
+                lines.extend(self.block(&[], tail, depth + 1)?);
+            }
+            (None, Tail::Drop | Tail::Value) => {}
+        }
+        lines.push(format!("{}}}", indent));
+        Ok(lines)
+    }
+
+    /// the expression, parenthesized if it binds less tightly than `min`
+    fn expr(&mut self, expr: &Expr, min: u8, depth: usize) -> Result<String, CodegenError> {
+        let (code, precedence) = self.expr_precedence(expr, depth)?;
+        Ok(if precedence < min { format!("({})", code) } else { code })
+    }
+
+    fn expr_precedence(&mut self, expr: &Expr, depth: usize) -> Result<(String, u8), CodegenError> {
+        Ok(match expr {
+            Expr::IdentExpr(ident @ Ident(name)) => {
+                // the declaration of the function's own binding would hide the enclosing one
+                let outer = self.resolution.slot(ident).is_some_and(|slot| slot.depth > 0);
+                if outer && self.scopes.last().is_some_and(|scope| scope.names.contains(name)) {
+                    return Err(unsupported(&format!("names read before a let shadowing them, such as {}", name)));
+                }
+                self.builtin(name)?;
+                (js_name(name), PRIMARY)
+            }
+            Expr::LiteralExpr(literal) => (self::literal(literal), PRIMARY),
+            Expr::PrefixExpr(prefix, e) => {
+                let op = match prefix {
+                    Prefix::PrefixPlus => "+",
+                    Prefix::PrefixMinus => "-",
+                    Prefix::Not => "!",
+                };
+                // parenthesized so that `- -x` does not read as `--x`
+                let min = if let Expr::PrefixExpr(..) = **e { PRIMARY } else { PREFIX };
+                (format!("{}{}", op, self.expr(e, min, depth)?), PREFIX)
+            }
+            Expr::InfixExpr(infix, e1, e2) => {
+                let (op, precedence) = match infix {
+                    Infix::Plus => ("+", ADDITIVE),
+                    Infix::Minus => ("-", ADDITIVE),
+                    Infix::Multiply => ("*", MULTIPLICATIVE),
+                    Infix::Divide => {
+                        let (e1, e2) = (self.expr(e1, MULTIPLICATIVE, depth)?, self.expr(e2, PREFIX, depth)?);
+                        return Ok((format!("Math.trunc({} / {})", e1, e2), PRIMARY));
+                    }
+                    Infix::GreaterThanEqual => (">=", RELATIONAL),
+                    Infix::LessThanEqual => ("<=", RELATIONAL),
+                    Infix::GreaterThan => (">", RELATIONAL),
+                    Infix::LessThan => ("<", RELATIONAL),
+                    // values compared to a literal cannot be equal to it without being identical
+                    Infix::Equal | Infix::NotEqual if !literal_operand(e1, e2) => {
+                        let not = if *infix == Infix::NotEqual { "!" } else { "" };
+                        let (e1, e2) = (self.expr(e1, ARROW, depth)?, self.expr(e2, ARROW, depth)?);
+                        self.use_prelude("$equal");
+                        let precedence = if not.is_empty() { PRIMARY } else { PREFIX };
+                        return Ok((format!("{}$equal({}, {})", not, e1, e2), precedence));
+                    }
+                    Infix::Equal => ("===", EQUALITY),
+                    Infix::NotEqual => ("!==", EQUALITY),
+                };
+                let (e1, e2) = (self.expr(e1, precedence, depth)?, self.expr(e2, precedence + 1, depth)?);
+                (format!("{} {} {}", e1, op, e2), precedence)
+            }
+            Expr::IfExpr { cond, consequence, alternative } if simple(expr) => {
+                let cond = self.expr(cond, ARROW + 1, depth)?;
+                let consequence = self.branch(consequence, depth)?;
+                let alternative = self.branch(alternative.as_deref().unwrap_or_default(), depth)?;
+                (format!("{} ? {} : {}", cond, consequence, alternative), ARROW)
+            }
+            Expr::IfExpr { cond, consequence, alternative } => {
+                if consequence.iter().chain(alternative.iter().flatten()).any(returns) {
+                    return Err(unsupported("return from an if used as an operand"));
+                }
+                let mut code = String::from("(() => {\n");
+                for line in self.if_stmt(cond, consequence, alternative.as_deref(), Tail::Return, depth + 1)? {
+                    code.push_str(&line);
+                    code.push('\n');
+                }
+                code.push_str(&format!("{}}})()", INDENT.repeat(depth)));
+                (code, PRIMARY)
+            }
+            Expr::FnExpr { params, body, .. } => {
+                let names = params.iter().map(|Ident(name)| js_name(name)).collect::<Vec<_>>();
+                let params_code = format!("({})", names.join(", "));
+                let mut bound = vec![];
+                bound_names(body, &mut bound);
+                match &body[..] {
+                    [] => (format!("{} => null", params_code), ARROW),
+                    [Stmt::ExprStmt(e)] if !matches!(e, Expr::IfExpr { .. }) && bound.is_empty() => {
+                        let names = params.iter().map(|Ident(name)| name.clone()).collect();
+                        self.scopes.push(Scope { names, constants: vec![] });
+                        let e = self.expr(e, ARROW, depth);
+                        self.scopes.pop();
+                        (format!("{} => {}", params_code, e?), ARROW)
+                    }
+                    _ => {
+                        let mut code = format!("{} => {{\n", params_code);
+                        for line in self.scope(params, body, Tail::Return, depth + 1)? {
+                            code.push_str(&line);
+                            code.push('\n');
+                        }
+                        code.push_str(&format!("{}}}", INDENT.repeat(depth)));
+                        (code, ARROW)
+                    }
+                }
+            }
+            Expr::CallExpr { function, arguments } => {
+                let function = self.expr(function, PRIMARY, depth)?;
+                let arguments = self.list(arguments, depth)?;
+                (format!("{}({})", function, arguments), PRIMARY)
+            }
+            Expr::ArrayExpr(exprs) => (format!("[{}]", self.list(exprs, depth)?), PRIMARY),
+            Expr::HashExpr(pairs) if pairs.is_empty() => ("new Map()".to_string(), PRIMARY),
+            Expr::HashExpr(pairs) => {
+                let mut entries = vec![];
+                for (key, value) in pairs {
+                    entries.push(format!("[{}, {}]", self::literal(key), self.expr(value, ARROW, depth)?));
+                }
+                (format!("new Map([{}])", entries.join(", ")), PRIMARY)
+            }
+            Expr::IndexExpr { array, index } => {
+                let (array, index) = (self.expr(array, ARROW, depth)?, self.expr(index, ARROW, depth)?);
+                self.use_prelude("$index");
+                (format!("$index({}, {})", array, index), PRIMARY)
+            }
+            Expr::MemberExpr { .. } => return Err(unsupported("modules")),
+        })
+    }
+
+    /// the value of a branch of an `if` written as a conditional expression
+    fn branch(&mut self, program: &[Stmt], depth: usize) -> Result<String, CodegenError> {
+        match program {
+            [] => Ok("null".to_string()),
+            [Stmt::ExprStmt(expr)] => self.expr(expr, ARROW, depth),
+            _ => unreachable!("branches of conditional expressions are single expressions"),
+        }
+    }
+
+    fn list(&mut self, exprs: &[Expr], depth: usize) -> Result<String, CodegenError> {
+        let mut codes = vec![];
+        for expr in exprs {
+            codes.push(self.expr(expr, ARROW, depth)?);
+        }
+        Ok(codes.join(", "))
+    }
+
+    /// marks the builtin a name refers to, if it does, as used
+    fn builtin(&mut self, name: &str) -> Result<(), CodegenError> {
+        let bound = self.scopes.iter().any(|scope| scope.names.iter().any(|n| n == name));
+        if !bound && self.builtins.iter().any(|builtin| builtin == name) {
+            // errors are thrown, so that none would reach it
+            if name == "assert_error" {
+                return Err(unsupported("assert_error"));
+            }
+            if let Some((name, _, _)) = PRELUDE.iter().find(|(prelude, _, _)| *prelude == name) {
+                self.use_prelude(name);
+            }
+        }
+        Ok(())
+    }
+
+    fn use_prelude(&mut self, name: &'static str) {
+        if !self.used.contains(&name) {
+            self.used.push(name);
+            let (_, dependencies, _) = PRELUDE.iter().find(|(prelude, _, _)| *prelude == name).unwrap();
+            for dependency in *dependencies {
+                self.use_prelude(dependency);
+            }
+        }
+    }
+}
+
+/// whether an `if` can be written as a conditional expression, its branches being single
+/// expressions that can be
+fn simple(expr: &Expr) -> bool {
+    let branch = |program: &[Stmt]| match program {
+        [] => true,
+        [Stmt::ExprStmt(expr)] => !matches!(expr, Expr::IfExpr { .. }) || simple(expr),
+        _ => false,
+    };
+    match expr {
+        Expr::IfExpr { consequence, alternative, .. } => {
+            branch(consequence) && branch(alternative.as_deref().unwrap_or_default())
+        }
+        _ => false,
+    }
+}
+
+/// whether a statement returns, from its function or from the enclosing ones
+fn returns(stmt: &Stmt) -> bool {
+    match stmt {
+        Stmt::ReturnStmt(_) => true,
+        Stmt::LetStmt(_, _, expr) | Stmt::ExprStmt(expr) => match expr {
+            Expr::IfExpr { consequence, alternative, .. } => {
+                consequence.iter().chain(alternative.iter().flatten()).any(returns)
+            }
+            _ => false,
+        },
+        Stmt::ImportStmt(..) | Stmt::ExportStmt(_) => false,
+    }
+}
+
+fn literal_operand(e1: &Expr, e2: &Expr) -> bool {
+    matches!(e1, Expr::LiteralExpr(_)) || matches!(e2, Expr::LiteralExpr(_))
+}
+
+fn literal(literal: &Literal) -> String {
+    match literal {
+        Literal::IntLiteral(i) => i.to_string(),
+        Literal::BoolLiteral(b) => b.to_string(),
+        Literal::StringLiteral(s) => {
+            let mut out = String::from("\"");
+            for c in s.chars() {
+                match c {
+                    '"' => out.push_str("\\\""),
+                    '\\' => out.push_str("\\\\"),
+                    '\n' => out.push_str("\\n"),
+                    '\r' => out.push_str("\\r"),
+                    '\t' => out.push_str("\\t"),
+                    c if c.is_control() || c == '\u{2028}' || c == '\u{2029}' => {
+                        out.push_str(&format!("\\u{:04x}", c as u32))
+                    }
+                    c => out.push(c),
+                }
+            }
+            out.push('"');
+            out
+        }
+    }
+}
+
+fn js_name(name: &str) -> String {
+    if RESERVED.contains(&name) {
+        format!("{}_", name)
+    } else {
+        name.to_string()
+    }
+}
+
+fn unsupported(what: &str) -> CodegenError {
+    CodegenError::Unsupported(what.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::token::Tokens;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn parse(input: &str) -> Program {
+        let (_, r) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+        let (_, program) = Parser::parse_tokens(Tokens::new(&r)).unwrap();
+        program
+    }
+
+    /// the translation, without the line saying the script is strict
+    fn body(input: &str) -> String {
+        emit(&parse(input)).unwrap().trim_start_matches("\"use strict\";\n\n").to_string()
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(
+            body("let new = 1; let Map = fn(this) { this }; Map(new)"),
+            "const new_ = 1;\nconst Map_ = (this_) => this_;\nMap_(new_);\n"
+        );
+        assert_eq!(body("let x = 1; let x = x + 1; x"), "let x;\nx = 1;\nx = x + 1;\nx;\n");
+        assert_eq!(
+            body("let f = fn(x) { let x = x * 2; x }"),
+            "const f = (x) => {\n  x = x * 2;\n  return x;\n};\nf;\n"
+        );
+        // only the builtins not bound by the program are defined
+        assert_eq!(body("let len = fn(x) { 0 }; len([])"), "const len = (x) => 0;\nlen([]);\n");
+        assert!(body("cons(1, [])").starts_with("const cons = (value, array) => [value].concat(array);\n\n"));
+    }
+
+    #[test]
+    fn errors() {
+        let error = |input: &str| emit(&parse(input)).unwrap_err().to_string();
+        assert_eq!(error("y"), "identifier not found: y");
+        assert_eq!(error("return 1"), "not supported by the target: return outside of functions");
+        assert_eq!(
+            error("fn(c) { 1 + if (c) { return 2 } else { 3 } }"),
+            "not supported by the target: return from an if used as an operand"
+        );
+        assert_eq!(error("import \"m.mk\" as m; fn() { m.f }"), "not supported by the target: modules");
+        assert_eq!(error("let x: int = 1; x"), "not supported by the target: type annotations");
+        assert_eq!(error("[fn(a) -> int { a }]"), "not supported by the target: type annotations");
+        assert_eq!(error("assert_error(len(1))"), "not supported by the target: assert_error");
+        assert_eq!(
+            error("let a = 1; fn() { let b = a; let a = 2; b }"),
+            "not supported by the target: names read before a let shadowing them, such as a"
+        );
+        // unless the program binds the name
+        assert!(emit(&parse("let assert_error = fn(x) { x }; assert_error(1)")).is_ok());
+    }
+}
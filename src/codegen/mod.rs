@@ -0,0 +1,147 @@
+use std::fmt::{self, Formatter};
+use std::str::FromStr;
+
+use crate::parser::ast::{Expr, Ident, Program, Stmt};
+use crate::resolver::ResolveError;
+
+pub mod c;
+pub mod js;
+pub mod wat;
+
+/// The languages programs can be translated to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    /// C99, with a runtime
+    C,
+    /// JavaScript
+    Js,
+    /// WebAssembly text format
+    Wat,
+}
+
+impl FromStr for Target {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "c" => Ok(Target::C),
+            "js" => Ok(Target::Js),
+            "wat" => Ok(Target::Wat),
+            _ => Err(format!("unknown target: {}", s)),
+        }
+    }
+}
+
+impl Target {
+    pub fn emit(self, program: &Program) -> Result<String, CodegenError> {
+        match self {
+            Target::C => c::emit(program),
+            Target::Js => js::emit(program),
+            Target::Wat => wat::emit(program),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CodegenError {
+    Resolve(ResolveError),
+    /// a construct the target does not support
+    Unsupported(String),
+    /// values of different types used in the same place
+    Type(String),
+}
+
+impl fmt::Display for CodegenError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            CodegenError::Resolve(err) => write!(f, "{}", err),
+            CodegenError::Unsupported(what) => write!(f, "not supported by the target: {}", what),
+            CodegenError::Type(err) => write!(f, "type error: {}", err),
+        }
+    }
+}
+
+/// The names bound by the `let`s of a scope, in order and once each. Function literals are
+/// not looked into, as they have their own scope.
+fn bound_names(program: &[Stmt], names: &mut Vec<String>) {
+    for stmt in program {
+        match stmt {
+            Stmt::LetStmt(Ident(name), _, expr) => {
+                expr_bound_names(expr, names);
+                if !names.contains(name) {
+                    names.push(name.clone());
+                }
+            }
+            Stmt::ReturnStmt(expr) | Stmt::ExprStmt(expr) => expr_bound_names(expr, names),
+            Stmt::ImportStmt(..) | Stmt::ExportStmt(_) => {}
+        }
+    }
+}
+
+fn expr_bound_names(expr: &Expr, names: &mut Vec<String>) {
+    match expr {
+        Expr::IdentExpr(_) | Expr::LiteralExpr(_) | Expr::FnExpr { .. } => {}
+        Expr::PrefixExpr(_, e) => expr_bound_names(e, names),
+        Expr::InfixExpr(_, e1, e2) => {
+            expr_bound_names(e1, names);
+            expr_bound_names(e2, names);
+        }
+        Expr::IfExpr { cond, consequence, alternative } => {
+            expr_bound_names(cond, names);
+            bound_names(consequence, names);
+            bound_names(alternative.as_deref().unwrap_or_default(), names);
+        }
+        Expr::CallExpr { function, arguments } => {
+            expr_bound_names(function, names);
+            arguments.iter().for_each(|e| expr_bound_names(e, names));
+        }
+        Expr::ArrayExpr(exprs) => exprs.iter().for_each(|e| expr_bound_names(e, names)),
+        Expr::HashExpr(pairs) => pairs.iter().for_each(|(_, e)| expr_bound_names(e, names)),
+        Expr::IndexExpr { array, index } => {
+            expr_bound_names(array, names);
+            expr_bound_names(index, names);
+        }
+        Expr::MemberExpr { module, .. } => expr_bound_names(module, names),
+    }
+}
+
+/// Fails on what only the evaluator and the `Vm` run, so that the targets do not meet it:
+/// the imports, exports and module members of a program, and its type annotations.
+fn supported(program: &[Stmt]) -> Result<(), CodegenError> {
+    match program.iter().find_map(unsupported) {
+        Some(what) => Err(CodegenError::Unsupported(what.to_string())),
+        None => Ok(()),
+    }
+}
+
+fn unsupported(stmt: &Stmt) -> Option<&'static str> {
+    match stmt {
+        Stmt::ImportStmt(..) | Stmt::ExportStmt(_) => Some("modules"),
+        Stmt::LetStmt(_, Some(_), _) => Some("type annotations"),
+        Stmt::LetStmt(_, None, expr) | Stmt::ReturnStmt(expr) | Stmt::ExprStmt(expr) => expr_unsupported(expr),
+    }
+}
+
+fn expr_unsupported(expr: &Expr) -> Option<&'static str> {
+    match expr {
+        Expr::IdentExpr(_) | Expr::LiteralExpr(_) => None,
+        Expr::MemberExpr { .. } => Some("modules"),
+        Expr::PrefixExpr(_, e) => expr_unsupported(e),
+        Expr::InfixExpr(_, e1, e2) => expr_unsupported(e1).or_else(|| expr_unsupported(e2)),
+        Expr::IfExpr { cond, consequence, alternative } => expr_unsupported(cond)
+            .or_else(|| consequence.iter().find_map(unsupported))
+            .or_else(|| alternative.iter().flatten().find_map(unsupported)),
+        Expr::FnExpr { signature, body, .. } => {
+            if signature.ret.is_some() || signature.params.iter().any(Option::is_some) {
+                return Some("type annotations");
+            }
+            body.iter().find_map(unsupported)
+        }
+        Expr::CallExpr { function, arguments } => {
+            expr_unsupported(function).or_else(|| arguments.iter().find_map(expr_unsupported))
+        }
+        Expr::ArrayExpr(exprs) => exprs.iter().find_map(expr_unsupported),
+        Expr::HashExpr(pairs) => pairs.iter().find_map(|(_, e)| expr_unsupported(e)),
+        Expr::IndexExpr { array, index } => expr_unsupported(array).or_else(|| expr_unsupported(index)),
+    }
+}
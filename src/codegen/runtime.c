@@ -0,0 +1,968 @@
+/* Runtime of the programs translated to C by monkey_lang.
+ *
+ * Values are reference counted: the functions below return a new reference and leave the
+ * references they are given to the caller, except where said otherwise. Values referring to
+ * each other in a cycle, such as a function stored in the scope it closes over, are never
+ * freed. Errors are values, as in the evaluator, integer overflows included.
+ */
+
+#include <inttypes.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+typedef struct Value Value;
+typedef struct Scope Scope;
+typedef Value *(*Code)(Scope *scope);
+typedef Value *(*Native)(Value **args);
+
+typedef enum {
+    TAG_INTEGER,
+    TAG_BOOLEAN,
+    TAG_STRING,
+    TAG_ARRAY,
+    TAG_HASH,
+    TAG_FUNCTION,
+    TAG_BUILTIN,
+    TAG_NULL,
+    TAG_RETURN,
+    TAG_ERROR
+} Tag;
+
+struct Value {
+    /* negative for the values that are never freed */
+    long refs;
+    Tag tag;
+    union {
+        int64_t integer;
+        int boolean;
+        /* strings and the messages of errors */
+        struct {
+            char *bytes;
+            size_t len;
+        } string;
+        struct {
+            Value **items;
+            size_t len;
+        } array;
+        struct {
+            Value **keys;
+            Value **values;
+            size_t len;
+        } hash;
+        struct {
+            Code code;
+            size_t params;
+            size_t slots;
+            Scope *scope;
+        } function;
+        struct {
+            const char *name;
+            size_t params;
+            Native call;
+        } builtin;
+        /* the value returned */
+        Value *inner;
+    } as;
+};
+
+/* the variables of a call to a function, or the globals */
+struct Scope {
+    long refs;
+    Scope *parent;
+    size_t len;
+    Value *slots[];
+};
+
+#define BUILTIN_VALUE(name, params, call) { -1, TAG_BUILTIN, { .builtin = { name, params, call } } }
+
+static Value null_value = { -1, TAG_NULL, { 0 } };
+static Value true_value = { -1, TAG_BOOLEAN, { .boolean = 1 } };
+static Value false_value = { -1, TAG_BOOLEAN, { .boolean = 0 } };
+
+static void *alloc(size_t size) {
+    void *p = malloc(size ? size : 1);
+    if (!p) {
+        fputs("out of memory\n", stderr);
+        exit(1);
+    }
+    return p;
+}
+
+static Value *retain(Value *v) {
+    if (v->refs >= 0) {
+        v->refs++;
+    }
+    return v;
+}
+
+static void scope_release(Scope *scope);
+
+static void release(Value *v) {
+    size_t i;
+    if (v->refs < 0 || --v->refs > 0) {
+        return;
+    }
+    switch (v->tag) {
+    case TAG_STRING:
+    case TAG_ERROR:
+        free(v->as.string.bytes);
+        break;
+    case TAG_ARRAY:
+        for (i = 0; i < v->as.array.len; i++) {
+            release(v->as.array.items[i]);
+        }
+        free(v->as.array.items);
+        break;
+    case TAG_HASH:
+        for (i = 0; i < v->as.hash.len; i++) {
+            release(v->as.hash.keys[i]);
+            release(v->as.hash.values[i]);
+        }
+        free(v->as.hash.keys);
+        free(v->as.hash.values);
+        break;
+    case TAG_FUNCTION:
+        scope_release(v->as.function.scope);
+        break;
+    case TAG_RETURN:
+        release(v->as.inner);
+        break;
+    default:
+        break;
+    }
+    free(v);
+}
+
+static Scope *scope_new(Scope *parent, size_t len) {
+    size_t i;
+    Scope *scope = alloc(sizeof(Scope) + len * sizeof(Value *));
+    scope->refs = 1;
+    scope->parent = parent;
+    if (parent) {
+        parent->refs++;
+    }
+    scope->len = len;
+    for (i = 0; i < len; i++) {
+        scope->slots[i] = NULL;
+    }
+    return scope;
+}
+
+static void scope_release(Scope *scope) {
+    while (scope && --scope->refs == 0) {
+        Scope *parent = scope->parent;
+        size_t i;
+        for (i = 0; i < scope->len; i++) {
+            if (scope->slots[i]) {
+                release(scope->slots[i]);
+            }
+        }
+        free(scope);
+        scope = parent;
+    }
+}
+
+static void scope_set(Scope *scope, size_t index, Value *v) {
+    retain(v);
+    if (scope->slots[index]) {
+        release(scope->slots[index]);
+    }
+    scope->slots[index] = v;
+}
+
+static Value *new_value(Tag tag) {
+    Value *v = alloc(sizeof(Value));
+    v->refs = 1;
+    v->tag = tag;
+    return v;
+}
+
+static Value *mk_int(int64_t i) {
+    Value *v = new_value(TAG_INTEGER);
+    v->as.integer = i;
+    return v;
+}
+
+static Value *mk_bool(int b) {
+    return b ? &true_value : &false_value;
+}
+
+static Value *mk_null(void) {
+    return &null_value;
+}
+
+static Value *mk_string(const char *bytes, size_t len) {
+    Value *v = new_value(TAG_STRING);
+    v->as.string.bytes = alloc(len);
+    memcpy(v->as.string.bytes, bytes, len);
+    v->as.string.len = len;
+    return v;
+}
+
+/* takes the value returned */
+static Value *mk_return(Value *inner) {
+    Value *v = new_value(TAG_RETURN);
+    v->as.inner = inner;
+    return v;
+}
+
+/* takes the items */
+static Value *mk_array(size_t len, Value **items) {
+    Value *v = new_value(TAG_ARRAY);
+    v->as.array.items = alloc(len * sizeof(Value *));
+    if (len) {
+        memcpy(v->as.array.items, items, len * sizeof(Value *));
+    }
+    v->as.array.len = len;
+    return v;
+}
+
+static Value *mk_hash(void) {
+    Value *v = new_value(TAG_HASH);
+    v->as.hash.keys = NULL;
+    v->as.hash.values = NULL;
+    v->as.hash.len = 0;
+    return v;
+}
+
+static Value *mk_function(Code code, size_t params, size_t slots, Scope *scope) {
+    Value *v = new_value(TAG_FUNCTION);
+    v->as.function.code = code;
+    v->as.function.params = params;
+    v->as.function.slots = slots;
+    v->as.function.scope = scope;
+    scope->refs++;
+    return v;
+}
+
+static int is_return(Value *v) {
+    return v->tag == TAG_RETURN;
+}
+
+/* the value returned by a function, from its body */
+static Value *unwrap(Value *v) {
+    if (v->tag == TAG_RETURN) {
+        Value *inner = retain(v->as.inner);
+        release(v);
+        return inner;
+    }
+    return v;
+}
+
+static int equal(Value *a, Value *b) {
+    size_t i, j;
+    if (a->tag != b->tag) {
+        return 0;
+    }
+    switch (a->tag) {
+    case TAG_INTEGER:
+        return a->as.integer == b->as.integer;
+    case TAG_BOOLEAN:
+        return a->as.boolean == b->as.boolean;
+    case TAG_STRING:
+    case TAG_ERROR:
+        return a->as.string.len == b->as.string.len
+            && memcmp(a->as.string.bytes, b->as.string.bytes, a->as.string.len) == 0;
+    case TAG_ARRAY:
+        if (a->as.array.len != b->as.array.len) {
+            return 0;
+        }
+        for (i = 0; i < a->as.array.len; i++) {
+            if (!equal(a->as.array.items[i], b->as.array.items[i])) {
+                return 0;
+            }
+        }
+        return 1;
+    case TAG_HASH:
+        if (a->as.hash.len != b->as.hash.len) {
+            return 0;
+        }
+        for (i = 0; i < a->as.hash.len; i++) {
+            for (j = 0; j < b->as.hash.len; j++) {
+                if (equal(a->as.hash.keys[i], b->as.hash.keys[j])) {
+                    break;
+                }
+            }
+            if (j == b->as.hash.len || !equal(a->as.hash.values[i], b->as.hash.values[j])) {
+                return 0;
+            }
+        }
+        return 1;
+    case TAG_FUNCTION:
+        /* identical function literals share their code */
+        return a->as.function.code == b->as.function.code && a->as.function.scope == b->as.function.scope;
+    case TAG_BUILTIN:
+        return a->as.builtin.call == b->as.builtin.call;
+    case TAG_NULL:
+        return 1;
+    case TAG_RETURN:
+        return equal(a->as.inner, b->as.inner);
+    }
+    return 0;
+}
+
+/* takes the key and the value, replacing the value of an equal key */
+static void hash_set(Value *hash, Value *key, Value *value) {
+    size_t i, len = hash->as.hash.len;
+    for (i = 0; i < len; i++) {
+        if (equal(hash->as.hash.keys[i], key)) {
+            release(key);
+            release(hash->as.hash.values[i]);
+            hash->as.hash.values[i] = value;
+            return;
+        }
+    }
+    hash->as.hash.keys = realloc(hash->as.hash.keys, (len + 1) * sizeof(Value *));
+    hash->as.hash.values = realloc(hash->as.hash.values, (len + 1) * sizeof(Value *));
+    if (!hash->as.hash.keys || !hash->as.hash.values) {
+        fputs("out of memory\n", stderr);
+        exit(1);
+    }
+    hash->as.hash.keys[len] = key;
+    hash->as.hash.values[len] = value;
+    hash->as.hash.len = len + 1;
+}
+
+/* the value of a key of a hash, not retained, or NULL */
+static Value *hash_get(Value *hash, Value *key) {
+    size_t i;
+    for (i = 0; i < hash->as.hash.len; i++) {
+        if (equal(hash->as.hash.keys[i], key)) {
+            return hash->as.hash.values[i];
+        }
+    }
+    return NULL;
+}
+
+typedef struct {
+    char *bytes;
+    size_t len;
+    size_t cap;
+} Buf;
+
+static void buf_push(Buf *buf, const char *bytes, size_t len) {
+    if (buf->len + len > buf->cap) {
+        buf->cap = (buf->len + len) * 2;
+        buf->bytes = realloc(buf->bytes, buf->cap);
+        if (!buf->bytes) {
+            fputs("out of memory\n", stderr);
+            exit(1);
+        }
+    }
+    if (len) {
+        memcpy(buf->bytes + buf->len, bytes, len);
+    }
+    buf->len += len;
+}
+
+static void buf_str(Buf *buf, const char *s) {
+    buf_push(buf, s, strlen(s));
+}
+
+/* writes a value as the evaluator displays it */
+static void show(Buf *buf, Value *v) {
+    char digits[32];
+    size_t i;
+    switch (v->tag) {
+    case TAG_INTEGER:
+        snprintf(digits, sizeof digits, "%" PRId64, v->as.integer);
+        buf_str(buf, digits);
+        break;
+    case TAG_BOOLEAN:
+        buf_str(buf, v->as.boolean ? "true" : "false");
+        break;
+    case TAG_STRING:
+        buf_push(buf, v->as.string.bytes, v->as.string.len);
+        break;
+    case TAG_ARRAY:
+        buf_str(buf, "[");
+        for (i = 0; i < v->as.array.len; i++) {
+            if (i > 0) {
+                buf_str(buf, ", ");
+            }
+            show(buf, v->as.array.items[i]);
+        }
+        buf_str(buf, "]");
+        break;
+    case TAG_HASH:
+        buf_str(buf, "{");
+        for (i = 0; i < v->as.hash.len; i++) {
+            if (i > 0) {
+                buf_str(buf, ", ");
+            }
+            show(buf, v->as.hash.keys[i]);
+            buf_str(buf, ": ");
+            show(buf, v->as.hash.values[i]);
+        }
+        buf_str(buf, "}");
+        break;
+    case TAG_FUNCTION:
+        buf_str(buf, "[function]");
+        break;
+    case TAG_BUILTIN:
+        buf_str(buf, "[built-in function: ");
+        buf_str(buf, v->as.builtin.name);
+        buf_str(buf, "]");
+        break;
+    case TAG_NULL:
+        buf_str(buf, "null");
+        break;
+    case TAG_RETURN:
+        show(buf, v->as.inner);
+        break;
+    case TAG_ERROR:
+        buf_str(buf, "Error: ");
+        buf_push(buf, v->as.string.bytes, v->as.string.len);
+        break;
+    }
+}
+
+/* prints a value and a newline */
+static void print_value(Value *v) {
+    Buf buf = { NULL, 0, 0 };
+    show(&buf, v);
+    buf_str(&buf, "\n");
+    fwrite(buf.bytes, 1, buf.len, stdout);
+    free(buf.bytes);
+}
+
+/* a key of a hash and how it is shown */
+typedef struct {
+    Value *key;
+    Buf shown;
+} ShownKey;
+
+static void show_source(Buf *buf, Value *v);
+
+static int compare_shown(const void *a, const void *b) {
+    const Buf *x = &((const ShownKey *)a)->shown;
+    const Buf *y = &((const ShownKey *)b)->shown;
+    size_t len = x->len < y->len ? x->len : y->len;
+    int order = len ? memcmp(x->bytes, y->bytes, len) : 0;
+    return order ? order : (x->len > y->len) - (x->len < y->len);
+}
+
+/* the keys in the order of how they are shown, the buffers of which the caller frees */
+static ShownKey *sort_keys(Value **keys, size_t len) {
+    size_t i;
+    ShownKey *sorted = alloc(len * sizeof(ShownKey));
+    for (i = 0; i < len; i++) {
+        Buf shown = { NULL, 0, 0 };
+        show_source(&shown, keys[i]);
+        sorted[i].key = keys[i];
+        sorted[i].shown = shown;
+    }
+    qsort(sorted, len, sizeof(ShownKey), compare_shown);
+    return sorted;
+}
+
+/* writes a value as it is written in the source, strings quoted and the keys of hashes in
+ * order, as assertions show it */
+static void show_source(Buf *buf, Value *v) {
+    size_t i;
+    ShownKey *keys;
+    switch (v->tag) {
+    case TAG_STRING:
+        buf_str(buf, "\"");
+        for (i = 0; i < v->as.string.len; i++) {
+            if (v->as.string.bytes[i] == '"' || v->as.string.bytes[i] == '\\') {
+                buf_str(buf, "\\");
+            }
+            buf_push(buf, v->as.string.bytes + i, 1);
+        }
+        buf_str(buf, "\"");
+        break;
+    case TAG_ARRAY:
+        buf_str(buf, "[");
+        for (i = 0; i < v->as.array.len; i++) {
+            if (i > 0) {
+                buf_str(buf, ", ");
+            }
+            show_source(buf, v->as.array.items[i]);
+        }
+        buf_str(buf, "]");
+        break;
+    case TAG_HASH:
+        keys = sort_keys(v->as.hash.keys, v->as.hash.len);
+        buf_str(buf, "{");
+        for (i = 0; i < v->as.hash.len; i++) {
+            if (i > 0) {
+                buf_str(buf, ", ");
+            }
+            buf_push(buf, keys[i].shown.bytes, keys[i].shown.len);
+            buf_str(buf, ": ");
+            show_source(buf, hash_get(v, keys[i].key));
+            free(keys[i].shown.bytes);
+        }
+        buf_str(buf, "}");
+        free(keys);
+        break;
+    case TAG_RETURN:
+        show_source(buf, v->as.inner);
+        break;
+    default:
+        show(buf, v);
+    }
+}
+
+/* takes the message */
+static Value *buf_error(Buf *buf) {
+    Value *v = new_value(TAG_ERROR);
+    v->as.string.bytes = buf->bytes;
+    v->as.string.len = buf->len;
+    return v;
+}
+
+static Value *mk_error(const char *message) {
+    Buf buf = { NULL, 0, 0 };
+    buf_str(&buf, message);
+    return buf_error(&buf);
+}
+
+/* the error of an integer operation that overflowed */
+static Value *overflow(const char *operation) {
+    Buf buf = { NULL, 0, 0 };
+    buf_str(&buf, "integer overflow in ");
+    buf_str(&buf, operation);
+    return buf_error(&buf);
+}
+
+/* an error saying something about a value */
+static Value *error_about(Value *v, const char *what) {
+    Buf buf = { NULL, 0, 0 };
+    show(&buf, v);
+    buf_str(&buf, what);
+    return buf_error(&buf);
+}
+
+static Value *scope_get(Scope *scope, size_t depth, size_t index, const char *name) {
+    Value *v;
+    while (depth-- > 0) {
+        scope = scope->parent;
+    }
+    v = scope->slots[index];
+    if (!v) {
+        Buf buf = { NULL, 0, 0 };
+        buf_str(&buf, "identifier not found: ");
+        buf_str(&buf, name);
+        return buf_error(&buf);
+    }
+    return retain(v);
+}
+
+/* gives the integer of a value, or returns the error it is */
+static Value *to_int(Value *v, int64_t *i) {
+    if (v->tag == TAG_INTEGER) {
+        *i = v->as.integer;
+        return NULL;
+    } else if (v->tag == TAG_ERROR) {
+        return retain(v);
+    }
+    return error_about(v, " is not an integer");
+}
+
+/* the truth of a value, or -1 when it is not a boolean, giving the error */
+static int to_bool(Value *v, Value **error) {
+    if (v->tag == TAG_BOOLEAN) {
+        return v->as.boolean;
+    } else if (v->tag == TAG_ERROR) {
+        *error = retain(v);
+    } else {
+        *error = error_about(v, " is not a bool");
+    }
+    return -1;
+}
+
+static Value *to_hashable(Value *v) {
+    switch (v->tag) {
+    case TAG_INTEGER:
+    case TAG_BOOLEAN:
+    case TAG_STRING:
+    case TAG_ERROR:
+        return retain(v);
+    default:
+        return error_about(v, " is not hashable");
+    }
+}
+
+static Value *op_plus(Value *v) {
+    int64_t i;
+    Value *error = to_int(v, &i);
+    return error ? error : mk_int(i);
+}
+
+static Value *op_minus(Value *v) {
+    int64_t i;
+    Value *error = to_int(v, &i);
+    if (error) {
+        return error;
+    }
+    if (i == INT64_MIN) {
+        return overflow("negation");
+    }
+    return mk_int(-i);
+}
+
+static Value *op_not(Value *v) {
+    Value *error;
+    int b = to_bool(v, &error);
+    return b < 0 ? error : mk_bool(!b);
+}
+
+static Value *op_add(Value *a, Value *b) {
+    if (a->tag == TAG_INTEGER && b->tag == TAG_INTEGER) {
+        int64_t i1 = a->as.integer, i2 = b->as.integer;
+        if ((i2 > 0 && i1 > INT64_MAX - i2) || (i2 < 0 && i1 < INT64_MIN - i2)) {
+            return overflow("addition");
+        }
+        return mk_int(i1 + i2);
+    } else if (a->tag == TAG_STRING && b->tag == TAG_STRING) {
+        Buf buf = { NULL, 0, 0 };
+        Value *v;
+        buf_push(&buf, a->as.string.bytes, a->as.string.len);
+        buf_push(&buf, b->as.string.bytes, b->as.string.len);
+        v = mk_string(buf.bytes, buf.len);
+        free(buf.bytes);
+        return v;
+    } else if (a->tag == TAG_ERROR) {
+        return retain(a);
+    } else if (b->tag == TAG_ERROR) {
+        return retain(b);
+    } else {
+        Buf buf = { NULL, 0, 0 };
+        show(&buf, a);
+        buf_str(&buf, " and ");
+        show(&buf, b);
+        buf_str(&buf, " are not addable");
+        return buf_error(&buf);
+    }
+}
+
+typedef enum { INT_SUB, INT_MUL, INT_DIV, INT_GE, INT_LE, INT_GT, INT_LT } IntOp;
+
+static Value *int_op(Value *a, Value *b, IntOp op) {
+    int64_t i1 = 0, i2 = 0;
+    Value *error1 = to_int(a, &i1);
+    Value *error2 = to_int(b, &i2);
+    if (error1) {
+        if (error2) {
+            release(error2);
+        }
+        return error1;
+    } else if (error2) {
+        return error2;
+    }
+    switch (op) {
+    case INT_SUB:
+        if ((i2 < 0 && i1 > INT64_MAX + i2) || (i2 > 0 && i1 < INT64_MIN + i2)) {
+            return overflow("subtraction");
+        }
+        return mk_int(i1 - i2);
+    case INT_MUL:
+        if (i1 > 0 ? (i2 > 0 ? i1 > INT64_MAX / i2 : i2 < INT64_MIN / i1)
+                   : (i2 > 0 ? i1 < INT64_MIN / i2 : i1 != 0 && i2 < INT64_MAX / i1)) {
+            return overflow("multiplication");
+        }
+        return mk_int(i1 * i2);
+    case INT_DIV:
+        if (i2 == 0) {
+            return mk_error("division by zero");
+        } else if (i1 == INT64_MIN && i2 == -1) {
+            return overflow("division");
+        }
+        return mk_int(i1 / i2);
+    case INT_GE:
+        return mk_bool(i1 >= i2);
+    case INT_LE:
+        return mk_bool(i1 <= i2);
+    case INT_GT:
+        return mk_bool(i1 > i2);
+    case INT_LT:
+        return mk_bool(i1 < i2);
+    }
+    return mk_null();
+}
+
+static Value *op_sub(Value *a, Value *b) {
+    return int_op(a, b, INT_SUB);
+}
+
+static Value *op_mul(Value *a, Value *b) {
+    return int_op(a, b, INT_MUL);
+}
+
+static Value *op_div(Value *a, Value *b) {
+    return int_op(a, b, INT_DIV);
+}
+
+static Value *op_ge(Value *a, Value *b) {
+    return int_op(a, b, INT_GE);
+}
+
+static Value *op_le(Value *a, Value *b) {
+    return int_op(a, b, INT_LE);
+}
+
+static Value *op_gt(Value *a, Value *b) {
+    return int_op(a, b, INT_GT);
+}
+
+static Value *op_lt(Value *a, Value *b) {
+    return int_op(a, b, INT_LT);
+}
+
+static Value *op_eq(Value *a, Value *b) {
+    return mk_bool(equal(a, b));
+}
+
+static Value *op_ne(Value *a, Value *b) {
+    return mk_bool(!equal(a, b));
+}
+
+static Value *op_index(Value *target, Value *index) {
+    size_t i;
+    if (target->tag == TAG_ARRAY) {
+        int64_t n;
+        Value *error = to_int(index, &n);
+        if (error) {
+            return error;
+        } else if (n < 0 || (uint64_t)n >= target->as.array.len) {
+            return mk_null();
+        }
+        return retain(target->as.array.items[n]);
+    } else if (target->tag == TAG_HASH) {
+        Value *key = to_hashable(index);
+        if (key->tag == TAG_ERROR) {
+            return key;
+        }
+        for (i = 0; i < target->as.hash.len; i++) {
+            if (equal(target->as.hash.keys[i], key)) {
+                release(key);
+                return retain(target->as.hash.values[i]);
+            }
+        }
+        release(key);
+        return mk_null();
+    }
+    {
+        Buf buf = { NULL, 0, 0 };
+        buf_str(&buf, "unexpected index target: ");
+        show(&buf, target);
+        return buf_error(&buf);
+    }
+}
+
+/* an error if the value cannot be called with `argc` arguments, or NULL */
+static Value *check_call(Value *f, size_t argc) {
+    size_t params;
+    char message[96];
+    if (f->tag == TAG_FUNCTION) {
+        params = f->as.function.params;
+    } else if (f->tag == TAG_BUILTIN) {
+        params = f->as.builtin.params;
+    } else if (f->tag == TAG_ERROR) {
+        return retain(f);
+    } else {
+        return error_about(f, " is not a valid function");
+    }
+    if (argc != params) {
+        snprintf(message, sizeof message, "wrong number of arguments: %lu expected but %lu given",
+                 (unsigned long)params, (unsigned long)argc);
+        return mk_error(message);
+    }
+    return NULL;
+}
+
+/* calls a value `check_call` accepted */
+static Value *call(Value *f, Value **args) {
+    size_t i;
+    Scope *scope;
+    Value *result;
+    if (f->tag == TAG_BUILTIN) {
+        return f->as.builtin.call(args);
+    }
+    scope = scope_new(f->as.function.scope, f->as.function.slots);
+    for (i = 0; i < f->as.function.params; i++) {
+        scope->slots[i] = retain(args[i]);
+    }
+    result = f->as.function.code(scope);
+    scope_release(scope);
+    return unwrap(result);
+}
+
+static Value *builtin_print(Value **args) {
+    print_value(args[0]);
+    return mk_null();
+}
+
+static Value *builtin_len(Value **args) {
+    if (args[0]->tag == TAG_STRING) {
+        return mk_int((int64_t)args[0]->as.string.len);
+    } else if (args[0]->tag == TAG_ARRAY) {
+        return mk_int((int64_t)args[0]->as.array.len);
+    }
+    return mk_error("invalid arguments for len");
+}
+
+static Value *builtin_head(Value **args) {
+    if (args[0]->tag != TAG_ARRAY) {
+        return mk_error("invalid arguments for head");
+    } else if (args[0]->as.array.len == 0) {
+        return mk_error("empty array");
+    }
+    return retain(args[0]->as.array.items[0]);
+}
+
+static Value *builtin_tail(Value **args) {
+    size_t i, len = args[0]->tag == TAG_ARRAY ? args[0]->as.array.len : 0;
+    Value *rest;
+    if (args[0]->tag != TAG_ARRAY) {
+        return mk_error("invalid arguments for tail");
+    } else if (len == 0) {
+        return mk_error("empty array");
+    }
+    rest = mk_array(len - 1, args[0]->as.array.items + 1);
+    for (i = 0; i < len - 1; i++) {
+        retain(rest->as.array.items[i]);
+    }
+    return rest;
+}
+
+static Value *builtin_cons(Value **args) {
+    size_t i, len;
+    Value *list;
+    if (args[1]->tag != TAG_ARRAY) {
+        return mk_error("invalid arguments for cons");
+    }
+    len = args[1]->as.array.len;
+    list = mk_array(0, NULL);
+    free(list->as.array.items);
+    list->as.array.items = alloc((len + 1) * sizeof(Value *));
+    list->as.array.items[0] = retain(args[0]);
+    for (i = 0; i < len; i++) {
+        list->as.array.items[i + 1] = retain(args[1]->as.array.items[i]);
+    }
+    list->as.array.len = len + 1;
+    return list;
+}
+
+static Value *builtin_assert(Value **args) {
+    if (args[0]->tag != TAG_BOOLEAN) {
+        Buf buf = { NULL, 0, 0 };
+        buf_str(&buf, "assertion failed: ");
+        show_source(&buf, args[0]);
+        buf_str(&buf, " is not a bool");
+        return buf_error(&buf);
+    }
+    return args[0]->as.boolean ? mk_null() : mk_error("assertion failed");
+}
+
+/* the most differences between two values an assertion lists */
+#define DIFFERENCES 5
+
+/* counts a difference at a path, starting its line in the message if it is listed */
+static int difference(Buf *message, Buf *path, size_t *count) {
+    if (++*count > DIFFERENCES) {
+        return 0;
+    }
+    buf_str(message, "\n  at ");
+    buf_push(message, path->bytes, path->len);
+    buf_str(message, ": ");
+    return 1;
+}
+
+static void only(Buf *message, Buf *path, size_t *count, Value *v, const char *side) {
+    if (difference(message, path, count)) {
+        show_source(message, v);
+        buf_str(message, " only on the ");
+        buf_str(message, side);
+    }
+}
+
+/* lists where the items of two values differ, each place as the indices and keys leading to
+ * it, `path` being the place of the values */
+static void differ(Buf *path, Value *left, Value *right, Buf *message, size_t *count) {
+    size_t i, n = 0, at = path->len;
+    char digits[32];
+    if (left->tag == TAG_ARRAY && right->tag == TAG_ARRAY) {
+        size_t lefts = left->as.array.len, rights = right->as.array.len;
+        for (i = 0; i < lefts || i < rights; i++) {
+            snprintf(digits, sizeof digits, "[%lu]", (unsigned long)i);
+            buf_str(path, digits);
+            if (i >= rights) {
+                only(message, path, count, left->as.array.items[i], "left");
+            } else if (i >= lefts) {
+                only(message, path, count, right->as.array.items[i], "right");
+            } else {
+                differ(path, left->as.array.items[i], right->as.array.items[i], message, count);
+            }
+            path->len = at;
+        }
+    } else if (left->tag == TAG_HASH && right->tag == TAG_HASH) {
+        Value **keys = alloc((left->as.hash.len + right->as.hash.len) * sizeof(Value *));
+        ShownKey *sorted;
+        for (i = 0; i < left->as.hash.len; i++) {
+            keys[n++] = left->as.hash.keys[i];
+        }
+        for (i = 0; i < right->as.hash.len; i++) {
+            if (!hash_get(left, right->as.hash.keys[i])) {
+                keys[n++] = right->as.hash.keys[i];
+            }
+        }
+        sorted = sort_keys(keys, n);
+        for (i = 0; i < n; i++) {
+            Value *l = hash_get(left, sorted[i].key), *r = hash_get(right, sorted[i].key);
+            buf_str(path, "[");
+            buf_push(path, sorted[i].shown.bytes, sorted[i].shown.len);
+            buf_str(path, "]");
+            if (l && r) {
+                differ(path, l, r, message, count);
+            } else if (l) {
+                only(message, path, count, l, "left");
+            } else {
+                only(message, path, count, r, "right");
+            }
+            path->len = at;
+            free(sorted[i].shown.bytes);
+        }
+        free(sorted);
+        free(keys);
+    } else if (path->len > 0 && !equal(left, right) && difference(message, path, count)) {
+        show_source(message, left);
+        buf_str(message, " is not ");
+        show_source(message, right);
+    }
+}
+
+static Value *builtin_assert_eq(Value **args) {
+    Buf buf = { NULL, 0, 0 }, path = { NULL, 0, 0 };
+    size_t count = 0;
+    char more[64];
+    if (equal(args[0], args[1])) {
+        return mk_null();
+    }
+    buf_str(&buf, "assertion failed: the values differ\n  left:  ");
+    show_source(&buf, args[0]);
+    buf_str(&buf, "\n  right: ");
+    show_source(&buf, args[1]);
+    differ(&path, args[0], args[1], &buf, &count);
+    free(path.bytes);
+    if (count > DIFFERENCES) {
+        snprintf(more, sizeof more, "\n  and %lu more", (unsigned long)(count - DIFFERENCES));
+        buf_str(&buf, more);
+    }
+    return buf_error(&buf);
+}
+
+static Value *builtin_assert_error(Value **args) {
+    Buf buf = { NULL, 0, 0 };
+    if (args[0]->tag == TAG_ERROR) {
+        return mk_null();
+    }
+    buf_str(&buf, "assertion failed: ");
+    show_source(&buf, args[0]);
+    buf_str(&buf, " is not an error");
+    return buf_error(&buf);
+}
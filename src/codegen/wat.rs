@@ -0,0 +1,612 @@
+//! Translation of programs working on integers and booleans to the WebAssembly text format.
+//!
+//! The program may bind functions with top-level `let`s, which become exported WebAssembly
+//! functions called by name, recursively or not. The language has no loop statement: loops
+//! are written as recursive functions and translate to calls. The other statements of the
+//! program make up the exported `main` function, which returns the value of the program when
+//! it is an integer or a boolean, and the other bindings of the program become globals.
+//!
+//! Types are inferred, with integers translating to `i64` and booleans to `i32`: every name
+//! holds values of a single type, and a function takes and returns the same types wherever it
+//! is called. Programs are expected to run without errors, which are not checked for: an
+//! integer division by zero traps, overflows wrap around, and a name read before being bound
+//! holds zero.
+
+use std::collections::HashMap;
+
+use super::{bound_names, supported, CodegenError};
+use crate::evaluator::builtins::BuiltinFunctions;
+use crate::parser::ast::{Expr, Ident, Infix, Literal, Prefix, Program, Stmt};
+use crate::resolver::{Resolution, Resolver};
+
+pub fn emit(program: &Program) -> Result<String, CodegenError> {
+    let builtins = BuiltinFunctions::new().names();
+    let resolution = Resolver::new(builtins.clone())
+        .resolve(program)
+        .map_err(|mut errors| CodegenError::Resolve(errors.remove(0)))?;
+    supported(program)?;
+    let mut translator = Translator::new(program, resolution, builtins)?;
+    // types are only all known once every function has been seen, so the module is
+    // generated again with them
+    translator.module(program)?;
+    translator.module(program)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Ty {
+    Int,
+    Bool,
+    /// the value of statements giving no value, such as an `if` without `else`
+    Null,
+    Var(usize),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Binding {
+    Global(Ty),
+    Function(usize),
+}
+
+/// a function and the names bound in its scope, parameters first
+#[derive(Debug, Clone)]
+struct Frame {
+    locals: Vec<(String, Ty)>,
+    result: Ty,
+}
+
+struct Function<'a> {
+    name: String,
+    params: usize,
+    body: &'a Program,
+    frame: Frame,
+}
+
+struct Translator<'a> {
+    /// what each type variable was unified with
+    vars: Vec<Option<Ty>>,
+    functions: Vec<Function<'a>>,
+    /// globals in the order they are first bound
+    globals: Vec<String>,
+    names: HashMap<String, Binding>,
+    /// tells the reads of a global before the `let` of a local shadowing it
+    resolution: Resolution,
+    builtins: Vec<String>,
+    main: Ty,
+}
+
+impl<'a> Translator<'a> {
+    fn new(program: &'a Program, resolution: Resolution, builtins: Vec<String>) -> Result<Self, CodegenError> {
+        let mut translator = Translator {
+            vars: vec![],
+            functions: vec![],
+            globals: vec![],
+            names: HashMap::new(),
+            resolution,
+            builtins,
+            main: Ty::Null,
+        };
+        translator.main = translator.fresh();
+        for stmt in program {
+            if let Some((Ident(name), params, body)) = definition(stmt) {
+                if name == "main" {
+                    return Err(CodegenError::Unsupported("a function named main, the entry point".to_string()));
+                }
+                if translator.names.contains_key(name) {
+                    return Err(bound_twice(name));
+                }
+                let mut locals = vec![];
+                for Ident(param) in params {
+                    locals.push((param.clone(), translator.fresh()));
+                }
+                let mut names = vec![];
+                bound_names(body, &mut names);
+                for name in names {
+                    if locals.iter().all(|(local, _)| *local != name) {
+                        locals.push((name, translator.fresh()));
+                    }
+                }
+                let frame = Frame { locals, result: translator.fresh() };
+                let index = translator.functions.len();
+                translator.functions.push(Function { name: name.clone(), params: params.len(), body, frame });
+                translator.names.insert(name.clone(), Binding::Function(index));
+            }
+        }
+        let mut names = vec![];
+        for stmt in program.iter().filter(|stmt| definition(stmt).is_none()) {
+            bound_names(std::slice::from_ref(stmt), &mut names);
+        }
+        for name in names {
+            match translator.names.get(&name) {
+                Some(Binding::Function(_)) => return Err(bound_twice(&name)),
+                Some(Binding::Global(_)) => {}
+                None => {
+                    let ty = translator.fresh();
+                    translator.globals.push(name.clone());
+                    translator.names.insert(name, Binding::Global(ty));
+                }
+            }
+        }
+        Ok(translator)
+    }
+
+    fn module(&mut self, program: &Program) -> Result<String, CodegenError> {
+        let mut out = "(module\n".to_string();
+        for name in &self.globals.clone() {
+            let ty = match self.names[name] {
+                Binding::Global(ty) => self.value_type(ty, name)?,
+                Binding::Function(_) => unreachable!("globals are not functions"),
+            };
+            out.push_str(&format!("  (global ${} (mut {}) ({}.const 0))\n", name, ty, ty));
+        }
+        for i in 0..self.functions.len() {
+            let frame = self.functions[i].frame.clone();
+            let (body, ty) = self.block(Some(&frame), self.functions[i].body, false)?;
+            let name = self.functions[i].name.clone();
+            self.unify(ty, frame.result, || format!("results of {}", name))?;
+            let function = &self.functions[i];
+            let mut header = format!("(func ${} (export \"{}\")", function.name, function.name);
+            let mut locals = vec![];
+            for (j, (name, ty)) in frame.locals.iter().enumerate() {
+                let ty = self.value_type(*ty, name)?;
+                if j < function.params {
+                    header.push_str(&format!(" (param ${} {})", name, ty));
+                } else {
+                    locals.push(format!("(local ${} {})", name, ty));
+                }
+            }
+            out.push_str(&self.func(header, frame.result, locals, body));
+        }
+        let (body, ty) = self.block(None, program, true)?;
+        self.unify(ty, self.main, || "results of the program".to_string())?;
+        out.push_str(&self.func("(func $main (export \"main\")".to_string(), self.main, vec![], body));
+        out.push_str(")\n");
+        Ok(out)
+    }
+
+    fn func(&self, mut header: String, result: Ty, locals: Vec<String>, body: Vec<String>) -> String {
+        if let Some(result) = self.wasm_type(result) {
+            header.push_str(&format!(" (result {})", result));
+        }
+        let lines = locals.into_iter().chain(body).collect::<Vec<_>>();
+        format!("  {}\n    {})\n", header, lines.join("\n    "))
+    }
+
+    /// The instructions of each statement of a block up to a `return`, and the type of its
+    /// value. Top-level function definitions are skipped when `top` is set.
+    fn block(&mut self, frame: Option<&Frame>, program: &Program, top: bool) -> Result<(Vec<String>, Ty), CodegenError> {
+        let mut code = vec![];
+        let mut ty = Ty::Null;
+        for (i, stmt) in program.iter().enumerate() {
+            let last = i == program.len() - 1;
+            match stmt {
+                _ if top && definition(stmt).is_some() => ty = Ty::Null,
+                Stmt::LetStmt(Ident(name), _, expr) => {
+                    let (value, value_ty) = self.expr(frame, expr)?;
+                    let (local, bound_ty) = match self.lookup(frame, name)? {
+                        Lookup::Local(ty) => (true, ty),
+                        Lookup::Global(ty) => (false, ty),
+                        Lookup::Function => unreachable!("functions are bound at the top level only"),
+                    };
+                    self.unify(value_ty, bound_ty, || format!("values bound to {}", name))?;
+                    self.value_type(value_ty, name)?;
+                    code.push(match (local, last) {
+                        (true, false) => format!("(local.set ${} {})", name, value),
+                        (true, true) => format!("(local.tee ${} {})", name, value),
+                        (false, false) => format!("(global.set ${} {})", name, value),
+                        (false, true) => format!("(global.set ${} {}) (global.get ${})", name, value, name),
+                    });
+                    ty = value_ty;
+                }
+                Stmt::ReturnStmt(expr) => {
+                    let (value, value_ty) = self.expr(frame, expr)?;
+                    let result = frame.map_or(self.main, |frame| frame.result);
+                    self.unify(value_ty, result, || "returned values".to_string())?;
+                    code.push(match self.wasm_type(value_ty) {
+                        Some(_) => format!("(return {})", value),
+                        None => format!("{} (return)", value),
+                    });
+                    // the statements after a return never run, and the block can be given any type
+                    ty = self.fresh();
+                    break;
+                }
+                Stmt::ExprStmt(expr) => {
+                    let (value, value_ty) = self.expr(frame, expr)?;
+                    code.push(match self.wasm_type(value_ty) {
+                        Some(_) if !last => format!("(drop {})", value),
+                        _ => value,
+                    });
+                    ty = value_ty;
+                }
+                Stmt::ImportStmt(..) | Stmt::ExportStmt(_) => return Err(unsupported("modules")),
+            }
+        }
+        Ok((code, ty))
+    }
+
+    fn expr(&mut self, frame: Option<&Frame>, expr: &Expr) -> Result<(String, Ty), CodegenError> {
+        match expr {
+            Expr::IdentExpr(ident @ Ident(name)) => match self.lookup(self.scope(frame, ident), name)? {
+                Lookup::Local(ty) => Ok((format!("(local.get ${})", name), ty)),
+                Lookup::Global(ty) => Ok((format!("(global.get ${})", name), ty)),
+                Lookup::Function => Err(unsupported(&format!("functions as values, such as {}", name))),
+            },
+            Expr::LiteralExpr(Literal::IntLiteral(i)) => Ok((format!("(i64.const {})", i), Ty::Int)),
+            Expr::LiteralExpr(Literal::BoolLiteral(b)) => Ok((format!("(i32.const {})", *b as i32), Ty::Bool)),
+            Expr::LiteralExpr(Literal::StringLiteral(_)) => Err(unsupported("strings")),
+            Expr::PrefixExpr(prefix, e) => {
+                let (value, ty) = self.expr(frame, e)?;
+                match prefix {
+                    Prefix::PrefixPlus => {
+                        self.unify(ty, Ty::Int, || "operand of +".to_string())?;
+                        Ok((value, Ty::Int))
+                    }
+                    Prefix::PrefixMinus => {
+                        self.unify(ty, Ty::Int, || "operand of -".to_string())?;
+                        Ok((format!("(i64.sub (i64.const 0) {})", value), Ty::Int))
+                    }
+                    Prefix::Not => {
+                        self.unify(ty, Ty::Bool, || "operand of !".to_string())?;
+                        Ok((format!("(i32.eqz {})", value), Ty::Bool))
+                    }
+                }
+            }
+            Expr::InfixExpr(infix, e1, e2) => {
+                let (value1, ty1) = self.expr(frame, e1)?;
+                let (value2, ty2) = self.expr(frame, e2)?;
+                let (op, ty) = match infix {
+                    Infix::Equal | Infix::NotEqual => {
+                        self.unify(ty1, ty2, || "operands of ==".to_string())?;
+                        let ty = self.value_type(ty1, "operands of ==")?;
+                        let op = if *infix == Infix::Equal { "eq" } else { "ne" };
+                        return Ok((format!("({}.{} {} {})", ty, op, value1, value2), Ty::Bool));
+                    }
+                    Infix::Plus => ("i64.add", Ty::Int),
+                    Infix::Minus => ("i64.sub", Ty::Int),
+                    Infix::Multiply => ("i64.mul", Ty::Int),
+                    Infix::Divide => ("i64.div_s", Ty::Int),
+                    Infix::GreaterThanEqual => ("i64.ge_s", Ty::Bool),
+                    Infix::LessThanEqual => ("i64.le_s", Ty::Bool),
+                    Infix::GreaterThan => ("i64.gt_s", Ty::Bool),
+                    Infix::LessThan => ("i64.lt_s", Ty::Bool),
+                };
+                self.unify(ty1, Ty::Int, || format!("operands of {}", op))?;
+                self.unify(ty2, Ty::Int, || format!("operands of {}", op))?;
+                Ok((format!("({} {} {})", op, value1, value2), ty))
+            }
+            Expr::IfExpr { cond, consequence, alternative } => {
+                let (cond, cond_ty) = self.expr(frame, cond)?;
+                self.unify(cond_ty, Ty::Bool, || "conditions".to_string())?;
+                let (consequence, consequence_ty) = self.block(frame, consequence, false)?;
+                match alternative {
+                    Some(alternative) => {
+                        let (alternative, alternative_ty) = self.block(frame, alternative, false)?;
+                        self.unify(consequence_ty, alternative_ty, || "branches of if".to_string())?;
+                        let result = match self.wasm_type(consequence_ty) {
+                            Some(ty) => format!(" (result {})", ty),
+                            None => String::new(),
+                        };
+                        let code = format!(
+                            "(if{} {} (then {}) (else {}))",
+                            result,
+                            cond,
+                            consequence.join(" "),
+                            alternative.join(" "),
+                        );
+                        Ok((code, consequence_ty))
+                    }
+                    // gives null when the condition is false, so the value is dropped
+                    None => {
+                        let drop = if self.wasm_type(consequence_ty).is_some() { " drop" } else { "" };
+                        Ok((format!("(if {} (then {}{}))", cond, consequence.join(" "), drop), Ty::Null))
+                    }
+                }
+            }
+            Expr::FnExpr { .. } => Err(unsupported("functions other than those bound by top-level lets")),
+            Expr::CallExpr { function, arguments } => {
+                let index = match &**function {
+                    Expr::IdentExpr(ident @ Ident(name)) => match self.lookup(self.scope(frame, ident), name)? {
+                        Lookup::Function => match self.names[name] {
+                            Binding::Function(index) => index,
+                            Binding::Global(_) => unreachable!("looked up as a function"),
+                        },
+                        _ => return Err(unsupported(&format!("calls to values, such as {}", name))),
+                    },
+                    _ => return Err(unsupported("calls to anything but functions bound by top-level lets")),
+                };
+                let params = self.functions[index].params;
+                let name = self.functions[index].name.clone();
+                if arguments.len() != params {
+                    return Err(CodegenError::Type(format!(
+                        "{} takes {} arguments, given {}",
+                        name,
+                        params,
+                        arguments.len()
+                    )));
+                }
+                let mut code = format!("(call ${}", name);
+                for (i, argument) in arguments.iter().enumerate() {
+                    let (value, ty) = self.expr(frame, argument)?;
+                    let param = self.functions[index].frame.locals[i].1;
+                    self.unify(ty, param, || format!("argument {} of {}", i + 1, name))?;
+                    code.push(' ');
+                    code.push_str(&value);
+                }
+                code.push(')');
+                Ok((code, self.functions[index].frame.result))
+            }
+            Expr::ArrayExpr(_) => Err(unsupported("arrays")),
+            Expr::HashExpr(_) => Err(unsupported("hashes")),
+            Expr::IndexExpr { .. } => Err(unsupported("index expressions")),
+            Expr::MemberExpr { .. } => Err(unsupported("modules")),
+        }
+    }
+
+    /// the frame a read is looked up in, none when it was resolved past the locals
+    fn scope<'f>(&self, frame: Option<&'f Frame>, ident: &Ident) -> Option<&'f Frame> {
+        frame.filter(|_| self.resolution.slot(ident).is_none_or(|slot| slot.depth == 0))
+    }
+
+    fn lookup(&self, frame: Option<&Frame>, name: &str) -> Result<Lookup, CodegenError> {
+        if let Some((_, ty)) = frame.and_then(|frame| frame.locals.iter().find(|(local, _)| local == name)) {
+            return Ok(Lookup::Local(*ty));
+        }
+        match self.names.get(name) {
+            Some(Binding::Global(ty)) => Ok(Lookup::Global(*ty)),
+            Some(Binding::Function(_)) => Ok(Lookup::Function),
+            None if self.builtins.iter().any(|builtin| builtin == name) => {
+                Err(unsupported(&format!("builtin functions, such as {}", name)))
+            }
+            None => unreachable!("names are resolved before translating"),
+        }
+    }
+
+    fn fresh(&mut self) -> Ty {
+        self.vars.push(None);
+        Ty::Var(self.vars.len() - 1)
+    }
+
+    /// the type a type variable stands for, or the variable when it is not known yet
+    fn find(&self, mut ty: Ty) -> Ty {
+        while let Ty::Var(var) = ty {
+            match self.vars[var] {
+                Some(bound) => ty = bound,
+                None => break,
+            }
+        }
+        ty
+    }
+
+    fn unify(&mut self, ty1: Ty, ty2: Ty, context: impl FnOnce() -> String) -> Result<(), CodegenError> {
+        match (self.find(ty1), self.find(ty2)) {
+            (Ty::Var(var1), Ty::Var(var2)) if var1 == var2 => Ok(()),
+            (Ty::Var(var), ty) | (ty, Ty::Var(var)) => {
+                self.vars[var] = Some(ty);
+                Ok(())
+            }
+            (ty1, ty2) if ty1 == ty2 => Ok(()),
+            (ty1, ty2) => Err(CodegenError::Type(format!(
+                "{} and {} mixed in {}",
+                type_name(ty1),
+                type_name(ty2),
+                context()
+            ))),
+        }
+    }
+
+    /// The WebAssembly type of values of a type, if they have one. Types not known after
+    /// inference are those of values never used, which are given the integer type.
+    fn wasm_type(&self, ty: Ty) -> Option<&'static str> {
+        match self.find(ty) {
+            Ty::Int | Ty::Var(_) => Some("i64"),
+            Ty::Bool => Some("i32"),
+            Ty::Null => None,
+        }
+    }
+
+    /// the WebAssembly type of something that must hold a value
+    fn value_type(&self, ty: Ty, what: &str) -> Result<&'static str, CodegenError> {
+        self.wasm_type(ty)
+            .ok_or_else(|| unsupported(&format!("null values, such as in {}", what)))
+    }
+}
+
+enum Lookup {
+    Local(Ty),
+    Global(Ty),
+    Function,
+}
+
+/// the name, parameters and body of a top-level function definition
+fn definition(stmt: &Stmt) -> Option<(&Ident, &Vec<Ident>, &Program)> {
+    match stmt {
+        Stmt::LetStmt(ident, _, Expr::FnExpr { params, body, .. }) => Some((ident, params, body)),
+        _ => None,
+    }
+}
+
+fn type_name(ty: Ty) -> &'static str {
+    match ty {
+        Ty::Int => "integers",
+        Ty::Bool => "booleans",
+        Ty::Null | Ty::Var(_) => "null",
+    }
+}
+
+fn unsupported(what: &str) -> CodegenError {
+    CodegenError::Unsupported(what.to_string())
+}
+
+fn bound_twice(name: &str) -> CodegenError {
+    unsupported(&format!("{} bound more than once, as a function", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::token::Tokens;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn parse(input: &str) -> Program {
+        let (_, r) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+        let (_, program) = Parser::parse_tokens(Tokens::new(&r)).unwrap();
+        program
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum Sexp {
+        Atom(String),
+        List(Vec<Sexp>),
+    }
+
+    /// reads the output back as s-expressions, checking parentheses are balanced
+    fn read(wat: &str) -> Sexp {
+        let mut stack = vec![vec![]];
+        let mut atom = String::new();
+        for c in wat.chars() {
+            if matches!(c, '(' | ')') || c.is_whitespace() {
+                if !atom.is_empty() {
+                    stack.last_mut().unwrap().push(Sexp::Atom(std::mem::take(&mut atom)));
+                }
+            } else {
+                atom.push(c);
+            }
+            match c {
+                '(' => stack.push(vec![]),
+                ')' => {
+                    let list = stack.pop().unwrap();
+                    stack.last_mut().expect("unbalanced )").push(Sexp::List(list));
+                }
+                _ => {}
+            }
+        }
+        assert_eq!(stack.len(), 1, "unbalanced (");
+        let mut top = stack.pop().unwrap();
+        assert_eq!(top.len(), 1, "expected a single module");
+        top.pop().unwrap()
+    }
+
+    fn atoms(list: &[Sexp]) -> Vec<&str> {
+        list.iter()
+            .filter_map(|sexp| match sexp {
+                Sexp::Atom(atom) => Some(atom.as_str()),
+                Sexp::List(_) => None,
+            })
+            .collect()
+    }
+
+    /// the fields of the module of a kind, such as `func`, as lists of their atoms and of the
+    /// atoms of their lists, leaving out the body of functions
+    fn fields(wat: &str, kind: &str) -> Vec<Vec<String>> {
+        let module = match read(wat) {
+            Sexp::List(module) => module,
+            sexp => panic!("not a module: {:?}", sexp),
+        };
+        assert_eq!(module[0], Sexp::Atom("module".to_string()));
+        module[1..]
+            .iter()
+            .filter_map(|field| match field {
+                Sexp::List(field) if field[0] == Sexp::Atom(kind.to_string()) => Some(field),
+                _ => None,
+            })
+            .map(|field| {
+                let mut out = atoms(&field[1..]).iter().map(|s| s.to_string()).collect::<Vec<_>>();
+                for sexp in &field[1..] {
+                    match sexp {
+                        Sexp::List(list) if matches!(atoms(list)[0], "export" | "param" | "result" | "local" | "mut") => {
+                            out.push(atoms(list).join(" "))
+                        }
+                        _ => {}
+                    }
+                }
+                out
+            })
+            .collect()
+    }
+
+    fn emit_ok(input: &str) -> String {
+        emit(&parse(input)).unwrap_or_else(|err| panic!("{}: {}", input, err))
+    }
+
+    #[test]
+    fn functions() {
+        let wat = emit_ok("let fib = fn(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } }; fib(20)");
+        assert_eq!(
+            fields(&wat, "func"),
+            vec![
+                vec!["$fib", "export \"fib\"", "param $n i64", "result i64"],
+                vec!["$main", "export \"main\"", "result i64"],
+            ]
+        );
+
+        let wat = emit_ok(
+            "let even = fn(n) { if (n == 0) { true } else { odd(n - 1) } };
+             let odd = fn(n) { if (n == 0) { false } else { even(n - 1) } };
+             let flip = fn(b, n) { let m = n * 2; !b };
+             flip(even(10), 1)",
+        );
+        assert_eq!(
+            fields(&wat, "func"),
+            vec![
+                vec!["$even", "export \"even\"", "param $n i64", "result i32"],
+                vec!["$odd", "export \"odd\"", "param $n i64", "result i32"],
+                vec!["$flip", "export \"flip\"", "param $b i32", "param $n i64", "result i32", "local $m i64"],
+                vec!["$main", "export \"main\"", "result i32"],
+            ]
+        );
+    }
+
+    #[test]
+    fn globals() {
+        let wat = emit_ok("let a = 1; let b = a > 0; let f = fn() { if (b) { a } else { 0 } }; let c = f();");
+        assert_eq!(
+            fields(&wat, "global"),
+            vec![vec!["$a", "mut i64"], vec!["$b", "mut i32"], vec!["$c", "mut i64"]]
+        );
+        assert_eq!(fields(&wat, "func")[1], vec!["$main", "export \"main\"", "result i64"]);
+
+        // ending with a function definition, or an if without else, gives no value
+        let wat = emit_ok("let x = 1; let f = fn() { x }");
+        assert_eq!(fields(&wat, "func")[1], vec!["$main", "export \"main\""]);
+        let wat = emit_ok("let x = 1; if (x > 0) { x }");
+        assert_eq!(fields(&wat, "func")[0], vec!["$main", "export \"main\""]);
+
+        // a global is read until the let of the local shadowing it
+        let wat = emit_ok("let a = 1; let f = fn() { let b = a; let a = 2; b + a }; f()");
+        assert!(wat.contains("(local.set $b (global.get $a))"), "{}", wat);
+        assert!(wat.contains("(local.get $a)"), "{}", wat);
+    }
+
+    #[test]
+    fn returns() {
+        let wat = emit_ok("let f = fn(x) { if (x > 1) { return true; }; return false; 5 }; return f(2); 3");
+        assert_eq!(
+            fields(&wat, "func"),
+            vec![
+                vec!["$f", "export \"f\"", "param $x i64", "result i32"],
+                vec!["$main", "export \"main\"", "result i32"],
+            ]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let error = |input: &str| emit(&parse(input)).unwrap_err().to_string();
+        assert_eq!(error("x"), "identifier not found: x");
+        assert_eq!(error("\"a\""), "not supported by the target: strings");
+        assert_eq!(error("[1, 2]"), "not supported by the target: arrays");
+        assert_eq!(error("len(1)"), "not supported by the target: builtin functions, such as len");
+        assert_eq!(
+            error("let f = fn(x) { fn(y) { x + y } }"),
+            "not supported by the target: functions other than those bound by top-level lets"
+        );
+        assert_eq!(error("let f = fn() { 1 }; let g = f"), "not supported by the target: functions as values, such as f");
+        assert_eq!(error("let f = fn() { 1 }; let f = 2"), "not supported by the target: f bound more than once, as a function");
+        assert_eq!(error("let main = fn() { 1 }"), "not supported by the target: a function named main, the entry point");
+        assert_eq!(error("let x = if (true) { 1 }"), "not supported by the target: null values, such as in x");
+        assert_eq!(error("1 + true"), "type error: booleans and integers mixed in operands of i64.add");
+        assert_eq!(error("if (1) { 2 }"), "type error: integers and booleans mixed in conditions");
+        assert_eq!(error("let id = fn(x) { x }; id(1); id(true)"), "type error: booleans and integers mixed in argument 1 of id");
+        assert_eq!(error("let f = fn(x) { x }; f()"), "type error: f takes 1 arguments, given 0");
+    }
+}
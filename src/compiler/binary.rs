@@ -0,0 +1,720 @@
+//! Binary format of compiled programs, as written by `monkey_lang compile`.
+//!
+//! Integers are little-endian; a string is its length as a `u32` followed by as many bytes
+//! of UTF-8.
+//!
+//! ```text
+//! file       := magic "MKBC", version u16, flags u16, globals, function
+//! globals    := count u32, string * count            names of the global slots
+//! function   := num_params u32,
+//!               params      count u32, annotation * count
+//!               returned    annotation
+//!               locals      count u32, string * count
+//!               constants   count u32, constant * count
+//!               code        length u32, byte * length
+//!               lines       count u32, (offset u32, line u32) * count   if flags & 1
+//! annotation := 0 | 1 type                               none, or the type
+//! type       := 0 | 1 | 2 | 3                            int, bool, string, null
+//!             | 4 type | 5 type type                     array, hash of keys and values
+//!             | 6 count u32, type * count, type          function
+//! constant   := 0 i64 | 1 string | 2 function | 3 type
+//! ```
+//!
+//! The main function is the top level of the program. The only flag, 1, says that every
+//! function has a table of the source line of its instructions from each offset on. A file
+//! setting another flag is rejected, as is a file of another version, as instructions may
+//! differ between versions. Decoding checks that every instruction refers to constants, slots
+//! and jump targets that exist, that every path through a function keeps the stack balanced,
+//! so the `Vm` can run any decoded program, and that line tables are in order.
+
+use std::fmt;
+use std::fmt::Formatter;
+use std::rc::Rc;
+
+use crate::compiler::code::{read_u16, read_u8, Op};
+use crate::compiler::{Bytecode, CompiledFunction, Constant};
+use crate::parser::ast::{Signature, Type};
+use crate::evaluator::builtins::BuiltinFunctions;
+
+pub const MAGIC: &[u8; 4] = b"MKBC";
+pub const FORMAT_VERSION: u16 = 2;
+
+const FLAG_LINES: u16 = 1;
+
+/// functions nest no deeper than a `GetFree` depth can reach, and types no deeper either
+const MAX_NESTING: usize = 256;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    NotBytecode,
+    UnsupportedVersion(u16),
+    Truncated,
+    Invalid(String),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::NotBytecode => write!(f, "not a Monkey bytecode file"),
+            DecodeError::UnsupportedVersion(version) => write!(
+                f,
+                "bytecode version {} is not supported, expected version {}: compile the source again",
+                version, FORMAT_VERSION,
+            ),
+            DecodeError::Truncated => write!(f, "truncated bytecode file"),
+            DecodeError::Invalid(reason) => write!(f, "invalid bytecode: {}", reason),
+        }
+    }
+}
+
+/// whether the bytes start like a bytecode file, of any version
+pub fn is_bytecode(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+impl Bytecode {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        out.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        let flags = if has_lines(&self.main) { FLAG_LINES } else { 0 };
+        out.extend_from_slice(&flags.to_le_bytes());
+        write_len(&mut out, self.globals.len());
+        self.globals.iter().for_each(|name| write_str(&mut out, name));
+        write_function(&mut out, &self.main, flags & FLAG_LINES != 0);
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Bytecode, DecodeError> {
+        if !is_bytecode(bytes) {
+            return Err(DecodeError::NotBytecode);
+        }
+        let mut reader = Reader { bytes, pos: MAGIC.len() };
+        let version = reader.u16()?;
+        if version != FORMAT_VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+        let flags = reader.u16()?;
+        if flags & !FLAG_LINES != 0 {
+            return Err(DecodeError::Invalid(format!("unknown flags {:#x}", flags & !FLAG_LINES)));
+        }
+        let globals = (0..reader.u32()?)
+            .map(|_| reader.string())
+            .collect::<Result<Vec<_>, _>>()?;
+        let main = reader.function(flags & FLAG_LINES != 0, 0)?;
+        if reader.pos != bytes.len() {
+            return Err(DecodeError::Invalid("trailing bytes".to_string()));
+        }
+        let builtins = BuiltinFunctions::new().get_builtins().len();
+        verify(&main, &mut vec![], globals.len(), builtins)?;
+        Ok(Bytecode {
+            main: Rc::new(main),
+            globals,
+        })
+    }
+}
+
+fn has_lines(function: &CompiledFunction) -> bool {
+    !function.lines.is_empty()
+        || function.constants.iter().any(|constant| match constant {
+            Constant::Function(f) => has_lines(f),
+            _ => false,
+        })
+}
+
+fn write_len(out: &mut Vec<u8>, len: usize) {
+    out.extend_from_slice(&(len as u32).to_le_bytes());
+}
+
+fn write_str(out: &mut Vec<u8>, s: &str) {
+    write_len(out, s.len());
+    out.extend_from_slice(s.as_bytes());
+}
+
+fn write_function(out: &mut Vec<u8>, function: &CompiledFunction, lines: bool) {
+    write_len(out, function.num_params);
+    write_len(out, function.signature.params.len());
+    function.signature.params.iter().for_each(|ty| write_annotation(out, ty.as_ref()));
+    write_annotation(out, function.signature.ret.as_ref());
+    write_len(out, function.locals.len());
+    function.locals.iter().for_each(|name| write_str(out, name));
+    write_len(out, function.constants.len());
+    for constant in &function.constants {
+        match constant {
+            Constant::Integer(i) => {
+                out.push(0);
+                out.extend_from_slice(&i.to_le_bytes());
+            }
+            Constant::String(s) => {
+                out.push(1);
+                write_str(out, s);
+            }
+            Constant::Function(f) => {
+                out.push(2);
+                write_function(out, f, lines);
+            }
+            Constant::Type(ty) => {
+                out.push(3);
+                write_type(out, ty);
+            }
+        }
+    }
+    write_len(out, function.instructions.len());
+    out.extend_from_slice(&function.instructions);
+    if lines {
+        write_len(out, function.lines.len());
+        for &(offset, line) in &function.lines {
+            write_len(out, offset);
+            write_len(out, line);
+        }
+    }
+}
+
+fn write_annotation(out: &mut Vec<u8>, ty: Option<&Type>) {
+    match ty {
+        Some(ty) => {
+            out.push(1);
+            write_type(out, ty);
+        }
+        None => out.push(0),
+    }
+}
+
+fn write_type(out: &mut Vec<u8>, ty: &Type) {
+    match ty {
+        Type::Int => out.push(0),
+        Type::Bool => out.push(1),
+        Type::String => out.push(2),
+        Type::Null => out.push(3),
+        Type::Array(t) => {
+            out.push(4);
+            write_type(out, t);
+        }
+        Type::Hash(k, v) => {
+            out.push(5);
+            write_type(out, k);
+            write_type(out, v);
+        }
+        Type::Fn(params, ret) => {
+            out.push(6);
+            write_len(out, params.len());
+            params.iter().for_each(|t| write_type(out, t));
+            write_type(out, ret);
+        }
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], DecodeError> {
+        let end = self.pos.checked_add(n).filter(|&end| end <= self.bytes.len());
+        match end {
+            Some(end) => {
+                let bytes = &self.bytes[self.pos..end];
+                self.pos = end;
+                Ok(bytes)
+            }
+            None => Err(DecodeError::Truncated),
+        }
+    }
+
+    fn u8(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, DecodeError> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, DecodeError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn len(&mut self) -> Result<usize, DecodeError> {
+        Ok(self.u32()? as usize)
+    }
+
+    fn i64(&mut self) -> Result<i64, DecodeError> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(i64::from_le_bytes(bytes))
+    }
+
+    fn string(&mut self) -> Result<String, DecodeError> {
+        let len = self.len()?;
+        String::from_utf8(self.take(len)?.to_vec())
+            .map_err(|_| DecodeError::Invalid("string is not UTF-8".to_string()))
+    }
+
+    fn function(&mut self, lines: bool, nesting: usize) -> Result<CompiledFunction, DecodeError> {
+        if nesting > MAX_NESTING {
+            return Err(DecodeError::Invalid("functions nested too deeply".to_string()));
+        }
+        let num_params = self.len()?;
+        let params = (0..self.u32()?)
+            .map(|_| self.annotation())
+            .collect::<Result<Vec<_>, _>>()?;
+        let ret = self.annotation()?;
+        let locals = (0..self.u32()?)
+            .map(|_| self.string())
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut constants = vec![];
+        for _ in 0..self.u32()? {
+            let constant = match self.u8()? {
+                0 => Constant::Integer(self.i64()?),
+                1 => Constant::String(self.string()?),
+                2 => Constant::Function(Rc::new(self.function(lines, nesting + 1)?)),
+                3 => Constant::Type(self.ty(0)?),
+                tag => return Err(DecodeError::Invalid(format!("unknown constant tag {}", tag))),
+            };
+            constants.push(constant);
+        }
+        let len = self.len()?;
+        let instructions = self.take(len)?.to_vec();
+        let mut table = vec![];
+        if lines {
+            for _ in 0..self.u32()? {
+                table.push((self.len()?, self.len()?));
+            }
+        }
+        Ok(CompiledFunction {
+            instructions,
+            constants,
+            locals: Rc::new(locals),
+            num_params,
+            signature: Rc::new(Signature { params, ret }),
+            lines: table,
+        })
+    }
+
+    fn annotation(&mut self) -> Result<Option<Type>, DecodeError> {
+        match self.u8()? {
+            0 => Ok(None),
+            1 => Ok(Some(self.ty(0)?)),
+            tag => Err(DecodeError::Invalid(format!("unknown annotation tag {}", tag))),
+        }
+    }
+
+    fn ty(&mut self, nesting: usize) -> Result<Type, DecodeError> {
+        if nesting > MAX_NESTING {
+            return Err(DecodeError::Invalid("types nested too deeply".to_string()));
+        }
+        Ok(match self.u8()? {
+            0 => Type::Int,
+            1 => Type::Bool,
+            2 => Type::String,
+            3 => Type::Null,
+            4 => Type::Array(Box::new(self.ty(nesting + 1)?)),
+            5 => Type::Hash(Box::new(self.ty(nesting + 1)?), Box::new(self.ty(nesting + 1)?)),
+            6 => {
+                let params = (0..self.u32()?)
+                    .map(|_| self.ty(nesting + 1))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Type::Fn(params, Box::new(self.ty(nesting + 1)?))
+            }
+            tag => return Err(DecodeError::Invalid(format!("unknown type tag {}", tag))),
+        })
+    }
+}
+
+/// Checks the operands of every instruction, the stack and the line table. `scopes` holds the number of slots of the
+/// scopes of the enclosing functions and of this one, innermost last; the top level has none.
+fn verify(function: &CompiledFunction, scopes: &mut Vec<usize>, globals: usize, builtins: usize) -> Result<(), DecodeError> {
+    let invalid = |reason: String| Err(DecodeError::Invalid(reason));
+    if function.num_params > function.locals.len() {
+        return invalid("more parameters than slots".to_string());
+    }
+    if function.signature.params.len() > function.num_params {
+        return invalid("more parameter annotations than parameters".to_string());
+    }
+
+    let code = &function.instructions;
+    let mut starts = vec![];
+    let mut ip = 0;
+    while ip < code.len() {
+        let op = match Op::from_byte(code[ip]) {
+            Some(op) => op,
+            None => return invalid(format!("unknown opcode {} at {}", code[ip], ip)),
+        };
+        if ip + op.width() > code.len() {
+            return Err(DecodeError::Truncated);
+        }
+        starts.push(ip);
+        ip += op.width();
+    }
+    if starts.last().map(|&last| code[last]) != Some(Op::Leave as u8) {
+        return invalid("function does not end with Leave".to_string());
+    }
+    // entries start at instructions, in increasing offsets, lines counting from 1
+    let mut previous = None;
+    for &(offset, line) in &function.lines {
+        if starts.binary_search(&offset).is_err() || previous.is_some_and(|previous| offset <= previous) || line == 0 {
+            return invalid(format!("bad line table entry for offset {}", offset));
+        }
+        previous = Some(offset);
+    }
+
+    for &ip in &starts {
+        let op = Op::from_byte(code[ip]).expect("checked opcode");
+        let operand = |i: usize| read_u16(code, ip + 1 + 2 * i);
+        let target_ok = |target: usize| starts.binary_search(&target).is_ok();
+        let ok = match op {
+            Op::Constant => matches!(
+                function.constants.get(operand(0)),
+                Some(Constant::Integer(_) | Constant::String(_))
+            ),
+            Op::Closure => match function.constants.get(operand(0)) {
+                Some(Constant::Function(f)) => {
+                    scopes.push(f.locals.len());
+                    let verified = verify(f, scopes, globals, builtins);
+                    scopes.pop();
+                    verified?;
+                    true
+                }
+                _ => false,
+            },
+            Op::GetGlobal | Op::SetGlobal => operand(0) < globals,
+            Op::GetLocal | Op::SetLocal => scopes.last().is_some_and(|&slots| operand(0) < slots),
+            Op::GetFree => {
+                let depth = read_u8(code, ip + 1);
+                let index = read_u16(code, ip + 2);
+                depth >= 1 && depth < scopes.len() && index < scopes[scopes.len() - 1 - depth]
+            }
+            Op::GetBuiltin => read_u8(code, ip + 1) < builtins,
+            Op::Jump | Op::JumpIfReturned => target_ok(operand(0)),
+            Op::Branch => target_ok(operand(0)) && target_ok(operand(1)),
+            Op::PrepareCall | Op::CheckArg => target_ok(read_u16(code, ip + 2)),
+            Op::Check => {
+                matches!(function.constants.get(operand(0)), Some(Constant::String(_)))
+                    && matches!(function.constants.get(operand(1)), Some(Constant::Type(_)))
+            }
+            _ => true,
+        };
+        if !ok {
+            return invalid(format!("bad operand of {:?} at {}", op, ip));
+        }
+    }
+    verify_stack(code, &starts)
+}
+
+/// The stack of a function before an instruction: how many values it pushed, and the calls
+/// prepared but not made yet, innermost last, each its number of arguments and the height of
+/// the stack up to its callee.
+#[derive(Debug, Clone, PartialEq)]
+struct Stack {
+    height: usize,
+    calls: Vec<(usize, usize)>,
+}
+
+/// Follows every path through the code, checking that no instruction pops more than the
+/// function pushed or the callee of a call still to make, that each `CheckArg` checks the
+/// argument just pushed and each `Call` makes the call of the `PrepareCall` before it, and that `Leave` leaves one value with no call pending. Paths
+/// meeting at an instruction must agree on the stack.
+fn verify_stack(code: &[u8], starts: &[usize]) -> Result<(), DecodeError> {
+    let invalid = |reason: String| Err(DecodeError::Invalid(reason));
+    let mut stacks: Vec<Option<Stack>> = vec![None; starts.len()];
+    stacks[0] = Some(Stack { height: 0, calls: vec![] });
+    let mut pending = vec![0];
+    while let Some(i) = pending.pop() {
+        let ip = starts[i];
+        let mut stack = stacks[i].clone().expect("stack of a pending instruction");
+        let op = Op::from_byte(code[ip]).expect("checked opcode");
+        let operand = |i: usize| read_u16(code, ip + 1 + 2 * i);
+        // a value only read is popped and pushed again
+        let pops = match op {
+            Op::Add
+            | Op::Sub
+            | Op::Mul
+            | Op::Div
+            | Op::Equal
+            | Op::NotEqual
+            | Op::GreaterThanEqual
+            | Op::LessThanEqual
+            | Op::GreaterThan
+            | Op::LessThan
+            | Op::Index => 2,
+            Op::Plus
+            | Op::Minus
+            | Op::Not
+            | Op::SetGlobal
+            | Op::SetLocal
+            | Op::Return
+            | Op::Branch
+            | Op::JumpIfReturned
+            | Op::PrepareCall
+            | Op::CheckArg
+            | Op::Check
+            | Op::Leave => 1,
+            Op::Array => operand(0),
+            Op::Hash => 2 * operand(0),
+            // the arguments, the callee being checked with the call
+            Op::Call => read_u8(code, ip + 1),
+            _ => 0,
+        };
+        let floor = stack.calls.last().map_or(0, |&(_, callee)| callee);
+        if stack.height < pops {
+            return invalid(format!("stack underflow at {}", ip));
+        }
+        if stack.height - pops < floor {
+            return invalid(format!("{:?} at {} pops the callee of a call", op, ip));
+        }
+
+        // the instructions which may run next, with their stacks
+        let mut next = vec![];
+        match op {
+            Op::Jump => next.push((operand(0), stack)),
+            Op::Branch => {
+                let popped = Stack { height: stack.height - 1, ..stack.clone() };
+                next.push((operand(0), popped.clone()));
+                next.push((operand(1), stack));
+                next.push((starts[i + 1], popped));
+            }
+            Op::JumpIfReturned => {
+                next.push((operand(0), stack.clone()));
+                next.push((starts[i + 1], Stack { height: stack.height - 1, ..stack }));
+            }
+            Op::PrepareCall => {
+                next.push((read_u16(code, ip + 2), stack.clone()));
+                stack.calls.push((read_u8(code, ip + 1), stack.height));
+                next.push((starts[i + 1], stack));
+            }
+            Op::CheckArg => {
+                let index = read_u8(code, ip + 1);
+                match stack.calls.last() {
+                    Some(&(argc, callee)) if index < argc && stack.height == callee + index + 1 => {
+                        let calls = stack.calls[..stack.calls.len() - 1].to_vec();
+                        next.push((read_u16(code, ip + 2), Stack { height: callee, calls }));
+                        next.push((starts[i + 1], stack));
+                    }
+                    _ => return invalid(format!("CheckArg at {} does not check an argument of a call", ip)),
+                }
+            }
+            Op::Call => match stack.calls.pop() {
+                Some((argc, callee)) if argc == pops && stack.height == callee + argc => {
+                    stack.height = callee;
+                    next.push((starts[i + 1], stack));
+                }
+                _ => return invalid(format!("Call at {} is not the call of a PrepareCall", ip)),
+            },
+            Op::Leave => {
+                if stack.height != 1 || !stack.calls.is_empty() {
+                    return invalid(format!("Leave at {} with {} values on the stack", ip, stack.height));
+                }
+            }
+            _ => {
+                stack.height = stack.height - pops + 1;
+                next.push((starts[i + 1], stack));
+            }
+        }
+        for (target, stack) in next {
+            let j = starts.binary_search(&target).expect("checked target");
+            match &stacks[j] {
+                Some(known) if *known != stack => {
+                    return invalid(format!("the stack differs between the paths to {}", target));
+                }
+                Some(_) => {}
+                None => {
+                    stacks[j] = Some(stack);
+                    pending.push(j);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::code::make;
+    use crate::compiler::Compiler;
+    use crate::lexer::token::Tokens;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::vm::Vm;
+
+    fn compile(input: &str) -> Bytecode {
+        let (_, r) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+        let (_, program) = Parser::parse_tokens(Tokens::new(&r)).unwrap();
+        Compiler::new().compile(&program).unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let bytecode = compile(
+            "let greet = fn(name) { \"hello \" + name }; \
+             let adder = fn(x) { fn(y) { x + y } }; \
+             [greet(\"monkey\"), adder(-2)(5), len([1, 2])]",
+        );
+        let bytes = bytecode.to_bytes();
+        assert_eq!(&bytes[..8], &[b'M', b'K', b'B', b'C', 2, 0, 0, 0]);
+        let decoded = Bytecode::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded, bytecode);
+        assert_eq!(Vm::new().run(&decoded).to_string(), "[hello monkey, 3, 2]");
+
+        let calls = compile("let f = fn(a, b) { if (a > b) { return a; } b }; [f(1, f(3, 2)), len(f), 1(2)]");
+        assert_eq!(Bytecode::from_bytes(&calls.to_bytes()).unwrap(), calls);
+
+        let annotated = compile(
+            "let f: fn([int], {string: bool}) -> null = fn(a: [int], b) -> {string: bool} { {} }; \
+             let n: int = 1; [f([n], 2), f(true, 2)]",
+        );
+        let decoded = Bytecode::from_bytes(&annotated.to_bytes()).unwrap();
+        assert_eq!(decoded, annotated);
+        assert_eq!(
+            Vm::new().run(&decoded).to_string(),
+            "[{}, Error: wrong type for a: [int] expected but true given]",
+        );
+    }
+
+    #[test]
+    fn rejected() {
+        let bytes = compile("let a = 1; fn(x) { x + a }").to_bytes();
+        assert_eq!(Bytecode::from_bytes(b"let a = 1;"), Err(DecodeError::NotBytecode));
+
+        let mut other_version = bytes.clone();
+        other_version[4] = 1;
+        let err = Bytecode::from_bytes(&other_version).unwrap_err();
+        assert_eq!(err, DecodeError::UnsupportedVersion(1));
+        assert_eq!(
+            err.to_string(),
+            "bytecode version 1 is not supported, expected version 2: compile the source again",
+        );
+
+        assert_eq!(Bytecode::from_bytes(&bytes[..bytes.len() - 1]), Err(DecodeError::Truncated));
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(matches!(Bytecode::from_bytes(&trailing), Err(DecodeError::Invalid(_))));
+
+        // the first instruction of the top level, `Constant 0`, made to read a missing constant
+        let code = bytes.len() - compile("let a = 1; fn(x) { x + a }").main.instructions.len();
+        let mut bad_operand = bytes.clone();
+        bad_operand[code + 2] = 9;
+        assert_eq!(
+            Bytecode::from_bytes(&bad_operand),
+            Err(DecodeError::Invalid("bad operand of Constant at 0".to_string())),
+        );
+        let mut bad_opcode = bytes.clone();
+        bad_opcode[code] = 200;
+        assert_eq!(
+            Bytecode::from_bytes(&bad_opcode),
+            Err(DecodeError::Invalid("unknown opcode 200 at 0".to_string())),
+        );
+        let mut flagged = bytes.clone();
+        flagged[6] = 3;
+        assert_eq!(
+            Bytecode::from_bytes(&flagged),
+            Err(DecodeError::Invalid("unknown flags 0x2".to_string())),
+        );
+        // the flag of line tables on a file without them
+        let mut lines_flagged = bytes;
+        lines_flagged[6] = 1;
+        assert_eq!(Bytecode::from_bytes(&lines_flagged), Err(DecodeError::Truncated));
+    }
+
+    #[test]
+    fn lines() {
+        let source = "let a = 1;\nlet f = fn(x) {\n  let y = x + a;\n  y * 2\n};\nf(3)";
+        let (_, r) = Lexer::lex_tokens(source.as_bytes()).unwrap();
+        let (_, program) = Parser::parse_tokens(Tokens::new(&r)).unwrap();
+        let bytecode = Compiler::new()
+            .compile_with_lines(&program, &crate::debug::lines(source, &program))
+            .unwrap();
+        assert_eq!(bytecode.main.lines, vec![(0, 1), (9, 2), (18, 6)]);
+        let f = match &bytecode.main.constants[1] {
+            Constant::Function(f) => f,
+            constant => panic!("expected a function, got {:?}", constant),
+        };
+        assert_eq!(f.lines, vec![(0, 3), (13, 4)]);
+
+        let bytes = bytecode.to_bytes();
+        assert_eq!(&bytes[6..8], &[1, 0]);
+        assert_eq!(Bytecode::from_bytes(&bytes).unwrap(), bytecode);
+
+        // the table of the top level, the last thing of the file, made to point inside an instruction
+        let mut inside = bytes.clone();
+        let at = inside.len() - 8;
+        inside[at] = 19;
+        assert_eq!(
+            Bytecode::from_bytes(&inside),
+            Err(DecodeError::Invalid("bad line table entry for offset 19".to_string())),
+        );
+        let mut unordered = bytes;
+        unordered[at] = 9;
+        assert_eq!(
+            Bytecode::from_bytes(&unordered),
+            Err(DecodeError::Invalid("bad line table entry for offset 9".to_string())),
+        );
+    }
+
+    /// the bytes of a program whose top level is the given instructions, with the constant 1
+    fn main_of(instructions: &[(Op, &[usize])]) -> Vec<u8> {
+        let main = CompiledFunction {
+            instructions: instructions.iter().flat_map(|(op, operands)| make(*op, operands).unwrap()).collect(),
+            constants: vec![Constant::Integer(1)],
+            locals: Rc::new(vec![]),
+            num_params: 0,
+            signature: Rc::default(),
+            lines: vec![],
+        };
+        Bytecode { main: Rc::new(main), globals: vec![] }.to_bytes()
+    }
+
+    #[test]
+    fn unbalanced() {
+        let invalid = |instructions: &[(Op, &[usize])]| match Bytecode::from_bytes(&main_of(instructions)) {
+            Err(DecodeError::Invalid(reason)) => reason,
+            decoded => panic!("decoded {:?}", decoded),
+        };
+        assert_eq!(invalid(&[(Op::Add, &[]), (Op::Leave, &[])]), "stack underflow at 0");
+        assert_eq!(
+            invalid(&[(Op::Constant, &[0]), (Op::Array, &[2]), (Op::Leave, &[])]),
+            "stack underflow at 3",
+        );
+        assert_eq!(
+            invalid(&[(Op::Constant, &[0]), (Op::Call, &[0]), (Op::Leave, &[])]),
+            "Call at 3 is not the call of a PrepareCall",
+        );
+        assert_eq!(
+            invalid(&[
+                (Op::GetBuiltin, &[0]),
+                (Op::PrepareCall, &[1, 14]),
+                (Op::Constant, &[0]),
+                (Op::Constant, &[0]),
+                (Op::Call, &[2]),
+                (Op::Leave, &[]),
+            ]),
+            "Call at 12 is not the call of a PrepareCall",
+        );
+        assert_eq!(
+            invalid(&[
+                (Op::Constant, &[0]),
+                (Op::PrepareCall, &[0, 12]),
+                (Op::Array, &[1]),
+                (Op::Call, &[0]),
+                (Op::Leave, &[]),
+            ]),
+            "Array at 7 pops the callee of a call",
+        );
+        assert_eq!(
+            invalid(&[(Op::Constant, &[0]), (Op::Constant, &[0]), (Op::Leave, &[])]),
+            "Leave at 6 with 2 values on the stack",
+        );
+        // the false path pushes a value the true path does not
+        assert_eq!(
+            invalid(&[
+                (Op::True, &[]),
+                (Op::Branch, &[9, 9]),
+                (Op::Constant, &[0]),
+                (Op::Jump, &[9]),
+                (Op::Leave, &[]),
+            ]),
+            "the stack differs between the paths to 9",
+        );
+    }
+}
@@ -0,0 +1,215 @@
+use std::fmt::Write;
+
+/// Instructions are one opcode byte followed by big-endian operands.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    /// pushes the constant at the operand's index
+    Constant,
+    Null,
+    True,
+    False,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Equal,
+    NotEqual,
+    GreaterThanEqual,
+    LessThanEqual,
+    GreaterThan,
+    LessThan,
+    Plus,
+    Minus,
+    Not,
+    GetGlobal,
+    SetGlobal,
+    GetLocal,
+    SetLocal,
+    /// reads a slot of the scope `depth` levels up: depth, index
+    GetFree,
+    GetBuiltin,
+    /// collects the given number of values into an array
+    Array,
+    /// collects the given number of key and value pairs into a hash
+    Hash,
+    Index,
+    /// wraps the top of the stack in a return value
+    Return,
+    /// creates a closure of the function constant, capturing the current scope
+    Closure,
+    Jump,
+    /// pops a condition: falls through when true, jumps to the first target when false, and
+    /// pushes an error and jumps to the second target when not a bool
+    Branch,
+    /// jumps when the top of the stack is a return value, pops it otherwise
+    JumpIfReturned,
+    /// checks the callee below the given number of arguments, replacing it with an error and
+    /// jumping to the target when it cannot be called with them
+    PrepareCall,
+    /// checks the argument of the given index, on the top of the stack, against the type the
+    /// callee below the arguments annotates it with, replacing the callee and the arguments
+    /// with an error and jumping to the target when it has another type
+    CheckArg,
+    Call,
+    /// checks the top of the stack against a type annotation: the constants of the name it
+    /// is bound to and of the type, replacing it with an error when it has another type
+    Check,
+    /// leaves the function, unwrapping the return value on the top of the stack and checking
+    /// it against the type the function annotates it with
+    Leave,
+}
+
+const OPS: [Op; 36] = [
+    Op::Constant,
+    Op::Null,
+    Op::True,
+    Op::False,
+    Op::Add,
+    Op::Sub,
+    Op::Mul,
+    Op::Div,
+    Op::Equal,
+    Op::NotEqual,
+    Op::GreaterThanEqual,
+    Op::LessThanEqual,
+    Op::GreaterThan,
+    Op::LessThan,
+    Op::Plus,
+    Op::Minus,
+    Op::Not,
+    Op::GetGlobal,
+    Op::SetGlobal,
+    Op::GetLocal,
+    Op::SetLocal,
+    Op::GetFree,
+    Op::GetBuiltin,
+    Op::Array,
+    Op::Hash,
+    Op::Index,
+    Op::Return,
+    Op::Closure,
+    Op::Jump,
+    Op::Branch,
+    Op::JumpIfReturned,
+    Op::PrepareCall,
+    Op::CheckArg,
+    Op::Call,
+    Op::Check,
+    Op::Leave,
+];
+
+impl Op {
+    pub fn from_byte(byte: u8) -> Option<Op> {
+        OPS.get(byte as usize).copied()
+    }
+
+    /// width in bytes of each operand
+    pub fn operand_widths(self) -> &'static [usize] {
+        match self {
+            Op::Constant
+            | Op::GetGlobal
+            | Op::SetGlobal
+            | Op::GetLocal
+            | Op::SetLocal
+            | Op::Array
+            | Op::Hash
+            | Op::Closure
+            | Op::Jump
+            | Op::JumpIfReturned => &[2],
+            Op::GetBuiltin | Op::Call => &[1],
+            Op::GetFree => &[1, 2],
+            Op::Branch | Op::Check => &[2, 2],
+            Op::PrepareCall | Op::CheckArg => &[1, 2],
+            _ => &[],
+        }
+    }
+
+    pub fn width(self) -> usize {
+        1 + self.operand_widths().iter().sum::<usize>()
+    }
+}
+
+/// Encodes an instruction, or returns `None` when an operand does not fit its width.
+pub fn make(op: Op, operands: &[usize]) -> Option<Vec<u8>> {
+    let mut instruction = vec![op as u8];
+    for (&operand, &width) in operands.iter().zip(op.operand_widths()) {
+        match width {
+            1 => instruction.push(u8::try_from(operand).ok()?),
+            _ => instruction.extend_from_slice(&u16::try_from(operand).ok()?.to_be_bytes()),
+        }
+    }
+    Some(instruction)
+}
+
+pub fn read_u8(instructions: &[u8], at: usize) -> usize {
+    instructions[at] as usize
+}
+
+pub fn read_u16(instructions: &[u8], at: usize) -> usize {
+    u16::from_be_bytes([instructions[at], instructions[at + 1]]) as usize
+}
+
+/// one instruction per line, prefixed by its offset
+pub fn disassemble(instructions: &[u8]) -> String {
+    let mut out = String::new();
+    let mut ip = 0;
+    while ip < instructions.len() {
+        let op = match Op::from_byte(instructions[ip]) {
+            Some(op) => op,
+            None => {
+                let _ = writeln!(out, "{:04} <invalid {}>", ip, instructions[ip]);
+                ip += 1;
+                continue;
+            }
+        };
+        let _ = write!(out, "{:04} {:?}", ip, op);
+        let mut at = ip + 1;
+        for &width in op.operand_widths() {
+            let operand = match width {
+                1 => read_u8(instructions, at),
+                _ => read_u16(instructions, at),
+            };
+            let _ = write!(out, " {}", operand);
+            at += width;
+        }
+        out.push('\n');
+        ip = at;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encoding() {
+        assert_eq!(make(Op::Constant, &[65534]), Some(vec![Op::Constant as u8, 255, 254]));
+        assert_eq!(make(Op::Add, &[]), Some(vec![Op::Add as u8]));
+        assert_eq!(make(Op::GetFree, &[2, 300]), Some(vec![Op::GetFree as u8, 2, 1, 44]));
+        assert_eq!(make(Op::Call, &[256]), None);
+        assert_eq!(make(Op::Jump, &[65536]), None);
+        for (byte, op) in OPS.iter().enumerate() {
+            assert_eq!(*op as u8 as usize, byte);
+            assert_eq!(Op::from_byte(byte as u8), Some(*op));
+        }
+    }
+
+    #[test]
+    fn disassembly() {
+        let instructions = [
+            make(Op::Constant, &[1]),
+            make(Op::GetFree, &[1, 2]),
+            make(Op::Add, &[]),
+            make(Op::Branch, &[10, 20]),
+        ]
+        .into_iter()
+        .flat_map(Option::unwrap)
+        .collect::<Vec<_>>();
+        assert_eq!(
+            disassemble(&instructions),
+            "0000 Constant 1\n0003 GetFree 1 2\n0007 Add\n0008 Branch 10 20\n"
+        );
+    }
+}
@@ -0,0 +1,496 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::Formatter;
+use std::rc::Rc;
+
+use crate::compiler::code::{make, Op};
+use crate::evaluator::builtins::BuiltinFunctions;
+use crate::parser::ast::{Expr, Ident, Infix, Literal, Prefix, Program, Signature, Stmt, Type};
+use crate::resolver::{Resolution, ResolveError, Resolver, Slot};
+
+pub mod binary;
+pub mod code;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Constant {
+    Integer(i64),
+    String(String),
+    Function(Rc<CompiledFunction>),
+    /// a type annotation, which `Check` refers to
+    Type(Type),
+}
+
+/// A function body lowered to bytecode, with the constants its instructions refer to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompiledFunction {
+    pub instructions: Vec<u8>,
+    pub constants: Vec<Constant>,
+    /// names of the slots of the function's scope, parameters first
+    pub locals: Rc<Vec<String>>,
+    pub num_params: usize,
+    /// the types the function annotates its parameters and its returned value with
+    pub signature: Rc<Signature>,
+    /// source line of the instructions starting at each offset, in increasing offsets;
+    /// empty when the lines are not known
+    pub lines: Vec<(usize, usize)>,
+}
+
+/// A compiled program: the top-level code, and the names of every global slot so far.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bytecode {
+    pub main: Rc<CompiledFunction>,
+    pub globals: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompileError {
+    Resolve(ResolveError),
+    /// a jump, constant or slot index does not fit its operand
+    TooLarge,
+    /// a statement or expression only the `Evaluator` runs
+    Unsupported(&'static str),
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            CompileError::Resolve(err) => write!(f, "{}", err),
+            CompileError::TooLarge => write!(f, "program too large to compile"),
+            CompileError::Unsupported(what) => write!(f, "{} is only supported by the evaluator", what),
+        }
+    }
+}
+
+/// Lowers programs to bytecode for the `Vm`.
+///
+/// Like the `Evaluator`, it keeps the global scope between programs so a REPL can refer to
+/// the bindings of earlier lines.
+#[derive(Debug, Clone)]
+pub struct Compiler {
+    resolver: Resolver,
+}
+
+impl Compiler {
+    pub fn new() -> Self {
+        Compiler {
+            resolver: Resolver::new(BuiltinFunctions::new().names()),
+        }
+    }
+
+    pub fn compile(&mut self, program: &Program) -> Result<Bytecode, CompileError> {
+        self.compile_with_lines(program, &HashMap::new())
+    }
+
+    /// compiles a program, noting the line of its instructions from the line each statement
+    /// starts on, as `debug::lines` gives them
+    pub fn compile_with_lines(&mut self, program: &Program, lines: &HashMap<*const Stmt, usize>) -> Result<Bytecode, CompileError> {
+        let resolution = self
+            .resolver
+            .resolve(program)
+            .map_err(|mut errors| CompileError::Resolve(errors.remove(0)))?;
+        let mut pass = Pass {
+            resolution: &resolution,
+            lines,
+            units: vec![],
+            fits: true,
+            unsupported: None,
+        };
+        let main = pass.function(program, Rc::new(vec![]), 0, Rc::default());
+        if let Some(what) = pass.unsupported {
+            Err(CompileError::Unsupported(what))
+        } else if pass.fits {
+            Ok(Bytecode {
+                main: Rc::new(main),
+                globals: self.resolver.globals().to_vec(),
+            })
+        } else {
+            Err(CompileError::TooLarge)
+        }
+    }
+}
+
+impl Default for Compiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Default)]
+struct Unit {
+    instructions: Vec<u8>,
+    constants: Vec<Constant>,
+    lines: Vec<(usize, usize)>,
+    /// the line of the statement being compiled
+    line: Option<usize>,
+}
+
+struct Pass<'a> {
+    resolution: &'a Resolution,
+    lines: &'a HashMap<*const Stmt, usize>,
+    /// the functions being compiled, innermost last
+    units: Vec<Unit>,
+    fits: bool,
+    /// the first construct met which only the evaluator runs
+    unsupported: Option<&'static str>,
+}
+
+impl<'a> Pass<'a> {
+    fn unit(&mut self) -> &mut Unit {
+        self.units.last_mut().expect("no function")
+    }
+
+    fn position(&mut self) -> usize {
+        self.unit().instructions.len()
+    }
+
+    /// appends an instruction and returns its position
+    fn emit(&mut self, op: Op, operands: &[usize]) -> usize {
+        let position = self.position();
+        let instruction = make(op, operands).unwrap_or_else(|| {
+            self.fits = false;
+            vec![op as u8; op.width()]
+        });
+        self.unit().instructions.extend(instruction);
+        position
+    }
+
+    /// rewrites the operands of the instruction at `position`
+    fn patch(&mut self, position: usize, operands: &[usize]) {
+        let op = Op::from_byte(self.unit().instructions[position]).expect("invalid opcode");
+        match make(op, operands) {
+            Some(instruction) => {
+                let end = position + instruction.len();
+                self.unit().instructions[position..end].copy_from_slice(&instruction);
+            }
+            None => self.fits = false,
+        }
+    }
+
+    fn constant(&mut self, constant: Constant) -> usize {
+        let constants = &mut self.unit().constants;
+        constants.push(constant);
+        constants.len() - 1
+    }
+
+    fn function(&mut self, body: &Program, locals: Rc<Vec<String>>, num_params: usize, signature: Rc<Signature>) -> CompiledFunction {
+        self.units.push(Unit::default());
+        self.block(body);
+        self.emit(Op::Leave, &[]);
+        let Unit { instructions, constants, lines, .. } = self.units.pop().expect("no function");
+        CompiledFunction {
+            instructions,
+            constants,
+            locals,
+            num_params,
+            signature,
+            lines,
+        }
+    }
+
+    /// notes that the instructions from here on come from a line
+    fn mark(&mut self, line: Option<usize>) {
+        let position = self.position();
+        let unit = self.unit();
+        unit.line = line;
+        let Some(line) = line else { return };
+        if unit.lines.last().is_some_and(|&(offset, _)| offset == position) {
+            unit.lines.pop();
+        }
+        if unit.lines.last().is_none_or(|&(_, last)| last != line) {
+            unit.lines.push((position, line));
+        }
+    }
+
+    /// Statements run until one evaluates to a return value, which is then the value of
+    /// the block as it is in the `Evaluator`.
+    fn block(&mut self, program: &[Stmt]) {
+        if program.is_empty() {
+            self.emit(Op::Null, &[]);
+            return;
+        }
+        let mut exits = vec![];
+        for (i, stmt) in program.iter().enumerate() {
+            self.stmt(stmt);
+            if i < program.len() - 1 {
+                exits.push(self.emit(Op::JumpIfReturned, &[0]));
+            }
+        }
+        let end = self.position();
+        for exit in exits {
+            self.patch(exit, &[end]);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        let outer = self.unit().line;
+        self.mark(self.lines.get(&(stmt as *const Stmt)).copied().or(outer));
+        self.stmt_code(stmt);
+        // the rest of the enclosing statement, after a block
+        self.mark(outer);
+    }
+
+    fn stmt_code(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::LetStmt(ident, ty, expr) => {
+                self.expr(expr);
+                if let Some(ty) = ty {
+                    let name = self.constant(Constant::String(ident.0.clone()));
+                    let ty = self.constant(Constant::Type(ty.clone()));
+                    self.emit(Op::Check, &[name, ty]);
+                }
+                let slot = self.slot(ident);
+                // a `let` always binds in the scope of the function it is in
+                if self.units.len() == 1 {
+                    self.emit(Op::SetGlobal, &[slot.index]);
+                } else {
+                    self.emit(Op::SetLocal, &[slot.index]);
+                }
+            }
+            Stmt::ReturnStmt(expr) => {
+                self.expr(expr);
+                self.emit(Op::Return, &[]);
+            }
+            Stmt::ExprStmt(expr) => self.expr(expr),
+            Stmt::ImportStmt(..) => self.unsupported("`import`"),
+            Stmt::ExportStmt(_) => self.unsupported("`export`"),
+        }
+    }
+
+    fn unsupported(&mut self, what: &'static str) {
+        self.unsupported.get_or_insert(what);
+        self.emit(Op::Null, &[]);
+    }
+
+    fn slot(&self, ident: &Ident) -> Slot {
+        self.resolution.slot(ident).expect("identifier left unresolved")
+    }
+
+    /// Globals are `depth` functions up, and the builtins one scope further.
+    fn load(&mut self, slot: Slot) {
+        let nesting = self.units.len() - 1;
+        if slot.depth == nesting {
+            self.emit(Op::GetGlobal, &[slot.index]);
+        } else if slot.depth == nesting + 1 {
+            self.emit(Op::GetBuiltin, &[slot.index]);
+        } else if slot.depth == 0 {
+            self.emit(Op::GetLocal, &[slot.index]);
+        } else {
+            self.emit(Op::GetFree, &[slot.depth, slot.index]);
+        }
+    }
+
+    fn literal(&mut self, literal: &Literal) {
+        match literal {
+            Literal::IntLiteral(i) => {
+                let index = self.constant(Constant::Integer(*i));
+                self.emit(Op::Constant, &[index]);
+            }
+            Literal::BoolLiteral(true) => {
+                self.emit(Op::True, &[]);
+            }
+            Literal::BoolLiteral(false) => {
+                self.emit(Op::False, &[]);
+            }
+            Literal::StringLiteral(s) => {
+                let index = self.constant(Constant::String(s.clone()));
+                self.emit(Op::Constant, &[index]);
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::IdentExpr(ident) => {
+                let slot = self.slot(ident);
+                self.load(slot);
+            }
+            Expr::LiteralExpr(literal) => self.literal(literal),
+            Expr::PrefixExpr(prefix, e) => {
+                self.expr(e);
+                let op = match prefix {
+                    Prefix::PrefixPlus => Op::Plus,
+                    Prefix::PrefixMinus => Op::Minus,
+                    Prefix::Not => Op::Not,
+                };
+                self.emit(op, &[]);
+            }
+            Expr::InfixExpr(infix, e1, e2) => {
+                self.expr(e1);
+                self.expr(e2);
+                let op = match infix {
+                    Infix::Plus => Op::Add,
+                    Infix::Minus => Op::Sub,
+                    Infix::Divide => Op::Div,
+                    Infix::Multiply => Op::Mul,
+                    Infix::Equal => Op::Equal,
+                    Infix::NotEqual => Op::NotEqual,
+                    Infix::GreaterThanEqual => Op::GreaterThanEqual,
+                    Infix::LessThanEqual => Op::LessThanEqual,
+                    Infix::GreaterThan => Op::GreaterThan,
+                    Infix::LessThan => Op::LessThan,
+                };
+                self.emit(op, &[]);
+            }
+            Expr::IfExpr { cond, consequence, alternative } => {
+                self.expr(cond);
+                let branch = self.emit(Op::Branch, &[0, 0]);
+                self.block(consequence);
+                let jump = self.emit(Op::Jump, &[0]);
+                let otherwise = self.position();
+                match alternative {
+                    Some(alternative) => self.block(alternative),
+                    None => {
+                        self.emit(Op::Null, &[]);
+                    }
+                }
+                let end = self.position();
+                self.patch(branch, &[otherwise, end]);
+                self.patch(jump, &[end]);
+            }
+            Expr::FnExpr { params, signature, body } => {
+                let locals = Rc::clone(self.resolution.frame(body).expect("function left unresolved"));
+                let function = self.function(body, locals, params.len(), Rc::clone(signature));
+                let index = self.constant(Constant::Function(Rc::new(function)));
+                self.emit(Op::Closure, &[index]);
+            }
+            Expr::CallExpr { function, arguments } => {
+                self.expr(function);
+                let prepare = self.emit(Op::PrepareCall, &[arguments.len(), 0]);
+                let mut checks = vec![];
+                for (i, e) in arguments.iter().enumerate() {
+                    self.expr(e);
+                    checks.push(self.emit(Op::CheckArg, &[i, 0]));
+                }
+                self.emit(Op::Call, &[arguments.len()]);
+                let end = self.position();
+                self.patch(prepare, &[arguments.len(), end]);
+                for (i, check) in checks.into_iter().enumerate() {
+                    self.patch(check, &[i, end]);
+                }
+            }
+            Expr::ArrayExpr(exprs) => {
+                exprs.iter().for_each(|e| self.expr(e));
+                self.emit(Op::Array, &[exprs.len()]);
+            }
+            Expr::HashExpr(pairs) => {
+                for (key, value) in pairs {
+                    self.literal(key);
+                    self.expr(value);
+                }
+                self.emit(Op::Hash, &[pairs.len()]);
+            }
+            Expr::IndexExpr { array, index } => {
+                self.expr(array);
+                self.expr(index);
+                self.emit(Op::Index, &[]);
+            }
+            Expr::MemberExpr { .. } => self.unsupported("module members"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::code::disassemble;
+    use crate::lexer::token::Tokens;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn compile(compiler: &mut Compiler, input: &str) -> Result<Bytecode, CompileError> {
+        let (_, r) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+        let (_, program) = Parser::parse_tokens(Tokens::new(&r)).unwrap();
+        compiler.compile(&program)
+    }
+
+    #[test]
+    fn statements() {
+        let bytecode = compile(&mut Compiler::new(), "let a = 1; return a; 2").unwrap();
+        assert_eq!(
+            disassemble(&bytecode.main.instructions),
+            "0000 Constant 0\n\
+             0003 SetGlobal 0\n\
+             0006 JumpIfReturned 19\n\
+             0009 GetGlobal 0\n\
+             0012 Return\n\
+             0013 JumpIfReturned 19\n\
+             0016 Constant 1\n\
+             0019 Leave\n"
+        );
+        assert_eq!(bytecode.main.constants, vec![Constant::Integer(1), Constant::Integer(2)]);
+        assert_eq!(bytecode.globals, vec!["a".to_string()]);
+    }
+
+    #[test]
+    fn conditionals_and_calls() {
+        let bytecode = compile(&mut Compiler::new(), "if (true) { len(\"a\") }").unwrap();
+        assert_eq!(
+            disassemble(&bytecode.main.instructions),
+            "0000 True\n\
+             0001 Branch 24 25\n\
+             0006 GetBuiltin 1\n\
+             0008 PrepareCall 1 21\n\
+             0012 Constant 0\n\
+             0015 CheckArg 0 21\n\
+             0019 Call 1\n\
+             0021 Jump 25\n\
+             0024 Null\n\
+             0025 Leave\n"
+        );
+    }
+
+    #[test]
+    fn annotations() {
+        let bytecode = compile(&mut Compiler::new(), "let x: int = 1; fn(a: bool) -> int { a }").unwrap();
+        assert_eq!(
+            disassemble(&bytecode.main.instructions),
+            "0000 Constant 0\n\
+             0003 Check 1 2\n\
+             0008 SetGlobal 0\n\
+             0011 JumpIfReturned 17\n\
+             0014 Closure 3\n\
+             0017 Leave\n"
+        );
+        assert_eq!(bytecode.main.constants[1..3], [Constant::String("x".to_string()), Constant::Type(Type::Int)]);
+        let Constant::Function(f) = &bytecode.main.constants[3] else { panic!("expected a function") };
+        assert_eq!(*f.signature, Signature { params: vec![Some(Type::Bool)], ret: Some(Type::Int) });
+    }
+
+    #[test]
+    fn closures() {
+        let bytecode = compile(&mut Compiler::new(), "let a = 1; fn(x) { let y = x; fn() { a + x + y } }").unwrap();
+        let outer = match &bytecode.main.constants[1] {
+            Constant::Function(f) => f,
+            c => panic!("expected a function, got {:?}", c),
+        };
+        assert_eq!(*outer.locals, vec!["x".to_string(), "y".to_string()]);
+        assert_eq!(outer.num_params, 1);
+        let inner = match &outer.constants[0] {
+            Constant::Function(f) => f,
+            c => panic!("expected a function, got {:?}", c),
+        };
+        assert_eq!(
+            disassemble(&inner.instructions),
+            "0000 GetGlobal 0\n\
+             0003 GetFree 1 0\n\
+             0007 Add\n\
+             0008 GetFree 1 1\n\
+             0012 Add\n\
+             0013 Leave\n"
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let mut compiler = Compiler::new();
+        assert_eq!(
+            compile(&mut compiler, "let a = 1; b").unwrap_err(),
+            CompileError::Resolve(ResolveError::UndefinedIdent("b".to_string())),
+        );
+        assert!(compile(&mut compiler, "a").is_err());
+        let huge = format!("[{}]", vec!["1"; 70000].join(", "));
+        assert_eq!(compile(&mut compiler, &huge).unwrap_err(), CompileError::TooLarge);
+        let import = compile(&mut compiler, "import \"m.mk\" as m; m.f").unwrap_err();
+        assert_eq!(import.to_string(), "`import` is only supported by the evaluator");
+    }
+}
@@ -0,0 +1,525 @@
+//! A debug adapter, running a program on the evaluator for editors to debug.
+//!
+//! Messages are framed as those of the language server. The program runs on a thread of its
+//! own, as an `EvalObserver` of its evaluator stopping before statements: at breakpoints, whose
+//! conditions it evaluates in the scope of the statement, and after steps, as its `Tracker`
+//! follows them. While it is stopped, the thread answers the requests about its calls, scopes
+//! and values, which are not `Send`.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::rc::Rc;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::debug::{lines, parse_expr, Frame, Step, Tracker};
+use crate::evaluator::builtins::redirect_print;
+use crate::evaluator::observer::EvalObserver;
+use crate::evaluator::environment::Environment;
+use crate::evaluator::object::{show, Object};
+use crate::evaluator::{Evaluator, InterruptHandle};
+use crate::json::Json;
+use crate::lsp::{read_message, write_message};
+use crate::parser::ast::{Expr, Stmt};
+use crate::parser::parse;
+
+/// the only thread the client is told of
+const THREAD: usize = 1;
+
+/// Answers the requests of a client until it disconnects.
+pub fn serve(mut input: impl BufRead, output: impl Write + Send + 'static) -> io::Result<()> {
+    let mut adapter = Adapter {
+        output: Arc::new(Mutex::new(Output { writer: Box::new(output), seq: 0 })),
+        control: Arc::default(),
+        launch: None,
+        configured: false,
+        program: None,
+    };
+    while let Some(message) = read_message(&mut input)? {
+        if !adapter.handle(&message)? {
+            break;
+        }
+    }
+    if let Some(program) = adapter.program {
+        program.interrupt.interrupt();
+    }
+    Ok(())
+}
+
+/// the messages sent to the client, numbered in the order they are sent
+struct Output {
+    writer: Box<dyn Write + Send>,
+    seq: usize,
+}
+
+impl Output {
+    fn send(&mut self, kind: &str, members: Vec<(&str, Json)>) -> io::Result<()> {
+        self.seq += 1;
+        let mut message = vec![("seq".to_string(), self.seq.into()), ("type".to_string(), Json::string(kind))];
+        message.extend(members.into_iter().map(|(key, value)| (key.to_string(), value)));
+        write_message(&mut self.writer, &Json::Object(message))
+    }
+
+    fn event(&mut self, event: &str, body: Json) -> io::Result<()> {
+        let mut members = vec![("event", Json::string(event))];
+        if body != Json::Null {
+            members.push(("body", body));
+        }
+        self.send("event", members)
+    }
+}
+
+/// what the client sets for the program to see while it runs
+#[derive(Debug, Default)]
+struct Control {
+    /// the condition of the breakpoint of each line, by path
+    breakpoints: HashMap<String, HashMap<usize, Option<String>>>,
+    /// whether the client asked to stop wherever the program is
+    pause: bool,
+    stopped: bool,
+}
+
+struct Launch {
+    path: String,
+    source: String,
+    stop_on_entry: bool,
+}
+
+/// the thread running the program
+struct Running {
+    commands: Sender<Command>,
+    interrupt: InterruptHandle,
+}
+
+enum Command {
+    /// answers a request about the stopped program
+    Inspect(Json, Sender<Result<Json, String>>),
+    Resume(Step),
+}
+
+struct Adapter {
+    output: Arc<Mutex<Output>>,
+    control: Arc<Mutex<Control>>,
+    launch: Option<Launch>,
+    configured: bool,
+    program: Option<Running>,
+}
+
+impl Adapter {
+    /// Answers a request, giving whether to go on.
+    fn handle(&mut self, request: &Json) -> io::Result<bool> {
+        let command = request.get("command").as_str().unwrap_or_default();
+        let arguments = request.get("arguments");
+        let result = match command {
+            "initialize" => Ok(Json::object([
+                ("supportsConfigurationDoneRequest", true.into()),
+                ("supportsConditionalBreakpoints", true.into()),
+                ("supportsEvaluateForHovers", true.into()),
+            ])),
+            "launch" => self.launch(arguments),
+            "setBreakpoints" => self.set_breakpoints(arguments),
+            "configurationDone" => {
+                self.configured = true;
+                Ok(Json::Null)
+            }
+            "threads" => Ok(Json::object([(
+                "threads",
+                Json::Array(vec![Json::object([("id", THREAD.into()), ("name", Json::string("main"))])]),
+            )])),
+            "stackTrace" | "scopes" | "variables" | "evaluate" => self.inspect(request),
+            "continue" => self.resume(Step::Continue),
+            "next" => self.resume(Step::Over),
+            "stepIn" => self.resume(Step::In),
+            "stepOut" => self.resume(Step::Out),
+            "pause" => {
+                self.control.lock().expect("poisoned").pause = true;
+                Ok(Json::Null)
+            }
+            "disconnect" | "terminate" => Ok(Json::Null),
+            _ => Err(format!("unknown command: {}", command)),
+        };
+        let mut response = vec![
+            ("request_seq", request.get("seq").clone()),
+            ("success", result.is_ok().into()),
+            ("command", Json::string(command)),
+        ];
+        match result {
+            Ok(Json::Null) => {}
+            Ok(body) => response.push(("body", body)),
+            Err(message) => response.push(("message", message.into())),
+        }
+        self.output.lock().expect("poisoned").send("response", response)?;
+
+        match command {
+            "initialize" => self.output.lock().expect("poisoned").event("initialized", Json::Null)?,
+            "disconnect" | "terminate" => return Ok(false),
+            _ => {}
+        }
+        if self.configured && self.program.is_none() {
+            if let Some(launch) = self.launch.take() {
+                self.program = Some(self.start(launch));
+            }
+        }
+        Ok(true)
+    }
+
+    fn launch(&mut self, arguments: &Json) -> Result<Json, String> {
+        let path = arguments.get("program").as_str().ok_or("no program to launch")?;
+        let source = fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?;
+        parse(&source).map_err(|err| format!("{}: {}", path, err))?;
+        let stop_on_entry = arguments.get("stopOnEntry").as_bool().unwrap_or(false);
+        self.launch = Some(Launch { path: path.to_string(), source, stop_on_entry });
+        Ok(Json::Null)
+    }
+
+    /// Sets the breakpoints of a source, each on a line where a statement starts.
+    fn set_breakpoints(&mut self, arguments: &Json) -> Result<Json, String> {
+        let path = arguments.get("source").get("path").as_str().ok_or("no source")?;
+        let lines = fs::read_to_string(path)
+            .map_err(|err| err.to_string())
+            .and_then(|source| {
+                let program = parse(&source)?;
+                Ok(lines(&source, &program).into_values().collect::<Vec<_>>())
+            })
+            .unwrap_or_default();
+        let mut set = HashMap::new();
+        let breakpoints = arguments
+            .get("breakpoints")
+            .as_array()
+            .unwrap_or_default()
+            .iter()
+            .map(|breakpoint| {
+                let line = breakpoint.get("line").as_usize().unwrap_or_default();
+                let condition = breakpoint.get("condition").as_str().filter(|c| !c.trim().is_empty());
+                let problem = match condition.map(parse_expr) {
+                    Some(Err(err)) => Some(format!("the condition does not parse: {}", err)),
+                    _ if !lines.contains(&line) => Some("no statement starts on this line".to_string()),
+                    _ => None,
+                };
+                let mut result = vec![("verified".to_string(), problem.is_none().into()), ("line".to_string(), line.into())];
+                match problem {
+                    Some(message) => result.push(("message".to_string(), message.into())),
+                    None => {
+                        set.insert(line, condition.map(str::to_string));
+                    }
+                }
+                Json::Object(result)
+            })
+            .collect();
+        self.control.lock().expect("poisoned").breakpoints.insert(path.to_string(), set);
+        Ok(Json::object([("breakpoints", Json::Array(breakpoints))]))
+    }
+
+    /// the stopped program, to which commands can be sent
+    fn stopped(&self) -> Result<&Running, String> {
+        match &self.program {
+            Some(program) if self.control.lock().expect("poisoned").stopped => Ok(program),
+            _ => Err("the program is not stopped".to_string()),
+        }
+    }
+
+    fn inspect(&self, request: &Json) -> Result<Json, String> {
+        let program = self.stopped()?;
+        let (reply, answer) = mpsc::channel();
+        program.commands.send(Command::Inspect(request.clone(), reply)).map_err(|_| "the program ended")?;
+        answer.recv().map_err(|_| "the program ended")?
+    }
+
+    fn resume(&self, step: Step) -> Result<Json, String> {
+        let program = self.stopped()?;
+        self.control.lock().expect("poisoned").stopped = false;
+        program.commands.send(Command::Resume(step)).map_err(|_| "the program ended")?;
+        Ok(Json::object([("allThreadsContinued", true.into())]))
+    }
+
+    /// Runs the program on a thread of its own.
+    fn start(&self, launch: Launch) -> Running {
+        let (commands, receiver) = mpsc::channel();
+        let (interrupt_sender, interrupt) = mpsc::channel();
+        let (output, control) = (Arc::clone(&self.output), Arc::clone(&self.control));
+        thread::spawn(move || {
+            let mut evaluator = Evaluator::new();
+            interrupt_sender.send(evaluator.interrupt_handle()).expect("the adapter is gone");
+            run(&mut evaluator, launch, output, control, receiver)
+        });
+        Running { commands, interrupt: interrupt.recv().expect("the program did not start") }
+    }
+}
+
+fn run(
+    evaluator: &mut Evaluator,
+    launch: Launch,
+    output: Arc<Mutex<Output>>,
+    control: Arc<Mutex<Control>>,
+    commands: Receiver<Command>,
+) -> io::Result<()> {
+    let program = parse(&launch.source).expect("the program was parsed at launch");
+    let step = if launch.stop_on_entry { Step::Entry } else { Step::Continue };
+    let printed = Arc::clone(&output);
+    redirect_print(Some(Box::new(move |line| {
+        let body = Json::object([("category", Json::string("stdout")), ("output", format!("{}\n", line).into())]);
+        // a client gone is seen by the adapter
+        let _ = printed.lock().expect("poisoned").event("output", body);
+    })));
+    evaluator.set_file(Some(&launch.path));
+    let session = Session {
+        path: launch.path,
+        tracker: Tracker::new(&launch.source, &program, evaluator, step),
+        control,
+        output: Arc::clone(&output),
+        commands,
+        references: vec![],
+    };
+    evaluator.set_observer(Some(Box::new(session)));
+    let result = evaluator.eval_program(program);
+    evaluator.set_observer(None);
+    redirect_print(None);
+
+    let mut output = output.lock().expect("poisoned");
+    let (category, code) = match result {
+        Object::Error(_) => ("stderr", 1usize),
+        _ => ("stdout", 0),
+    };
+    let body = Json::object([("category", Json::string(category)), ("output", format!("{}\n", result).into())]);
+    output.event("output", body)?;
+    output.event("exited", Json::object([("exitCode", code.into())]))?;
+    output.event("terminated", Json::Null)
+}
+
+/// what a variables reference lists
+enum Reference {
+    Scope(Rc<RefCell<Environment>>),
+    Value(Object),
+}
+
+struct Session {
+    path: String,
+    tracker: Tracker,
+    control: Arc<Mutex<Control>>,
+    output: Arc<Mutex<Output>>,
+    commands: Receiver<Command>,
+    /// what the variables references given since the program stopped list, from 1
+    references: Vec<Reference>,
+}
+
+impl EvalObserver for Session {
+    fn statement(&mut self, evaluator: &mut Evaluator, stmt: &Stmt) {
+        let Some(line) = self.tracker.statement(evaluator, stmt) else {
+            return;
+        };
+        let reason = if std::mem::take(&mut self.control.lock().expect("poisoned").pause) {
+            Some("pause")
+        } else if self.breaks(evaluator, line) {
+            Some("breakpoint")
+        } else {
+            self.tracker.stepped()
+        };
+        if let Some(reason) = reason {
+            self.stop(evaluator, reason);
+        }
+    }
+
+    fn call(&mut self, evaluator: &mut Evaluator, callee: &Expr, _: &Object, _: &[Object]) {
+        self.tracker.call(evaluator, callee);
+    }
+
+    fn returned(&mut self, _: &mut Evaluator, _: &Object) {
+        self.tracker.returned();
+    }
+}
+
+impl Session {
+    /// whether a breakpoint of the line stops the program, its condition holding
+    fn breaks(&mut self, evaluator: &mut Evaluator, line: usize) -> bool {
+        let condition = {
+            let control = self.control.lock().expect("poisoned");
+            match control.breakpoints.get(&self.path).and_then(|lines| lines.get(&line)) {
+                None => return false,
+                Some(condition) => condition.clone(),
+            }
+        };
+        let Some(condition) = condition else {
+            return true;
+        };
+        let env = evaluator.environment();
+        match parse_expr(&condition).map(|expr| evaluator.eval_in(&env, expr)) {
+            Ok(Object::Boolean(b)) => b,
+            Ok(object) => {
+                let message = format!("the condition `{}` of line {} gives {}\n", condition, line, show(&object));
+                let body = Json::object([("category", Json::string("console")), ("output", message.into())]);
+                let _ = self.output.lock().expect("poisoned").event("output", body);
+                false
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Stops the program until the client resumes it, answering its requests meanwhile.
+    fn stop(&mut self, evaluator: &mut Evaluator, reason: &str) {
+        self.control.lock().expect("poisoned").stopped = true;
+        let body = Json::object([
+            ("reason", Json::string(reason)),
+            ("threadId", THREAD.into()),
+            ("allThreadsStopped", true.into()),
+        ]);
+        if self.output.lock().expect("poisoned").event("stopped", body).is_err() {
+            return evaluator.interrupt_handle().interrupt();
+        }
+        loop {
+            match self.commands.recv() {
+                Ok(Command::Inspect(request, reply)) => {
+                    let _ = reply.send(self.inspect(evaluator, &request));
+                }
+                Ok(Command::Resume(step)) => {
+                    self.tracker.resume(step);
+                    break;
+                }
+                // the client is gone
+                Err(_) => {
+                    evaluator.interrupt_handle().interrupt();
+                    break;
+                }
+            }
+        }
+        self.references.clear();
+    }
+
+    fn reference(&mut self, reference: Reference) -> usize {
+        self.references.push(reference);
+        self.references.len()
+    }
+
+    /// the frame of an id given in a stack trace, the innermost one by default
+    fn frame(&self, id: &Json) -> Result<&Frame, String> {
+        match id.as_usize() {
+            None => Ok(self.tracker.top()),
+            Some(id) => self.tracker.frames().get(id.wrapping_sub(1)).ok_or_else(|| format!("no frame {}", id)),
+        }
+    }
+
+    fn inspect(&mut self, evaluator: &mut Evaluator, request: &Json) -> Result<Json, String> {
+        let arguments = request.get("arguments");
+        match request.get("command").as_str().unwrap_or_default() {
+            "stackTrace" => {
+                let source = Json::object([("path", Json::string(&self.path))]);
+                let frames = self.tracker.frames();
+                let trace = (0..frames.len())
+                    .rev()
+                    .map(|i| {
+                        let frame = &frames[i];
+                        Json::object([
+                            ("id", (i + 1).into()),
+                            ("name", Json::string(&frame.name)),
+                            ("source", source.clone()),
+                            ("line", frame.line.into()),
+                            ("column", 1.into()),
+                        ])
+                    })
+                    .collect();
+                Ok(Json::object([("stackFrames", Json::Array(trace)), ("totalFrames", frames.len().into())]))
+            }
+            "scopes" => {
+                // the scope of the call, those it closes over, then the global one
+                let mut envs = vec![];
+                let mut env = Rc::clone(&self.frame(arguments.get("frameId"))?.env);
+                loop {
+                    let parent = env.borrow().parent().cloned();
+                    match parent {
+                        Some(parent) => {
+                            envs.push(env);
+                            env = parent;
+                        }
+                        // the prelude
+                        None => break,
+                    }
+                }
+                let count = envs.len();
+                let scopes = envs
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, env)| {
+                        let name = match i {
+                            _ if i == count - 1 => "Globals",
+                            0 => "Locals",
+                            _ => "Closure",
+                        };
+                        let reference = self.reference(Reference::Scope(env));
+                        Json::object([
+                            ("name", Json::string(name)),
+                            ("variablesReference", reference.into()),
+                            ("expensive", false.into()),
+                        ])
+                    })
+                    .collect();
+                Ok(Json::object([("scopes", Json::Array(scopes))]))
+            }
+            "variables" => {
+                let reference = arguments.get("variablesReference").as_usize().unwrap_or_default();
+                let members = match self.references.get(reference.wrapping_sub(1)) {
+                    Some(Reference::Scope(env)) => {
+                        env.borrow().bindings().map(|(name, object)| (name.to_string(), object.clone())).collect()
+                    }
+                    Some(Reference::Value(Object::Array(items))) => {
+                        items.iter().enumerate().map(|(i, item)| (i.to_string(), item.clone())).collect()
+                    }
+                    Some(Reference::Value(Object::Hash(pairs))) => {
+                        let mut pairs = pairs.iter().map(|(k, v)| (show(k), v.clone())).collect::<Vec<_>>();
+                        pairs.sort_by(|(k1, _), (k2, _)| k1.cmp(k2));
+                        pairs
+                    }
+                    _ => return Err(format!("no variables reference {}", reference)),
+                };
+                let variables = members
+                    .into_iter()
+                    .map(|(name, object)| {
+                        let (value, ty, reference) = self.value(object);
+                        Json::object([
+                            ("name", name.into()),
+                            ("value", value.into()),
+                            ("type", Json::string(ty)),
+                            ("variablesReference", reference.into()),
+                        ])
+                    })
+                    .collect();
+                Ok(Json::object([("variables", Json::Array(variables))]))
+            }
+            "evaluate" => {
+                let env = Rc::clone(&self.frame(arguments.get("frameId"))?.env);
+                let expr = parse_expr(arguments.get("expression").as_str().unwrap_or_default())?;
+                let (result, ty, reference) = self.value(evaluator.eval_in(&env, expr));
+                Ok(Json::object([
+                    ("result", result.into()),
+                    ("type", Json::string(ty)),
+                    ("variablesReference", reference.into()),
+                ]))
+            }
+            command => Err(format!("unknown command: {}", command)),
+        }
+    }
+
+    /// a value as shown to the client, its type, and the reference listing its items if it
+    /// has any
+    fn value(&mut self, object: Object) -> (String, &'static str, usize) {
+        let ty = match &object {
+            Object::Integer(_) => "int",
+            Object::Boolean(_) => "bool",
+            Object::String(_) => "string",
+            Object::Array(_) => "array",
+            Object::Hash(_) => "hash",
+            Object::Function(..) | Object::Closure(_) | Object::Builtin(..) => "function",
+            Object::Module(_) => "module",
+            Object::Null => "null",
+            Object::ReturnValue(_) | Object::Error(_) => "error",
+        };
+        let shown = show(&object);
+        let reference = match &object {
+            Object::Array(items) if !items.is_empty() => self.reference(Reference::Value(object)),
+            Object::Hash(pairs) if !pairs.is_empty() => self.reference(Reference::Value(object)),
+            _ => 0,
+        };
+        (shown, ty, reference)
+    }
+}
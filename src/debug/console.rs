@@ -0,0 +1,309 @@
+//! A debugger at the terminal, reading commands each time the program it runs stops.
+
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::fmt::{self, Formatter};
+use std::io::Write;
+use std::rc::Rc;
+
+use crate::debug::{parse_expr, Step, Tracker};
+use crate::evaluator::observer::EvalObserver;
+use crate::evaluator::object::{show, Object};
+use crate::evaluator::Evaluator;
+use crate::parser::ast::{Expr, Program, Stmt};
+
+pub const HELP: &str = "\
+:break <line|fn>   stops before a line, or in each call of a function; alone, lists the breakpoints
+:delete <line|fn>  removes a breakpoint
+:step              runs to the next line, in this call or another
+:next              runs to the next line of this call, or of a caller once it returns
+:continue          runs to a breakpoint
+:locals            shows the variables of the innermost scope
+:bt                shows the calls running, the innermost first
+:quit              interrupts the program
+<expression>       shows its value where the program stopped";
+
+/// where the program stops
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Breakpoints {
+    lines: BTreeSet<usize>,
+    functions: BTreeSet<String>,
+}
+
+enum Place {
+    Line(usize),
+    Function(String),
+}
+
+fn place(at: &str) -> Result<Place, String> {
+    let at = at.trim();
+    let mut chars = at.chars();
+    if let Ok(line) = at.parse() {
+        Ok(Place::Line(line))
+    } else if chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+    {
+        Ok(Place::Function(at.to_string()))
+    } else {
+        Err(format!("`{}` is neither a line nor the name of a function", at))
+    }
+}
+
+impl Breakpoints {
+    /// Sets a breakpoint on a line, or on the calls of a function, as `:break` is given them.
+    pub fn set(&mut self, at: &str) -> Result<(), String> {
+        match place(at)? {
+            Place::Line(line) => self.lines.insert(line),
+            Place::Function(name) => self.functions.insert(name),
+        };
+        Ok(())
+    }
+
+    pub fn delete(&mut self, at: &str) -> Result<(), String> {
+        let deleted = match place(at)? {
+            Place::Line(line) => self.lines.remove(&line),
+            Place::Function(name) => self.functions.remove(&name),
+        };
+        match deleted {
+            true => Ok(()),
+            false => Err(format!("no breakpoint on {}", at.trim())),
+        }
+    }
+}
+
+impl fmt::Display for Breakpoints {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.lines.is_empty() && self.functions.is_empty() {
+            return write!(f, "no breakpoints");
+        }
+        let lines = self.lines.iter().map(|line| format!("line {}", line));
+        let functions = self.functions.iter().map(|name| format!("fn {}", name));
+        write!(f, "{}", lines.chain(functions).collect::<Vec<_>>().join(", "))
+    }
+}
+
+/// reads a line of input after a prompt, none once there is no more
+pub type Input = Box<dyn FnMut(&str) -> Option<String>>;
+
+struct Console {
+    tracker: Tracker,
+    source: Vec<String>,
+    breakpoints: Rc<RefCell<Breakpoints>>,
+    input: Input,
+    output: Box<dyn Write>,
+}
+
+/// Runs a program parsed from `source`, stopping at the breakpoints, which the commands read
+/// from `input` may change meanwhile.
+pub fn run(
+    evaluator: &mut Evaluator,
+    source: &str,
+    program: Program,
+    breakpoints: Rc<RefCell<Breakpoints>>,
+    input: Input,
+    mut output: Box<dyn Write>,
+) -> Object {
+    let tracker = Tracker::new(source, &program, evaluator, Step::Continue);
+    let starts = crate::debug::lines(source, &program).into_values().collect::<BTreeSet<_>>();
+    for line in breakpoints.borrow().lines.difference(&starts) {
+        let _ = writeln!(output, "no statement starts on line {}", line);
+    }
+    let source = source.lines().map(str::to_string).collect();
+    evaluator.set_observer(Some(Box::new(Console { tracker, source, breakpoints, input, output })));
+    let result = evaluator.eval_program(program);
+    evaluator.set_observer(None);
+    result
+}
+
+impl EvalObserver for Console {
+    fn statement(&mut self, evaluator: &mut Evaluator, stmt: &Stmt) {
+        let Some(line) = self.tracker.statement(evaluator, stmt) else {
+            return;
+        };
+        let breakpoints = self.breakpoints.borrow();
+        let called = self.tracker.entered().is_some_and(|name| breakpoints.functions.contains(name));
+        let reason = if breakpoints.lines.contains(&line) || called {
+            Some("breakpoint")
+        } else {
+            self.tracker.stepped()
+        };
+        drop(breakpoints);
+        if let Some(reason) = reason {
+            self.stop(evaluator, reason);
+        }
+    }
+
+    fn call(&mut self, evaluator: &mut Evaluator, callee: &Expr, _: &Object, _: &[Object]) {
+        self.tracker.call(evaluator, callee);
+    }
+
+    fn returned(&mut self, _: &mut Evaluator, _: &Object) {
+        self.tracker.returned();
+    }
+}
+
+impl Console {
+    /// Reads commands until one resumes the program.
+    fn stop(&mut self, evaluator: &mut Evaluator, reason: &str) {
+        let frame = self.tracker.top();
+        let text = self.source.get(frame.line.wrapping_sub(1)).map_or("", String::as_str);
+        let _ = writeln!(self.output, "{} at line {} in {}\n{:>5} | {}", reason, frame.line, frame.name, frame.line, text);
+        loop {
+            let Some(line) = (self.input)("(debug) ") else {
+                return evaluator.interrupt_handle().interrupt();
+            };
+            let (command, argument) = line.trim().split_once(' ').unwrap_or((line.trim(), ""));
+            let step = match command {
+                "" => continue,
+                ":step" => Step::In,
+                ":next" => Step::Over,
+                ":continue" => Step::Continue,
+                ":quit" => return evaluator.interrupt_handle().interrupt(),
+                _ => {
+                    let answer = match command.starts_with(':') {
+                        true => self.answer(command, argument.trim()),
+                        false => self.evaluate(evaluator, &line),
+                    };
+                    let _ = writeln!(self.output, "{}", answer);
+                    continue;
+                }
+            };
+            return self.tracker.resume(step);
+        }
+    }
+
+    /// the answer to a command which does not resume the program
+    fn answer(&mut self, command: &str, argument: &str) -> String {
+        let mut breakpoints = self.breakpoints.borrow_mut();
+        let changed = match command {
+            ":break" if argument.is_empty() => Ok(()),
+            ":break" => breakpoints.set(argument),
+            ":delete" => breakpoints.delete(argument),
+            ":locals" => {
+                let env = self.tracker.top().env.borrow();
+                let locals = env.bindings().map(|(name, object)| format!("{} = {}", name, show(object)));
+                let locals = locals.collect::<Vec<_>>();
+                return match locals.is_empty() {
+                    true => "no locals".to_string(),
+                    false => locals.join("\n"),
+                };
+            }
+            ":bt" => {
+                let frames = self.tracker.frames().iter().rev().enumerate();
+                let frames = frames.map(|(i, frame)| format!("#{} {} at line {}", i, frame.name, frame.line));
+                return frames.collect::<Vec<_>>().join("\n");
+            }
+            ":help" => return HELP.to_string(),
+            command => return format!("unknown command `{}`, see :help", command),
+        };
+        changed.map_or_else(|err| err, |_| breakpoints.to_string())
+    }
+
+    /// the value of an expression in the scope of the call running
+    fn evaluate(&mut self, evaluator: &mut Evaluator, line: &str) -> String {
+        let env = Rc::clone(&self.tracker.top().env);
+        match parse_expr(line) {
+            Ok(expr) => show(&evaluator.eval_in(&env, expr)),
+            Err(err) => err,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+    use std::collections::VecDeque;
+
+    /// what the console writes, kept to be read once it is done
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Runs a program with breakpoints, giving its result and the session, the commands
+    /// written after their prompts.
+    fn session(source: &str, breakpoints: &[&str], commands: &[&str]) -> (Object, String) {
+        let output = Shared::default();
+        let mut commands = commands.iter().map(|c| c.to_string()).collect::<VecDeque<_>>();
+        let mut echo = output.clone();
+        let input = Box::new(move |prompt: &str| {
+            let command = commands.pop_front()?;
+            writeln!(echo, "{}{}", prompt, command).unwrap();
+            Some(command)
+        });
+        let mut set = Breakpoints::default();
+        for at in breakpoints {
+            set.set(at).unwrap();
+        }
+        let mut evaluator = Evaluator::new();
+        let program = parse(source).unwrap();
+        let result = run(&mut evaluator, source, program, Rc::new(RefCell::new(set)), input, Box::new(output.clone()));
+        let transcript = String::from_utf8(output.0.borrow().clone()).unwrap();
+        (result, transcript)
+    }
+
+    const PROGRAM: &str = "let double = fn(x) {
+  let y = x * 2;
+  y
+};
+let total = double(3) + double(4);
+total";
+
+    #[test]
+    fn breakpoints() {
+        let commands = [":bt", ":locals", "x * 10", ":break 3x", ":frob", ":next", ":delete double", ":continue"];
+        let (result, transcript) = session(PROGRAM, &["double", "9"], &commands);
+        assert_eq!(result, Object::Integer(14));
+        assert_eq!(
+            transcript,
+            "no statement starts on line 9
+breakpoint at line 2 in double
+    2 |   let y = x * 2;
+(debug) :bt
+#0 double at line 2
+#1 main at line 5
+(debug) :locals
+x = 3
+(debug) x * 10
+30
+(debug) :break 3x
+`3x` is neither a line nor the name of a function
+(debug) :frob
+unknown command `:frob`, see :help
+(debug) :next
+step at line 3 in double
+    3 |   y
+(debug) :delete double
+line 9
+(debug) :continue
+"
+        );
+    }
+
+    #[test]
+    fn stepping() {
+        let commands = [":step", ":step", ":break 6", ":continue", "total", ":continue"];
+        let (result, transcript) = session(PROGRAM, &["5"], &commands);
+        assert_eq!(result, Object::Integer(14));
+        let stops = transcript.lines().filter(|line| line.contains(" at line ")).collect::<Vec<_>>();
+        assert_eq!(
+            stops,
+            vec!["breakpoint at line 5 in main", "step at line 2 in double", "step at line 3 in double", "breakpoint at line 6 in main"]
+        );
+        assert!(transcript.ends_with("(debug) total\n14\n(debug) :continue\n"));
+
+        // running out of input interrupts the program
+        let (result, _) = session(PROGRAM, &["2"], &[]);
+        assert!(matches!(result, Object::Error(_)));
+    }
+}
@@ -0,0 +1,405 @@
+//! The statements, `if` branches and functions of the files a program runs which ran, written
+//! as lcov tracefiles for coverage viewers.
+//!
+//! A line counts the runs of the statement starting on it which ran the most. The branches of
+//! an `if` are its consequence, taken when the condition is true, and its alternative, whether
+//! written or not, taken when it is false.
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Formatter};
+use std::rc::Rc;
+
+use crate::evaluator::object::Object;
+use crate::evaluator::observer::EvalObserver;
+use crate::evaluator::Evaluator;
+use crate::linter::spans::Locations;
+use crate::linter::Lines;
+use crate::parser::ast::{Expr, Ident, Program, Stmt};
+
+#[derive(Debug, Clone, PartialEq)]
+struct Branch {
+    line: usize,
+    /// how many times the condition was evaluated
+    evaluated: usize,
+    /// how many times each branch was taken
+    taken: [usize; 2],
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Function {
+    name: String,
+    line: usize,
+    calls: usize,
+}
+
+/// what ran of a file
+#[derive(Debug, Clone, PartialEq)]
+pub struct File {
+    pub path: String,
+    /// the line of each statement, in the order of the source, and how many times it ran
+    stmts: Vec<(usize, usize)>,
+    branches: Vec<Branch>,
+    functions: Vec<Function>,
+}
+
+impl File {
+    /// how many times each line where a statement starts ran
+    pub fn lines(&self) -> BTreeMap<usize, usize> {
+        let mut lines = BTreeMap::new();
+        for &(line, hits) in &self.stmts {
+            let most = lines.entry(line).or_insert(0);
+            *most = hits.max(*most);
+        }
+        lines
+    }
+
+    /// the lines which ran, and those there are
+    pub fn line_counts(&self) -> (usize, usize) {
+        let lines = self.lines();
+        (lines.values().filter(|&&hits| hits > 0).count(), lines.len())
+    }
+
+    /// the branches taken, and those there are
+    pub fn branch_counts(&self) -> (usize, usize) {
+        let taken = self.branches.iter().flat_map(|b| b.taken).filter(|&taken| taken > 0).count();
+        (taken, self.branches.len() * 2)
+    }
+
+    /// the functions called, and those there are
+    pub fn function_counts(&self) -> (usize, usize) {
+        (self.functions.iter().filter(|f| f.calls > 0).count(), self.functions.len())
+    }
+}
+
+#[derive(Default)]
+struct State {
+    files: Vec<File>,
+    /// the file and the index in it of each statement
+    stmts: HashMap<*const Stmt, (usize, usize)>,
+    /// of the condition of each `if`
+    conds: HashMap<*const Expr, (usize, usize)>,
+    /// of the body of each function
+    bodies: HashMap<*const Program, (usize, usize)>,
+}
+
+/// Follows the programs run, sharing what it records with its clones.
+#[derive(Clone, Default)]
+pub struct Coverage(Rc<RefCell<State>>);
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Follows a program parsed from the source of a file, about to run. The runs of the same
+    /// file add up.
+    pub fn add(&self, path: &str, source: &str, program: &Program) {
+        let lines = Lines::new(source);
+        let locations = Locations::new(source, program);
+        let line = |start: usize| lines.position(start).line;
+
+        let mut stmts = locations.stmts().map(|(stmt, range)| (range.start, range.end, stmt)).collect::<Vec<_>>();
+        stmts.sort();
+        let mut ifs = vec![];
+        let mut functions = vec![];
+        program.iter().for_each(|stmt| walk_stmt(stmt, &mut ifs, &mut functions));
+
+        let file = File {
+            path: path.to_string(),
+            stmts: stmts.iter().map(|&(start, ..)| (line(start), 0)).collect(),
+            branches: ifs
+                .iter()
+                .map(|&(expr, _)| Branch { line: line(locations.expr(expr).start), evaluated: 0, taken: [0, 0] })
+                .collect(),
+            functions: functions
+                .iter()
+                .map(|&(expr, name, _)| {
+                    let line = line(locations.expr(expr).start);
+                    let name = name.map_or_else(|| format!("fn at line {}", line), |ident: &Ident| ident.0.clone());
+                    Function { name, line, calls: 0 }
+                })
+                .collect(),
+        };
+
+        let mut state = self.0.borrow_mut();
+        let shape = |f: &File| (f.stmts.len(), f.branches.len(), f.functions.len());
+        let index = match state.files.iter().position(|f| f.path == file.path && shape(f) == shape(&file)) {
+            Some(index) => index,
+            None => {
+                state.files.push(file);
+                state.files.len() - 1
+            }
+        };
+        // the nodes of programs run before may have had the same addresses
+        for (i, &(_, _, stmt)) in stmts.iter().enumerate() {
+            state.stmts.insert(stmt, (index, i));
+        }
+        for (i, &(_, cond)) in ifs.iter().enumerate() {
+            state.conds.insert(cond, (index, i));
+        }
+        for (i, &(_, _, body)) in functions.iter().enumerate() {
+            state.bodies.insert(body, (index, i));
+        }
+    }
+
+    /// what ran of the files, in the order they were added
+    pub fn report(&self) -> Report {
+        Report { files: self.0.borrow().files.clone() }
+    }
+}
+
+/// the `if` expressions with their conditions, and the functions with the name bound to them
+/// and their bodies
+type Ifs<'a> = Vec<(&'a Expr, *const Expr)>;
+type Functions<'a> = Vec<(&'a Expr, Option<&'a Ident>, *const Program)>;
+
+fn walk_stmt<'a>(stmt: &'a Stmt, ifs: &mut Ifs<'a>, functions: &mut Functions<'a>) {
+    match stmt {
+        Stmt::LetStmt(ident, _, expr) => walk_expr(expr, Some(ident), ifs, functions),
+        Stmt::ReturnStmt(expr) | Stmt::ExprStmt(expr) => walk_expr(expr, None, ifs, functions),
+        Stmt::ImportStmt(..) | Stmt::ExportStmt(_) => {}
+    }
+}
+
+fn walk_expr<'a>(expr: &'a Expr, name: Option<&'a Ident>, ifs: &mut Ifs<'a>, functions: &mut Functions<'a>) {
+    let mut walk = |expr| walk_expr(expr, None, ifs, functions);
+    match expr {
+        Expr::IdentExpr(_) | Expr::LiteralExpr(_) => {}
+        Expr::PrefixExpr(_, expr) | Expr::MemberExpr { module: expr, .. } => walk(expr),
+        Expr::InfixExpr(_, left, right) => {
+            walk(left);
+            walk(right);
+        }
+        Expr::IfExpr { cond, consequence, alternative } => {
+            ifs.push((expr, &**cond as *const Expr));
+            walk_expr(cond, None, ifs, functions);
+            for stmt in consequence.iter().chain(alternative.iter().flatten()) {
+                walk_stmt(stmt, ifs, functions);
+            }
+        }
+        Expr::FnExpr { body, .. } => {
+            functions.push((expr, name, Rc::as_ptr(body)));
+            body.iter().for_each(|stmt| walk_stmt(stmt, ifs, functions));
+        }
+        Expr::CallExpr { function, arguments } => {
+            walk(function);
+            arguments.iter().for_each(walk);
+        }
+        Expr::ArrayExpr(items) => items.iter().for_each(walk),
+        Expr::HashExpr(pairs) => pairs.iter().for_each(|(_, value)| walk(value)),
+        Expr::IndexExpr { array, index } => {
+            walk(array);
+            walk(index);
+        }
+    }
+}
+
+impl EvalObserver for Coverage {
+    fn statement(&mut self, _: &mut Evaluator, stmt: &Stmt) {
+        let mut state = self.0.borrow_mut();
+        if let Some(&(file, i)) = state.stmts.get(&(stmt as *const Stmt)) {
+            state.files[file].stmts[i].1 += 1;
+        }
+    }
+
+    fn expression(&mut self, _: &mut Evaluator, expr: &Expr, value: &Object) {
+        let mut state = self.0.borrow_mut();
+        if let Some(&(file, i)) = state.conds.get(&(expr as *const Expr)) {
+            let branch = &mut state.files[file].branches[i];
+            branch.evaluated += 1;
+            match value {
+                Object::Boolean(true) => branch.taken[0] += 1,
+                Object::Boolean(false) => branch.taken[1] += 1,
+                _ => {}
+            }
+        }
+    }
+
+    fn call(&mut self, _: &mut Evaluator, _: &Expr, function: &Object, _: &[Object]) {
+        let mut state = self.0.borrow_mut();
+        if let Object::Function(_, _, body, _) = function {
+            if let Some(&(file, i)) = state.bodies.get(&Rc::as_ptr(body)) {
+                state.files[file].functions[i].calls += 1;
+            }
+        }
+    }
+}
+
+/// What ran of the files of the programs run.
+pub struct Report {
+    pub files: Vec<File>,
+}
+
+impl Report {
+    /// the lcov tracefile of the files
+    pub fn lcov(&self) -> String {
+        let mut out = String::new();
+        for file in &self.files {
+            out += &format!("TN:\nSF:{}\n", file.path);
+            for function in &file.functions {
+                out += &format!("FN:{},{}\n", function.line, function.name);
+            }
+            for function in &file.functions {
+                out += &format!("FNDA:{},{}\n", function.calls, function.name);
+            }
+            let (hit, found) = file.function_counts();
+            out += &format!("FNF:{}\nFNH:{}\n", found, hit);
+            for (block, branch) in file.branches.iter().enumerate() {
+                for (i, taken) in branch.taken.iter().enumerate() {
+                    // a branch whose condition was never evaluated is not counted
+                    let taken = if branch.evaluated == 0 { "-".to_string() } else { taken.to_string() };
+                    out += &format!("BRDA:{},{},{},{}\n", branch.line, block, i, taken);
+                }
+            }
+            let (hit, found) = file.branch_counts();
+            out += &format!("BRF:{}\nBRH:{}\n", found, hit);
+            for (line, hits) in file.lines() {
+                out += &format!("DA:{},{}\n", line, hits);
+            }
+            let (hit, found) = file.line_counts();
+            out += &format!("LF:{}\nLH:{}\nend_of_record\n", found, hit);
+        }
+        out
+    }
+}
+
+/// how many of a kind of thing ran
+fn ratio(f: &mut Formatter<'_>, kind: &str, (hit, found): (usize, usize)) -> fmt::Result {
+    match found {
+        0 => write!(f, "{} -", kind),
+        _ => write!(f, "{} {:.1}% ({}/{})", kind, hit as f64 * 100.0 / found as f64, hit, found),
+    }
+}
+
+/// The coverage of each file, with the lines which did not run, then of all the files.
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let sum = |counts: &dyn Fn(&File) -> (usize, usize)| {
+            self.files.iter().map(counts).fold((0, 0), |(h, t), (hit, found)| (h + hit, t + found))
+        };
+        let mut rows = self
+            .files
+            .iter()
+            .map(|file| (file.path.as_str(), file.line_counts(), file.branch_counts(), file.function_counts(), Some(file)))
+            .collect::<Vec<_>>();
+        if self.files.len() > 1 {
+            rows.push(("total", sum(&File::line_counts), sum(&File::branch_counts), sum(&File::function_counts), None));
+        }
+        for (name, lines, branches, functions, file) in rows {
+            write!(f, "{}: ", name)?;
+            ratio(f, "lines", lines)?;
+            write!(f, ", ")?;
+            ratio(f, "branches", branches)?;
+            write!(f, ", ")?;
+            ratio(f, "functions", functions)?;
+            writeln!(f)?;
+            let missed = file.map(|file| missed(&file.lines())).unwrap_or_default();
+            if !missed.is_empty() {
+                writeln!(f, "  not run: {}", missed)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// the lines which did not run, those in a row as a range
+fn missed(lines: &BTreeMap<usize, usize>) -> String {
+    let mut ranges: Vec<(usize, usize)> = vec![];
+    let mut previous = None;
+    for (&line, &hits) in lines {
+        if hits == 0 {
+            match ranges.last_mut() {
+                // no line where a statement starts between them ran
+                Some(range) if previous == Some(range.1) => range.1 = line,
+                _ => ranges.push((line, line)),
+            }
+        }
+        previous = Some(line);
+    }
+    let ranges = ranges.into_iter().map(|(first, last)| match first == last {
+        true => first.to_string(),
+        false => format!("{}-{}", first, last),
+    });
+    ranges.collect::<Vec<_>>().join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    const SOURCE: &str = "let sign = fn(n) {
+  if (n < 0) {
+    return -1;
+  }
+  if (n == 0) { 0 } else { 1 }
+};
+let unused = fn() {
+  1
+};
+sign(2) + sign(-3)";
+
+    #[test]
+    fn lcov() {
+        let coverage = Coverage::new();
+        let mut evaluator = Evaluator::new();
+        evaluator.set_observer(Some(Box::new(coverage.clone())));
+        let program = parse(SOURCE).unwrap();
+        coverage.add("sign.mk", SOURCE, &program);
+        assert_eq!(evaluator.eval_program(program), Object::Integer(0));
+        let report = coverage.report();
+        assert_eq!(
+            report.lcov(),
+            "TN:
+SF:sign.mk
+FN:1,sign
+FN:7,unused
+FNDA:2,sign
+FNDA:0,unused
+FNF:2
+FNH:1
+BRDA:2,0,0,1
+BRDA:2,0,1,1
+BRDA:5,1,0,0
+BRDA:5,1,1,1
+BRF:4
+BRH:3
+DA:1,1
+DA:2,2
+DA:3,1
+DA:5,1
+DA:7,1
+DA:8,0
+DA:10,1
+LF:7
+LH:6
+end_of_record
+"
+        );
+        assert_eq!(
+            report.to_string(),
+            "sign.mk: lines 85.7% (6/7), branches 75.0% (3/4), functions 50.0% (1/2)\n  not run: 8\n"
+        );
+
+        // running the file again adds up, another file comes after it
+        let program = parse(SOURCE).unwrap();
+        coverage.add("sign.mk", SOURCE, &program);
+        evaluator.eval_program(program);
+        let other = parse("let f = fn() { 1 };\n2").unwrap();
+        coverage.add("other.mk", "let f = fn() { 1 };\n2", &other);
+        evaluator.eval_program(other);
+        let report = coverage.report();
+        assert_eq!(report.files.len(), 2);
+        assert_eq!(report.files[0].lines()[&2], 4);
+        assert_eq!(
+            report.to_string(),
+            "sign.mk: lines 85.7% (6/7), branches 75.0% (3/4), functions 50.0% (1/2)
+  not run: 8
+other.mk: lines 100.0% (2/2), branches -, functions 0.0% (0/1)
+total: lines 88.9% (8/9), branches 75.0% (3/4), functions 33.3% (1/3)
+"
+        );
+        assert_eq!(missed(&BTreeMap::from([(1, 0), (2, 0), (4, 1), (5, 0), (7, 0)])), "1-2, 5-7");
+    }
+}
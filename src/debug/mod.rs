@@ -0,0 +1,155 @@
+//! Following a program the evaluator runs, for the tools observing it.
+//!
+//! For the debuggers stopping it, a line is one stop: stepping from a statement goes on to the next one starting on another
+//! line, or in another call, such as each call of a function written on one line.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::evaluator::environment::Environment;
+use crate::evaluator::Evaluator;
+use crate::linter::spans::Locations;
+use crate::linter::Lines;
+use crate::parser::ast::{Expr, Program, Stmt};
+use crate::parser::parse;
+
+pub mod console;
+pub mod coverage;
+pub mod profile;
+pub mod trace;
+
+/// a call of a function, or the program
+pub struct Frame {
+    pub name: String,
+    /// the line of the statement running
+    pub line: usize,
+    pub env: Rc<RefCell<Environment>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Step {
+    /// until a breakpoint
+    Continue,
+    /// to the first statement
+    Entry,
+    /// to the next line, in this call or another
+    In,
+    /// to the next line of this call, or of a caller once it returns
+    Over,
+    /// to the caller
+    Out,
+}
+
+/// The calls of a running program and the lines they are at.
+pub struct Tracker {
+    /// the line each statement starts on
+    lines: HashMap<*const Stmt, usize>,
+    /// the calls running, the program first
+    frames: Vec<Frame>,
+    step: Step,
+    /// the depth and line the step is from
+    from: (usize, usize),
+    /// the depth and line of the statement before
+    last: Option<(usize, usize)>,
+    /// whether the call on top has not run a statement yet, then whether the statement is its
+    /// first
+    entered: bool,
+    first: bool,
+}
+
+impl Tracker {
+    /// Follows `program`, parsed from `source`, which `evaluator` is about to run.
+    pub fn new(source: &str, program: &Program, evaluator: &Evaluator, step: Step) -> Self {
+        Tracker {
+            lines: lines(source, program),
+            frames: vec![Frame { name: "main".to_string(), line: 1, env: evaluator.environment() }],
+            step,
+            from: (0, 0),
+            last: None,
+            entered: false,
+            first: false,
+        }
+    }
+
+    /// the calls running, the program first
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    /// the call running
+    pub fn top(&self) -> &Frame {
+        self.frames.last().expect("no frame")
+    }
+
+    /// Follows the program to a statement about to run, giving its line unless the one before
+    /// was on the same line of the same call.
+    pub fn statement(&mut self, evaluator: &Evaluator, stmt: &Stmt) -> Option<usize> {
+        let line = self.lines.get(&(stmt as *const Stmt)).copied().unwrap_or_default();
+        let frame = self.frames.last_mut().expect("no frame");
+        frame.line = line;
+        frame.env = evaluator.environment();
+        self.first = std::mem::take(&mut self.entered);
+        let here = (self.frames.len(), line);
+        (self.last.replace(here) != Some(here)).then_some(line)
+    }
+
+    /// the function called, when the statement is the first it runs
+    pub fn entered(&self) -> Option<&str> {
+        self.first.then(|| self.top().name.as_str())
+    }
+
+    /// why the program stops at the statement after the step it was resumed with, if it does
+    pub fn stepped(&self) -> Option<&'static str> {
+        let (depth, here) = (self.frames.len(), self.last.unwrap_or_default());
+        match self.step {
+            Step::Continue => None,
+            Step::Entry => Some("entry"),
+            Step::In => Some("step"),
+            Step::Over => (depth <= self.from.0 && here != self.from).then_some("step"),
+            Step::Out => (depth < self.from.0).then_some("step"),
+        }
+    }
+
+    /// Resumes the program stopped at the statement, to stop again after a step.
+    pub fn resume(&mut self, step: Step) {
+        self.step = step;
+        self.from = self.last.unwrap_or_default();
+    }
+
+    pub fn call(&mut self, evaluator: &Evaluator, callee: &Expr) {
+        let line = self.top().line;
+        self.frames.push(Frame { name: name(callee), line, env: evaluator.environment() });
+        self.entered = true;
+    }
+
+    pub fn returned(&mut self) {
+        self.frames.pop();
+        self.entered = false;
+    }
+}
+
+/// the name a function is called by
+pub fn name(callee: &Expr) -> String {
+    match callee {
+        Expr::IdentExpr(ident) => ident.0.clone(),
+        Expr::FnExpr { .. } => "fn".to_string(),
+        callee => callee.to_string(),
+    }
+}
+
+/// the line each statement of a program starts on
+pub fn lines(source: &str, program: &Program) -> HashMap<*const Stmt, usize> {
+    let lines = Lines::new(source);
+    let locations = Locations::new(source, program);
+    locations.stmts().map(|(stmt, range)| (stmt, lines.position(range.start).line)).collect()
+}
+
+/// an expression alone
+pub fn parse_expr(source: &str) -> Result<Expr, String> {
+    let mut program = parse(source)?;
+    match (program.pop(), program.is_empty()) {
+        (Some(Stmt::ExprStmt(expr)), true) => Ok(expr),
+        _ => Err("not an expression".to_string()),
+    }
+}
@@ -0,0 +1,221 @@
+//! The time spent in each function of a program, and in each builtin it calls.
+//!
+//! A function of the program is named by the `let` binding it is first bound by, or as it is
+//! called when it has none. The time of a call counts as the inclusive time of its function
+//! unless a call of the same function is already running, so that recursion does not count
+//! it twice, and as its exclusive time without the calls it makes.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::{self, Formatter};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crate::debug::name;
+use crate::evaluator::object::Object;
+use crate::evaluator::observer::EvalObserver;
+use crate::evaluator::Evaluator;
+use crate::parser::ast::{Expr, Ident, Program};
+
+/// what is known of the calls of a function
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stats {
+    pub name: String,
+    pub builtin: bool,
+    pub calls: usize,
+    pub inclusive: Duration,
+    pub exclusive: Duration,
+}
+
+struct Frame {
+    name: String,
+    start: Instant,
+    /// the time of the calls it made
+    children: Duration,
+}
+
+#[derive(Default)]
+struct State {
+    /// the program, then the calls running
+    frames: Vec<Frame>,
+    stats: HashMap<String, Stats>,
+    /// the exclusive time of each stack of names
+    stacks: HashMap<Vec<String>, Duration>,
+    /// the names of the functions, by their body
+    names: HashMap<*const Program, String>,
+    /// the parameters of the call made last which are not bound yet
+    params: usize,
+}
+
+/// Follows a program, sharing what it records with its clones.
+#[derive(Clone)]
+pub struct Profiler(Rc<RefCell<State>>);
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Profiler {
+    /// Starts profiling a program about to run.
+    pub fn new() -> Self {
+        let mut state = State::default();
+        state.frames.push(Frame { name: "main".to_string(), start: Instant::now(), children: Duration::ZERO });
+        Profiler(Rc::new(RefCell::new(state)))
+    }
+
+    /// the profile of the program, once it ran
+    pub fn finish(&self) -> Profile {
+        let state = self.0.borrow();
+        let main = &state.frames[0];
+        let total = main.start.elapsed();
+        let mut stacks = state.stacks.clone();
+        *stacks.entry(vec![main.name.clone()]).or_default() += total.saturating_sub(main.children);
+        let mut functions = state.stats.values().cloned().collect::<Vec<_>>();
+        functions.sort_by(|a, b| b.exclusive.cmp(&a.exclusive).then_with(|| a.name.cmp(&b.name)));
+        Profile { total, functions, stacks }
+    }
+}
+
+impl EvalObserver for Profiler {
+    fn call(&mut self, _: &mut Evaluator, callee: &Expr, function: &Object, args: &[Object]) {
+        let mut state = self.0.borrow_mut();
+        let (name, builtin) = match function {
+            Object::Builtin(name, ..) => (name.clone(), true),
+            Object::Function(_, _, body, _) => {
+                state.params = args.len();
+                let name = state.names.get(&Rc::as_ptr(body)).cloned();
+                (name.unwrap_or_else(|| self::name(callee)), false)
+            }
+            _ => (self::name(callee), false),
+        };
+        state.stats.entry(name.clone()).or_insert_with(|| Stats {
+            name: name.clone(),
+            builtin,
+            calls: 0,
+            inclusive: Duration::ZERO,
+            exclusive: Duration::ZERO,
+        });
+        state.frames.push(Frame { name, start: Instant::now(), children: Duration::ZERO });
+    }
+
+    fn returned(&mut self, _: &mut Evaluator, _: &Object) {
+        let mut state = self.0.borrow_mut();
+        let frame = state.frames.pop().expect("no call");
+        let elapsed = frame.start.elapsed();
+        let exclusive = elapsed.saturating_sub(frame.children);
+        let outermost = !state.frames.iter().any(|f| f.name == frame.name);
+        let mut stack = state.frames.iter().map(|f| f.name.clone()).collect::<Vec<_>>();
+        if let Some(caller) = state.frames.last_mut() {
+            caller.children += elapsed;
+        }
+        let stats = state.stats.get_mut(&frame.name).expect("a call without stats");
+        stats.calls += 1;
+        stats.exclusive += exclusive;
+        if outermost {
+            stats.inclusive += elapsed;
+        }
+        stack.push(frame.name);
+        *state.stacks.entry(stack).or_default() += exclusive;
+    }
+
+    fn bound(&mut self, _: &mut Evaluator, ident: &Ident, value: &Object) {
+        let mut state = self.0.borrow_mut();
+        if state.params > 0 {
+            state.params -= 1;
+        } else if let Object::Function(_, _, body, _) = value {
+            state.names.entry(Rc::as_ptr(body)).or_insert_with(|| ident.0.clone());
+        }
+    }
+}
+
+/// What a program spent its time on.
+pub struct Profile {
+    pub total: Duration,
+    /// the functions called, those with the longest exclusive time first
+    pub functions: Vec<Stats>,
+    stacks: HashMap<Vec<String>, Duration>,
+}
+
+impl Profile {
+    /// The exclusive time of each stack of calls in microseconds, a line each, the names of
+    /// the calls separated by `;` from the program's on, as flame graph tools read them.
+    pub fn folded(&self) -> String {
+        let mut stacks = self.stacks.iter().map(|(stack, time)| (stack.join(";"), time.as_micros())).collect::<Vec<_>>();
+        stacks.sort();
+        stacks.into_iter().map(|(stack, time)| format!("{} {}\n", stack, time)).collect()
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let names = self
+            .functions
+            .iter()
+            .map(|stats| match stats.builtin {
+                true => format!("{} (builtin)", stats.name),
+                false => stats.name.clone(),
+            })
+            .collect::<Vec<_>>();
+        let width = names.iter().map(String::len).chain(["function".len()]).max().unwrap_or_default();
+        let ms = |time: Duration| time.as_secs_f64() * 1000.0;
+        writeln!(f, "the program ran for {:.3} ms", ms(self.total))?;
+        writeln!(f, "{:<width$} {:>8} {:>12} {:>12}", "function", "calls", "total (ms)", "self (ms)")?;
+        for (name, stats) in names.iter().zip(&self.functions) {
+            writeln!(f, "{:<width$} {:>8} {:>12.3} {:>12.3}", name, stats.calls, ms(stats.inclusive), ms(stats.exclusive))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    #[test]
+    fn calls() {
+        let profiler = Profiler::new();
+        let mut evaluator = Evaluator::new();
+        evaluator.set_observer(Some(Box::new(profiler.clone())));
+        let source = "let fib = fn(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } };
+            let twice = fn(f, x) { f(f(x)) };
+            let size = len;
+            [twice(fib, 4), size(\"ab\"), fn(x) { x }(1)]";
+        assert_eq!(evaluator.eval_program(parse(source).unwrap()).to_string(), "[2, 2, 1]");
+        let profile = profiler.finish();
+
+        let mut calls = profile.functions.iter().map(|s| (s.name.as_str(), s.calls, s.builtin)).collect::<Vec<_>>();
+        calls.sort();
+        assert_eq!(calls, vec![("fib", 14, false), ("fn", 1, false), ("len", 1, true), ("twice", 1, false)]);
+        for stats in &profile.functions {
+            assert!(stats.exclusive <= stats.inclusive, "{:?}", stats);
+        }
+        assert!(profile.functions.windows(2).all(|w| w[0].exclusive >= w[1].exclusive));
+        let twice = profile.functions.iter().find(|s| s.name == "twice").unwrap();
+        let fib = profile.functions.iter().find(|s| s.name == "fib").unwrap();
+        assert!(twice.inclusive >= fib.inclusive);
+        assert!(profile.total >= twice.inclusive);
+
+        let folded = profile.folded();
+        let stacks = folded.lines().map(|line| line.rsplit_once(' ').unwrap().0).collect::<Vec<_>>();
+        assert_eq!(
+            stacks,
+            vec![
+                "main",
+                "main;fn",
+                "main;len",
+                "main;twice",
+                "main;twice;fib",
+                "main;twice;fib;fib",
+                "main;twice;fib;fib;fib",
+                "main;twice;fib;fib;fib;fib",
+            ]
+        );
+        let table = profile.to_string();
+        assert!(table.starts_with("the program ran for "));
+        assert_eq!(table.lines().nth(1).unwrap(), "function         calls   total (ms)    self (ms)");
+        assert!(table.contains("\nlen (builtin) "));
+    }
+}
@@ -0,0 +1,98 @@
+//! The calls of a program as it runs, each call indented under the one it is made in.
+
+use std::io::Write;
+
+use crate::debug::name;
+use crate::evaluator::object::{show, Object};
+use crate::evaluator::observer::EvalObserver;
+use crate::evaluator::Evaluator;
+use crate::parser::ast::Expr;
+
+/// Writes a line for each call, with its arguments, one for the value it returns, and one for
+/// each error arising meanwhile.
+pub struct Tracer {
+    output: Box<dyn Write>,
+    /// the names of the calls running
+    calls: Vec<String>,
+}
+
+impl Tracer {
+    pub fn new(output: Box<dyn Write>) -> Self {
+        Tracer { output, calls: vec![] }
+    }
+
+    fn line(&mut self, line: String) {
+        let indent = "  ".repeat(self.calls.len());
+        // a trace cut short is still a trace
+        let _ = writeln!(self.output, "{}{}", indent, line);
+    }
+}
+
+impl EvalObserver for Tracer {
+    fn call(&mut self, _: &mut Evaluator, callee: &Expr, _: &Object, args: &[Object]) {
+        let name = name(callee);
+        let args = args.iter().map(show).collect::<Vec<_>>();
+        self.line(format!("{}({})", name, args.join(", ")));
+        self.calls.push(name);
+    }
+
+    fn returned(&mut self, _: &mut Evaluator, value: &Object) {
+        let name = self.calls.pop().unwrap_or_default();
+        self.line(format!("{} = {}", name, show(value)));
+    }
+
+    fn error(&mut self, _: &mut Evaluator, message: &str) {
+        self.line(format!("error: {}", message));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn calls() {
+        let output = Shared::default();
+        let mut evaluator = Evaluator::new();
+        evaluator.set_observer(Some(Box::new(Tracer::new(Box::new(output.clone())))));
+        let source = "let fib = fn(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } };
+            let f = fn(s) { [s + 1] };
+            [fib(2), len(f(\"a\")), fn(x) { x }(true)]";
+        let result = evaluator.eval_program(parse(source).unwrap());
+        assert_eq!(result.to_string(), "[1, 1, true]");
+        assert_eq!(
+            String::from_utf8(output.0.borrow().clone()).unwrap(),
+            "fib(2)
+  fib(1)
+  fib = 1
+  fib(0)
+  fib = 0
+fib = 1
+f(\"a\")
+  error: a and 1 are not addable
+f = [Error: a and 1 are not addable]
+len([Error: a and 1 are not addable])
+len = 1
+fn(true)
+fn = true
+"
+        );
+    }
+}
@@ -0,0 +1,63 @@
+use std::str::FromStr;
+
+use crate::compiler::Compiler;
+use crate::evaluator::object::Object;
+use crate::evaluator::{Evaluator, InterruptHandle};
+use crate::parser::ast::Program;
+use crate::vm::Vm;
+
+/// The ways a program can be run, which give the same results.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// walks the syntax tree
+    Eval,
+    /// compiles to bytecode run by a stack machine
+    Vm,
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "eval" => Ok(Backend::Eval),
+            "vm" => Ok(Backend::Vm),
+            _ => Err(format!("unknown engine: {}", s)),
+        }
+    }
+}
+
+/// Runs programs on the selected backend, keeping the global bindings between them.
+pub enum Engine {
+    Eval(Evaluator),
+    Vm { compiler: Compiler, vm: Vm },
+}
+
+impl Engine {
+    pub fn new(backend: Backend) -> Self {
+        match backend {
+            Backend::Eval => Engine::Eval(Evaluator::new()),
+            Backend::Vm => Engine::Vm {
+                compiler: Compiler::new(),
+                vm: Vm::new(),
+            },
+        }
+    }
+
+    pub fn eval_program(&mut self, program: Program) -> Object {
+        match self {
+            Engine::Eval(evaluator) => evaluator.eval_program(program),
+            Engine::Vm { compiler, vm } => match compiler.compile(&program) {
+                Ok(bytecode) => vm.run(&bytecode),
+                Err(err) => Object::Error(err.to_string()),
+            },
+        }
+    }
+
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        match self {
+            Engine::Eval(evaluator) => evaluator.interrupt_handle(),
+            Engine::Vm { vm, .. } => vm.interrupt_handle(),
+        }
+    }
+}
@@ -1,6 +1,34 @@
-use crate::evaluator::object::{BuiltinFunction, Object};
+use std::cell::RefCell;
+
+use crate::evaluator::object::{show, BuiltinFunction, Object};
 use crate::parser::ast::Ident;
 
+/// a receiver of the lines `print` writes
+pub type Output = Box<dyn FnMut(&str)>;
+
+thread_local! {
+    /// where `print` writes the lines of the evaluations of this thread, if not to stdout
+    static OUTPUT: RefCell<Option<Output>> = RefCell::new(None);
+}
+
+/// Sends the lines `print` writes on this thread to `output` rather than to stdout.
+pub fn redirect_print(output: Option<Output>) {
+    OUTPUT.with(|o| *o.borrow_mut() = output);
+}
+
+fn print_line(line: &str) {
+    OUTPUT.with(|output| match output.borrow_mut().as_mut() {
+        Some(output) => output(line),
+        None => println!("{}", line),
+    })
+}
+
+/// how the errors of failed assertions start
+pub const ASSERTION_FAILED: &str = "assertion failed";
+
+/// the most differences between two values an assertion lists
+const DIFFERENCES: usize = 5;
+
 pub struct BuiltinFunctions;
 
 impl BuiltinFunctions {
@@ -15,8 +43,15 @@ impl BuiltinFunctions {
             add_builtin("head", 1, bhead_fn),
             add_builtin("tail", 1, btail_fn),
             add_builtin("cons", 2, bcons_fn),
+            add_builtin("assert", 1, bassert_fn),
+            add_builtin("assert_eq", 2, bassert_eq_fn),
+            add_builtin("assert_error", 1, bassert_error_fn),
         ]
     }
+
+    pub fn names(&self) -> Vec<String> {
+        self.get_builtins().into_iter().map(|(Ident(name), _)| name).collect()
+    }
 }
 
 impl Default for BuiltinFunctions {
@@ -31,13 +66,13 @@ fn add_builtin(name: &str, param_num: usize, func: BuiltinFunction) -> (Ident, O
 }
 
 fn bprint_fn(args: Vec<Object>) -> Result<Object, String> {
-    match args.get(0) {
+    match args.first() {
         Some(Object::String(s)) => {
-            println!("{}", s);
+            print_line(s);
             Ok(Object::Null)
         },
         Some(o) => {
-            println!("{}", o);
+            print_line(&o.to_string());
             Ok(Object::Null)
         }
         _ => Err(String::from("invalid arguments for print")),
@@ -45,7 +80,7 @@ fn bprint_fn(args: Vec<Object>) -> Result<Object, String> {
 }
 
 fn blen_fn(args: Vec<Object>) -> Result<Object, String> {
-    match args.get(0) {
+    match args.first() {
         Some(Object::String(s)) => Ok(Object::Integer(s.len() as i64)),
         Some(Object::Array(arr)) => Ok(Object::Integer(arr.len() as i64)),
         _ => Err(String::from("invalid arguments for len")),
@@ -83,4 +118,75 @@ fn bcons_fn(args: Vec<Object>) -> Result<Object, String> {
         }
         _ => Err(String::from("invalid arguments for cons")),
     }
-}
\ No newline at end of file
+}
+
+fn bassert_fn(args: Vec<Object>) -> Result<Object, String> {
+    match args.first() {
+        Some(Object::Boolean(true)) => Ok(Object::Null),
+        Some(Object::Boolean(false)) => Err(ASSERTION_FAILED.to_string()),
+        Some(o) => Err(format!("{}: {} is not a bool", ASSERTION_FAILED, show(o))),
+        _ => Err(String::from("invalid arguments for assert")),
+    }
+}
+
+fn bassert_eq_fn(args: Vec<Object>) -> Result<Object, String> {
+    let (left, right) = match args.as_slice() {
+        [left, right] => (left, right),
+        _ => return Err(String::from("invalid arguments for assert_eq")),
+    };
+    if left == right {
+        return Ok(Object::Null);
+    }
+    let mut message = format!("{}: the values differ\n  left:  {}\n  right: {}", ASSERTION_FAILED, show(left), show(right));
+    let mut differences = vec![];
+    differ("", left, right, &mut differences);
+    for difference in differences.iter().take(DIFFERENCES) {
+        message.push_str(&format!("\n  at {}", difference));
+    }
+    if differences.len() > DIFFERENCES {
+        message.push_str(&format!("\n  and {} more", differences.len() - DIFFERENCES));
+    }
+    Err(message)
+}
+
+fn bassert_error_fn(args: Vec<Object>) -> Result<Object, String> {
+    match args.first() {
+        Some(Object::Error(_)) => Ok(Object::Null),
+        Some(o) => Err(format!("{}: {} is not an error", ASSERTION_FAILED, show(o))),
+        _ => Err(String::from("invalid arguments for assert_error")),
+    }
+}
+
+/// Lists where the items of two values differ, each place as the indices and keys leading to
+/// it.
+#[allow(clippy::mutable_key_type)]
+fn differ(path: &str, left: &Object, right: &Object, differences: &mut Vec<String>) {
+    match (left, right) {
+        (Object::Array(ls), Object::Array(rs)) => {
+            for (i, (l, r)) in ls.iter().zip(rs).enumerate() {
+                differ(&format!("{}[{}]", path, i), l, r, differences);
+            }
+            for (i, l) in ls.iter().enumerate().skip(rs.len()) {
+                differences.push(format!("{}[{}]: {} only on the left", path, i, show(l)));
+            }
+            for (i, r) in rs.iter().enumerate().skip(ls.len()) {
+                differences.push(format!("{}[{}]: {} only on the right", path, i, show(r)));
+            }
+        }
+        (Object::Hash(ls), Object::Hash(rs)) => {
+            let mut keys = ls.keys().chain(rs.keys().filter(|k| !ls.contains_key(k))).collect::<Vec<_>>();
+            keys.sort_by_cached_key(|k| show(k));
+            for key in keys {
+                let at = format!("{}[{}]", path, show(key));
+                match (ls.get(key), rs.get(key)) {
+                    (Some(l), Some(r)) => differ(&at, l, r, differences),
+                    (Some(l), None) => differences.push(format!("{}: {} only on the left", at, show(l))),
+                    (None, Some(r)) => differences.push(format!("{}: {} only on the right", at, show(r))),
+                    (None, None) => {}
+                }
+            }
+        }
+        (l, r) if l != r && !path.is_empty() => differences.push(format!("{}: {} is not {}", path, show(l), show(r))),
+        _ => {}
+    }
+}
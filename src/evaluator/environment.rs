@@ -1,63 +1,115 @@
 use std::cell::RefCell;
-use std::collections::HashMap;
 use std::rc::Rc;
 use crate::evaluator::builtins::BuiltinFunctions;
-use crate::evaluator::object::{BuiltinFunction, Object};
+use crate::evaluator::object::Object;
 use crate::parser::ast::Ident;
 
-#[derive(Debug, Clone, PartialEq)]
+/// A scope whose variables are stored in slots assigned by the resolver.
+#[derive(Debug, Clone)]
 pub struct Environment {
-    store: HashMap<String, Object>,
+    names: Rc<Vec<String>>,
+    slots: Vec<Option<Object>>,
     parent: Option<Rc<RefCell<Environment>>>,
 }
 
 impl Environment {
     pub fn new() -> Self {
-        let mut hashmap = HashMap::new();
-        Self::fill_env_with_builtins(&mut hashmap);
         Environment {
-            store: hashmap,
+            names: Rc::new(vec![]),
+            slots: vec![],
             parent: None,
         }
     }
 
     pub fn new_with_outer(outer: Rc<RefCell<Environment>>) -> Self {
-        let mut hashmap = HashMap::new();
-        Self::fill_env_with_builtins(&mut hashmap);
         Environment {
-            store: hashmap,
+            names: Rc::new(vec![]),
+            slots: vec![],
             parent: Some(outer),
         }
     }
 
-    fn fill_env_with_builtins(hashmap: &mut HashMap<String, Object>) {
-        let builtins_functions = BuiltinFunctions::new();
-        let builtins = builtins_functions.get_builtins();
-        for (Ident(name), object) in builtins {
-            hashmap.insert(name, object);
+    /// an environment with one empty slot per name of the layout
+    pub fn with_layout(names: Rc<Vec<String>>, outer: Rc<RefCell<Environment>>) -> Self {
+        Environment {
+            slots: vec![None; names.len()],
+            names,
+            parent: Some(outer),
+        }
+    }
+
+    /// the outermost scope, holding the builtins shared by every other environment
+    pub fn prelude() -> Self {
+        let (names, slots) = BuiltinFunctions::new()
+            .get_builtins()
+            .into_iter()
+            .map(|(Ident(name), object)| (name, Some(object)))
+            .unzip();
+        Environment {
+            names: Rc::new(names),
+            slots,
+            parent: None,
         }
     }
-    
-    pub fn set(&mut self, name: &str, val: Object) {
-        self.store.insert(name.to_string(), val);
-    }
-    
-    pub fn get(&self, name: &str) -> Option<Object> {
-        match self.store.get(name) {
-            Some(o) => Some(o.clone()),
-            None => match self.parent {
-                Some(ref parent_env) => {
-                    let env = parent_env.borrow();
-                    env.get(name)
-                },
-                None => None,
-            },
+
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// appends the slots of the names a layout has in addition to this environment's
+    pub fn extend_layout(&mut self, names: &[String]) {
+        let known = self.names.len();
+        if names.len() > known {
+            Rc::make_mut(&mut self.names).extend_from_slice(&names[known..]);
+            self.slots.resize(names.len(), None);
+        }
+    }
+
+    pub fn set(&mut self, index: usize, val: Object) {
+        self.slots[index] = Some(val);
+    }
+
+    pub fn get_at(&self, depth: usize, index: usize) -> Option<Object> {
+        match (depth, &self.parent) {
+            (0, _) => self.slots.get(index).cloned().flatten(),
+            (_, Some(parent_env)) => parent_env.borrow().get_at(depth - 1, index),
+            (_, None) => None,
         }
     }
+
+    pub fn parent(&self) -> Option<&Rc<RefCell<Environment>>> {
+        self.parent.as_ref()
+    }
+
+    /// empties the environment, handing back what it held
+    pub fn clear(&mut self) -> Environment {
+        Environment {
+            names: Rc::clone(&self.names),
+            slots: std::mem::take(&mut self.slots),
+            parent: self.parent.take(),
+        }
+    }
+
+    /// the names of this scope bound so far, with their values
+    pub fn bindings(&self) -> impl Iterator<Item = (&str, &Object)> {
+        self.names.iter().zip(&self.slots).filter_map(|(name, slot)| Some((name.as_str(), slot.as_ref()?)))
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &Object> {
+        self.slots.iter().flatten()
+    }
+}
+
+/// Environments are equal only to themselves: comparing their contents would not terminate
+/// once a function is stored in the environment it captured.
+impl PartialEq for Environment {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
 }
 
 impl Default for Environment {
     fn default() -> Self {
         Self::new()
     }
-}
\ No newline at end of file
+}
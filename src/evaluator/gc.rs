@@ -0,0 +1,115 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+
+use crate::evaluator::environment::Environment;
+use crate::evaluator::object::Object;
+
+const MIN_THRESHOLD: usize = 128;
+
+/// Cycle collector for environments.
+///
+/// A closure stored in the environment it captures forms an `Rc` cycle. The collector
+/// counts, for every tracked environment, the references coming from other tracked
+/// environments; any environment with more strong references than that is held from
+/// outside (the evaluator, a host, a value being computed) and is a root. Environments
+/// not reachable from a root are garbage and get cleared, which breaks their cycles.
+#[derive(Debug, Default)]
+pub struct Collector {
+    envs: Vec<Weak<RefCell<Environment>>>,
+    threshold: usize,
+}
+
+impl Collector {
+    pub fn new() -> Self {
+        Collector {
+            envs: vec![],
+            threshold: MIN_THRESHOLD,
+        }
+    }
+
+    pub fn track(&mut self, env: &Rc<RefCell<Environment>>) {
+        self.envs.push(Rc::downgrade(env));
+    }
+
+    pub fn should_collect(&self) -> bool {
+        self.envs.len() >= self.threshold
+    }
+
+    pub fn live(&self) -> usize {
+        self.envs.iter().filter(|env| env.strong_count() > 0).count()
+    }
+
+    /// clears unreachable environments and returns how many were freed
+    pub fn collect(&mut self) -> usize {
+        let envs = self
+            .envs
+            .iter()
+            .filter_map(Weak::upgrade)
+            .collect::<Vec<_>>();
+        let index = envs
+            .iter()
+            .enumerate()
+            .map(|(i, env)| (Rc::as_ptr(env), i))
+            .collect::<HashMap<_, _>>();
+
+        let mut edges = vec![vec![]; envs.len()];
+        let mut internal = vec![0; envs.len()];
+        let mut roots = vec![];
+        for (i, env) in envs.iter().enumerate() {
+            match env.try_borrow() {
+                Ok(env) => {
+                    let mut refs = vec![];
+                    if let Some(parent) = env.parent() {
+                        refs.push(Rc::as_ptr(parent));
+                    }
+                    for object in env.values() {
+                        env_refs(object, &mut refs);
+                    }
+                    for ptr in refs {
+                        if let Some(&j) = index.get(&ptr) {
+                            internal[j] += 1;
+                            edges[i].push(j);
+                        }
+                    }
+                }
+                // an environment in use is alive
+                Err(_) => roots.push(i),
+            }
+        }
+        // one strong reference is held by `envs` itself
+        roots.extend((0..envs.len()).filter(|&i| Rc::strong_count(&envs[i]) - 1 > internal[i]));
+
+        let mut marked = vec![false; envs.len()];
+        while let Some(i) = roots.pop() {
+            if !marked[i] {
+                marked[i] = true;
+                roots.extend(edges[i].iter().copied());
+            }
+        }
+
+        let garbage = envs
+            .iter()
+            .zip(&marked)
+            .filter(|(_, marked)| !**marked)
+            .map(|(env, _)| env.borrow_mut().clear())
+            .collect::<Vec<_>>();
+        let freed = garbage.len();
+        drop(garbage);
+        drop(envs);
+
+        self.envs.retain(|env| env.strong_count() > 0);
+        self.threshold = MIN_THRESHOLD.max(self.envs.len() * 2);
+        freed
+    }
+}
+
+fn env_refs(object: &Object, refs: &mut Vec<*const RefCell<Environment>>) {
+    match object {
+        Object::Function(.., env) => refs.push(Rc::as_ptr(env)),
+        Object::Array(arr) => arr.iter().for_each(|o| env_refs(o, refs)),
+        Object::Hash(hash) => hash.values().for_each(|o| env_refs(o, refs)),
+        Object::ReturnValue(o) => env_refs(o, refs),
+        _ => {}
+    }
+}
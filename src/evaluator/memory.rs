@@ -0,0 +1,136 @@
+use std::mem;
+
+use crate::evaluator::object::Object;
+
+/// Approximate accounting of the memory held by the values an evaluator creates.
+///
+/// Values are accounted for incrementally, so that no value is measured more than it is copied:
+/// a new array or hash counts its own slots, its elements having been counted when they were
+/// built, and a copy, such as the value of a binding as it is read, counts in full as it is made.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryTracker {
+    used: usize,
+    peak: usize,
+    limit: Option<usize>,
+    exceeded: bool,
+}
+
+impl MemoryTracker {
+    pub fn new() -> Self {
+        MemoryTracker {
+            used: 0,
+            peak: 0,
+            limit: None,
+            exceeded: false,
+        }
+    }
+
+    pub fn set_limit(&mut self, limit: Option<usize>) {
+        self.limit = limit;
+    }
+
+    pub fn limit(&self) -> Option<usize> {
+        self.limit
+    }
+
+    pub fn used(&self) -> usize {
+        self.used
+    }
+
+    pub fn peak(&self) -> usize {
+        self.peak
+    }
+
+    pub fn exceeded(&self) -> bool {
+        self.exceeded
+    }
+
+    /// accounts for a newly built value, turning it into an error when it doesn't fit the limit
+    pub fn track(&mut self, object: Object) -> Object {
+        let size = own_size(&object);
+        self.add(size, object)
+    }
+
+    /// accounts for a copy of a value, as `track` does
+    pub fn track_copy(&mut self, object: Object) -> Object {
+        let size = match object {
+            Object::String(_) | Object::Array(_) | Object::Hash(_) => size_of(&object),
+            _ => 0,
+        };
+        self.add(size, object)
+    }
+
+    fn add(&mut self, size: usize, object: Object) -> Object {
+        match self.limit {
+            Some(limit) if self.used + size > limit => {
+                self.exceeded = true;
+                self.limit_error()
+            }
+            _ => {
+                self.used += size;
+                self.peak = self.peak.max(self.used);
+                object
+            }
+        }
+    }
+
+    pub fn limit_error(&self) -> Object {
+        Object::Error(format!(
+            "memory limit exceeded: {} bytes",
+            self.limit.unwrap_or_default()
+        ))
+    }
+
+    /// releases everything allocated since `mark` except the `kept` bytes
+    pub fn release_to(&mut self, mark: usize, kept: usize) {
+        self.used = self.used.min(mark + kept);
+    }
+
+    /// starts a new run, measuring its peak from the current usage
+    pub fn begin(&mut self) {
+        self.peak = self.used;
+        self.exceeded = false;
+    }
+
+    /// ends a run, resynchronizing the usage with what the environment still holds
+    pub fn settle(&mut self, used: usize) {
+        self.used = used;
+        self.exceeded = false;
+    }
+}
+
+/// the size of a value without the values it holds
+fn own_size(object: &Object) -> usize {
+    let own = mem::size_of::<Object>();
+    match object {
+        Object::String(s) | Object::Error(s) => own + s.len(),
+        Object::Array(arr) => own + arr.len() * own,
+        Object::Hash(hash) => own + hash.len() * 2 * own,
+        _ => own,
+    }
+}
+
+/// The size of a value returned by a call which it keeps of what was accounted for during the
+/// call, or `None` when it holds other values, which the call may have built.
+pub fn kept_size(object: &Object) -> Option<usize> {
+    match object {
+        Object::Array(_) | Object::Hash(_) | Object::ReturnValue(_) => None,
+        _ => Some(own_size(object)),
+    }
+}
+
+pub fn size_of(object: &Object) -> usize {
+    let own = mem::size_of::<Object>();
+    match object {
+        Object::String(s) | Object::Error(s) => own + s.len(),
+        Object::Array(arr) => own + arr.iter().map(size_of).sum::<usize>(),
+        Object::Hash(hash) => {
+            own + hash
+                .iter()
+                .map(|(k, v)| size_of(k) + size_of(v))
+                .sum::<usize>()
+        }
+        Object::ReturnValue(o) => own + size_of(o),
+        _ => own,
+    }
+}
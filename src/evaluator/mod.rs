@@ -1,24 +1,181 @@
 use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::PathBuf;
 use std::rc::Rc;
 use crate::evaluator::environment::Environment;
-use crate::evaluator::object::{BuiltinFunction, Object};
-use crate::parser::ast::{Expr, Ident, Infix, Literal, Prefix, Program, Stmt};
+use crate::evaluator::gc::Collector;
+use crate::evaluator::memory::MemoryTracker;
+use crate::evaluator::object::{BuiltinFunction, Module, Object};
+use crate::evaluator::observer::EvalObserver;
+use crate::parser::ast::{Expr, Ident, Infix, Literal, Prefix, Program, Signature, Stmt};
+use crate::resolver::{Resolution, Resolver};
 
-mod environment;
-mod object;
-mod builtins;
+pub use crate::evaluator::interrupt::InterruptHandle;
+
+pub mod environment;
+pub mod observer;
+pub mod object;
+pub mod builtins;
+pub(crate) mod operators;
+mod interrupt;
+mod memory;
+mod gc;
+mod modules;
 
 pub struct Evaluator {
     env: Rc<RefCell<Environment>>,
+    resolver: Resolver,
+    resolution: Resolution,
+    interrupt: InterruptHandle,
+    memory: MemoryTracker,
+    collector: Collector,
+    observer: Option<Box<dyn EvalObserver>>,
+    /// the errors among the values evaluated, while there is an observer, dropping those in
+    /// an error evaluated since
+    errors: Vec<String>,
+    /// the modules imported, by the canonical path of their file
+    modules: HashMap<PathBuf, Rc<Module>>,
+    /// the files running, each imported by the one before, as their path and its canonical form
+    files: Vec<(PathBuf, PathBuf)>,
+    /// the modules running, the innermost last
+    loading: Vec<modules::Loading>,
 }
 
 impl Evaluator {
     pub fn new() -> Self {
+        let prelude = Environment::prelude();
+        let resolver = Resolver::new(prelude.names().to_vec());
+        let env = Rc::new(RefCell::new(Environment::new_with_outer(Rc::new(RefCell::new(prelude)))));
+        let mut collector = Collector::new();
+        collector.track(&env);
         Evaluator {
-            env: Rc::new(RefCell::new(Environment::new())),
+            env,
+            resolver,
+            resolution: Resolution::default(),
+            interrupt: InterruptHandle::new(),
+            memory: MemoryTracker::new(),
+            collector,
+            observer: None,
+            errors: vec![],
+            modules: HashMap::new(),
+            files: vec![],
+            loading: vec![],
+        }
+    }
+
+    /// Frees the environments only kept alive by reference cycles between closures and
+    /// the environments they are stored in, returning how many were freed. This also
+    /// happens automatically as function calls create new environments.
+    pub fn collect_garbage(&mut self) -> usize {
+        self.collector.collect()
+    }
+
+    /// number of environments still alive, including the global one
+    pub fn live_environments(&self) -> usize {
+        self.collector.live()
+    }
+
+    /// Returns a handle that cancels the evaluation currently running on this evaluator.
+    /// The interrupted program evaluates to an error and the environment is kept.
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.interrupt.clone()
+    }
+
+    /// Caps the approximate number of bytes held by the values of this evaluator.
+    /// A program going over the limit stops and evaluates to an error.
+    pub fn set_memory_limit(&mut self, limit: Option<usize>) {
+        self.memory.set_limit(limit);
+    }
+
+    pub fn memory_limit(&self) -> Option<usize> {
+        self.memory.limit()
+    }
+
+    /// approximate number of bytes held by the values bound in the environment
+    pub fn memory_used(&self) -> usize {
+        self.memory.used()
+    }
+
+    /// highest approximate number of bytes used during the last evaluated program
+    pub fn peak_memory_used(&self) -> usize {
+        self.memory.peak()
+    }
+
+    /// Lets an observer follow, or a debugger stop, the evaluation.
+    pub fn set_observer(&mut self, observer: Option<Box<dyn EvalObserver>>) {
+        self.observer = observer;
+    }
+
+    /// the scope the evaluation is in
+    pub fn environment(&self) -> Rc<RefCell<Environment>> {
+        Rc::clone(&self.env)
+    }
+
+    /// Evaluates an expression in a scope of the running program, as if it was written where
+    /// the scope is, without the observer.
+    pub fn eval_in(&mut self, env: &Rc<RefCell<Environment>>, expr: Expr) -> Object {
+        let program = vec![Stmt::ExprStmt(expr)];
+        let mut scopes = vec![];
+        let mut scope = Some(Rc::clone(env));
+        while let Some(env) = scope {
+            let env = env.borrow();
+            scopes.push(env.names().to_vec());
+            scope = env.parent().cloned();
+        }
+        // the prelude is the resolver's own
+        scopes.pop();
+        scopes.reverse();
+        match self.resolver.resolve_in(scopes, &program) {
+            Ok(resolution) => self.resolution.extend(resolution),
+            Err(errors) => return Object::Error(errors[0].to_string()),
+        }
+        let observer = self.observer.take();
+        let old_env = std::mem::replace(&mut self.env, Rc::clone(env));
+        let object = self.eval_blockstmt(&program);
+        self.env = old_env;
+        self.observer = observer;
+        self.resolution.forget(&program);
+        match self.halted() {
+            Some(err) => err,
+            None => self.returned(object),
         }
     }
 
+    /// tells the observer of an event, the evaluation waiting for it
+    fn observe(&mut self, event: impl FnOnce(&mut dyn EvalObserver, &mut Evaluator)) {
+        if let Some(mut observer) = self.observer.take() {
+            event(observer.as_mut(), self);
+            self.observer = Some(observer);
+        }
+    }
+
+    /// Tells the observer of the error an object is, unless a value evaluated since `mark` was
+    /// that error already.
+    fn arisen(&mut self, object: &Object, mark: usize) {
+        if let Object::Error(message) = object {
+            let new = !self.errors[mark..].contains(message);
+            self.errors.truncate(mark);
+            self.errors.push(message.clone());
+            if new {
+                self.observe(|observer, evaluator| observer.error(evaluator, message));
+            }
+        }
+    }
+
+    fn halted(&self) -> Option<Object> {
+        if self.interrupt.is_interrupted() {
+            Some(Object::Error("evaluation interrupted".to_string()))
+        } else if self.memory.exceeded() {
+            Some(self.memory.limit_error())
+        } else {
+            None
+        }
+    }
+
+    fn env_memory_used(&self) -> usize {
+        self.env.borrow().values().map(memory::size_of).sum()
+    }
+
     fn returned(&mut self, object: Object) -> Object {
         match object {
             Object::ReturnValue(v) => *v,
@@ -26,167 +183,143 @@ impl Evaluator {
         }
     }
 
+    /// Resolves then evaluates a program. Undefined identifiers and duplicate parameters are
+    /// reported before anything runs.
     pub fn eval_program(&mut self, program: Program) -> Object {
-        let return_data = self.eval_blockstmt(program);
-        self.returned(return_data)
-    }
-
-    pub fn eval_blockstmt(&mut self, mut program: Program) -> Object {
-        match program.len() {
-            0 => Object::Null,
-            1 => self.eval_statement(program.remove(0)),
-            _ => {
-                let s = program.remove(0);
-                let object = self.eval_statement(s);
-                if object.is_returned() {
-                    object
-                } else {
-                    self.eval_blockstmt(program)
-                }
+        self.errors.clear();
+        match self.resolver.resolve(&program) {
+            Ok(resolution) => self.resolution.extend(resolution),
+            Err(errors) => {
+                let err = Object::Error(errors[0].to_string());
+                self.arisen(&err, 0);
+                return err;
             }
         }
+        self.env.borrow_mut().extend_layout(self.resolver.globals());
+        self.memory.begin();
+        let return_data = self.eval_blockstmt(&program);
+        let object = match self.halted() {
+            Some(err) => {
+                self.interrupt.reset();
+                err
+            }
+            None => self.returned(return_data),
+        };
+        let used = self.env_memory_used();
+        self.memory.settle(used);
+        self.resolution.forget(&program);
+        object
     }
-    pub fn eval_statement(&mut self, stmt: Stmt) -> Object {
+
+    pub fn eval_blockstmt(&mut self, program: &[Stmt]) -> Object {
+        let mut object = Object::Null;
+        for stmt in program {
+            object = self.eval_statement(stmt);
+            if object.is_returned() || self.halted().is_some() {
+                break;
+            }
+        }
+        object
+    }
+
+    pub fn eval_statement(&mut self, stmt: &Stmt) -> Object {
+        if let Some(err) = self.halted() {
+            return err;
+        }
+        self.observe(|observer, evaluator| observer.statement(evaluator, stmt));
         match stmt {
             Stmt::ExprStmt(expr) => self.eval_expr(expr),
             Stmt::ReturnStmt(expr) => Object::ReturnValue(Box::new(self.eval_expr(expr))),
-            Stmt::LetStmt(ident, expr) => {
+            Stmt::LetStmt(ident, ty, expr) => {
+                let mark = self.errors.len();
                 let object = self.eval_expr(expr);
+                let object = match ty {
+                    Some(ty) => operators::annotated(&ident.0, ty, object),
+                    None => object,
+                };
+                if self.observer.is_some() {
+                    self.arisen(&object, mark);
+                }
                 self.register_ident(ident, object)
             }
+            Stmt::ImportStmt(path, import) => self.eval_import(path, import),
+            Stmt::ExportStmt(idents) => self.eval_export(idents),
         }
     }
 
-    pub fn register_ident(&mut self, ident: Ident, object: Object) -> Object {
-        let Ident(name) = ident;
-        self.env.borrow_mut().set(&name, object.clone());
+    pub fn register_ident(&mut self, ident: &Ident, object: Object) -> Object {
+        match self.resolution.slot(ident) {
+            Some(slot) => {
+                self.env.borrow_mut().set(slot.index, object.clone());
+                self.observe(|observer, evaluator| observer.bound(evaluator, ident, &object));
+                object
+            }
+            None => Object::Error(format!("unresolved binding: {}", ident.0)),
+        }
+    }
+
+    pub fn eval_expr(&mut self, expr: &Expr) -> Object {
+        if self.observer.is_none() {
+            return self.eval_expr_kind(expr);
+        }
+        let mark = self.errors.len();
+        let object = self.eval_expr_kind(expr);
+        self.arisen(&object, mark);
+        self.observe(|observer, evaluator| observer.expression(evaluator, expr, &object));
         object
     }
 
-    pub fn eval_expr(&mut self, expr: Expr) -> Object {
+    fn eval_expr_kind(&mut self, expr: &Expr) -> Object {
         match expr {
             Expr::IdentExpr(i) => self.eval_ident(i),
             Expr::LiteralExpr(l) => self.eval_literal(l),
-            Expr::PrefixExpr(prefix, expr) => self.eval_prefix(&prefix, *expr),
-            Expr::InfixExpr(infix, expr1, expr2) => self.eval_infix(&infix, *expr1, *expr2),
-            Expr::IfExpr { cond, consequence, alternative } => self.eval_if(*cond, consequence, alternative),
-            Expr::FnExpr { params, body } => self.eval_fn(params, body),
-            Expr::CallExpr { function: func_expr, arguments } => self.eval_call(*func_expr, arguments),
+            Expr::PrefixExpr(prefix, expr) => self.eval_prefix(prefix, expr),
+            Expr::InfixExpr(infix, expr1, expr2) => self.eval_infix(infix, expr1, expr2),
+            Expr::IfExpr { cond, consequence, alternative } => self.eval_if(cond, consequence, alternative.as_deref()),
+            Expr::FnExpr { params, signature, body } => self.eval_fn(params, signature, body),
+            Expr::CallExpr { function: func_expr, arguments } => self.eval_call(func_expr, arguments),
             Expr::ArrayExpr(exprs) => self.eval_array(exprs),
             Expr::HashExpr(hash_exprs) => self.eval_hash(hash_exprs),
-            Expr::IndexExpr { array, index } => self.eval_index(*array, *index),
+            Expr::IndexExpr { array, index } => self.eval_index(array, index),
+            Expr::MemberExpr { module, name } => self.eval_member(module, name),
         }
     }
 
-    pub fn eval_ident(&mut self, ident: Ident) -> Object {
+    pub fn eval_ident(&mut self, ident: &Ident) -> Object {
         let Ident(name) = ident;
-        let borrow_env = self.env.borrow();
-        let var = borrow_env.get(&name);
+        let var = self
+            .resolution
+            .slot(ident)
+            .and_then(|slot| self.env.borrow().get_at(slot.depth, slot.index));
         match var {
             None => Object::Error(format!("identifier not found: {}", name)),
-            Some(o) => o,
+            Some(o) => self.memory.track_copy(o),
         }
     }
 
-    pub fn eval_literal(&mut self, literal: Literal) -> Object {
+    pub fn eval_literal(&mut self, literal: &Literal) -> Object {
         match literal {
-            Literal::IntLiteral(i) => Object::Integer(i),
-            Literal::BoolLiteral(b) => Object::Boolean(b),
-            Literal::StringLiteral(s) => Object::String(s),
+            Literal::IntLiteral(i) => Object::Integer(*i),
+            Literal::BoolLiteral(b) => Object::Boolean(*b),
+            Literal::StringLiteral(s) => self.memory.track(Object::String(s.clone())),
         }
     }
 
-    pub fn eval_prefix(&mut self, prefix: &Prefix, expr: Expr) -> Object {
+    pub fn eval_prefix(&mut self, prefix: &Prefix, expr: &Expr) -> Object {
         let object = self.eval_expr(expr);
-        match *prefix {
-            Prefix::PrefixPlus => match self.oti(object) {
-                Ok(i) => Object::Integer(i),
-                Err(err) => err,
-            }
-            Prefix::PrefixMinus => match self.oti(object) {
-                Ok(i) => Object::Integer(-i),
-                Err(err) => err,
-            }
-            Prefix::Not => match self.otb(object) {
-                Ok(b) => Object::Boolean(!b),
-                Err(err) => err,
-            }
-        }
+        operators::prefix(prefix, object)
     }
 
-    pub fn eval_infix(&mut self, infix: &Infix, expr1: Expr, expr2: Expr) -> Object {
+    pub fn eval_infix(&mut self, infix: &Infix, expr1: &Expr, expr2: &Expr) -> Object {
         let object1 = self.eval_expr(expr1);
         let object2 = self.eval_expr(expr2);
         match *infix {
             Infix::Plus => self.object_add(object1, object2),
-            Infix::Minus => {
-                let i1 = self.oti(object1);
-                let i2 = self.oti(object2);
-                match (i1, i2) {
-                    (Ok(i1), Ok(i2)) => Object::Integer(i1 - i2),
-                    (Err(err), _) | (_, Err(err)) => err,
-                }
-            },
-            Infix::Divide => {
-                let i1 = self.oti(object1);
-                let i2 = self.oti(object2);
-                match (i1, i2) {
-                    (Ok(i1), Ok(i2)) => {
-                        if i2 == 0 {
-                            Object::Error("division by zero".to_string())
-                        } else {
-                            Object::Integer(i1 / i2)
-                        }
-                    }
-                    (Err(err), _) | (_, Err(err)) => err,
-                }
-            },
-            Infix::Multiply => {
-                let i1 = self.oti(object1);
-                let i2 = self.oti(object2);
-                match (i1, i2) {
-                    (Ok(i1), Ok(i2)) => Object::Integer(i1 * i2),
-                    (Err(err), _) | (_, Err(err)) => err,
-                }
-            },
-            Infix::Equal => Object::Boolean(object1 == object2),
-            Infix::NotEqual => Object::Boolean(object1 != object2),
-            Infix::GreaterThanEqual => {
-                let i1 = self.oti(object1);
-                let i2 = self.oti(object2);
-                match (i1, i2) {
-                    (Ok(i1), Ok(i2)) => Object::Boolean(i1 >= i2),
-                    (Err(err), _) | (_, Err(err)) => err,
-                }
-            },
-            Infix::LessThanEqual => {
-                let i1 = self.oti(object1);
-                let i2 = self.oti(object2);
-                match (i1, i2) {
-                    (Ok(i1), Ok(i2)) => Object::Boolean(i1 <= i2),
-                    (Err(err), _) | (_, Err(err)) => err,
-                }
-            },
-            Infix::GreaterThan => {
-                let i1 = self.oti(object1);
-                let i2 = self.oti(object2);
-                match (i1, i2) {
-                    (Ok(i1), Ok(i2)) => Object::Boolean(i1 > i2),
-                    (Err(err), _) | (_, Err(err)) => err,
-                }
-            },
-            Infix::LessThan => {
-                let i1 = self.oti(object1);
-                let i2 = self.oti(object2);
-                match (i1, i2) {
-                    (Ok(i1), Ok(i2)) => Object::Boolean(i1 < i2),
-                    (Err(err), _) | (_, Err(err)) => err,
-                }
-            },
+            _ => operators::infix(infix, object1, object2),
         }
     }
 
-    pub fn eval_if(&mut self, cond: Expr, conse: Program, maybe_alter: Option<Program>) -> Object {
+    pub fn eval_if(&mut self, cond: &Expr, conse: &[Stmt], maybe_alter: Option<&[Stmt]>) -> Object {
         let object = self.eval_expr(cond);
         match self.otb(object) {
             Ok(b) => {
@@ -203,151 +336,158 @@ impl Evaluator {
         }
     }
 
-    pub fn eval_fn(&mut self, params: Vec<Ident>, body: Program) -> Object {
+    pub fn eval_fn(&mut self, params: &[Ident], signature: &Rc<Signature>, body: &Rc<Program>) -> Object {
         let env = Rc::clone(&self.env);
-        Object::Function(params, body, env)
+        Object::Function(Rc::new(params.to_vec()), Rc::clone(signature), Rc::clone(body), env)
     }
 
-    pub fn eval_call(&mut self, fn_expr: Expr, args_expr: Vec<Expr>) -> Object {
+    pub fn eval_call(&mut self, fn_expr: &Expr, args_expr: &[Expr]) -> Object {
+        if let Some(err) = self.halted() {
+            return err;
+        }
         let fn_object = self.eval_expr(fn_expr);
         let fn_ = self.otf(fn_object);
-        match fn_ {
-            Object::Function(param, body, f_evn) => {
-                self.eval_fn_call(args_expr, param, body, &f_evn)
-            }
+        match &fn_ {
+            Object::Function(..) => self.eval_fn_call(fn_expr, &fn_, args_expr),
             Object::Builtin(_, num_params, builtin_fn) => {
-                self.eval_builtin_call(args_expr, num_params, builtin_fn)
+                self.eval_builtin_call(fn_expr, &fn_, args_expr, *num_params, *builtin_fn)
             }
-            err => err,
+            _ => fn_,
         }
     }
 
-    pub fn eval_fn_call(&mut self, args_expr: Vec<Expr>, params: Vec<Ident>, body: Program, f_evn: &Rc<RefCell<Environment>>) -> Object {
+    /// Calls the function `fn_expr` evaluated to, checking the arguments and the returned
+    /// value against its annotations.
+    pub fn eval_fn_call(&mut self, fn_expr: &Expr, function: &Object, args_expr: &[Expr]) -> Object {
+        let Object::Function(params, signature, body, f_evn) = function else {
+            return operators::call_error(function.clone());
+        };
         if args_expr.len() != params.len() {
-            Object::Error(format!(
-                "wrong number of arguments: {} expected but {} given",
-                params.len(),
-                args_expr.len(),
-            ))
+            operators::arity_error(params.len(), args_expr.len())
         } else {
-            let args = args_expr
-                .into_iter()
-                .map(|expr: Expr| self.eval_expr(expr))
-                .collect::<Vec<_>>();
+            let mut args = Vec::with_capacity(args_expr.len());
+            for (index, expr) in args_expr.iter().enumerate() {
+                let object = self.eval_expr(expr);
+                match signature.param(index) {
+                    Some(ty) => match operators::annotated(&params[index].0, ty, object) {
+                        Object::Error(err) => return Object::Error(err),
+                        object => args.push(object),
+                    },
+                    None => args.push(object),
+                }
+            }
 
+            let layout = match self.resolution.frame(body) {
+                Some(layout) => Rc::clone(layout),
+                None => return Object::Error("unresolved function".to_string()),
+            };
+            let observed = self.observer.is_some().then(|| args.clone());
             let old_env = Rc::clone(&self.env);
-            let mut new_env = Environment::new_with_outer(Rc::clone(f_evn));
-            let zipped = params.into_iter().zip(args);
-            for (_, (Ident(name), o)) in zipped.enumerate() {
-                new_env.set(&name, o);
+            let mut new_env = Environment::with_layout(layout, Rc::clone(f_evn));
+            // parameters take the first slots of the body's scope
+            for (index, o) in args.into_iter().enumerate() {
+                new_env.set(index, o);
+            }
+            let mark = self.memory.used();
+            if self.collector.should_collect() {
+                self.collector.collect();
             }
             self.env = Rc::new(RefCell::new(new_env));
+            self.collector.track(&self.env);
+            if let Some(args) = observed {
+                self.observe(|observer, evaluator| {
+                    observer.call(evaluator, fn_expr, function, &args);
+                    for (param, arg) in params.iter().zip(&args) {
+                        observer.bound(evaluator, param, arg);
+                    }
+                });
+            }
             let object = self.eval_blockstmt(body);
-            self.env = old_env;
-            self.returned(object)
+            let call_env = std::mem::replace(&mut self.env, old_env);
+            let object = match &signature.ret {
+                Some(ty) => operators::annotated("the returned value", ty, self.returned(object)),
+                None => self.returned(object),
+            };
+            self.observe(|observer, evaluator| observer.returned(evaluator, &object));
+            // values local to the call are gone unless a closure kept its environment alive
+            if let (1, Some(kept)) = (Rc::strong_count(&call_env), memory::kept_size(&object)) {
+                self.memory.release_to(mark, kept);
+            }
+            object
         }
     }
 
-    pub fn eval_builtin_call(&mut self, args_expr: Vec<Expr>, num_params: usize, builtin_fn: BuiltinFunction) -> Object {
+    pub fn eval_builtin_call(&mut self, fn_expr: &Expr, function: &Object, args_expr: &[Expr], num_params: usize, builtin_fn: BuiltinFunction) -> Object {
         if args_expr.len() != num_params {
-            Object::Error(format!(
-                "wrong number of arguments: {} expected but {} given",
-                num_params,
-                args_expr.len(),
-            ))
+            operators::arity_error(num_params, args_expr.len())
         } else {
             let args = args_expr
-                .into_iter()
-                .map(|expr: Expr| self.eval_expr(expr))
+                .iter()
+                .map(|expr| self.eval_expr(expr))
                 .collect::<Vec<_>>();
 
-            builtin_fn(args).unwrap_or_else(Object::Error)
+            self.observe(|observer, evaluator| observer.call(evaluator, fn_expr, function, &args));
+            let object = match builtin_fn(args) {
+                Ok(object) => self.memory.track(object),
+                Err(err) => Object::Error(err),
+            };
+            self.observe(|observer, evaluator| observer.returned(evaluator, &object));
+            object
         }
     }
 
-    pub fn eval_array(&mut self, exprs: Vec<Expr>) -> Object {
-        let new_vec = exprs.into_iter().map(|expr| self.eval_expr(expr)).collect::<Vec<_>>();
-        Object::Array(new_vec)
+    pub fn eval_array(&mut self, exprs: &[Expr]) -> Object {
+        let new_vec = exprs.iter().map(|expr| self.eval_expr(expr)).collect::<Vec<_>>();
+        self.memory.track(Object::Array(new_vec))
     }
 
     pub fn object_add(&mut self, object1: Object, object2: Object) -> Object {
-        match (object1, object2) {
-            (Object::Integer(i1), Object::Integer(i2)) => Object::Integer(i1 + i2),
-            (Object::String(s1), Object::String(s2)) => Object::String(s1 + &s2),
-            (Object::Error(s), _) | (_, Object::Error(s)) => Object::Error(s),
-            (x, y) => Object::Error(format!("{:?} and {:?} are not addable", x, y)),
+        match operators::add(object1, object2) {
+            Object::String(s) => self.memory.track(Object::String(s)),
+            o => o,
         }
     }
 
-    pub fn eval_hash(&mut self, hs: Vec<(Literal, Expr)>) -> Object {
-        let hashmap = hs.into_iter().map(|pair| self.eval_pair(pair)).collect();
-        Object::Hash(hashmap)
+    #[allow(clippy::mutable_key_type)]
+    pub fn eval_hash(&mut self, hs: &[(Literal, Expr)]) -> Object {
+        let hashmap = hs.iter().map(|pair| self.eval_pair(pair)).collect();
+        self.memory.track(Object::Hash(hashmap))
     }
 
-    pub fn eval_pair(&mut self, tuple: (Literal, Expr)) -> (Object, Object) {
+    pub fn eval_pair(&mut self, tuple: &(Literal, Expr)) -> (Object, Object) {
         let (l, e) = tuple;
         let hash = self.l2h(l);
         let object = self.eval_expr(e);
         (hash, object)
     }
 
-    pub fn eval_index(&mut self, target_expr: Expr, id_expr: Expr) -> Object {
+    pub fn eval_index(&mut self, target_expr: &Expr, id_expr: &Expr) -> Object {
         let target = self.eval_expr(target_expr);
         let index = self.eval_expr(id_expr);
-        match target {
-            Object::Array(arr) => match self.oti(index) {
-                Ok(index_number) => arr.into_iter()
-                    .nth(index_number as usize)
-                    .unwrap_or(Object::Null),
-                Err(err) => err,
-            },
-            Object::Hash(mut hash) => {
-                let name = self.oth(index);
-                match name {
-                    Object::Error(_) => name,
-                    _ => hash.remove(&name).unwrap_or(Object::Null),
-                }
-            }
-            o => Object::Error(format!("unexpected index target: {}", o)),
-        }
+        operators::index(target, index)
     }
 
     pub fn otb(&mut self, object: Object) -> Result<bool, Object> {
-        match object {
-            Object::Boolean(b) => Ok(b),
-            Object::Error(s) => Err(Object::Error(s)),
-            b => Err(Object::Error(format!("{} is not a bool", b))),
-        }
+        operators::to_bool(object)
     }
     
     pub fn oti(&mut self, object: Object) -> Result<i64, Object> {
-        match object {
-            Object::Integer(i) => Ok(i),
-            Object::Error(s) => Err(Object::Error(s)),
-            _ => Err(Object::Error(format!("{} is not an integer", object))),
-        }
+        operators::to_int(object)
     }
     
     /// object to function
     pub fn otf(&mut self, object: Object) -> Object {
         match object {
-            Object::Function(_, _, _) | Object::Builtin(_, _, _) => object,
-            Object::Error(s) => Object::Error(s),
-            f => Object::Error(format!("{} is not a valid function", f)),
+            Object::Function(..) | Object::Builtin(_, _, _) => object,
+            o => operators::call_error(o),
         }
     }
     
     pub fn oth(&mut self, object: Object) -> Object {
-        match object {
-            Object::Integer(i) => Object::Integer(i),
-            Object::Boolean(b) => Object::Boolean(b),
-            Object::String(s) => Object::String(s),
-            Object::Error(s) => Object::Error(s),
-            x => Object::Error(format!("{} is not hashable", x)),
-        }
+        operators::to_hashable(object)
     }
     
-    pub fn l2h(&mut self, literal: Literal) -> Object {
+    pub fn l2h(&mut self, literal: &Literal) -> Object {
         let object = self.eval_literal(literal);
         self.oth(object)
     }
@@ -359,6 +499,16 @@ impl Default for Evaluator {
     }
 }
 
+impl Drop for Evaluator {
+    fn drop(&mut self) {
+        // the global environment is part of a cycle as soon as it holds a function
+        let global = std::mem::replace(&mut self.env, Rc::new(RefCell::new(Environment::new())));
+        drop(global);
+        self.modules.clear();
+        self.collector.collect();
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -367,12 +517,16 @@ mod tests {
     use crate::parser::*;
 
     fn compare(input: &[u8], object: Object) {
+        let mut evaluator = Evaluator::new();
+        let eval = eval_with(&mut evaluator, input);
+        assert_eq!(eval, object);
+    }
+
+    fn eval_with(evaluator: &mut Evaluator, input: &[u8]) -> Object {
         let (_, r) = Lexer::lex_tokens(input).unwrap();
         let tokens = Tokens::new(&r);
         let (_, result_parse) = Parser::parse_tokens(tokens).unwrap();
-        let mut evaluator = Evaluator::new();
-        let eval = evaluator.eval_program(result_parse);
-        assert_eq!(eval, object);
+        evaluator.eval_program(result_parse)
     }
 
     #[test]
@@ -456,6 +610,14 @@ mod tests {
             "(5 + 10 * 2 + 15 / 3) * 2 + -10".as_bytes(),
             Object::Integer(50),
         );
+        // integer overflows
+        let overflow = |operation: &str| Object::Error(format!("integer overflow in {}", operation));
+        compare("9223372036854775807 + 1".as_bytes(), overflow("addition"));
+        compare("-9223372036854775807 - 2".as_bytes(), overflow("subtraction"));
+        compare("4611686018427387904 * 2".as_bytes(), overflow("multiplication"));
+        compare("(-9223372036854775807 - 1) / -1".as_bytes(), overflow("division"));
+        compare("-(-9223372036854775807 - 1)".as_bytes(), overflow("negation"));
+        compare("1 / 0".as_bytes(), Object::Error("division by zero".to_string()));
         // logic algebra
         compare("1 < 2".as_bytes(), Object::Boolean(true));
         compare("1 > 2".as_bytes(), Object::Boolean(false));
@@ -547,6 +709,10 @@ mod tests {
             "\"foo\" - \"bar\"".as_bytes(),
             Object::Error("foo is not an integer".to_string()),
         );
+        compare(
+            "\"foo\" + [1]".as_bytes(),
+            Object::Error("foo and [1] are not addable".to_string()),
+        );
     }
 
     #[test]
@@ -643,6 +809,110 @@ mod tests {
         compare(fn_input5, Object::Integer(9));
         compare(fn_input6, Object::Integer(5));
         compare(fn_input7, Object::Integer(4));
+
+        // functions stored in the environment they captured
+        compare("let f = fn(x) { x }; f == f".as_bytes(), Object::Boolean(true));
+        compare("let f = fn(x) { x }; let g = fn(x) { x * 2 }; f == g".as_bytes(), Object::Boolean(false));
+    }
+
+    /// what an observer is told, with the value of `n` at each statement, and the values of
+    /// infix expressions only
+    struct Recorder(Rc<RefCell<Vec<String>>>);
+
+    impl EvalObserver for Recorder {
+        fn statement(&mut self, evaluator: &mut Evaluator, stmt: &Stmt) {
+            let env = evaluator.environment();
+            let n = evaluator.eval_in(&env, Expr::IdentExpr(Ident("n".to_string())));
+            self.0.borrow_mut().push(format!("{} with n = {}", stmt, n));
+        }
+
+        fn expression(&mut self, _: &mut Evaluator, expr: &Expr, value: &Object) {
+            if let Expr::InfixExpr(..) = expr {
+                self.0.borrow_mut().push(format!("{} is {}", expr, value));
+            }
+        }
+
+        fn call(&mut self, _: &mut Evaluator, callee: &Expr, _: &Object, args: &[Object]) {
+            let args = args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+            self.0.borrow_mut().push(format!("call {} with [{}]", callee, args.join(", ")));
+        }
+
+        fn returned(&mut self, _: &mut Evaluator, value: &Object) {
+            self.0.borrow_mut().push(format!("return {}", value));
+        }
+
+        fn bound(&mut self, _: &mut Evaluator, ident: &Ident, value: &Object) {
+            self.0.borrow_mut().push(format!("bind {} to {}", ident.0, value));
+        }
+
+        fn error(&mut self, _: &mut Evaluator, message: &str) {
+            self.0.borrow_mut().push(format!("error {}", message));
+        }
+    }
+
+    #[test]
+    fn test_observer() {
+        let events = Rc::new(RefCell::new(vec![]));
+        let mut evaluator = Evaluator::new();
+        evaluator.set_observer(Some(Box::new(Recorder(Rc::clone(&events)))));
+        let program = "let n = 1; let f = fn(n) { n * 2 }; f(n + 1) + len([]); [(n + true) * 2, n]";
+        let array = Object::Array(vec![Object::Error("1 and true are not addable".to_string()), Object::Integer(1)]);
+        assert_eq!(eval_with(&mut evaluator, program.as_bytes()), array);
+        assert_eq!(
+            *events.borrow(),
+            vec![
+                "let n = 1 with n = Error: identifier not found: n",
+                "bind n to 1",
+                "let f = fn(n) {\n    n * 2\n} with n = 1",
+                "bind f to [function]",
+                "f(n + 1) + len([]) with n = 1",
+                "n + 1 is 2",
+                "call f with [2]",
+                "bind n to 2",
+                "n * 2 with n = 2",
+                "n * 2 is 4",
+                "return 4",
+                "call len with [[]]",
+                "return 0",
+                "f(n + 1) + len([]) is 4",
+                "[(n + true) * 2, n] with n = 1",
+                "error 1 and true are not addable",
+                "n + true is Error: 1 and true are not addable",
+                "(n + true) * 2 is Error: 1 and true are not addable",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_annotations() {
+        compare("let x: int = 1; x".as_bytes(), Object::Integer(1));
+        compare(
+            "let x: [int] = [1, true]; 2".as_bytes(),
+            Object::Integer(2),
+        );
+        compare(
+            "let x: [int] = [1, true]; x".as_bytes(),
+            Object::Error("wrong type for x: [int] expected but [1, true] given".to_string()),
+        );
+        compare(
+            "let f = fn(a: string, g: fn(int) -> int) { a }; f(\"a\", len)".as_bytes(),
+            Object::String("a".to_string()),
+        );
+        compare(
+            "let f = fn(a: string, g: fn(int) -> int) { a }; f(1, len)".as_bytes(),
+            Object::Error("wrong type for a: string expected but 1 given".to_string()),
+        );
+        compare(
+            "let f = fn(a: string, g: fn(int) -> int) { a }; f(\"a\", cons)".as_bytes(),
+            Object::Error("wrong type for g: fn(int) -> int expected but [built-in function: cons] given".to_string()),
+        );
+        compare(
+            "let f = fn(n) -> bool { if (n > 0) { return n } else { n > 0 } }; [f(0), f(1)]".as_bytes(),
+            Object::Array(vec![
+                Object::Boolean(false),
+                Object::Error("wrong type for the returned value: bool expected but 1 given".to_string()),
+            ]),
+        );
     }
 
     #[test]
@@ -836,4 +1106,223 @@ mod tests {
             Object::Integer(15),
         );
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_assertions() {
+        let failed = |message: &str| Object::Error(message.to_string());
+        compare("assert(1 < 2)".as_bytes(), Object::Null);
+        compare("assert(2 < 1)".as_bytes(), failed("assertion failed"));
+        compare("assert(\"yes\")".as_bytes(), failed("assertion failed: \"yes\" is not a bool"));
+        compare("assert_eq([1, {\"a\": 2}], [1, {\"a\": 2}])".as_bytes(), Object::Null);
+        compare(
+            "assert_eq(1 + 1, 3)".as_bytes(),
+            failed("assertion failed: the values differ\n  left:  2\n  right: 3"),
+        );
+        compare(
+            "assert_eq([1, [2, 4], 5, 6], [1, [2, 3]])".as_bytes(),
+            failed(
+                "assertion failed: the values differ
+  left:  [1, [2, 4], 5, 6]
+  right: [1, [2, 3]]
+  at [1][1]: 4 is not 3
+  at [2]: 5 only on the left
+  at [3]: 6 only on the left",
+            ),
+        );
+        compare(
+            "assert_eq({\"a\": \"x\", \"b\": 1}, {\"a\": \"y\", \"c\": true})".as_bytes(),
+            failed(
+                "assertion failed: the values differ
+  left:  {\"a\": \"x\", \"b\": 1}
+  right: {\"a\": \"y\", \"c\": true}
+  at [\"a\"]: \"x\" is not \"y\"
+  at [\"b\"]: 1 only on the left
+  at [\"c\"]: true only on the right",
+            ),
+        );
+        compare("assert_error(1 + true)".as_bytes(), Object::Null);
+        compare("assert_error([])".as_bytes(), failed("assertion failed: [] is not an error"));
+    }
+
+    #[test]
+    fn test_interrupt() {
+        let mut evaluator = Evaluator::new();
+        let handle = evaluator.interrupt_handle();
+        eval_with(&mut evaluator, "let a = 5;".as_bytes());
+
+        handle.interrupt();
+        assert_eq!(
+            eval_with(&mut evaluator, "let b = 10; a + b".as_bytes()),
+            Object::Error("evaluation interrupted".to_string()),
+        );
+        assert_eq!(eval_with(&mut evaluator, "a".as_bytes()), Object::Integer(5));
+
+        let fib = "let fib = fn(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } }; fib(60)";
+        let canceller = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            handle.interrupt();
+        });
+        assert_eq!(
+            eval_with(&mut evaluator, fib.as_bytes()),
+            Object::Error("evaluation interrupted".to_string()),
+        );
+        canceller.join().unwrap();
+        assert_eq!(eval_with(&mut evaluator, "a + 1".as_bytes()), Object::Integer(6));
+    }
+
+    #[test]
+    fn test_memory_limit() {
+        let build = "let build = fn(n, acc) { if (n == 0) { acc } else { build(n - 1, cons(n, acc)) } };";
+        let mut evaluator = Evaluator::new();
+        eval_with(&mut evaluator, build.as_bytes());
+
+        assert_eq!(
+            eval_with(&mut evaluator, "len(build(50, []))".as_bytes()),
+            Object::Integer(50),
+        );
+        let peak = evaluator.peak_memory_used();
+        assert!(peak >= memory::size_of(&Object::Array(vec![Object::Integer(0); 50])));
+
+        evaluator.set_memory_limit(Some(peak / 2));
+        assert_eq!(
+            eval_with(&mut evaluator, "let a = 1; len(build(50, [])); let b = 2;".as_bytes()),
+            Object::Error(format!("memory limit exceeded: {} bytes", peak / 2)),
+        );
+        assert!(evaluator.peak_memory_used() <= peak / 2);
+        assert_eq!(eval_with(&mut evaluator, "a".as_bytes()), Object::Integer(1));
+        assert_eq!(
+            eval_with(&mut evaluator, "b".as_bytes()),
+            Object::Error("identifier not found: b".to_string()),
+        );
+
+        evaluator.set_memory_limit(None);
+        assert_eq!(
+            eval_with(&mut evaluator, "len(build(50, []))".as_bytes()),
+            Object::Integer(50),
+        );
+
+        let mut evaluator = Evaluator::new();
+        evaluator.set_memory_limit(Some(1024));
+        assert_eq!(
+            eval_with(
+                &mut evaluator,
+                "let grow = fn(s, n) { if (n == 0) { s } else { grow(s + s, n - 1) } }; grow(\"ab\", 20)".as_bytes(),
+            ),
+            Object::Error("memory limit exceeded: 1024 bytes".to_string()),
+        );
+
+        // copies, and the values a call returns inside others, stay accounted for
+        let mut evaluator = Evaluator::new();
+        evaluator.set_memory_limit(Some(1 << 20));
+        let exceeded = Object::Error(format!("memory limit exceeded: {} bytes", 1 << 20));
+        assert_eq!(
+            eval_with(&mut evaluator, "let g = fn(a, n) { if (n == 0) { len(a) } else { g([a, a], n - 1) } }; g([1], 40)".as_bytes()),
+            exceeded,
+        );
+        assert_eq!(
+            eval_with(&mut evaluator, "let tree = fn(n) { if (n == 0) { [] } else { [tree(n - 1), tree(n - 1)] } }; tree(40)".as_bytes()),
+            exceeded,
+        );
+    }
+
+    #[test]
+    fn test_cycle_collection() {
+        let input = "let make = fn() { let g = fn() { g }; g }; make();".as_bytes();
+        let mut evaluator = Evaluator::new();
+        for _ in 0..1000 {
+            eval_with(&mut evaluator, input);
+        }
+        assert!(evaluator.live_environments() < 300);
+        evaluator.collect_garbage();
+        assert_eq!(evaluator.live_environments(), 1);
+
+        let input = "let loop = fn(n) {\
+                let f = fn() { f };\
+                if (n == 0) { 0 } else { loop(n - 1) }\
+            };\
+            loop(100);\
+            loop(100);\
+            let kept = loop;\
+            kept(5)\
+            ";
+        assert_eq!(eval_with(&mut evaluator, input.as_bytes()), Object::Integer(0));
+        assert!(evaluator.live_environments() < 300);
+        evaluator.collect_garbage();
+        assert_eq!(evaluator.live_environments(), 1);
+
+        let counter = eval_with(&mut evaluator, "let counter = fn(n) { fn() { n } }; counter(3)".as_bytes());
+        evaluator.collect_garbage();
+        assert_eq!(evaluator.live_environments(), 2);
+        match counter {
+            Object::Function(.., env) => {
+                let weak = Rc::downgrade(&env);
+                drop(env);
+                drop(evaluator);
+                assert_eq!(weak.strong_count(), 0);
+            }
+            o => panic!("expected a function, got {}", o),
+        }
+    }
+
+    #[test]
+    fn test_prelude() {
+        compare(
+            "let f = fn() { let head = 5; head }; f() + head([1])".as_bytes(),
+            Object::Integer(6),
+        );
+        compare(
+            "let len = fn(x) { 0 }; len(\"abc\")".as_bytes(),
+            Object::Integer(0),
+        );
+        compare(
+            "let outer = fn() { fn() { len([1, 2]) } }; outer()()".as_bytes(),
+            Object::Integer(2),
+        );
+    }
+
+    #[test]
+    fn test_resolution() {
+        compare(
+            "let a = 1; if (false) { b }".as_bytes(),
+            Object::Error("identifier not found: b".to_string()),
+        );
+        compare(
+            "let f = fn(x, x) { x }; 1".as_bytes(),
+            Object::Error("duplicate parameter: x".to_string()),
+        );
+        compare(
+            "let f = fn(x) { if (x) { let y = 1; } y }; f(true)".as_bytes(),
+            Object::Integer(1),
+        );
+        compare(
+            "let f = fn(x) { if (x) { let y = 1; } y }; f(false)".as_bytes(),
+            Object::Error("identifier not found: y".to_string()),
+        );
+        compare(
+            "let x = 1; let f = fn(x) { let x = x + 10; x }; f(5) + x".as_bytes(),
+            Object::Integer(16),
+        );
+        // the body reads the enclosing binding until its own `let`
+        compare(
+            "let a = 1; let f = fn() { let b = a; let a = 2; b }; f()".as_bytes(),
+            Object::Integer(1),
+        );
+        compare(
+            "let n = len; let len = fn(x) { 0 }; [n(\"ab\"), len(\"ab\")]".as_bytes(),
+            Object::Array(vec![Object::Integer(2), Object::Integer(0)]),
+        );
+
+        let mut evaluator = Evaluator::new();
+        assert_eq!(
+            eval_with(&mut evaluator, "let a = 1; let f = fn() { c }".as_bytes()),
+            Object::Error("identifier not found: c".to_string()),
+        );
+        assert_eq!(
+            eval_with(&mut evaluator, "a".as_bytes()),
+            Object::Error("identifier not found: a".to_string()),
+        );
+        eval_with(&mut evaluator, "let c = 3; let f = fn() { c };".as_bytes());
+        eval_with(&mut evaluator, "let g = fn(n) { f() + n };".as_bytes());
+        assert_eq!(eval_with(&mut evaluator, "g(4)".as_bytes()), Object::Integer(7));
+    }
+}
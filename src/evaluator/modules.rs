@@ -0,0 +1,165 @@
+//! Importing files as modules.
+//!
+//! A module runs once, in its own global environment over the prelude, the first time it is
+//! imported; later imports of the same file, by any path, share what it exported. It exports
+//! the names of the `export` statements it ran, bound to their values once it finished, unless
+//! one of its own imports failed, which fails it too. The statements of a module run without
+//! the observer, the calls of its functions do not.
+
+use std::cell::RefCell;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::evaluator::environment::Environment;
+use crate::evaluator::object::{Module, Object};
+use crate::evaluator::Evaluator;
+use crate::parser::ast::{Expr, Ident, Import};
+use crate::parser::parse;
+use crate::resolver::Resolver;
+
+/// a module running: the names it exported so far, and why an import of it failed if one did
+#[derive(Default)]
+pub(super) struct Loading {
+    exports: Vec<String>,
+    failed: Option<String>,
+}
+
+impl Evaluator {
+    /// Runs the programs which follow as the file at `path`, which their imports are relative
+    /// to. Without a file, they are relative to the current directory.
+    pub fn set_file(&mut self, path: Option<&str>) {
+        self.files = path
+            .map(|path| {
+                let path = PathBuf::from(path);
+                let canonical = fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+                (path, canonical)
+            })
+            .into_iter()
+            .collect();
+    }
+
+    pub fn eval_import(&mut self, path: &str, import: &Import) -> Object {
+        let module = match self.import(path) {
+            Ok(module) => module,
+            Err(err) => {
+                if let Some(loading) = self.loading.last_mut() {
+                    loading.failed.get_or_insert_with(|| err.clone());
+                }
+                let err = Object::Error(err);
+                for ident in import.idents() {
+                    self.register_ident(ident, err.clone());
+                }
+                return err;
+            }
+        };
+        match import {
+            Import::Module(ident) => self.register_ident(ident, Object::Module(module)),
+            Import::Names(idents) => {
+                let mut object = Object::Null;
+                for ident in idents {
+                    let export = module.get(&ident.0).cloned();
+                    let export = export.unwrap_or_else(|| Object::Error(not_exported(&module, &ident.0)));
+                    let bound = self.register_ident(ident, export);
+                    if matches!(bound, Object::Error(_)) && !matches!(object, Object::Error(_)) {
+                        object = bound;
+                    }
+                }
+                object
+            }
+        }
+    }
+
+    /// Exports the names from the module running, in the order they are first exported.
+    pub fn eval_export(&mut self, idents: &[Ident]) -> Object {
+        if let Some(loading) = self.loading.last_mut() {
+            for Ident(name) in idents {
+                if !loading.exports.contains(name) {
+                    loading.exports.push(name.clone());
+                }
+            }
+        }
+        Object::Null
+    }
+
+    pub fn eval_member(&mut self, module: &Expr, name: &Ident) -> Object {
+        match self.eval_expr(module) {
+            Object::Module(module) => match module.get(&name.0) {
+                Some(object) => object.clone(),
+                None => Object::Error(not_exported(&module, &name.0)),
+            },
+            Object::Error(err) => Object::Error(err),
+            object => Object::Error(format!("{} is not a module", object)),
+        }
+    }
+
+    /// the module of the file at `path`, relative to the file running, run unless it already was
+    fn import(&mut self, path: &str) -> Result<Rc<Module>, String> {
+        let dir = match self.files.last() {
+            Some((file, _)) => file.parent().map(Path::to_path_buf).unwrap_or_default(),
+            None => PathBuf::new(),
+        };
+        let path = dir.join(path);
+        let cannot = |err: std::io::Error| format!("cannot import {}: {}", path.display(), err);
+        let canonical = fs::canonicalize(&path).map_err(cannot)?;
+        if let Some(module) = self.modules.get(&canonical) {
+            return Ok(Rc::clone(module));
+        }
+        if let Some(start) = self.files.iter().position(|(_, file)| *file == canonical) {
+            let cycle = self.files[start..].iter().map(|(file, _)| file).chain([&path]);
+            let cycle = cycle.map(|file| file.display().to_string()).collect::<Vec<_>>();
+            return Err(format!("import cycle: {}", cycle.join(" -> ")));
+        }
+        let source = fs::read_to_string(&path).map_err(cannot)?;
+        let program = parse(&source).map_err(|err| format!("{}: {}", path.display(), err))?;
+
+        let mut prelude = Rc::clone(&self.env);
+        loop {
+            let parent = prelude.borrow().parent().cloned();
+            match parent {
+                Some(parent) => prelude = parent,
+                None => break,
+            }
+        }
+        let mut resolver = Resolver::new(prelude.borrow().names().to_vec());
+        match resolver.resolve(&program) {
+            Ok(resolution) => self.resolution.extend(resolution),
+            Err(errors) => return Err(format!("{}: {}", path.display(), errors[0])),
+        }
+        let env = Rc::new(RefCell::new(Environment::new_with_outer(prelude)));
+        env.borrow_mut().extend_layout(resolver.globals());
+        self.collector.track(&env);
+
+        self.files.push((path.clone(), canonical.clone()));
+        let outer = std::mem::replace(&mut self.env, env);
+        let observer = self.observer.take();
+        self.loading.push(Loading::default());
+        self.eval_blockstmt(&program);
+        let loading = self.loading.pop().unwrap_or_default();
+        let mut exports = vec![];
+        for name in loading.exports {
+            let env = self.env.borrow();
+            let object = env.names().iter().position(|bound| *bound == name).and_then(|index| env.get_at(0, index));
+            let object = object.unwrap_or_else(|| Object::Error(format!("identifier not found: {}", name)));
+            exports.push((name, object));
+        }
+        self.observer = observer;
+        self.env = outer;
+        self.files.pop();
+        self.resolution.forget(&program);
+        if let Some(Object::Error(err)) = self.halted() {
+            return Err(err);
+        }
+        if let Some(err) = loading.failed {
+            return Err(err);
+        }
+
+        let module = Rc::new(Module { path: path.display().to_string(), exports });
+        self.modules.insert(canonical, Rc::clone(&module));
+        Ok(module)
+    }
+}
+
+fn not_exported(module: &Module, name: &str) -> String {
+    format!("{} does not export {}", module.path, name)
+}
@@ -1,5 +1,6 @@
 use crate::evaluator::environment::Environment;
-use crate::parser::ast::{Ident, Program};
+use crate::parser::ast::{Ident, Literal, Program, Signature};
+use crate::vm::Closure;
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::fmt;
@@ -7,6 +8,7 @@ use std::fmt::Formatter;
 use std::hash::{Hash, Hasher};
 use std::rc::Rc;
 
+#[allow(unpredictable_function_pointer_comparisons)]
 #[derive(Debug, Clone, PartialEq)]
 pub enum Object {
     Integer(i64),
@@ -14,8 +16,10 @@ pub enum Object {
     String(String),
     Array(Vec<Object>),
     Hash(HashMap<Object, Object>),
-    Function(Vec<Ident>, Program, Rc<RefCell<Environment>>),
+    Function(Rc<Vec<Ident>>, Rc<Signature>, Rc<Program>, Rc<RefCell<Environment>>),
+    Closure(Closure),
     Builtin(String, usize, BuiltinFunction),
+    Module(Rc<Module>),
     Null,
     ReturnValue(Box<Object>),
     Error(String),
@@ -34,6 +38,21 @@ impl Object {
     }
 }
 
+/// a file imported, once it ran
+#[derive(Debug, PartialEq)]
+pub struct Module {
+    /// the path it was imported from
+    pub path: String,
+    /// the bindings it exports, in the order they are exported
+    pub exports: Vec<(String, Object)>,
+}
+
+impl Module {
+    pub fn get(&self, name: &str) -> Option<&Object> {
+        self.exports.iter().find(|(export, _)| export == name).map(|(_, object)| object)
+    }
+}
+
 impl Eq for Object {}
 
 #[allow(clippy::all)]
@@ -84,8 +103,9 @@ impl fmt::Display for Object {
                 fmt_string.push('}');
                 write!(f, "{}", fmt_string)
             },
-            Object::Function(_, _, _) => write!(f, "[function]"),
+            Object::Function(..) | Object::Closure(_) => write!(f, "[function]"),
             Object::Builtin(name, _, _) => write!(f, "[built-in function: {}]", *name),
+            Object::Module(module) => write!(f, "[module: {}]", module.path),
             Object::Null => write!(f, "null"),
             Object::ReturnValue(o) => write!(f, "{}", *o),
             Object::Error(s) => write!(f, "Error: {}", s),
@@ -93,4 +113,19 @@ impl fmt::Display for Object {
     }
 }
 
+/// a value as written in the source, strings quoted and the keys of hashes in order
+pub fn show(object: &Object) -> String {
+    match object {
+        Object::String(s) => Literal::StringLiteral(s.clone()).to_string(),
+        Object::Array(items) => format!("[{}]", items.iter().map(show).collect::<Vec<_>>().join(", ")),
+        Object::Hash(pairs) => {
+            let mut pairs = pairs.iter().map(|(k, v)| (show(k), show(v))).collect::<Vec<_>>();
+            pairs.sort();
+            let pairs = pairs.into_iter().map(|(k, v)| format!("{}: {}", k, v)).collect::<Vec<_>>();
+            format!("{{{}}}", pairs.join(", "))
+        }
+        object => object.to_string(),
+    }
+}
+
 pub type BuiltinFunction = fn(Vec<Object>) -> Result<Object, String>;
@@ -0,0 +1,27 @@
+use crate::evaluator::object::Object;
+use crate::evaluator::Evaluator;
+use crate::parser::ast::{Expr, Ident, Stmt};
+
+/// What an observer is told of an evaluation, which waits for it to return, so a debugger can
+/// stop the program and look at it meanwhile. The observer is not told of what it evaluates
+/// itself. Each event is ignored unless told otherwise.
+pub trait EvalObserver {
+    /// a statement is about to run
+    fn statement(&mut self, _evaluator: &mut Evaluator, _stmt: &Stmt) {}
+
+    /// an expression evaluated to a value
+    fn expression(&mut self, _evaluator: &mut Evaluator, _expr: &Expr, _value: &Object) {}
+
+    /// the function `callee` evaluated to is called with its arguments, a function of the
+    /// program in its own scope
+    fn call(&mut self, _evaluator: &mut Evaluator, _callee: &Expr, _function: &Object, _args: &[Object]) {}
+
+    /// the function called last returns a value
+    fn returned(&mut self, _evaluator: &mut Evaluator, _value: &Object) {}
+
+    /// a name is bound to a value, by a let statement or as a parameter of a call
+    fn bound(&mut self, _evaluator: &mut Evaluator, _ident: &Ident, _value: &Object) {}
+
+    /// an error arises, rather than one of the values evaluated for it being an error already
+    fn error(&mut self, _evaluator: &mut Evaluator, _message: &str) {}
+}
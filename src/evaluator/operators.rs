@@ -0,0 +1,153 @@
+use crate::evaluator::object::Object;
+use crate::parser::ast::{Infix, Prefix, Type};
+
+// The semantics of the operators, shared by every backend so they give the same results.
+
+pub fn prefix(prefix: &Prefix, object: Object) -> Object {
+    match *prefix {
+        Prefix::PrefixPlus => match to_int(object) {
+            Ok(i) => Object::Integer(i),
+            Err(err) => err,
+        },
+        Prefix::PrefixMinus => match to_int(object) {
+            Ok(i) => checked(i.checked_neg(), "negation"),
+            Err(err) => err,
+        },
+        Prefix::Not => match to_bool(object) {
+            Ok(b) => Object::Boolean(!b),
+            Err(err) => err,
+        },
+    }
+}
+
+pub fn infix(infix: &Infix, object1: Object, object2: Object) -> Object {
+    match *infix {
+        Infix::Plus => add(object1, object2),
+        Infix::Minus => int_op(object1, object2, |i1, i2| checked(i1.checked_sub(i2), "subtraction")),
+        Infix::Divide => int_op(object1, object2, |i1, i2| {
+            if i2 == 0 {
+                Object::Error("division by zero".to_string())
+            } else {
+                checked(i1.checked_div(i2), "division")
+            }
+        }),
+        Infix::Multiply => int_op(object1, object2, |i1, i2| checked(i1.checked_mul(i2), "multiplication")),
+        Infix::Equal => Object::Boolean(object1 == object2),
+        Infix::NotEqual => Object::Boolean(object1 != object2),
+        Infix::GreaterThanEqual => int_op(object1, object2, |i1, i2| Object::Boolean(i1 >= i2)),
+        Infix::LessThanEqual => int_op(object1, object2, |i1, i2| Object::Boolean(i1 <= i2)),
+        Infix::GreaterThan => int_op(object1, object2, |i1, i2| Object::Boolean(i1 > i2)),
+        Infix::LessThan => int_op(object1, object2, |i1, i2| Object::Boolean(i1 < i2)),
+    }
+}
+
+fn int_op(object1: Object, object2: Object, op: impl FnOnce(i64, i64) -> Object) -> Object {
+    match (to_int(object1), to_int(object2)) {
+        (Ok(i1), Ok(i2)) => op(i1, i2),
+        (Err(err), _) | (_, Err(err)) => err,
+    }
+}
+
+/// the result of an integer operation, or an error when it overflowed
+fn checked(result: Option<i64>, operation: &str) -> Object {
+    match result {
+        Some(i) => Object::Integer(i),
+        None => Object::Error(format!("integer overflow in {}", operation)),
+    }
+}
+
+pub fn add(object1: Object, object2: Object) -> Object {
+    match (object1, object2) {
+        (Object::Integer(i1), Object::Integer(i2)) => checked(i1.checked_add(i2), "addition"),
+        (Object::String(s1), Object::String(s2)) => Object::String(s1 + &s2),
+        (Object::Error(s), _) | (_, Object::Error(s)) => Object::Error(s),
+        (x, y) => Object::Error(format!("{} and {} are not addable", x, y)),
+    }
+}
+
+pub fn index(target: Object, index: Object) -> Object {
+    match target {
+        Object::Array(arr) => match to_int(index) {
+            Ok(index_number) => arr.into_iter()
+                .nth(index_number as usize)
+                .unwrap_or(Object::Null),
+            Err(err) => err,
+        },
+        Object::Hash(mut hash) => {
+            let name = to_hashable(index);
+            match name {
+                Object::Error(_) => name,
+                _ => hash.remove(&name).unwrap_or(Object::Null),
+            }
+        }
+        o => Object::Error(format!("unexpected index target: {}", o)),
+    }
+}
+
+pub fn to_bool(object: Object) -> Result<bool, Object> {
+    match object {
+        Object::Boolean(b) => Ok(b),
+        Object::Error(s) => Err(Object::Error(s)),
+        b => Err(Object::Error(format!("{} is not a bool", b))),
+    }
+}
+
+pub fn to_int(object: Object) -> Result<i64, Object> {
+    match object {
+        Object::Integer(i) => Ok(i),
+        Object::Error(s) => Err(Object::Error(s)),
+        _ => Err(Object::Error(format!("{} is not an integer", object))),
+    }
+}
+
+pub fn to_hashable(object: Object) -> Object {
+    match object {
+        Object::Integer(i) => Object::Integer(i),
+        Object::Boolean(b) => Object::Boolean(b),
+        Object::String(s) => Object::String(s),
+        Object::Error(s) => Object::Error(s),
+        x => Object::Error(format!("{} is not hashable", x)),
+    }
+}
+
+/// error of a call to something that is not a function
+pub fn call_error(object: Object) -> Object {
+    match object {
+        Object::Error(s) => Object::Error(s),
+        f => Object::Error(format!("{} is not a valid function", f)),
+    }
+}
+
+/// error of a call passed `given` arguments when `expected` were declared
+pub fn arity_error(expected: usize, given: usize) -> Object {
+    Object::Error(format!(
+        "wrong number of arguments: {} expected but {} given",
+        expected,
+        given,
+    ))
+}
+
+/// The value bound to `name`, or the error of its not having the type `name` is annotated
+/// with. Functions are only checked for their number of parameters.
+pub fn annotated(name: &str, ty: &Type, object: Object) -> Object {
+    match object {
+        Object::Error(_) => object,
+        _ if has_type(&object, ty) => object,
+        _ => Object::Error(format!("wrong type for {}: {} expected but {} given", name, ty, object)),
+    }
+}
+
+fn has_type(object: &Object, ty: &Type) -> bool {
+    match (object, ty) {
+        (Object::Integer(_), Type::Int)
+        | (Object::Boolean(_), Type::Bool)
+        | (Object::String(_), Type::String)
+        | (Object::Null, Type::Null) => true,
+        (Object::Array(items), Type::Array(t)) => items.iter().all(|o| has_type(o, t)),
+        (Object::Hash(pairs), Type::Hash(k, v)) => pairs.iter().all(|(key, value)| has_type(key, k) && has_type(value, v)),
+        (Object::Function(params, ..), Type::Fn(types, _)) => params.len() == types.len(),
+        (Object::Builtin(_, arity, _), Type::Fn(types, _)) => *arity == types.len(),
+        (Object::Closure(closure), Type::Fn(types, _)) => closure.function.num_params == types.len(),
+        _ => false,
+    }
+}
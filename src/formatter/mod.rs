@@ -0,0 +1,197 @@
+//! Formatting of source files to the canonical style of `parser::printer`, keeping comments.
+//!
+//! The program is printed from its syntax tree, then the comments of the source are put back.
+//! The printed tokens are those of the source in the same order, but for the parentheses and
+//! semicolons the printer may add or leave out, so a comment written on a line of its own goes
+//! on a line of its own before the line of the token it preceded, and a comment ending a line
+//! of code ends the line of the token it followed. When the printer joined the token after a
+//! comment to the line of the token before it, the comment follows that token as if it ended
+//! its line, keeping the comments in order. Blank lines between statements and comments are
+//! kept, one at most.
+
+use std::fmt::{self, Formatter};
+
+use crate::lexer::token::{Token, Tokens};
+use crate::lexer::{Lexer, Spans};
+use crate::parser::printer::print;
+use crate::parser::Parser;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FormatError {
+    /// the source is not a program
+    Syntax,
+    /// the printed program has other tokens than its source, a bug of the printer
+    Tokens,
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            FormatError::Syntax => write!(f, "the code does not parse"),
+            FormatError::Tokens => write!(f, "the formatted code would not have the tokens of the source"),
+        }
+    }
+}
+
+/// what goes on the lines before a printed line
+#[derive(Debug, Clone, PartialEq)]
+enum Line {
+    Blank,
+    Comment(String),
+}
+
+pub fn format(source: &str) -> Result<String, FormatError> {
+    let (_, tokens) = Lexer::lex_tokens(source.as_bytes()).map_err(|_| FormatError::Syntax)?;
+    let (_, program) = Parser::parse_tokens(Tokens::new(&tokens)).map_err(|_| FormatError::Syntax)?;
+    let printed = print(&program);
+
+    let (tokens, comments) = Lexer::lex_spans(source.as_bytes());
+    let source_tokens = significant(tokens);
+    let printed_tokens = significant(Lexer::lex_spans(printed.as_bytes()).0);
+    if !source_tokens.iter().map(|(_, t)| t).eq(printed_tokens.iter().map(|(_, t)| t)) {
+        return Err(FormatError::Tokens);
+    }
+    let lines = printed.lines().collect::<Vec<_>>();
+    let newlines = printed.match_indices('\n').map(|(i, _)| i).collect::<Vec<_>>();
+    let line_of = |offset: usize| newlines.partition_point(|&i| i < offset);
+
+    // what goes before each printed line, at the end of it, and at the end of the output
+    let mut before = vec![vec![]; lines.len()];
+    let mut after = vec![vec![]; lines.len()];
+    let mut end = vec![];
+    let mut comments = comments.into_iter().peekable();
+    for (k, (span, token)) in source_tokens.iter().enumerate() {
+        let offset = span.start;
+        let line = line_of(printed_tokens[k].0.start);
+        while let Some((o, text)) = comments.next_if(|(o, _)| o.start < offset) {
+            let (o, text) = (o.start, text.trim_end().to_string());
+            let previous = k.checked_sub(1).map(|k| line_of(printed_tokens[k].0.start));
+            if previous.is_some_and(|previous| !first_on_line(source, o) || previous == line) {
+                after[previous.unwrap_or_default()].push(text);
+                continue;
+            }
+            if blank_before(source, o) {
+                before[line].push(Line::Blank);
+            }
+            before[line].push(Line::Comment(text));
+        }
+        if blank_before(source, offset) && first_on_line(&printed, printed_tokens[k].0.start) && !closing(token) {
+            before[line].push(Line::Blank);
+        }
+    }
+    for (o, text) in comments {
+        let (o, text) = (o.start, text.trim_end().to_string());
+        match source_tokens.len().checked_sub(1) {
+            Some(last) if !first_on_line(source, o) => after[line_of(printed_tokens[last].0.start)].push(text),
+            _ => {
+                if blank_before(source, o) {
+                    end.push(Line::Blank);
+                }
+                end.push(Line::Comment(text));
+            }
+        }
+    }
+
+    let mut out = String::new();
+    for (i, line) in lines.iter().enumerate() {
+        let indent = &line[..line.len() - line.trim_start().len()];
+        write_lines(&mut out, indent, &before[i]);
+        out.push_str(line);
+        let mut trailing = after[i].iter();
+        if let Some(comment) = trailing.next() {
+            out.push(' ');
+            out.push_str(comment);
+        }
+        out.push('\n');
+        // comments ending lines the printer joined go on the lines after
+        for comment in trailing {
+            out.push_str(indent);
+            out.push_str(comment);
+            out.push('\n');
+        }
+    }
+    write_lines(&mut out, "", &end);
+    Ok(out)
+}
+
+/// the tokens but for parentheses and semicolons
+fn significant(tokens: Spans<Token>) -> Spans<Token> {
+    tokens
+        .into_iter()
+        .filter(|(_, token)| !matches!(token, Token::LParen | Token::RParen | Token::SemiColon))
+        .collect()
+}
+
+fn write_lines(out: &mut String, indent: &str, lines: &[Line]) {
+    for line in lines {
+        match line {
+            Line::Blank => {
+                // no blank line at the start of the output or of a block, nor two in a row
+                let last = out.trim_end_matches('\n').lines().last().unwrap_or_default();
+                let opening = last.ends_with(['{', '[', '(']);
+                if !out.is_empty() && !out.ends_with("\n\n") && !opening {
+                    out.push('\n');
+                }
+            }
+            Line::Comment(text) => {
+                out.push_str(indent);
+                out.push_str(text);
+                out.push('\n');
+            }
+        }
+    }
+}
+
+/// whether only whitespace comes before `offset` on its line
+fn first_on_line(text: &str, offset: usize) -> bool {
+    let start = text[..offset].rfind('\n').map_or(0, |i| i + 1);
+    text[start..offset].trim().is_empty()
+}
+
+/// whether a blank line separates `offset` from what comes before it
+fn blank_before(text: &str, offset: usize) -> bool {
+    let before = text[..offset].trim_end();
+    !before.is_empty() && text[before.len()..offset].matches('\n').count() >= 2
+}
+
+fn closing(token: &Token) -> bool {
+    matches!(token, Token::RBrace | Token::RBracket)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(input: &str, expected: &str) {
+        let formatted = format(input).unwrap();
+        assert_eq!(formatted, expected, "{}", input);
+        assert_eq!(format(&formatted).unwrap(), formatted, "{}", formatted);
+    }
+
+    #[test]
+    fn code() {
+        check("let  x=1\nlet f = fn(a){a*(x+1)};f( 2 )", "let x = 1;\nlet f = fn(a) {\n    a * (x + 1)\n};\nf(2)\n");
+        check("", "");
+        assert_eq!(format("let = 1"), Err(FormatError::Syntax));
+    }
+
+    #[test]
+    fn comments() {
+        check(
+            "// header\n\n\n// about x\nlet x = 1; // one\nlet f = fn() { // body\n  // first\n  x // value\n};\n// end\n",
+            "// header\n\n// about x\nlet x = 1; // one\nlet f = fn() { // body\n    // first\n    x // value\n};\n// end\n",
+        );
+        // comments in a list the printer joins follow its line, in order
+        check("f(1, // one\n  // two\n  2) // three\n", "f(1, 2) // one\n// two\n// three\n");
+        check("[\n  // first\n  1,\n  // second\n  2\n]\n", "[1, 2] // first\n// second\n");
+        check("// only\n", "// only\n");
+    }
+
+    #[test]
+    fn blank_lines() {
+        check(
+            "let a = 1;\n\n\n\nlet b = fn() {\n\n  a\n\n};\n\n// c\n\nb()",
+            "let a = 1;\n\nlet b = fn() {\n    a\n};\n\n// c\n\nb()\n",
+        );
+    }
+}
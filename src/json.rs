@@ -0,0 +1,299 @@
+//! JSON values, read and written for the tools speaking it.
+
+use std::fmt::{self, Formatter};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    /// the members in the order they were written
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// Builds an object from its members.
+    pub fn object<const N: usize>(members: [(&str, Json); N]) -> Json {
+        Json::Object(members.into_iter().map(|(key, value)| (key.to_string(), value)).collect())
+    }
+
+    pub fn string(s: &str) -> Json {
+        Json::String(s.to_string())
+    }
+
+    /// The value of a member of an object, null if there is none.
+    pub fn get(&self, key: &str) -> &Json {
+        match self {
+            Json::Object(members) => members.iter().find(|(k, _)| k == key).map_or(&Json::Null, |(_, v)| v),
+            _ => &Json::Null,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// the value of a number which is a non-negative integer
+    pub fn as_usize(&self) -> Option<usize> {
+        match self {
+            Json::Number(n) if *n >= 0.0 && n.fract() == 0.0 => Some(*n as usize),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    /// Reads a value from text holding it alone, with whitespace around it.
+    pub fn parse(text: &str) -> Result<Json, String> {
+        let mut reader = Reader { text, at: 0 };
+        let value = reader.value()?;
+        reader.spaces();
+        match reader.at == text.len() {
+            true => Ok(value),
+            false => Err(reader.error("end of text")),
+        }
+    }
+}
+
+impl From<usize> for Json {
+    fn from(n: usize) -> Json {
+        Json::Number(n as f64)
+    }
+}
+
+impl From<bool> for Json {
+    fn from(b: bool) -> Json {
+        Json::Bool(b)
+    }
+}
+
+impl From<String> for Json {
+    fn from(s: String) -> Json {
+        Json::String(s)
+    }
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Number(n) if n.is_finite() => write!(f, "{}", n),
+            Json::Number(_) => write!(f, "null"),
+            Json::String(s) => write_string(f, s),
+            Json::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(members) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ": {}", value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut Formatter<'_>, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+struct Reader<'a> {
+    text: &'a str,
+    at: usize,
+}
+
+impl Reader<'_> {
+    fn error(&self, expected: &str) -> String {
+        format!("{} expected at byte {}", expected, self.at)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.text[self.at..].chars().next()
+    }
+
+    fn spaces(&mut self) {
+        while let Some(' ' | '\t' | '\n' | '\r') = self.peek() {
+            self.at += 1;
+        }
+    }
+
+    fn eat(&mut self, word: &str) -> bool {
+        let found = self.text[self.at..].starts_with(word);
+        if found {
+            self.at += word.len();
+        }
+        found
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.spaces();
+        match self.peek() {
+            Some('{') => {
+                self.at += 1;
+                let mut members = vec![];
+                self.spaces();
+                if !self.eat("}") {
+                    loop {
+                        self.spaces();
+                        let key = self.string()?;
+                        self.spaces();
+                        if !self.eat(":") {
+                            return Err(self.error("`:`"));
+                        }
+                        members.push((key, self.value()?));
+                        self.spaces();
+                        if self.eat("}") {
+                            break;
+                        } else if !self.eat(",") {
+                            return Err(self.error("`,` or `}`"));
+                        }
+                    }
+                }
+                Ok(Json::Object(members))
+            }
+            Some('[') => {
+                self.at += 1;
+                let mut items = vec![];
+                self.spaces();
+                if !self.eat("]") {
+                    loop {
+                        items.push(self.value()?);
+                        self.spaces();
+                        if self.eat("]") {
+                            break;
+                        } else if !self.eat(",") {
+                            return Err(self.error("`,` or `]`"));
+                        }
+                    }
+                }
+                Ok(Json::Array(items))
+            }
+            Some('"') => self.string().map(Json::String),
+            Some('-' | '0'..='9') => {
+                let len = self.text[self.at..]
+                    .find(|c: char| !matches!(c, '-' | '+' | '.' | 'e' | 'E' | '0'..='9'))
+                    .unwrap_or(self.text.len() - self.at);
+                let number = self.text[self.at..self.at + len].parse().map_err(|_| self.error("a number"))?;
+                self.at += len;
+                Ok(Json::Number(number))
+            }
+            _ if self.eat("null") => Ok(Json::Null),
+            _ if self.eat("true") => Ok(Json::Bool(true)),
+            _ if self.eat("false") => Ok(Json::Bool(false)),
+            _ => Err(self.error("a value")),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        if !self.eat("\"") {
+            return Err(self.error("a string"));
+        }
+        let mut out = String::new();
+        loop {
+            let c = self.peek().ok_or_else(|| self.error("`\"`"))?;
+            self.at += c.len_utf8();
+            match c {
+                '"' => return Ok(out),
+                '\\' => {
+                    let escaped = self.peek().ok_or_else(|| self.error("an escape"))?;
+                    self.at += 1;
+                    match escaped {
+                        '"' | '\\' | '/' => out.push(escaped),
+                        'b' => out.push('\u{8}'),
+                        'f' => out.push('\u{c}'),
+                        'n' => out.push('\n'),
+                        'r' => out.push('\r'),
+                        't' => out.push('\t'),
+                        'u' => {
+                            let mut unit = self.unit()?;
+                            // a character outside the basic plane is escaped as two halves
+                            if (0xd800..0xdc00).contains(&unit) && self.eat("\\u") {
+                                let low = self.unit()?;
+                                unit = 0x10000 + ((unit - 0xd800) << 10) + (low.wrapping_sub(0xdc00) & 0x3ff);
+                            }
+                            out.push(char::from_u32(unit).unwrap_or(char::REPLACEMENT_CHARACTER));
+                        }
+                        _ => return Err(self.error("an escape")),
+                    }
+                }
+                c => out.push(c),
+            }
+        }
+    }
+
+    /// the four hexadecimal digits of a `\u` escape
+    fn unit(&mut self) -> Result<u32, String> {
+        let digits = self.text.get(self.at..self.at + 4).ok_or_else(|| self.error("four hexadecimal digits"))?;
+        let unit = u32::from_str_radix(digits, 16).map_err(|_| self.error("four hexadecimal digits"))?;
+        self.at += 4;
+        Ok(unit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values() {
+        let text = r#" {"a": [1, -2.5e1, true, null], "b\"é😀": {}, "c": []} "#;
+        let value = Json::parse(text).unwrap();
+        assert_eq!(
+            value,
+            Json::object([
+                ("a", Json::Array(vec![1.into(), Json::Number(-25.0), true.into(), Json::Null])),
+                ("b\"é😀", Json::Object(vec![])),
+                ("c", Json::Array(vec![])),
+            ])
+        );
+        assert_eq!(value.get("a").as_array().unwrap()[0].as_usize(), Some(1));
+        assert_eq!(value.get("d"), &Json::Null);
+        assert_eq!(value.to_string(), "{\"a\": [1, -25, true, null], \"b\\\"é😀\": {}, \"c\": []}");
+        assert_eq!(Json::parse(&value.to_string()), Ok(value));
+        assert_eq!(Json::string("a\n\u{1}").to_string(), "\"a\\n\\u0001\"");
+
+        assert_eq!(Json::parse("[1,]"), Err("a value expected at byte 3".to_string()));
+        assert_eq!(Json::parse("{\"a\" 1}"), Err("`:` expected at byte 5".to_string()));
+        assert_eq!(Json::parse("1 2"), Err("end of text expected at byte 2".to_string()));
+    }
+}
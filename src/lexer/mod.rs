@@ -1,8 +1,10 @@
 mod char_util;
 pub mod token;
 
+use std::ops::Range;
+
 use nom::branch::alt;
-use nom::bytes::complete::{tag, take};
+use nom::bytes::complete::{tag, take, take_till};
 use nom::combinator::{map, map_res, recognize};
 use nom::{AsBytes, IResult};
 
@@ -10,13 +12,13 @@ use crate::lexer::char_util::{
     complete_byte_slice_str_from_utf8, complete_str_from_str, concat_slice_vec, convert_vec_utf8,
 };
 use crate::lexer::token::Token;
-use nom::character::complete::{alpha1, alphanumeric1, digit1, multispace0};
+use nom::character::complete::{alpha1, alphanumeric1, digit1, multispace1};
 use nom::multi::many0;
 use nom::sequence::{delimited, pair};
 
 macro_rules! syntax_func_map_tag {
     ($func_name: ident, $tag_string: literal, $output_token: expr) => {
-        fn $func_name<'a>(s: &'a [u8]) -> IResult<&[u8], Token> {
+        fn $func_name(s: &[u8]) -> IResult<&[u8], Token> {
             map(tag($tag_string), |_| $output_token)(s)
         }
     };
@@ -42,6 +44,8 @@ pub fn lex_operator(input: &[u8]) -> IResult<&[u8], Token> {
         not_equal_operator,
         assign_operator,
         plus_operator,
+        // the arrow of return types, before `-` reads its first character
+        arrow_punctuation,
         minus_operator,
         multiply_operator,
         divide_operator,
@@ -54,9 +58,11 @@ pub fn lex_operator(input: &[u8]) -> IResult<&[u8], Token> {
 }
 
 // punctuations
+syntax_func_map_tag!(arrow_punctuation, "->", Token::Arrow);
 syntax_func_map_tag!(comma_punctuation, ",", Token::Comma);
 syntax_func_map_tag!(semicolon_punctuation, ";", Token::SemiColon);
 syntax_func_map_tag!(colon_punctuation, ":", Token::Colon);
+syntax_func_map_tag!(dot_punctuation, ".", Token::Dot);
 syntax_func_map_tag!(lparen_punctuation, "(", Token::LParen);
 syntax_func_map_tag!(rparen_punctuation, ")", Token::RParen);
 syntax_func_map_tag!(lbrace_punctuation, "{", Token::LBrace);
@@ -69,6 +75,7 @@ pub fn lex_punctuations(input: &[u8]) -> IResult<&[u8], Token> {
         comma_punctuation,
         semicolon_punctuation,
         colon_punctuation,
+        dot_punctuation,
         lparen_punctuation,
         rparen_punctuation,
         lbrace_punctuation,
@@ -115,6 +122,8 @@ fn lex_reserved_ident(input: &[u8]) -> IResult<&[u8], Token> {
                 "if" => Token::If,
                 "else" => Token::Else,
                 "return" => Token::Return,
+                "import" => Token::Import,
+                "export" => Token::Export,
                 "true" => Token::BoolLiteral(true),
                 "false" => Token::BoolLiteral(false),
                 _ => Token::Ident(syntax.to_string()),
@@ -150,10 +159,23 @@ fn lex_token(input: &[u8]) -> IResult<&[u8], Token> {
     ))(input)
 }
 
+// comments, from `//` to the end of the line
+fn comment(input: &[u8]) -> IResult<&[u8], &[u8]> {
+    recognize(pair(tag("//"), take_till(|c| c == b'\n')))(input)
+}
+
+// whitespace and comments
+fn trivia(input: &[u8]) -> IResult<&[u8], ()> {
+    map(many0(alt((multispace1, comment))), |_| ())(input)
+}
+
 fn lex_tokens(input: &[u8]) -> IResult<&[u8], Vec<Token>> {
-    many0(delimited(multispace0, lex_token, multispace0))(input)
+    many0(delimited(trivia, lex_token, trivia))(input)
 }
 
+/// tokens or comments, each with the range of the source it was read from
+pub type Spans<T> = Vec<(Range<usize>, T)>;
+
 pub struct Lexer;
 
 impl Lexer {
@@ -161,6 +183,29 @@ impl Lexer {
         lex_tokens(bytes)
             .map(|(slice, result)| (slice, [&result[..], &vec![Token::EOF][..]].concat()))
     }
+
+    /// Lexes like `lex_tokens`, without the final `EOF`, giving the range in the source of
+    /// each token, and the comments skipped with their ranges.
+    pub fn lex_spans(bytes: &[u8]) -> (Spans<Token>, Spans<String>) {
+        let mut tokens = vec![];
+        let mut comments = vec![];
+        let mut rest = bytes;
+        loop {
+            let spaces = rest.iter().take_while(|&&b| matches!(b, b' ' | b'\t' | b'\r' | b'\n')).count();
+            rest = &rest[spaces..];
+            let offset = bytes.len() - rest.len();
+            if let Ok((r, text)) = comment(rest) {
+                comments.push((offset..offset + text.len(), String::from_utf8_lossy(text).into_owned()));
+                rest = r;
+            } else if let Ok((r, token)) = lex_token(rest) {
+                tokens.push((offset..bytes.len() - r.len(), token));
+                rest = r;
+            } else {
+                break;
+            }
+        }
+        (tokens, comments)
+    }
 }
 
 #[cfg(test)]
@@ -364,6 +409,32 @@ mod tests {
         );
     }
 
+    #[test]
+    fn comments() {
+        let input = &b"let a = 1; // one\n// two\na / 2 //\n"[..];
+        let (_, result) = Lexer::lex_tokens(input).unwrap();
+        let expected_results = vec![
+            Token::Let,
+            Token::Ident("a".to_owned()),
+            Token::Assign,
+            Token::IntLiteral(1),
+            Token::SemiColon,
+            Token::Ident("a".to_owned()),
+            Token::Divide,
+            Token::IntLiteral(2),
+            Token::EOF,
+        ];
+        assert_eq!(result, expected_results);
+
+        let (tokens, comments) = Lexer::lex_spans(input);
+        assert_eq!(tokens.len(), expected_results.len() - 1);
+        assert_eq!(tokens[5], (25..26, Token::Ident("a".to_owned())));
+        assert_eq!(
+            comments,
+            vec![(11..17, "// one".to_owned()), (18..24, "// two".to_owned()), (31..33, "//".to_owned())]
+        );
+    }
+
     #[test]
     fn id_with_numbers() {
         let (_, result) = Lexer::lex_tokens(&b"hello2 hel301oo120"[..]).unwrap();
@@ -403,4 +474,24 @@ mod tests {
         ];
         assert_eq!(result, expected);
     }
+
+    #[test]
+    fn module_tokens() {
+        let (_, result) = Lexer::lex_tokens(&b"import \"lib.mk\" as lib; export f; lib.f"[..]).unwrap();
+        let expected = vec![
+            Token::Import,
+            Token::StringLiteral("lib.mk".to_owned()),
+            Token::Ident("as".to_owned()),
+            Token::Ident("lib".to_owned()),
+            Token::SemiColon,
+            Token::Export,
+            Token::Ident("f".to_owned()),
+            Token::SemiColon,
+            Token::Ident("lib".to_owned()),
+            Token::Dot,
+            Token::Ident("f".to_owned()),
+            Token::EOF,
+        ];
+        assert_eq!(result, expected);
+    }
 }
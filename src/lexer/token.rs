@@ -31,9 +31,13 @@ pub enum Token {
     Function,
     Let,
     Return,
+    Import,
+    Export,
     // punctuations
+    Arrow,
     Comma,
     Colon,
+    Dot,
     SemiColon,
     LParen,
     RParen,
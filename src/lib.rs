@@ -1,4 +1,16 @@
-
 pub mod evaluator;
 pub mod parser;
-pub mod lexer;
\ No newline at end of file
+pub mod lexer;
+pub mod resolver;
+pub mod compiler;
+pub mod vm;
+pub mod engine;
+pub mod optimizer;
+pub mod codegen;
+pub mod formatter;
+pub mod linter;
+pub mod json;
+pub mod lsp;
+pub mod dap;
+pub mod debug;
+pub mod testing;
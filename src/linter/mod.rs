@@ -0,0 +1,715 @@
+//! Static analysis of programs, reporting likely mistakes without running them.
+//!
+//! Scopes follow the resolver: function bodies create them, and a `let` anywhere in a body
+//! binds in the body's scope, for the functions of the whole body and for the body itself
+//! from the `let` on. Checking types is optional, as programs
+//! need not have static types to run.
+
+pub(crate) mod spans;
+mod types;
+
+use std::collections::HashMap;
+use std::fmt::{self, Formatter};
+use std::ops::Range;
+
+use crate::evaluator::builtins::BuiltinFunctions;
+use crate::evaluator::object::Object;
+use crate::json::Json;
+use crate::lexer::token::Tokens;
+use crate::lexer::Lexer;
+use crate::optimizer::Optimizer;
+use crate::parser::ast::{Expr, Ident, Infix, Program, Stmt};
+use crate::parser::Parser;
+use spans::Locations;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Rule {
+    /// a `let` binding never read
+    UnusedVariable,
+    /// a parameter never read
+    UnusedParameter,
+    /// a binding hiding one of an enclosing scope, or a builtin
+    ShadowedName,
+    /// statements after a `return`
+    UnreachableCode,
+    /// a call to a known function or builtin with another number of arguments than it takes
+    WrongArity,
+    /// a comparison of an expression with itself
+    SelfComparison,
+    /// a name bound nowhere
+    UndefinedName,
+    /// an expression whose type is not the one its use needs
+    TypeMismatch,
+}
+
+impl Rule {
+    pub fn id(self) -> &'static str {
+        match self {
+            Rule::UnusedVariable => "unused-variable",
+            Rule::UnusedParameter => "unused-parameter",
+            Rule::ShadowedName => "shadowed-name",
+            Rule::UnreachableCode => "unreachable-code",
+            Rule::WrongArity => "wrong-arity",
+            Rule::SelfComparison => "self-comparison",
+            Rule::UndefinedName => "undefined-name",
+            Rule::TypeMismatch => "type-mismatch",
+        }
+    }
+
+    /// errors are mistakes failing the program when reached
+    pub fn severity(self) -> Severity {
+        match self {
+            Rule::WrongArity | Rule::UndefinedName | Rule::TypeMismatch => Severity::Error,
+            _ => Severity::Warning,
+        }
+    }
+}
+
+/// A place in the source, both counted from 1, columns in characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// The source from `start` up to, not including, `end`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub rule: Rule,
+    pub span: Span,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn severity(&self) -> Severity {
+        self.rule.severity()
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let Position { line, column } = self.span.start;
+        write!(f, "{}:{}: {}: {} [{}]", line, column, self.severity(), self.message, self.rule.id())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LintError {
+    /// the source is not a program from the statement starting at the position
+    Syntax(Position),
+}
+
+impl fmt::Display for LintError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            LintError::Syntax(Position { line, column }) => {
+                write!(f, "the code does not parse from line {}, column {}", line, column)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Let,
+    Param,
+    Import,
+}
+
+/// A name a program binds, with where it is used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: Kind,
+    /// the name where it is first bound in its scope
+    pub definition: Span,
+    /// the name where it is bound again or read
+    pub references: Vec<Span>,
+    /// its inferred type
+    pub ty: Option<String>,
+    /// the literal it is bound to, when it is bound once to a value known before running
+    pub value: Option<String>,
+}
+
+/// Analyzes a program, inferring its types too if `types`, giving what it finds in the order
+/// of the source.
+pub fn check(source: &str, types: bool) -> Result<Vec<Diagnostic>, LintError> {
+    let program = parse(source)?;
+    let analysis = Analysis::run(source, &program);
+    let mut found = analysis.found;
+    if types {
+        // wrong arities to known functions are found by both
+        found.extend(types::infer(&analysis.locations, &analysis.builtins, &program).found);
+    }
+    found.sort_by(|(rule1, range1, message1), (rule2, range2, message2)| {
+        (range1.start, rule1, message1).cmp(&(range2.start, rule2, message2))
+    });
+    found.dedup();
+    let lines = Lines::new(source);
+    Ok(found
+        .into_iter()
+        .map(|(rule, range, message)| Diagnostic {
+            rule,
+            span: Span { start: lines.position(range.start), end: lines.position(range.end) },
+            message,
+        })
+        .collect())
+}
+
+/// The names bound by a program, in the order of the source.
+pub fn symbols(source: &str) -> Result<Vec<Symbol>, LintError> {
+    let program = parse(source)?;
+    let analysis = Analysis::run(source, &program);
+    let mut inferred = types::infer(&analysis.locations, &analysis.builtins, &program);
+    let lines = Lines::new(source);
+    let span = |range: &Range<usize>| Span { start: lines.position(range.start), end: lines.position(range.end) };
+    let mut symbols = analysis.symbols;
+    symbols.sort_by_key(|(_, binding)| binding.range.start);
+    Ok(symbols
+        .into_iter()
+        .map(|(name, binding)| Symbol {
+            name,
+            kind: binding.kind,
+            definition: span(&binding.range),
+            references: binding.references.iter().map(span).collect(),
+            ty: inferred.bindings.remove(&binding.range),
+            value: binding.value,
+        })
+        .collect())
+}
+
+/// The program of a source.
+fn parse(source: &str) -> Result<Program, LintError> {
+    let syntax = |offset| LintError::Syntax(Lines::new(source).position(offset));
+    let (_, tokens) = Lexer::lex_tokens(source.as_bytes()).map_err(|_| syntax(0))?;
+    match Parser::parse_tokens(Tokens::new(&tokens)) {
+        Ok((_, program)) => Ok(program),
+        // statements are parsed until one does not, then the end is expected
+        Err(nom::Err::Error(e) | nom::Err::Failure(e)) => {
+            let (spans, _) = Lexer::lex_spans(source.as_bytes());
+            let parsed = tokens.len() - e.input.tok.len();
+            Err(syntax(spans.get(parsed).map_or(source.len(), |(range, _)| range.start)))
+        }
+        Err(nom::Err::Incomplete(_)) => Err(syntax(source.len())),
+    }
+}
+
+/// Writes the diagnostics of files as a JSON array of objects.
+pub fn to_json(files: &[(String, Vec<Diagnostic>)]) -> String {
+    let position = |p: Position| format!("{{\"line\": {}, \"column\": {}}}", p.line, p.column);
+    let objects = files
+        .iter()
+        .flat_map(|(file, diagnostics)| diagnostics.iter().map(move |d| (file, d)))
+        .map(|(file, d)| {
+            format!(
+                "  {{\"file\": {}, \"rule\": \"{}\", \"severity\": \"{}\", \"message\": {}, \"start\": {}, \"end\": {}}}",
+                json_string(file),
+                d.rule.id(),
+                d.severity(),
+                json_string(&d.message),
+                position(d.span.start),
+                position(d.span.end)
+            )
+        })
+        .collect::<Vec<_>>();
+    if objects.is_empty() {
+        "[]".to_string()
+    } else {
+        format!("[\n{}\n]", objects.join(",\n"))
+    }
+}
+
+fn json_string(s: &str) -> String {
+    Json::string(s).to_string()
+}
+
+/// the offsets where the lines of a source start
+pub(crate) struct Lines<'a> {
+    source: &'a str,
+    starts: Vec<usize>,
+}
+
+impl<'a> Lines<'a> {
+    pub(crate) fn new(source: &'a str) -> Self {
+        let starts = std::iter::once(0).chain(source.match_indices('\n').map(|(i, _)| i + 1)).collect();
+        Lines { source, starts }
+    }
+
+    pub(crate) fn position(&self, offset: usize) -> Position {
+        let line = self.starts.partition_point(|&start| start <= offset) - 1;
+        let column = self.source[self.starts[line]..offset].chars().count() + 1;
+        Position { line: line + 1, column }
+    }
+}
+
+#[derive(Debug)]
+struct Binding {
+    kind: Kind,
+    /// where the name is first bound
+    range: Range<usize>,
+    reads: usize,
+    /// where the name is bound again or read
+    references: Vec<Range<usize>>,
+    /// the number of parameters of the function literal bound to the name, if it is bound
+    /// only once and to one
+    arity: Option<usize>,
+    /// the literal the name is bound to, if it is bound only once and to a value folding into one
+    value: Option<String>,
+    /// whether the analysis passed where the name is first bound, as parameters always were
+    bound: bool,
+}
+
+#[derive(Debug, Default)]
+struct Scope {
+    names: Vec<String>,
+    bindings: HashMap<String, Binding>,
+}
+
+impl Scope {
+    fn declare(&mut self, name: &str, binding: Binding) {
+        match self.bindings.get_mut(name) {
+            Some(bound) => {
+                bound.arity = None;
+                bound.value = None;
+                bound.references.push(binding.range);
+            }
+            None => {
+                self.names.push(name.to_string());
+                self.bindings.insert(name.to_string(), binding);
+            }
+        }
+    }
+}
+
+struct Analysis {
+    locations: Locations,
+    builtins: HashMap<String, usize>,
+    scopes: Vec<Scope>,
+    /// the bindings of the scopes left
+    symbols: Vec<(String, Binding)>,
+    found: Vec<(Rule, Range<usize>, String)>,
+}
+
+impl Analysis {
+    fn run(source: &str, program: &Program) -> Self {
+        let builtins = BuiltinFunctions::new()
+            .get_builtins()
+            .into_iter()
+            .filter_map(|(_, object)| match object {
+                Object::Builtin(name, arity, _) => Some((name, arity)),
+                _ => None,
+            })
+            .collect();
+        let mut analysis = Analysis {
+            locations: Locations::new(source, program),
+            builtins,
+            scopes: vec![],
+            symbols: vec![],
+            found: vec![],
+        };
+        analysis.scope(&[], program);
+        analysis
+    }
+
+    fn report(&mut self, rule: Rule, range: Range<usize>, message: String) {
+        self.found.push((rule, range, message));
+    }
+
+    /// Analyzes the body of a function, or the program with no parameters.
+    fn scope(&mut self, params: &[Ident], body: &Program) {
+        let mut scope = Scope::default();
+        for param in params {
+            let range = self.locations.ident(param);
+            let binding = Binding {
+                kind: Kind::Param,
+                range,
+                reads: 0,
+                references: vec![],
+                arity: None,
+                value: None,
+                bound: true,
+            };
+            scope.declare(&param.0, binding);
+        }
+        self.hoist(&mut scope, body);
+        for name in &scope.names {
+            let range = scope.bindings[name].range.clone();
+            if self.scopes.iter().any(|s| s.bindings.contains_key(name)) {
+                self.report(Rule::ShadowedName, range, format!("`{}` shadows a binding of an enclosing scope", name));
+            } else if self.builtins.contains_key(name) {
+                self.report(Rule::ShadowedName, range, format!("`{}` shadows a builtin", name));
+            }
+        }
+
+        self.scopes.push(scope);
+        self.block(body);
+        let mut scope = self.scopes.pop().expect("no scope");
+        // the test runner calls the tests of the program
+        let top = self.scopes.is_empty();
+        for name in scope.names {
+            let binding = scope.bindings.remove(&name).expect("no binding");
+            if binding.reads == 0 && !name.starts_with('_') && !(top && name.starts_with("test_")) {
+                let (rule, what) = match binding.kind {
+                    Kind::Let => (Rule::UnusedVariable, "variable"),
+                    Kind::Param => (Rule::UnusedParameter, "parameter"),
+                    Kind::Import => (Rule::UnusedVariable, "import"),
+                };
+                self.report(rule, binding.range.clone(), format!("{} `{}` is never used", what, name));
+            }
+            self.symbols.push((name, binding));
+        }
+    }
+
+    fn hoist(&self, scope: &mut Scope, program: &[Stmt]) {
+        for stmt in program {
+            match stmt {
+                Stmt::LetStmt(ident, _, expr) => {
+                    let arity = match expr {
+                        Expr::FnExpr { params, .. } => Some(params.len()),
+                        _ => None,
+                    };
+                    let range = self.locations.ident(ident);
+                    let value = folded(expr);
+                    let binding = Binding { kind: Kind::Let, range, reads: 0, references: vec![], arity, value, bound: false };
+                    scope.declare(&ident.0, binding);
+                    self.hoist_expr(scope, expr);
+                }
+                Stmt::ReturnStmt(expr) | Stmt::ExprStmt(expr) => self.hoist_expr(scope, expr),
+                Stmt::ImportStmt(_, import) => {
+                    for ident in import.idents() {
+                        let range = self.locations.ident(ident);
+                        let binding = Binding {
+                            kind: Kind::Import,
+                            range,
+                            reads: 0,
+                            references: vec![],
+                            arity: None,
+                            value: None,
+                            bound: false,
+                        };
+                        scope.declare(&ident.0, binding);
+                    }
+                }
+                Stmt::ExportStmt(_) => {}
+            }
+        }
+    }
+
+    fn hoist_expr(&self, scope: &mut Scope, expr: &Expr) {
+        match expr {
+            Expr::IdentExpr(_) | Expr::LiteralExpr(_) | Expr::FnExpr { .. } => {}
+            Expr::PrefixExpr(_, e) => self.hoist_expr(scope, e),
+            Expr::InfixExpr(_, e1, e2) => {
+                self.hoist_expr(scope, e1);
+                self.hoist_expr(scope, e2);
+            }
+            Expr::IfExpr { cond, consequence, alternative } => {
+                self.hoist_expr(scope, cond);
+                self.hoist(scope, consequence);
+                if let Some(alternative) = alternative {
+                    self.hoist(scope, alternative);
+                }
+            }
+            Expr::CallExpr { function, arguments } => {
+                self.hoist_expr(scope, function);
+                arguments.iter().for_each(|e| self.hoist_expr(scope, e));
+            }
+            Expr::ArrayExpr(exprs) => exprs.iter().for_each(|e| self.hoist_expr(scope, e)),
+            Expr::HashExpr(pairs) => pairs.iter().for_each(|(_, e)| self.hoist_expr(scope, e)),
+            Expr::IndexExpr { array, index } => {
+                self.hoist_expr(scope, array);
+                self.hoist_expr(scope, index);
+            }
+            Expr::MemberExpr { module, .. } => self.hoist_expr(scope, module),
+        }
+    }
+
+    /// The binding a name refers to, as the resolver finds it: one of the current scope once
+    /// it is bound, else one of an enclosing scope, else a builtin, else one of the current
+    /// scope bound later.
+    fn binding(&mut self, name: &str) -> Option<&mut Binding> {
+        let top = self.scopes.len() - 1;
+        let passed = |i: usize, scope: &Scope| scope.bindings.get(name).is_some_and(|binding| i < top || binding.bound);
+        let index = match (0..=top).rev().find(|&i| passed(i, &self.scopes[i])) {
+            Some(i) => i,
+            None if self.builtins.contains_key(name) => return None,
+            None => top,
+        };
+        self.scopes[index].bindings.get_mut(name)
+    }
+
+    /// marks a name of the current scope as bound from here on
+    fn bind(&mut self, name: &str) {
+        if let Some(binding) = self.scopes.last_mut().and_then(|scope| scope.bindings.get_mut(name)) {
+            binding.bound = true;
+        }
+    }
+
+    fn block(&mut self, program: &Program) {
+        if let Some(i) = program.iter().position(|stmt| matches!(stmt, Stmt::ReturnStmt(_))) {
+            if let (Some(first), Some(last)) = (program.get(i + 1), program.last()) {
+                let range = self.locations.stmt(first).start..self.locations.stmt(last).end;
+                self.report(Rule::UnreachableCode, range, "unreachable code after `return`".to_string());
+            }
+        }
+        for stmt in program {
+            match stmt {
+                Stmt::LetStmt(ident, _, expr) => {
+                    self.expr(expr);
+                    self.bind(&ident.0);
+                }
+                Stmt::ReturnStmt(expr) | Stmt::ExprStmt(expr) => self.expr(expr),
+                Stmt::ImportStmt(_, import) => import.idents().for_each(|ident| self.bind(&ident.0)),
+                Stmt::ExportStmt(idents) => {
+                    for ident in idents {
+                        self.read(&ident.0, self.locations.ident(ident));
+                    }
+                }
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::IdentExpr(Ident(name)) => self.read(name, self.locations.expr(expr)),
+            Expr::LiteralExpr(_) => {}
+            Expr::PrefixExpr(_, e) => self.expr(e),
+            Expr::InfixExpr(infix, e1, e2) => {
+                if is_comparison(infix) && e1 == e2 && !has_call(e1) {
+                    let range = self.locations.expr(expr);
+                    self.report(Rule::SelfComparison, range, format!("`{}` compares a value with itself", expr));
+                }
+                self.expr(e1);
+                self.expr(e2);
+            }
+            Expr::IfExpr { cond, consequence, alternative } => {
+                self.expr(cond);
+                self.block(consequence);
+                if let Some(alternative) = alternative {
+                    self.block(alternative);
+                }
+            }
+            Expr::FnExpr { params, body, .. } => self.scope(params, body),
+            Expr::CallExpr { function, arguments } => {
+                if let Some(arity) = self.arity(function) {
+                    if arity != arguments.len() {
+                        let range = self.locations.expr(expr);
+                        let message =
+                            format!("wrong number of arguments: {} expected but {} given", arity, arguments.len());
+                        self.report(Rule::WrongArity, range, message);
+                    }
+                }
+                self.expr(function);
+                arguments.iter().for_each(|e| self.expr(e));
+            }
+            Expr::ArrayExpr(exprs) => exprs.iter().for_each(|e| self.expr(e)),
+            Expr::HashExpr(pairs) => pairs.iter().for_each(|(_, e)| self.expr(e)),
+            Expr::IndexExpr { array, index } => {
+                self.expr(array);
+                self.expr(index);
+            }
+            Expr::MemberExpr { module, .. } => self.expr(module),
+        }
+    }
+
+    fn read(&mut self, name: &str, range: Range<usize>) {
+        if let Some(binding) = self.binding(name) {
+            binding.reads += 1;
+            binding.references.push(range);
+        } else if !self.builtins.contains_key(name) {
+            self.report(Rule::UndefinedName, range, format!("identifier not found: {}", name));
+        }
+    }
+
+    /// the number of parameters of a callee known before running the program
+    fn arity(&mut self, function: &Expr) -> Option<usize> {
+        match function {
+            Expr::FnExpr { params, .. } => Some(params.len()),
+            Expr::IdentExpr(Ident(name)) => match self.binding(name) {
+                Some(binding) => binding.arity,
+                None => self.builtins.get(name).copied(),
+            },
+            _ => None,
+        }
+    }
+}
+
+fn is_comparison(infix: &Infix) -> bool {
+    matches!(
+        infix,
+        Infix::Equal
+            | Infix::NotEqual
+            | Infix::GreaterThanEqual
+            | Infix::LessThanEqual
+            | Infix::GreaterThan
+            | Infix::LessThan
+    )
+}
+
+/// the literal an expression folds into, if it does without running the program
+fn folded(expr: &Expr) -> Option<String> {
+    match Optimizer::new().optimize(vec![Stmt::ExprStmt(expr.clone())]).as_slice() {
+        [Stmt::ExprStmt(Expr::LiteralExpr(literal))] => Some(literal.to_string()),
+        _ => None,
+    }
+}
+
+/// whether evaluating the expression may call a function, so twice may give other values
+fn has_call(expr: &Expr) -> bool {
+    match expr {
+        Expr::IdentExpr(_) | Expr::LiteralExpr(_) | Expr::FnExpr { .. } => false,
+        Expr::CallExpr { .. } | Expr::IfExpr { .. } => true,
+        Expr::PrefixExpr(_, e) | Expr::MemberExpr { module: e, .. } => has_call(e),
+        Expr::InfixExpr(_, e1, e2) | Expr::IndexExpr { array: e1, index: e2 } => has_call(e1) || has_call(e2),
+        Expr::ArrayExpr(exprs) => exprs.iter().any(has_call),
+        Expr::HashExpr(pairs) => pairs.iter().any(|(_, e)| has_call(e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// the rules found, each with the text it spans
+    fn check_rules(source: &str) -> Vec<(Rule, String)> {
+        let lines = source.lines().collect::<Vec<_>>();
+        check(source, false)
+            .unwrap()
+            .into_iter()
+            .map(|d| {
+                assert_eq!(d.span.start.line, d.span.end.line, "{}", d);
+                let line = lines[d.span.start.line - 1].chars();
+                let text = line.skip(d.span.start.column - 1).take(d.span.end.column - d.span.start.column);
+                (d.rule, text.collect())
+            })
+            .collect()
+    }
+
+    fn found(expected: &[(Rule, &str)]) -> Vec<(Rule, String)> {
+        expected.iter().map(|&(rule, text)| (rule, text.to_string())).collect()
+    }
+
+    #[test]
+    fn bindings() {
+        let source = "let used = 1; let unused = 2;\nlet f = fn(a, b, _c) { let len = a; len + used };\nf(1, 2, 3)";
+        assert_eq!(
+            check_rules(source),
+            found(&[
+                (Rule::UnusedVariable, "unused"),
+                (Rule::UnusedParameter, "b"),
+                (Rule::ShadowedName, "len"),
+            ])
+        );
+        // a binding read before it is bound, or only by itself, is used
+        assert_eq!(check_rules("let f = fn() { g() }; let g = fn() { f() }; f()"), vec![]);
+        // until its `let`, a body reads the binding of an enclosing scope
+        assert_eq!(
+            check_rules("let a = 1; let f = fn() { let b = a; let a = 2; b }; f()"),
+            found(&[(Rule::UnusedVariable, "a"), (Rule::ShadowedName, "a")])
+        );
+        // the tests of a program are used by the test runner
+        assert_eq!(
+            check_rules("let test_f = fn() { let test_x = 1; 2 }"),
+            found(&[(Rule::UnusedVariable, "test_x")])
+        );
+        assert_eq!(
+            check_rules("let x = 1; let g = fn(x) { let y = x; fn() { let y = 2; y } }; g(x)"),
+            found(&[(Rule::ShadowedName, "x"), (Rule::UnusedVariable, "y"), (Rule::ShadowedName, "y")])
+        );
+        // an export reads what it exports
+        assert_eq!(
+            check_rules("import \"a.mk\" as a; import { f, g } from \"b.mk\"; let h = 1; export f, h, i"),
+            found(&[(Rule::UnusedVariable, "a"), (Rule::UnusedVariable, "g"), (Rule::UndefinedName, "i")])
+        );
+    }
+
+    #[test]
+    fn code() {
+        assert_eq!(
+            check_rules("fn(x) {\n  if (x == x) { return 1; x; x } else { 2 };\n  return x\n}(1 < 1)"),
+            found(&[
+                (Rule::SelfComparison, "x == x"),
+                (Rule::UnreachableCode, "x; x"),
+                (Rule::SelfComparison, "1 < 1"),
+            ])
+        );
+        // calls may give another value each time
+        assert_eq!(check_rules("let f = fn() { 1 }; f() == f()"), vec![]);
+    }
+
+    #[test]
+    fn calls() {
+        let source = "let f = fn(a, b) { a + b };\nlet g = fn(a) { a };\nlet g = g;\nf(1) + len(1, 2) + g(1, 2) + fn() { 1 }(f)";
+        assert_eq!(
+            check_rules(source),
+            found(&[
+                (Rule::WrongArity, "f(1)"),
+                (Rule::WrongArity, "len(1, 2)"),
+                (Rule::WrongArity, "fn() { 1 }(f)"),
+            ])
+        );
+        let d = &check("f(x)", false).unwrap()[0];
+        assert_eq!((d.rule, d.severity(), d.message.as_str()), (Rule::UndefinedName, Severity::Error, "identifier not found: f"));
+        assert_eq!(d.to_string(), "1:1: error: identifier not found: f [undefined-name]");
+        assert_eq!(check("let = 1", false), Err(LintError::Syntax(Position { line: 1, column: 1 })));
+        let err = check("let x = 1;\nx + ;\nx", false).unwrap_err();
+        assert_eq!(err.to_string(), "the code does not parse from line 2, column 1");
+    }
+
+    #[test]
+    fn symbols() {
+        let source = "let n = 2 * 3;\nlet id = fn(x) { x };\nlet m = id(n); let m = m + n;";
+        let at = |line, column, len| Span {
+            start: Position { line, column },
+            end: Position { line, column: column + len },
+        };
+        let symbol = |name: &str, kind, definition, references, ty: &str, value: Option<&str>| Symbol {
+            name: name.to_string(),
+            kind,
+            definition,
+            references,
+            ty: Some(ty.to_string()),
+            value: value.map(str::to_string),
+        };
+        assert_eq!(
+            super::symbols(source).unwrap(),
+            vec![
+                symbol("n", Kind::Let, at(1, 5, 1), vec![at(3, 12, 1), at(3, 28, 1)], "int", Some("6")),
+                symbol("id", Kind::Let, at(2, 5, 2), vec![at(3, 9, 2)], "fn(a) -> a", None),
+                symbol("x", Kind::Param, at(2, 13, 1), vec![at(2, 18, 1)], "a", None),
+                symbol("m", Kind::Let, at(3, 5, 1), vec![at(3, 20, 1), at(3, 24, 1)], "int", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn json() {
+        let diagnostics = check("let x = 1;\n\"é\" + b", false).unwrap();
+        assert_eq!(
+            to_json(&[("dir/\"x\".mk".to_string(), diagnostics), ("y.mk".to_string(), vec![])]),
+            "[\n  {\"file\": \"dir/\\\"x\\\".mk\", \"rule\": \"unused-variable\", \"severity\": \"warning\", \"message\": \"variable `x` is never used\", \"start\": {\"line\": 1, \"column\": 5}, \"end\": {\"line\": 1, \"column\": 6}},\n  {\"file\": \"dir/\\\"x\\\".mk\", \"rule\": \"undefined-name\", \"severity\": \"error\", \"message\": \"identifier not found: b\", \"start\": {\"line\": 2, \"column\": 7}, \"end\": {\"line\": 2, \"column\": 8}}\n]"
+        );
+        assert_eq!(to_json(&[]), "[]");
+    }
+}
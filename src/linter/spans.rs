@@ -0,0 +1,338 @@
+//! Where the nodes of a syntax tree are in their source.
+//!
+//! The tree keeps no positions, but walking it in the order of the source meets its tokens in
+//! that order: only grouping parentheses and semicolons leave no node, so they are skipped, and
+//! the parentheses of a call are found again from the end of its callee. The range of a node
+//! takes in the parentheses its tokens open or close.
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::lexer::token::Token;
+use crate::lexer::{Lexer, Spans};
+use crate::parser::ast::{Expr, Ident, Import, Literal, Program, Stmt, Type};
+
+/// The range of the source of every statement, expression and identifier of a program, keyed
+/// by the address of the node.
+#[derive(Debug, Default)]
+pub struct Locations {
+    idents: HashMap<*const Ident, Range<usize>>,
+    exprs: HashMap<*const Expr, Range<usize>>,
+    stmts: HashMap<*const Stmt, Range<usize>>,
+}
+
+impl Locations {
+    /// Locates the nodes of `program`, which must be the one parsed from `source`.
+    pub fn new(source: &str, program: &Program) -> Self {
+        let (tokens, _) = Lexer::lex_spans(source.as_bytes());
+        let significant = tokens
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, token))| !matches!(token, Token::LParen | Token::RParen | Token::SemiColon))
+            .map(|(i, _)| i)
+            .collect();
+        let mut walk = Walk { tokens: &tokens, significant, next: 0, locations: Locations::default() };
+        program.iter().for_each(|stmt| {
+            walk.stmt(stmt);
+        });
+        walk.locations
+    }
+
+    pub fn ident(&self, ident: &Ident) -> Range<usize> {
+        self.idents[&(ident as *const Ident)].clone()
+    }
+
+    pub fn expr(&self, expr: &Expr) -> Range<usize> {
+        self.exprs[&(expr as *const Expr)].clone()
+    }
+
+    pub fn stmt(&self, stmt: &Stmt) -> Range<usize> {
+        self.stmts[&(stmt as *const Stmt)].clone()
+    }
+
+    /// every statement, with its range
+    pub fn stmts(&self) -> impl Iterator<Item = (*const Stmt, &Range<usize>)> {
+        self.stmts.iter().map(|(&stmt, range)| (stmt, range))
+    }
+}
+
+struct Walk<'a> {
+    tokens: &'a Spans<Token>,
+    /// indices in `tokens` of the tokens making nodes
+    significant: Vec<usize>,
+    next: usize,
+    locations: Locations,
+}
+
+// Each node is walked from its first token, giving the index in `tokens` of its last one.
+impl Walk<'_> {
+    fn take(&mut self) -> usize {
+        self.next += 1;
+        self.significant[self.next - 1]
+    }
+
+    /// the source from the token `first` to the token `last`, with the parentheses they
+    /// leave open
+    fn range(&self, first: usize, last: usize) -> Range<usize> {
+        let (mut depth, mut lowest) = (0isize, 0);
+        for (_, token) in &self.tokens[first..=last] {
+            match token {
+                Token::LParen => depth += 1,
+                Token::RParen => depth -= 1,
+                _ => {}
+            }
+            lowest = lowest.min(depth);
+        }
+        let (first, last) = (first - (-lowest) as usize, last + (depth - lowest) as usize);
+        self.tokens[first].0.start..self.tokens[last].0.end
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) -> usize {
+        let first = self.significant[self.next];
+        let last = match stmt {
+            Stmt::LetStmt(ident, ty, expr) => {
+                self.take();
+                self.ident(ident);
+                if let Some(ty) = ty {
+                    self.take();
+                    self.ty(ty);
+                }
+                self.take();
+                self.expr(expr)
+            }
+            Stmt::ReturnStmt(expr) => {
+                self.take();
+                self.expr(expr)
+            }
+            Stmt::ExprStmt(expr) => self.expr(expr),
+            Stmt::ImportStmt(_, Import::Module(ident)) => {
+                self.take();
+                self.take();
+                self.take();
+                self.ident(ident)
+            }
+            Stmt::ImportStmt(_, Import::Names(idents)) => {
+                self.take();
+                self.take();
+                self.list(idents, Self::ident);
+                self.take();
+                self.take();
+                self.take()
+            }
+            Stmt::ExportStmt(idents) => {
+                self.take();
+                self.list(idents, Self::ident);
+                self.significant[self.next - 1]
+            }
+        };
+        let range = self.range(first, last);
+        self.locations.stmts.insert(stmt, range);
+        last
+    }
+
+    fn block(&mut self, program: &Program) -> usize {
+        self.take();
+        program.iter().for_each(|stmt| {
+            self.stmt(stmt);
+        });
+        self.take()
+    }
+
+    fn ident(&mut self, ident: &Ident) -> usize {
+        let last = self.take();
+        let range = self.range(last, last);
+        self.locations.idents.insert(ident, range);
+        last
+    }
+
+    fn literal(&mut self, literal: &Literal) -> usize {
+        if let Literal::IntLiteral(n) = literal {
+            if *n < 0 {
+                self.take();
+            }
+        }
+        self.take()
+    }
+
+    fn ty(&mut self, ty: &Type) -> usize {
+        match ty {
+            Type::Int | Type::Bool | Type::String | Type::Null => self.take(),
+            Type::Array(t) => {
+                self.take();
+                self.ty(t);
+                self.take()
+            }
+            Type::Hash(k, v) => {
+                self.take();
+                self.ty(k);
+                self.take();
+                self.ty(v);
+                self.take()
+            }
+            Type::Fn(params, ret) => {
+                self.take();
+                self.list(params, Self::ty);
+                self.take();
+                self.ty(ret)
+            }
+        }
+    }
+
+    /// the items of a list separated by commas
+    fn list<T>(&mut self, items: &[T], mut item: impl FnMut(&mut Self, &T) -> usize) {
+        for (i, x) in items.iter().enumerate() {
+            if i > 0 {
+                self.take();
+            }
+            item(self, x);
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) -> usize {
+        let first = self.significant[self.next];
+        let last = match expr {
+            Expr::IdentExpr(ident) => self.ident(ident),
+            Expr::LiteralExpr(literal) => self.literal(literal),
+            Expr::PrefixExpr(_, e) => {
+                self.take();
+                self.expr(e)
+            }
+            Expr::InfixExpr(_, e1, e2) => {
+                self.expr(e1);
+                self.take();
+                self.expr(e2)
+            }
+            Expr::IfExpr { cond, consequence, alternative } => {
+                self.take();
+                self.expr(cond);
+                let last = self.block(consequence);
+                match alternative {
+                    Some(alternative) => {
+                        self.take();
+                        self.block(alternative)
+                    }
+                    None => last,
+                }
+            }
+            Expr::FnExpr { params, signature, body } => {
+                self.take();
+                for (i, param) in params.iter().enumerate() {
+                    if i > 0 {
+                        self.take();
+                    }
+                    self.ident(param);
+                    if let Some(ty) = signature.param(i) {
+                        self.take();
+                        self.ty(ty);
+                    }
+                }
+                if let Some(ty) = &signature.ret {
+                    self.take();
+                    self.ty(ty);
+                }
+                self.block(body)
+            }
+            Expr::CallExpr { function, arguments } => {
+                let callee = self.expr(function);
+                self.list(arguments, Self::expr);
+                self.closing_paren(callee)
+            }
+            Expr::ArrayExpr(items) => {
+                self.take();
+                self.list(items, Self::expr);
+                self.take()
+            }
+            Expr::HashExpr(pairs) => {
+                self.take();
+                self.list(pairs, |walk, (key, value)| {
+                    walk.literal(key);
+                    walk.take();
+                    walk.expr(value)
+                });
+                self.take()
+            }
+            Expr::IndexExpr { array, index } => {
+                self.expr(array);
+                self.take();
+                self.expr(index);
+                self.take()
+            }
+            Expr::MemberExpr { module, name } => {
+                self.expr(module);
+                self.take();
+                self.ident(name)
+            }
+        };
+        let range = self.range(first, last);
+        self.locations.exprs.insert(expr, range);
+        last
+    }
+
+    /// the parenthesis closing the arguments of a call whose callee ends at `callee`: the
+    /// first opening one after it, past those closing the groups of the callee
+    fn closing_paren(&self, callee: usize) -> usize {
+        let open = (callee + 1..self.tokens.len())
+            .find(|&i| self.tokens[i].1 == Token::LParen)
+            .expect("no arguments");
+        let mut depth = 0;
+        for i in open.. {
+            match self.tokens[i].1 {
+                Token::LParen => depth += 1,
+                Token::RParen if depth == 1 => return i,
+                Token::RParen => depth -= 1,
+                _ => {}
+            }
+        }
+        unreachable!()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::token::Tokens;
+    use crate::parser::Parser;
+
+    #[test]
+    fn ranges() {
+        let source = "let f = fn(a: int, b) -> fn([int]) -> int { (a + b) };\n((f))(1, [2][0]) + {\"k\": -1}[\"k\"]";
+        let (_, tokens) = Lexer::lex_tokens(source.as_bytes()).unwrap();
+        let (_, program) = Parser::parse_tokens(Tokens::new(&tokens)).unwrap();
+        let locations = Locations::new(source, &program);
+        let text = |range: Range<usize>| &source[range];
+
+        assert_eq!(text(locations.stmt(&program[0])), "let f = fn(a: int, b) -> fn([int]) -> int { (a + b) }");
+        let Stmt::LetStmt(name, _, Expr::FnExpr { params, body, .. }) = &program[0] else { panic!() };
+        assert_eq!(text(locations.ident(name)), "f");
+        assert_eq!(text(locations.ident(&params[1])), "b");
+        assert_eq!(text(locations.stmt(&body[0])), "a + b");
+
+        let Stmt::ExprStmt(sum) = &program[1] else { panic!() };
+        assert_eq!(text(locations.expr(sum)), "((f))(1, [2][0]) + {\"k\": -1}[\"k\"]");
+        let Expr::InfixExpr(_, call, index) = sum else { panic!() };
+        assert_eq!(text(locations.expr(call)), "((f))(1, [2][0])");
+        assert_eq!(text(locations.expr(index)), "{\"k\": -1}[\"k\"]");
+        let Expr::CallExpr { function, arguments } = &**call else { panic!() };
+        assert_eq!(text(locations.expr(function)), "f");
+        assert_eq!(text(locations.expr(&arguments[1])), "[2][0]");
+    }
+
+    #[test]
+    fn modules() {
+        let source = "import \"a.mk\" as a;\nimport { f, g } from \"b.mk\"\nexport f, g;\n(a).h(1)";
+        let (_, tokens) = Lexer::lex_tokens(source.as_bytes()).unwrap();
+        let (_, program) = Parser::parse_tokens(Tokens::new(&tokens)).unwrap();
+        let locations = Locations::new(source, &program);
+        let text = |range: Range<usize>| &source[range];
+
+        assert_eq!(text(locations.stmt(&program[0])), "import \"a.mk\" as a");
+        assert_eq!(text(locations.stmt(&program[1])), "import { f, g } from \"b.mk\"");
+        let Stmt::ImportStmt(_, Import::Names(names)) = &program[1] else { panic!() };
+        assert_eq!(text(locations.ident(&names[1])), "g");
+        assert_eq!(text(locations.stmt(&program[2])), "export f, g");
+        let Stmt::ExprStmt(Expr::CallExpr { function, .. }) = &program[3] else { panic!() };
+        assert_eq!(text(locations.expr(function)), "(a).h");
+        let Expr::MemberExpr { name, .. } = &**function else { panic!() };
+        assert_eq!(text(locations.ident(name)), "h");
+    }
+}
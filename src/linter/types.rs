@@ -0,0 +1,858 @@
+//! Hindley–Milner type inference, reporting the expressions whose types do not match.
+//!
+//! Values are ints, bools, strings, null, arrays and hashes of one type of element, and
+//! functions. A `let` bound once is generalized after its value, so `let id = fn(x) { x }` can
+//! be called with anything; a name bound more than once, or read by a function before it is
+//! bound, keeps one type. `+` takes two ints or two strings and `len` a string or an array,
+//! which type variables record as a class. Whether an index is into an array or a hash is
+//! decided by the type of its target, and kept open until it is known. Annotations give the
+//! types of the bindings, parameters and returned values they are written on.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use std::ops::Range;
+
+use super::spans::Locations;
+use super::Rule;
+use crate::parser::ast::{Expr, Ident, Infix, Literal, Prefix, Program, Signature, Stmt, Type as Annotation};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Type {
+    Int,
+    Bool,
+    Str,
+    Null,
+    Array(Box<Type>),
+    Hash(Box<Type>, Box<Type>),
+    Fn(Vec<Type>, Box<Type>),
+    Var(usize),
+}
+
+/// the types a variable may stand for
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Class {
+    /// int or string
+    Addable,
+    /// string or array
+    Sized,
+}
+
+#[derive(Debug, Clone)]
+struct Var {
+    bound: Option<Type>,
+    /// the number of `let` values being inferred when the variable was made
+    level: usize,
+    class: Option<Class>,
+}
+
+/// a type for every type its variables stand for
+#[derive(Debug, Clone)]
+struct Scheme {
+    vars: Vec<usize>,
+    ty: Type,
+}
+
+#[derive(Debug)]
+enum Entry {
+    /// a parameter, or a name bound more than once
+    Mono(Type),
+    /// a name bound once whose `let` is not reached yet, and whether it was read
+    Pending(Type, bool),
+    /// a name whose value is being inferred
+    Defining(Type),
+    Defined(Scheme),
+}
+
+enum Mismatch {
+    Types,
+    Class(Class, Type),
+}
+
+/// an index into a target of a type not known yet
+struct Index {
+    target: Type,
+    index: Type,
+    result: Type,
+    at: Range<usize>,
+}
+
+pub struct Inferred {
+    pub found: Vec<(Rule, Range<usize>, String)>,
+    /// the type of every name where it is bound
+    pub bindings: HashMap<Range<usize>, String>,
+}
+
+/// Infers the types of a program, giving where they do not match.
+pub fn infer(locations: &Locations, builtins: &HashMap<String, usize>, program: &Program) -> Inferred {
+    let mut inference = Inference {
+        locations,
+        vars: vec![],
+        level: 0,
+        scopes: vec![],
+        passed: vec![],
+        prelude: HashMap::new(),
+        returns: vec![],
+        indices: vec![],
+        bindings: vec![],
+        found: vec![],
+    };
+    for (name, &arity) in builtins {
+        let scheme = inference.builtin(name, arity);
+        inference.prelude.insert(name.clone(), scheme);
+    }
+    inference.function(&[], &Signature::default(), program);
+    let bindings = inference
+        .bindings
+        .iter()
+        .map(|(range, ty)| (range.clone(), inference.show(ty, &mut vec![])))
+        .collect();
+    Inferred { found: inference.found, bindings }
+}
+
+struct Inference<'a> {
+    locations: &'a Locations,
+    vars: Vec<Var>,
+    level: usize,
+    scopes: Vec<HashMap<String, Entry>>,
+    /// the names of each scope whose `let` was passed, and its parameters
+    passed: Vec<HashSet<String>>,
+    prelude: HashMap<String, Scheme>,
+    /// the return types of the functions being inferred
+    returns: Vec<Type>,
+    indices: Vec<Index>,
+    bindings: Vec<(Range<usize>, Type)>,
+    found: Vec<(Rule, Range<usize>, String)>,
+}
+
+impl Inference<'_> {
+    fn fresh(&mut self, class: Option<Class>) -> Type {
+        self.vars.push(Var { bound: None, level: self.level, class });
+        Type::Var(self.vars.len() - 1)
+    }
+
+    fn builtin(&mut self, name: &str, arity: usize) -> Scheme {
+        self.level += 1;
+        let a = self.fresh(None);
+        let array = Type::Array(Box::new(a.clone()));
+        let ty = match name {
+            "print" => Type::Fn(vec![a], Box::new(Type::Null)),
+            "len" => Type::Fn(vec![self.fresh(Some(Class::Sized))], Box::new(Type::Int)),
+            "head" => Type::Fn(vec![array], Box::new(a)),
+            "tail" => Type::Fn(vec![array.clone()], Box::new(array)),
+            "cons" => Type::Fn(vec![a, array.clone()], Box::new(array)),
+            "assert" => Type::Fn(vec![Type::Bool], Box::new(Type::Null)),
+            "assert_eq" => Type::Fn(vec![a.clone(), a], Box::new(Type::Null)),
+            "assert_error" => Type::Fn(vec![a], Box::new(Type::Null)),
+            _ => Type::Fn((0..arity).map(|_| self.fresh(None)).collect(), Box::new(a)),
+        };
+        self.level -= 1;
+        self.generalize(&ty)
+    }
+
+    /// the type a type variable is bound to, through the variables it is bound to
+    fn resolve(&self, ty: &Type) -> Type {
+        match ty {
+            Type::Var(v) => match &self.vars[*v].bound {
+                Some(bound) => self.resolve(bound),
+                None => ty.clone(),
+            },
+            _ => ty.clone(),
+        }
+    }
+
+    /// the type with all its bound variables replaced
+    fn zonk(&self, ty: &Type) -> Type {
+        match self.resolve(ty) {
+            Type::Array(e) => Type::Array(Box::new(self.zonk(&e))),
+            Type::Hash(k, v) => Type::Hash(Box::new(self.zonk(&k)), Box::new(self.zonk(&v))),
+            Type::Fn(params, ret) => Type::Fn(params.iter().map(|p| self.zonk(p)).collect(), Box::new(self.zonk(&ret))),
+            ty => ty,
+        }
+    }
+
+    fn generalize(&self, ty: &Type) -> Scheme {
+        let ty = self.zonk(ty);
+        let mut vars = vec![];
+        free_vars(&ty, &mut vars);
+        vars.retain(|&v| self.vars[v].level > self.level);
+        Scheme { vars, ty }
+    }
+
+    fn instantiate(&mut self, scheme: &Scheme) -> Type {
+        let fresh = scheme
+            .vars
+            .iter()
+            .map(|&v| {
+                let class = self.vars[v].class;
+                (v, self.fresh(class))
+            })
+            .collect::<HashMap<_, _>>();
+        substitute(&scheme.ty, &fresh)
+    }
+
+    fn unify(&mut self, t1: &Type, t2: &Type) -> Result<(), Mismatch> {
+        match (self.resolve(t1), self.resolve(t2)) {
+            (Type::Var(v1), Type::Var(v2)) if v1 == v2 => Ok(()),
+            (Type::Var(v), ty) | (ty, Type::Var(v)) => self.bind(v, ty),
+            (Type::Int, Type::Int) | (Type::Bool, Type::Bool) | (Type::Str, Type::Str) | (Type::Null, Type::Null) => {
+                Ok(())
+            }
+            (Type::Array(e1), Type::Array(e2)) => self.unify(&e1, &e2),
+            (Type::Hash(k1, v1), Type::Hash(k2, v2)) => {
+                self.unify(&k1, &k2)?;
+                self.unify(&v1, &v2)
+            }
+            (Type::Fn(params1, ret1), Type::Fn(params2, ret2)) if params1.len() == params2.len() => {
+                for (p1, p2) in params1.iter().zip(&params2) {
+                    self.unify(p1, p2)?;
+                }
+                self.unify(&ret1, &ret2)
+            }
+            _ => Err(Mismatch::Types),
+        }
+    }
+
+    fn bind(&mut self, v: usize, ty: Type) -> Result<(), Mismatch> {
+        let Var { level, class, .. } = self.vars[v];
+        if let Type::Var(w) = ty {
+            self.vars[w].level = self.vars[w].level.min(level);
+            match (class, self.vars[w].class) {
+                // only strings are both addable and sized
+                (Some(c1), Some(c2)) if c1 != c2 => {
+                    self.vars[w].bound = Some(Type::Str);
+                }
+                (Some(c), None) => self.vars[w].class = Some(c),
+                _ => {}
+            }
+            self.vars[v].bound = Some(ty);
+            return Ok(());
+        }
+        if !self.can_bind(v, level, &ty) {
+            return Err(Mismatch::Types);
+        }
+        let allowed = match class {
+            None => true,
+            Some(Class::Addable) => matches!(ty, Type::Int | Type::Str),
+            Some(Class::Sized) => matches!(ty, Type::Str | Type::Array(_)),
+        };
+        if !allowed {
+            return Err(Mismatch::Class(class.expect("no class"), ty));
+        }
+        self.vars[v].bound = Some(ty);
+        Ok(())
+    }
+
+    /// Whether `v` can be bound to `ty`, not being in it, lowering the level of the variables
+    /// of `ty` to `level`.
+    fn can_bind(&mut self, v: usize, level: usize, ty: &Type) -> bool {
+        match self.resolve(ty) {
+            Type::Var(w) => {
+                self.vars[w].level = self.vars[w].level.min(level);
+                v != w
+            }
+            Type::Array(e) => self.can_bind(v, level, &e),
+            Type::Hash(k, e) => self.can_bind(v, level, &k) && self.can_bind(v, level, &e),
+            Type::Fn(params, ret) => params.iter().all(|p| self.can_bind(v, level, p)) && self.can_bind(v, level, &ret),
+            _ => true,
+        }
+    }
+
+    /// Unifies the type expected at `at` with the type found there, reporting them if they
+    /// do not match.
+    fn expect(&mut self, expected: &Type, found: &Type, at: Range<usize>) -> bool {
+        match self.unify(expected, found) {
+            Ok(()) => return true,
+            Err(Mismatch::Types) => {
+                let mut names = vec![];
+                let expected = self.show(expected, &mut names);
+                let found = self.show(found, &mut names);
+                let message = format!("mismatched types: expected {}, found {}", expected, found);
+                self.found.push((Rule::TypeMismatch, at, message));
+            }
+            Err(Mismatch::Class(class, ty)) => {
+                let ty = self.show(&ty, &mut vec![]);
+                let message = match class {
+                    Class::Addable => format!("{} is not addable", ty),
+                    Class::Sized => format!("{} has no length", ty),
+                };
+                self.found.push((Rule::TypeMismatch, at, message));
+            }
+        }
+        false
+    }
+
+    fn expect_expr(&mut self, expected: &Type, expr: &Expr) -> bool {
+        let found = self.expr(expr);
+        self.expect(expected, &found, self.locations.expr(expr))
+    }
+
+    /// the type written in the source, its variables named in the order they appear
+    fn show(&self, ty: &Type, names: &mut Vec<usize>) -> String {
+        let mut out = String::new();
+        self.write(&mut out, &self.zonk(ty), names);
+        out
+    }
+
+    fn write(&self, out: &mut String, ty: &Type, names: &mut Vec<usize>) {
+        match ty {
+            Type::Int => out.push_str("int"),
+            Type::Bool => out.push_str("bool"),
+            Type::Str => out.push_str("string"),
+            Type::Null => out.push_str("null"),
+            Type::Array(e) => {
+                out.push('[');
+                self.write(out, e, names);
+                out.push(']');
+            }
+            Type::Hash(k, v) => {
+                out.push('{');
+                self.write(out, k, names);
+                out.push_str(": ");
+                self.write(out, v, names);
+                out.push('}');
+            }
+            Type::Fn(params, ret) => {
+                out.push_str("fn(");
+                for (i, p) in params.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(", ");
+                    }
+                    self.write(out, p, names);
+                }
+                out.push_str(") -> ");
+                self.write(out, ret, names);
+            }
+            Type::Var(v) => {
+                let i = names.iter().position(|w| w == v).unwrap_or_else(|| {
+                    names.push(*v);
+                    names.len() - 1
+                });
+                out.push((b'a' + (i % 26) as u8) as char);
+                if i >= 26 {
+                    write!(out, "{}", i / 26).expect("write to a string");
+                }
+            }
+        }
+    }
+
+    /// Infers the body of a function, or the program with no parameters, giving its type.
+    fn function(&mut self, params: &[Ident], signature: &Signature, body: &Program) -> Type {
+        self.passed.push(params.iter().map(|Ident(name)| name.clone()).collect());
+        let mut scope = HashMap::new();
+        let params = params
+            .iter()
+            .enumerate()
+            .map(|(i, param)| {
+                let ty = signature.param(i).map_or_else(|| self.fresh(None), annotated);
+                self.bindings.push((self.locations.ident(param), ty.clone()));
+                scope.insert(param.0.clone(), Entry::Mono(ty.clone()));
+                ty
+            })
+            .collect();
+        let mut lets = HashMap::new();
+        hoist(&mut lets, body);
+        for (name, count) in lets {
+            match scope.get(&name) {
+                Some(_) => {}
+                None if count > 1 => {
+                    let ty = self.fresh(None);
+                    scope.insert(name, Entry::Mono(ty));
+                }
+                None => {
+                    let ty = self.fresh(None);
+                    scope.insert(name, Entry::Pending(ty, false));
+                }
+            }
+        }
+
+        self.scopes.push(scope);
+        let ret = signature.ret.as_ref().map_or_else(|| self.fresh(None), annotated);
+        self.returns.push(ret.clone());
+        let value = self.block(body);
+        let at = body.last().map_or(0..0, |stmt| self.locations.stmt(stmt));
+        self.expect(&ret, &value, at);
+        self.returns.pop();
+        self.scopes.pop();
+        self.passed.pop();
+        if self.scopes.is_empty() {
+            // what the program leaves open may be an index into either
+            self.settle_indices();
+            self.indices.clear();
+        }
+        Type::Fn(params, Box::new(ret))
+    }
+
+    fn block(&mut self, program: &[Stmt]) -> Type {
+        let mut value = Type::Null;
+        for stmt in program {
+            value = self.stmt(stmt);
+        }
+        value
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) -> Type {
+        match stmt {
+            Stmt::LetStmt(ident, annotation, expr) => {
+                let ty = self.define(&ident.0, annotation.as_ref(), expr);
+                self.passed.last_mut().expect("no scope").insert(ident.0.clone());
+                self.bindings.push((self.locations.ident(ident), ty.clone()));
+                ty
+            }
+            Stmt::ReturnStmt(expr) => {
+                let ret = self.returns.last().expect("no function").clone();
+                self.expect_expr(&ret, expr);
+                // nothing comes of a block after it returns
+                self.fresh(None)
+            }
+            Stmt::ExprStmt(expr) => self.expr(expr),
+            Stmt::ImportStmt(_, import) => {
+                // what a module binds is only known once it runs, so it takes any type
+                for ident in import.idents() {
+                    let ty = match self.scopes.last().expect("no scope").get(&ident.0) {
+                        Some(Entry::Mono(ty)) => ty.clone(),
+                        _ => {
+                            self.level += 1;
+                            let ty = self.fresh(None);
+                            self.level -= 1;
+                            let scheme = self.generalize(&ty);
+                            self.scopes.last_mut().expect("no scope").insert(ident.0.clone(), Entry::Defined(scheme));
+                            ty
+                        }
+                    };
+                    self.bindings.push((self.locations.ident(ident), ty));
+                    self.passed.last_mut().expect("no scope").insert(ident.0.clone());
+                }
+                self.fresh(None)
+            }
+            Stmt::ExportStmt(idents) => {
+                for Ident(name) in idents {
+                    self.lookup(name);
+                }
+                Type::Null
+            }
+        }
+    }
+
+    fn define(&mut self, name: &str, annotation: Option<&Annotation>, expr: &Expr) -> Type {
+        let scope = self.scopes.last_mut().expect("no scope");
+        match scope.remove(name) {
+            Some(Entry::Pending(ty, read)) => {
+                self.level += 1;
+                let value = annotation.map_or_else(|| self.fresh(None), annotated);
+                self.scopes.last_mut().expect("no scope").insert(name.to_string(), Entry::Defining(value.clone()));
+                self.expect_expr(&value, expr);
+                self.level -= 1;
+                self.settle_indices();
+                if read {
+                    self.expect(&ty, &value, self.locations.expr(expr));
+                }
+                let scheme = self.generalize(&value);
+                self.scopes.last_mut().expect("no scope").insert(name.to_string(), Entry::Defined(scheme));
+                value
+            }
+            Some(Entry::Mono(ty)) => {
+                self.scopes.last_mut().expect("no scope").insert(name.to_string(), Entry::Mono(ty.clone()));
+                let value = self.expr(expr);
+                let at = self.locations.expr(expr);
+                if let Some(annotation) = annotation {
+                    self.expect(&annotated(annotation), &value, at.clone());
+                }
+                self.expect(&ty, &value, at);
+                ty
+            }
+            entry => panic!("{} bound once is bound again: {:?}", name, entry),
+        }
+    }
+
+    /// the type of a name, found in the scopes as the resolver finds it
+    fn lookup(&mut self, name: &str) -> Type {
+        let top = self.scopes.len() - 1;
+        let passed = self.passed[top].contains(name);
+        let scope = match (0..=top).rev().find(|&i| (i < top || passed) && self.scopes[i].contains_key(name)) {
+            Some(i) => Some(i),
+            None if self.prelude.contains_key(name) => None,
+            None => Some(top),
+        };
+        let entry = scope.and_then(|i| self.scopes[i].get_mut(name));
+        let scheme = match entry {
+            Some(Entry::Mono(ty)) | Some(Entry::Defining(ty)) => return ty.clone(),
+            Some(Entry::Pending(ty, read)) => {
+                *read = true;
+                return ty.clone();
+            }
+            Some(Entry::Defined(scheme)) => scheme.clone(),
+            None => match self.prelude.get(name) {
+                Some(scheme) => scheme.clone(),
+                // reported by the linter
+                None => return self.fresh(None),
+            },
+        };
+        self.instantiate(&scheme)
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Type {
+        match expr {
+            Expr::IdentExpr(Ident(name)) => self.lookup(name),
+            Expr::LiteralExpr(literal) => literal_type(literal),
+            Expr::PrefixExpr(prefix, e) => {
+                let ty = match prefix {
+                    Prefix::PrefixPlus | Prefix::PrefixMinus => Type::Int,
+                    Prefix::Not => Type::Bool,
+                };
+                self.expect_expr(&ty, e);
+                ty
+            }
+            Expr::InfixExpr(infix, e1, e2) => match infix {
+                Infix::Plus => {
+                    let ty = self.fresh(Some(Class::Addable));
+                    if self.expect_expr(&ty, e1) {
+                        self.expect_expr(&ty, e2);
+                    } else {
+                        self.expr(e2);
+                    }
+                    ty
+                }
+                Infix::Minus | Infix::Multiply | Infix::Divide => {
+                    self.expect_expr(&Type::Int, e1);
+                    self.expect_expr(&Type::Int, e2);
+                    Type::Int
+                }
+                Infix::GreaterThanEqual | Infix::LessThanEqual | Infix::GreaterThan | Infix::LessThan => {
+                    self.expect_expr(&Type::Int, e1);
+                    self.expect_expr(&Type::Int, e2);
+                    Type::Bool
+                }
+                Infix::Equal | Infix::NotEqual => {
+                    let ty = self.expr(e1);
+                    self.expect_expr(&ty, e2);
+                    Type::Bool
+                }
+            },
+            Expr::IfExpr { cond, consequence, alternative } => {
+                self.expect_expr(&Type::Bool, cond);
+                let value = self.block(consequence);
+                match alternative {
+                    Some(alternative) => {
+                        let other = self.block(alternative);
+                        let at = alternative.last().map_or_else(|| self.locations.expr(expr), |s| self.locations.stmt(s));
+                        self.expect(&value, &other, at);
+                        value
+                    }
+                    None => Type::Null,
+                }
+            }
+            Expr::FnExpr { params, signature, body } => self.function(params, signature, body),
+            Expr::CallExpr { function, arguments } => {
+                let callee = self.expr(function);
+                match self.resolve(&callee) {
+                    Type::Fn(params, ret) if params.len() == arguments.len() => {
+                        for (param, argument) in params.iter().zip(arguments) {
+                            self.expect_expr(param, argument);
+                        }
+                        *ret
+                    }
+                    Type::Fn(params, _) => {
+                        let message = format!(
+                            "wrong number of arguments: {} expected but {} given",
+                            params.len(),
+                            arguments.len()
+                        );
+                        self.found.push((Rule::WrongArity, self.locations.expr(expr), message));
+                        arguments.iter().for_each(|e| {
+                            self.expr(e);
+                        });
+                        self.fresh(None)
+                    }
+                    _ => {
+                        let params = arguments.iter().map(|e| self.expr(e)).collect();
+                        let ret = self.fresh(None);
+                        let ty = Type::Fn(params, Box::new(ret.clone()));
+                        self.expect(&ty, &callee, self.locations.expr(function));
+                        ret
+                    }
+                }
+            }
+            Expr::ArrayExpr(items) => {
+                let item = self.fresh(None);
+                for e in items {
+                    self.expect_expr(&item, e);
+                }
+                Type::Array(Box::new(item))
+            }
+            Expr::HashExpr(pairs) => {
+                let (key, value) = (self.fresh(None), self.fresh(None));
+                for (literal, e) in pairs {
+                    self.expect(&key, &literal_type(literal), self.locations.expr(expr));
+                    self.expect_expr(&value, e);
+                }
+                Type::Hash(Box::new(key), Box::new(value))
+            }
+            Expr::IndexExpr { array, index } => {
+                let target = self.expr(array);
+                let index_type = self.expr(index);
+                let result = self.fresh(None);
+                let index = Index { target, index: index_type, result: result.clone(), at: self.locations.expr(expr) };
+                match self.resolve(&index.target) {
+                    Type::Var(_) => self.indices.push(index),
+                    _ => self.index(index),
+                }
+                result
+            }
+            Expr::MemberExpr { module, .. } => {
+                self.expr(module);
+                self.fresh(None)
+            }
+        }
+    }
+
+    fn index(&mut self, index: Index) {
+        match self.resolve(&index.target) {
+            Type::Hash(key, value) => {
+                self.expect(&key, &index.index, index.at.clone());
+                self.expect(&value, &index.result, index.at);
+            }
+            _ => {
+                let array = Type::Array(Box::new(index.result));
+                self.expect(&array, &index.target, index.at.clone());
+                self.expect(&Type::Int, &index.index, index.at);
+            }
+        }
+    }
+
+    /// Checks the indices whose targets got known, and keeps the variables of the others from
+    /// being generalized, so their uses decide.
+    fn settle_indices(&mut self) {
+        let mut open = vec![];
+        for index in std::mem::take(&mut self.indices) {
+            match self.resolve(&index.target) {
+                Type::Var(_) => open.push(index),
+                _ => self.index(index),
+            }
+        }
+        for index in &open {
+            let mut vars = vec![];
+            for ty in [&index.target, &index.index, &index.result] {
+                free_vars(&self.zonk(ty), &mut vars);
+            }
+            for v in vars {
+                self.vars[v].level = self.vars[v].level.min(self.level);
+            }
+        }
+        self.indices = open;
+    }
+}
+
+fn annotated(annotation: &Annotation) -> Type {
+    match annotation {
+        Annotation::Int => Type::Int,
+        Annotation::Bool => Type::Bool,
+        Annotation::String => Type::Str,
+        Annotation::Null => Type::Null,
+        Annotation::Array(t) => Type::Array(Box::new(annotated(t))),
+        Annotation::Hash(k, v) => Type::Hash(Box::new(annotated(k)), Box::new(annotated(v))),
+        Annotation::Fn(params, ret) => Type::Fn(params.iter().map(annotated).collect(), Box::new(annotated(ret))),
+    }
+}
+
+fn literal_type(literal: &Literal) -> Type {
+    match literal {
+        Literal::IntLiteral(_) => Type::Int,
+        Literal::BoolLiteral(_) => Type::Bool,
+        Literal::StringLiteral(_) => Type::Str,
+    }
+}
+
+/// counts the `let`s of each name of a function scope
+fn hoist(lets: &mut HashMap<String, usize>, program: &[Stmt]) {
+    for stmt in program {
+        match stmt {
+            Stmt::LetStmt(Ident(name), _, expr) => {
+                *lets.entry(name.clone()).or_default() += 1;
+                hoist_expr(lets, expr);
+            }
+            Stmt::ReturnStmt(expr) | Stmt::ExprStmt(expr) => hoist_expr(lets, expr),
+            Stmt::ImportStmt(_, import) => {
+                for Ident(name) in import.idents() {
+                    *lets.entry(name.clone()).or_default() += 1;
+                }
+            }
+            Stmt::ExportStmt(_) => {}
+        }
+    }
+}
+
+fn hoist_expr(lets: &mut HashMap<String, usize>, expr: &Expr) {
+    match expr {
+        Expr::IdentExpr(_) | Expr::LiteralExpr(_) | Expr::FnExpr { .. } => {}
+        Expr::PrefixExpr(_, e) => hoist_expr(lets, e),
+        Expr::InfixExpr(_, e1, e2) | Expr::IndexExpr { array: e1, index: e2 } => {
+            hoist_expr(lets, e1);
+            hoist_expr(lets, e2);
+        }
+        Expr::IfExpr { cond, consequence, alternative } => {
+            hoist_expr(lets, cond);
+            hoist(lets, consequence);
+            if let Some(alternative) = alternative {
+                hoist(lets, alternative);
+            }
+        }
+        Expr::CallExpr { function, arguments } => {
+            hoist_expr(lets, function);
+            arguments.iter().for_each(|e| hoist_expr(lets, e));
+        }
+        Expr::ArrayExpr(exprs) => exprs.iter().for_each(|e| hoist_expr(lets, e)),
+        Expr::HashExpr(pairs) => pairs.iter().for_each(|(_, e)| hoist_expr(lets, e)),
+        Expr::MemberExpr { module, .. } => hoist_expr(lets, module),
+    }
+}
+
+fn free_vars(ty: &Type, vars: &mut Vec<usize>) {
+    match ty {
+        Type::Var(v) if !vars.contains(v) => vars.push(*v),
+        Type::Array(e) => free_vars(e, vars),
+        Type::Hash(k, v) => {
+            free_vars(k, vars);
+            free_vars(v, vars);
+        }
+        Type::Fn(params, ret) => {
+            params.iter().for_each(|p| free_vars(p, vars));
+            free_vars(ret, vars);
+        }
+        _ => {}
+    }
+}
+
+fn substitute(ty: &Type, vars: &HashMap<usize, Type>) -> Type {
+    match ty {
+        Type::Var(v) => vars.get(v).cloned().unwrap_or(Type::Var(*v)),
+        Type::Array(e) => Type::Array(Box::new(substitute(e, vars))),
+        Type::Hash(k, v) => Type::Hash(Box::new(substitute(k, vars)), Box::new(substitute(v, vars))),
+        Type::Fn(params, ret) => {
+            Type::Fn(params.iter().map(|p| substitute(p, vars)).collect(), Box::new(substitute(ret, vars)))
+        }
+        ty => ty.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::check;
+    use super::*;
+
+    /// the mismatches found, each with the text it spans
+    fn mismatches(source: &str) -> Vec<(String, String)> {
+        let lines = source.lines().collect::<Vec<_>>();
+        check(source, true)
+            .unwrap()
+            .into_iter()
+            .filter(|d| d.rule == Rule::TypeMismatch)
+            .map(|d| {
+                let line = lines[d.span.start.line - 1].chars();
+                let text = line.skip(d.span.start.column - 1).take(d.span.end.column - d.span.start.column);
+                (text.collect(), d.message)
+            })
+            .collect()
+    }
+
+    fn found(expected: &[(&str, &str)]) -> Vec<(String, String)> {
+        expected.iter().map(|&(text, message)| (text.to_string(), message.to_string())).collect()
+    }
+
+    #[test]
+    fn typed() {
+        let programs = [
+            "let id = fn(x) { x }; id(1) + 1; id(\"a\") + \"b\"; id(id)(true)",
+            "let concat = fn(a, b) { a + b }; concat(1, 2) * 3; len(concat(\"a\", \"b\"))",
+            "let map = fn(f, xs) { if (len(xs) == 0) { [] } else { cons(f(head(xs)), map(f, tail(xs))) } };\nmap(fn(x) { x * 2 }, [1, 2])",
+            "let f = fn() { g(1) }; let g = fn(x) { x + 1 }; f() * 2",
+            "let h = {\"a\": 1}; let get = fn(t, k) { t[k] }; get(h, \"a\") + [1][0]",
+            "let sign = fn(n) { if (n < 0) { return -1 }; if (n > 0) { 1 } else { 0 } }; sign(2) - 1",
+            "let x = 1; let x = x + 1; print(x); print(\"x\")",
+            "import { id } from \"id.mk\"; import \"m.mk\" as m; id(1) + 1; id(\"a\") + m.f(\"b\"); export id",
+        ];
+        for program in programs {
+            assert_eq!(mismatches(program), vec![], "{}", program);
+        }
+    }
+
+    #[test]
+    fn mismatched() {
+        assert_eq!(
+            mismatches("1 + \"a\";\ntrue + true;\nlen(5);\n[1, \"a\"]"),
+            found(&[
+                ("\"a\"", "mismatched types: expected int, found string"),
+                ("true", "bool is not addable"),
+                ("5", "int has no length"),
+                ("\"a\"", "mismatched types: expected int, found string"),
+            ])
+        );
+        assert_eq!(
+            mismatches("if (1) { 2 } else { \"b\" };\nlet f = fn(x) { x * 2 };\nf(\"a\")"),
+            found(&[
+                ("1", "mismatched types: expected bool, found int"),
+                ("\"b\"", "mismatched types: expected int, found string"),
+                ("\"a\"", "mismatched types: expected int, found string"),
+            ])
+        );
+        assert_eq!(
+            mismatches("let x = 1;\nlet x = \"a\";\nfn(y) { if (y) { return 1 } }"),
+            found(&[
+                ("\"a\"", "mismatched types: expected int, found string"),
+                ("if (y) { return 1 }", "mismatched types: expected int, found null"),
+            ])
+        );
+    }
+
+    #[test]
+    fn functions() {
+        assert_eq!(
+            mismatches("let apply = fn(f) { f(1, 2) };\napply(5);\napply(fn(x) { x })"),
+            found(&[
+                ("5", "mismatched types: expected fn(int, int) -> a, found int"),
+                ("fn(x) { x }", "mismatched types: expected fn(int, int) -> a, found fn(b) -> b"),
+            ])
+        );
+        assert_eq!(mismatches("fn(x) { x(x) }"), found(&[("x", "mismatched types: expected fn(a) -> b, found a")]));
+        // a function read before it is bound keeps the type it has there
+        assert_eq!(
+            mismatches("let f = fn() { id(1) };\nlet id = fn(x) { x };\nid(\"a\")"),
+            found(&[("\"a\"", "mismatched types: expected int, found string")])
+        );
+    }
+
+    #[test]
+    fn annotations() {
+        assert_eq!(
+            mismatches("let x: string = 1;\nlet f = fn(a: [int]) -> int { return a };\nf([true]);\nlet g: fn(int) -> int = f"),
+            found(&[
+                ("1", "mismatched types: expected string, found int"),
+                ("a", "mismatched types: expected int, found [int]"),
+                ("[true]", "mismatched types: expected [int], found [bool]"),
+                ("f", "mismatched types: expected fn(int) -> int, found fn([int]) -> int"),
+            ])
+        );
+        // an annotation of a generic function makes it as specific
+        assert_eq!(
+            mismatches("let id: fn(int) -> int = fn(x) { x };\nid(\"a\")"),
+            found(&[("\"a\"", "mismatched types: expected int, found string")])
+        );
+    }
+
+    #[test]
+    fn indices() {
+        assert_eq!(
+            mismatches("let h = {\"a\": 1};\nh[1];\n\"abc\"[0];\n[1][\"a\"];\n{\"a\": 1, 2: 3}"),
+            found(&[
+                ("h[1]", "mismatched types: expected string, found int"),
+                ("\"abc\"[0]", "mismatched types: expected [a], found string"),
+                ("[1][\"a\"]", "mismatched types: expected int, found string"),
+                ("{\"a\": 1, 2: 3}", "mismatched types: expected string, found int"),
+            ])
+        );
+        // a function indexing its parameter keeps the target it is first called with
+        assert_eq!(
+            mismatches("let get = fn(t) { t[0] };\nget([1]);\nget({0: 1})"),
+            found(&[("{0: 1}", "mismatched types: expected [int], found {int: int}")])
+        );
+    }
+}
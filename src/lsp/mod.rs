@@ -0,0 +1,409 @@
+//! A language server, answering the requests of editors about Monkey sources.
+//!
+//! Messages are JSON-RPC, each after a `Content-Length` header; content which is not JSON is
+//! answered with a parse error. Editors send documents whole when they open or change them,
+//! and get back the diagnostics of `linter::check` with types.
+//! Names are found through `linter::symbols`, so a document must parse to go to definitions,
+//! find references or hover, while completion falls back to the identifiers of its tokens.
+//!
+//! Positions in the protocol count lines from 0 and characters in UTF-16 code units, where the
+//! linter counts from 1 in characters.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+use crate::evaluator::builtins::BuiltinFunctions;
+use crate::formatter::format;
+use crate::json::Json;
+use crate::lexer::token::Token;
+use crate::lexer::Lexer;
+use crate::linter::{self, LintError, Position, Severity, Span, Symbol};
+
+const METHOD_NOT_FOUND: i32 = -32601;
+const INVALID_PARAMS: i32 = -32602;
+const INVALID_REQUEST: i32 = -32600;
+const PARSE_ERROR: i32 = -32700;
+
+/// Reads a message, or nothing at the end of the input.
+pub fn read_message(input: &mut impl BufRead) -> io::Result<Option<Json>> {
+    match read_content(input)? {
+        Some(content) => Json::parse(&content).map(Some).map_err(invalid_data),
+        None => Ok(None),
+    }
+}
+
+/// Reads the content of a message, unparsed, or nothing at the end of the input.
+pub fn read_content(input: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let length = length.ok_or_else(|| invalid_data("no Content-Length header"))?;
+    let mut content = vec![0; length];
+    input.read_exact(&mut content)?;
+    let text = String::from_utf8(content).map_err(|_| invalid_data("the content is not UTF-8"))?;
+    Ok(Some(text))
+}
+
+pub fn write_message(output: &mut impl Write, message: &Json) -> io::Result<()> {
+    let content = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", content.len(), content)?;
+    output.flush()
+}
+
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+#[derive(Debug, Default)]
+pub struct Server {
+    /// the text of the open documents by URI
+    documents: HashMap<String, String>,
+    shut_down: bool,
+    exit_code: Option<i32>,
+}
+
+impl Server {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The status to exit with once the client asked to, 0 if it asked to shut down first.
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit_code
+    }
+
+    /// Handles the content of a message, answering a parse error if it is not JSON.
+    pub fn receive(&mut self, content: &str) -> Vec<Json> {
+        match Json::parse(content) {
+            Ok(message) => self.handle(&message),
+            Err(err) => vec![response(Json::Null, Err((PARSE_ERROR, err)))],
+        }
+    }
+
+    /// Handles a request or a notification, giving the messages to send back.
+    pub fn handle(&mut self, message: &Json) -> Vec<Json> {
+        let method = message.get("method").as_str().unwrap_or_default();
+        let params = message.get("params");
+        let id = match message.get("id") {
+            Json::Null => None,
+            id => Some(id.clone()),
+        };
+        let Some(id) = id else {
+            return self.notification(method, params);
+        };
+        let result = match method {
+            _ if self.shut_down => Err((INVALID_REQUEST, "the server is shut down".to_string())),
+            "initialize" => Ok(capabilities()),
+            "shutdown" => {
+                self.shut_down = true;
+                Ok(Json::Null)
+            }
+            "textDocument/definition" => self.at(params).map(|(uri, symbol)| match symbol {
+                Some(symbol) => location(uri, self.text(uri), &symbol.definition),
+                None => Json::Null,
+            }),
+            "textDocument/references" => self.at(params).map(|(uri, symbol)| {
+                let text = self.text(uri);
+                let declaration = params.get("context").get("includeDeclaration").as_bool().unwrap_or(true);
+                let spans = symbol.iter().flat_map(|symbol| {
+                    declaration.then_some(&symbol.definition).into_iter().chain(&symbol.references)
+                });
+                Json::Array(spans.map(|span| location(uri, text, span)).collect())
+            }),
+            "textDocument/hover" => self.at(params).map(|(uri, symbol)| match symbol {
+                Some(symbol) => hover(self.text(uri), &symbol),
+                None => Json::Null,
+            }),
+            "textDocument/completion" => self.document(params).map(|uri| completion(self.text(uri))),
+            "textDocument/formatting" => self.document(params).map(|uri| formatting(self.text(uri))),
+            _ => Err((METHOD_NOT_FOUND, format!("unknown method: {}", method))),
+        };
+        vec![response(id, result)]
+    }
+
+    fn notification(&mut self, method: &str, params: &Json) -> Vec<Json> {
+        let uri = params.get("textDocument").get("uri").as_str().unwrap_or_default().to_string();
+        match method {
+            "exit" => {
+                self.exit_code = Some(if self.shut_down { 0 } else { 1 });
+                vec![]
+            }
+            "textDocument/didOpen" => {
+                let text = params.get("textDocument").get("text").as_str().unwrap_or_default();
+                self.documents.insert(uri.clone(), text.to_string());
+                vec![diagnostics(&uri, text)]
+            }
+            "textDocument/didChange" => {
+                // the whole text, as synced
+                let changes = params.get("contentChanges").as_array().unwrap_or_default();
+                match changes.last().and_then(|change| change.get("text").as_str()) {
+                    Some(text) => {
+                        self.documents.insert(uri.clone(), text.to_string());
+                        vec![diagnostics(&uri, text)]
+                    }
+                    None => vec![],
+                }
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                vec![diagnostics(&uri, "")]
+            }
+            _ => vec![],
+        }
+    }
+
+    fn text(&self, uri: &str) -> &str {
+        &self.documents[uri]
+    }
+
+    /// the URI of the open document a request is about
+    fn document<'a>(&self, params: &'a Json) -> Result<&'a str, (i32, String)> {
+        match params.get("textDocument").get("uri").as_str() {
+            Some(uri) if self.documents.contains_key(uri) => Ok(uri),
+            Some(uri) => Err((INVALID_PARAMS, format!("unknown document: {}", uri))),
+            None => Err((INVALID_PARAMS, "no document".to_string())),
+        }
+    }
+
+    /// the document and position a request is about, with the symbol named there
+    fn at<'a>(&self, params: &'a Json) -> Result<(&'a str, Option<Symbol>), (i32, String)> {
+        let uri = self.document(params)?;
+        let text = self.text(uri);
+        let position = from_lsp(text, params.get("position")).ok_or((INVALID_PARAMS, "no position".to_string()))?;
+        let within = |span: &Span| span.start <= position && position <= span.end;
+        let symbol = linter::symbols(text)
+            .unwrap_or_default()
+            .into_iter()
+            .find(|symbol| within(&symbol.definition) || symbol.references.iter().any(within));
+        Ok((uri, symbol))
+    }
+}
+
+fn response(id: Json, result: Result<Json, (i32, String)>) -> Json {
+    match result {
+        Ok(result) => Json::object([("jsonrpc", Json::string("2.0")), ("id", id), ("result", result)]),
+        Err((code, message)) => {
+            let error = Json::object([("code", Json::Number(code.into())), ("message", message.into())]);
+            Json::object([("jsonrpc", Json::string("2.0")), ("id", id), ("error", error)])
+        }
+    }
+}
+
+fn capabilities() -> Json {
+    let capabilities = Json::object([
+        // documents are sent whole
+        ("textDocumentSync", 1.into()),
+        ("definitionProvider", true.into()),
+        ("referencesProvider", true.into()),
+        ("hoverProvider", true.into()),
+        ("completionProvider", Json::object([])),
+        ("documentFormattingProvider", true.into()),
+    ]);
+    let info = Json::object([
+        ("name", Json::string("monkey_lsp")),
+        ("version", Json::string(env!("CARGO_PKG_VERSION"))),
+    ]);
+    Json::object([("capabilities", capabilities), ("serverInfo", info)])
+}
+
+fn diagnostics(uri: &str, text: &str) -> Json {
+    let diagnostics = match linter::check(text, true) {
+        Ok(diagnostics) => diagnostics
+            .into_iter()
+            .map(|d| {
+                let severity = match d.severity() {
+                    Severity::Error => 1,
+                    Severity::Warning => 2,
+                };
+                Json::object([
+                    ("range", range(text, &d.span)),
+                    ("severity", severity.into()),
+                    ("code", Json::string(d.rule.id())),
+                    ("source", Json::string("monkey")),
+                    ("message", d.message.into()),
+                ])
+            })
+            .collect(),
+        // the statement not parsing, to the end of its line
+        Err(err @ LintError::Syntax(start)) => {
+            let line = text.lines().nth(start.line - 1).unwrap_or_default();
+            let end = Position { line: start.line, column: line.chars().count() + 1 };
+            vec![Json::object([
+                ("range", range(text, &Span { start, end })),
+                ("severity", 1.into()),
+                ("source", Json::string("monkey")),
+                ("message", err.to_string().into()),
+            ])]
+        }
+    };
+    let params = Json::object([("uri", Json::string(uri)), ("diagnostics", Json::Array(diagnostics))]);
+    Json::object([
+        ("jsonrpc", Json::string("2.0")),
+        ("method", Json::string("textDocument/publishDiagnostics")),
+        ("params", params),
+    ])
+}
+
+fn hover(text: &str, symbol: &Symbol) -> Json {
+    let mut signature = match symbol.kind {
+        linter::Kind::Let => format!("let {}", symbol.name),
+        linter::Kind::Param => symbol.name.clone(),
+        linter::Kind::Import => format!("import {}", symbol.name),
+    };
+    if let Some(ty) = &symbol.ty {
+        signature += &format!(": {}", ty);
+    }
+    if let Some(value) = &symbol.value {
+        signature += &format!(" = {}", value);
+    }
+    let contents = Json::object([
+        ("kind", Json::string("markdown")),
+        ("value", format!("```monkey\n{}\n```", signature).into()),
+    ]);
+    Json::object([("contents", contents), ("range", range(text, &symbol.definition))])
+}
+
+/// the names bound in the document, or only written in it if it does not parse, and the
+/// builtins
+fn completion(text: &str) -> Json {
+    const FUNCTION: usize = 3;
+    const VARIABLE: usize = 6;
+    let mut names = HashMap::new();
+    match linter::symbols(text) {
+        Ok(symbols) => {
+            for symbol in symbols {
+                names.entry(symbol.name).or_insert(symbol.ty);
+            }
+        }
+        Err(_) => {
+            for (_, token) in Lexer::lex_spans(text.as_bytes()).0 {
+                if let Token::Ident(name) = token {
+                    names.entry(name).or_insert(None);
+                }
+            }
+        }
+    }
+    let mut names = names.into_iter().collect::<Vec<_>>();
+    names.sort();
+    let builtins = BuiltinFunctions::new().names();
+    let items = names
+        .into_iter()
+        .filter(|(name, _)| !builtins.contains(name))
+        .map(|(name, ty)| {
+            let mut item = vec![("label".to_string(), name.into()), ("kind".to_string(), VARIABLE.into())];
+            item.extend(ty.map(|ty| ("detail".to_string(), ty.into())));
+            Json::Object(item)
+        })
+        .chain(builtins.iter().map(|name| {
+            Json::object([
+                ("label", Json::string(name)),
+                ("kind", FUNCTION.into()),
+                ("detail", Json::string("built-in function")),
+            ])
+        }))
+        .collect();
+    Json::Array(items)
+}
+
+/// the edit replacing the document by its formatted text, if it parses and needs one
+fn formatting(text: &str) -> Json {
+    match format(text) {
+        Ok(formatted) if formatted == text => Json::Array(vec![]),
+        Ok(formatted) => {
+            let lines = text.split('\n').collect::<Vec<_>>();
+            let end = Position { line: lines.len(), column: lines[lines.len() - 1].chars().count() + 1 };
+            let start = Position { line: 1, column: 1 };
+            let edit = Json::object([("range", range(text, &Span { start, end })), ("newText", formatted.into())]);
+            Json::Array(vec![edit])
+        }
+        Err(_) => Json::Null,
+    }
+}
+
+fn location(uri: &str, text: &str, span: &Span) -> Json {
+    Json::object([("uri", Json::string(uri)), ("range", range(text, span))])
+}
+
+fn range(text: &str, span: &Span) -> Json {
+    Json::object([("start", to_lsp(text, span.start)), ("end", to_lsp(text, span.end))])
+}
+
+fn to_lsp(text: &str, position: Position) -> Json {
+    let line = text.split('\n').nth(position.line - 1).unwrap_or_default();
+    let character = line.chars().take(position.column - 1).map(char::len_utf16).sum::<usize>();
+    Json::object([("line", (position.line - 1).into()), ("character", character.into())])
+}
+
+fn from_lsp(text: &str, position: &Json) -> Option<Position> {
+    let line = position.get("line").as_usize()?;
+    let character = position.get("character").as_usize()?;
+    let mut units = 0;
+    let column = text
+        .split('\n')
+        .nth(line)
+        .unwrap_or_default()
+        .chars()
+        .take_while(|c| {
+            units += c.len_utf16();
+            units <= character
+        })
+        .count();
+    Some(Position { line: line + 1, column: column + 1 })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn positions() {
+        let text = "let s = \"😀\"; s\nlet é = 1";
+        let position = |line: usize, character: usize| {
+            Json::object([("line", line.into()), ("character", character.into())])
+        };
+        assert_eq!(to_lsp(text, Position { line: 1, column: 14 }), position(0, 14));
+        assert_eq!(from_lsp(text, &position(0, 14)), Some(Position { line: 1, column: 14 }));
+        assert_eq!(to_lsp(text, Position { line: 2, column: 6 }), position(1, 5));
+        assert_eq!(from_lsp(text, &position(1, 5)), Some(Position { line: 2, column: 6 }));
+        assert_eq!(from_lsp(text, &Json::Null), None);
+    }
+
+    #[test]
+    fn messages() {
+        let message = Json::object([("id", 1.into())]);
+        let mut output = vec![];
+        write_message(&mut output, &message).unwrap();
+        assert_eq!(String::from_utf8(output.clone()).unwrap(), "Content-Length: 9\r\n\r\n{\"id\": 1}");
+        let mut input = &output[..];
+        assert_eq!(read_message(&mut input).unwrap(), Some(message));
+        assert_eq!(read_message(&mut input).unwrap(), None);
+        assert!(read_message(&mut &b"Content-Type: x\r\n\r\n{}"[..]).is_err());
+        assert!(read_message(&mut &b"Content-Length: 1\r\n\r\n{"[..]).is_err());
+        assert_eq!(read_content(&mut &b"Content-Length: 1\r\n\r\n{"[..]).unwrap(), Some("{".to_string()));
+    }
+
+    #[test]
+    fn parse_error() {
+        let mut server = Server::new();
+        let responses = server.receive("{\"id\": 1, \"method\": }");
+        assert_eq!(responses.len(), 1);
+        assert_eq!(responses[0].get("id"), &Json::Null);
+        assert_eq!(responses[0].get("error").get("code"), &Json::Number(-32700.0));
+        let responses = server.receive("{\"id\": 2, \"method\": \"shutdown\"}");
+        assert_eq!(responses[0].get("error"), &Json::Null);
+        assert_eq!(responses[0].get("id").as_usize(), Some(2));
+    }
+}
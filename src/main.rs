@@ -1,44 +1,259 @@
+use std::collections::HashMap;
 use std::fs::File;
-use std::io::Read;
-use nom::IResult;
+use std::io::{self, Read};
+use std::process;
 
 mod cmd;
-use cmd::{Command, read_command};
-use monkey_lang_lib::evaluator::Evaluator;
+use cmd::{Command, Options};
+use monkey_lang_lib::compiler::binary::is_bytecode;
+use monkey_lang_lib::compiler::{Bytecode, Compiler};
+use monkey_lang_lib::debug;
+use monkey_lang_lib::debug::coverage::Coverage;
+use monkey_lang_lib::debug::profile::Profiler;
+use monkey_lang_lib::debug::trace::Tracer;
+use monkey_lang_lib::engine::Engine;
+use monkey_lang_lib::evaluator::observer::EvalObserver;
+use monkey_lang_lib::formatter;
 use monkey_lang_lib::lexer::Lexer;
+use monkey_lang_lib::linter::{self, Severity};
 use monkey_lang_lib::lexer::token::Tokens;
+use monkey_lang_lib::optimizer::Optimizer;
+use monkey_lang_lib::parser::ast::Program;
 use monkey_lang_lib::parser::Parser;
+use monkey_lang_lib::testing::{self, Format, Report};
+use monkey_lang_lib::vm::Vm;
 
 fn main() {
-    let code_string = match cmd::read_command() {
-        Command::FileRead(file_path) => read_file(file_path).ok(),
-        Command::RunInlineCode(code) => Some(code),
+    let (command, options) = cmd::read_command();
+    let code_string = match command {
+        Command::FileRead(file_path) => read_file(file_path.clone()).ok().map(|code| (file_path, code)),
+        Command::RunInlineCode(code) => Some(("<inline>".to_string(), code)),
+        Command::Compile(input, output) => {
+            compile(&input, &output, &options);
+            None
+        }
+        Command::Run(input) => {
+            run(&input, &options);
+            None
+        }
+        Command::Format(files, check) => {
+            format(&files, check);
+            None
+        }
+        Command::Check(files, types, json) => {
+            check(&files, types, json);
+            None
+        }
+        Command::Test(paths, format) => {
+            test(&paths, format);
+            None
+        }
         Command::Noop => None,
     };
 
-    if let Some(code_string) = code_string {
-        let mut evaluator = Evaluator::new();
-        let lex_tokens = Lexer::lex_tokens(code_string.as_bytes());
-        match lex_tokens {
-            Ok((_, r)) => {
-                let tokens = Tokens::new(&r);
-                let parsed = Parser::parse_tokens(tokens);
-                match parsed {
-                    Ok((_, program)) => {
-                        let eval = evaluator.eval_program(program);
-                        println!("{}", eval);
-                    }
-                    Err(nom::Err::Error(_)) => println!("Parser error"),
-                    Err(nom::Err::Failure(_)) => println!("Parser failure"),
-                    Err(nom::Err::Incomplete(_)) => println!("Incomplete parsing"),
+    if let Some((path, code_string)) = code_string {
+        eval(&path, &code_string, &options);
+    }
+
+}
+
+fn parse(code_string: &str) -> Option<Program> {
+    let lex_tokens = Lexer::lex_tokens(code_string.as_bytes());
+    match lex_tokens {
+        Ok((_, r)) => {
+            let tokens = Tokens::new(&r);
+            let parsed = Parser::parse_tokens(tokens);
+            match parsed {
+                Ok((_, program)) => return Some(program),
+                Err(nom::Err::Error(_)) => println!("Parser error"),
+                Err(nom::Err::Failure(_)) => println!("Parser failure"),
+                Err(nom::Err::Incomplete(_)) => println!("Incomplete parsing"),
+            }
+        }
+        Err(nom::Err::Error(_)) => println!("Lexer error"),
+        Err(nom::Err::Failure(_)) => println!("Lexer failure"),
+        Err(nom::Err::Incomplete(_)) => println!("Incomplete lexing"),
+    }
+    None
+}
+
+/// parses the code and applies the options concerning the syntax tree
+fn prepare(code_string: &str, options: &Options) -> Option<Program> {
+    let program = parse(code_string)?;
+    let program = if options.optimize { Optimizer::new().optimize(program) } else { program };
+    if options.dump_ast {
+        println!("{:#?}", program);
+        return None;
+    }
+    Some(program)
+}
+
+/// runs code read from a path on the selected backend, or prints it translated
+fn eval(path: &str, code_string: &str, options: &Options) {
+    if let Some(program) = prepare(code_string, options) {
+        if let Some(target) = options.emit {
+            match target.emit(&program) {
+                Ok(code) => print!("{}", code),
+                Err(err) => {
+                    eprintln!("{}", err);
+                    process::exit(1)
                 }
             }
-            Err(nom::Err::Error(_)) => println!("Lexer error"),
-            Err(nom::Err::Failure(_)) => println!("Lexer failure"),
-            Err(nom::Err::Incomplete(_)) => println!("Incomplete lexing"),
+            return;
+        }
+        let mut engine = Engine::new(options.backend);
+        let profiler = (options.profile || options.folded.is_some()).then(Profiler::new);
+        let coverage = options.coverage.as_ref().map(|_| Coverage::new());
+        let observer: Option<Box<dyn EvalObserver>> = match (&profiler, &coverage) {
+            (Some(profiler), _) => Some(Box::new(profiler.clone())),
+            (_, Some(coverage)) => {
+                coverage.add(path, code_string, &program);
+                Some(Box::new(coverage.clone()))
+            }
+            _ if options.trace => Some(Box::new(Tracer::new(Box::new(io::stderr())))),
+            _ => None,
+        };
+        if let Some(observer) = observer {
+            match &mut engine {
+                Engine::Eval(evaluator) => evaluator.set_observer(Some(observer)),
+                Engine::Vm { .. } => fail(path, "only the programs run on the eval engine are followed"),
+            }
+        }
+        if let Engine::Eval(evaluator) = &mut engine {
+            if path != "<inline>" {
+                evaluator.set_file(Some(path));
+            }
         }
+        let eval = engine.eval_program(program);
+        if let Some(profiler) = profiler {
+            let profile = profiler.finish();
+            if options.profile {
+                eprint!("{}", profile);
+            }
+            if let Some(path) = &options.folded {
+                std::fs::write(path, profile.folded()).unwrap_or_else(|err| fail(path, err));
+            }
+        }
+        if let (Some(coverage), Some(info)) = (coverage, &options.coverage) {
+            let report = coverage.report();
+            eprint!("{}", report);
+            std::fs::write(info, report.lcov()).unwrap_or_else(|err| fail(info, err));
+        }
+        println!("{}", eval);
     }
+}
+
+fn compile(input: &str, output: &str, options: &Options) {
+    let source = read_file(input.to_string()).unwrap_or_else(|err| fail(input, err));
+    let program = match prepare(&source, options) {
+        Some(program) => program,
+        None => process::exit(if options.dump_ast { 0 } else { 1 }),
+    };
+    // the lines of statements are only known in the tree parsed from the source
+    let lines = if options.optimize { HashMap::new() } else { debug::lines(&source, &program) };
+    match Compiler::new().compile_with_lines(&program, &lines) {
+        Ok(bytecode) => std::fs::write(output, bytecode.to_bytes()).unwrap_or_else(|err| fail(output, err)),
+        Err(err) => fail(input, err),
+    }
+}
+
+/// runs a bytecode file on the vm, or a source file on the selected backend
+fn run(input: &str, options: &Options) {
+    let bytes = std::fs::read(input).unwrap_or_else(|err| fail(input, err));
+    if is_bytecode(&bytes) {
+        if let Some(option) = source_option(options) {
+            fail(input, format!("{} does not apply to bytecode, which runs on the vm as it was compiled", option));
+        }
+        match Bytecode::from_bytes(&bytes) {
+            Ok(bytecode) => println!("{}", Vm::new().run(&bytecode)),
+            Err(err) => fail(input, err),
+        }
+    } else {
+        match String::from_utf8(bytes) {
+            Ok(source) => eval(input, &source, options),
+            Err(err) => fail(input, err),
+        }
+    }
+}
+
+/// the first option given which only applies to source code, or to the eval engine
+fn source_option(options: &Options) -> Option<&'static str> {
+    [
+        (options.optimize, "--opt"),
+        (options.dump_ast, "--dump-ast"),
+        (options.emit.is_some(), "--emit"),
+        (options.trace, "--trace"),
+        (options.profile, "--profile"),
+        (options.folded.is_some(), "--folded"),
+        (options.coverage.is_some(), "--coverage"),
+    ]
+    .into_iter()
+    .find_map(|(given, option)| given.then_some(option))
+}
+
+/// formats files in place, or lists those not formatted and fails if there are any
+fn format(files: &[String], check: bool) {
+    let mut unformatted = false;
+    for path in files {
+        let source = read_file(path.clone()).unwrap_or_else(|err| fail(path, err));
+        let formatted = formatter::format(&source).unwrap_or_else(|err| fail(path, err));
+        if formatted == source {
+            continue;
+        }
+        if check {
+            println!("{}", path);
+            unformatted = true;
+        } else {
+            std::fs::write(path, formatted).unwrap_or_else(|err| fail(path, err));
+        }
+    }
+    if unformatted {
+        process::exit(1);
+    }
+}
+
+/// prints what the linter finds in files, failing if there are errors
+fn check(files: &[String], types: bool, json: bool) {
+    let mut reports = vec![];
+    for path in files {
+        let source = read_file(path.clone()).unwrap_or_else(|err| fail(path, err));
+        let diagnostics = linter::check(&source, types).unwrap_or_else(|err| fail(path, err));
+        reports.push((path.clone(), diagnostics));
+    }
+    if json {
+        println!("{}", linter::to_json(&reports));
+    } else {
+        for (path, diagnostics) in &reports {
+            diagnostics.iter().for_each(|d| println!("{}:{}", path, d));
+        }
+    }
+    if reports.iter().flat_map(|(_, diagnostics)| diagnostics).any(|d| d.severity() == Severity::Error) {
+        process::exit(1);
+    }
+}
+
+/// runs the tests of the test files of paths, failing if any fails
+fn test(paths: &[String], format: Format) {
+    let mut suites = vec![];
+    for path in paths {
+        for file in testing::discover(path).unwrap_or_else(|err| fail(path, err)) {
+            let source = read_file(file.clone()).unwrap_or_else(|err| fail(&file, err));
+            suites.push(testing::run(&file, &source));
+        }
+    }
+    if suites.is_empty() {
+        eprintln!("no {} files found", testing::SUFFIX);
+    }
+    let report = Report { suites };
+    print!("{}", report.write(format));
+    if !report.success() {
+        process::exit(1);
+    }
+}
 
+fn fail(path: &str, err: impl std::fmt::Display) -> ! {
+    eprintln!("{}: {}", path, err);
+    process::exit(1)
 }
 
 fn read_file(file_path: String) -> Result<String, std::io::Error> {
@@ -0,0 +1,289 @@
+use std::rc::Rc;
+
+use crate::evaluator::builtins::BuiltinFunctions;
+use crate::evaluator::object::Object;
+use crate::evaluator::operators;
+use crate::parser::ast::{Expr, Literal, Program, Stmt};
+use crate::resolver::Resolver;
+
+/// Rewrites programs into ones giving the same results with less work.
+///
+/// - prefix and infix expressions of literals are folded into a literal, unless evaluating
+///   them gives an error, such as a division by zero or an overflow, which is left to happen
+///   at run time, giving the error value it would without optimizing
+/// - an `if` with a literal condition is replaced by the statements of the branch taken
+/// - statements after a `return` are removed
+///
+/// Code is never removed when it binds a name, as bindings are visible from the whole
+/// scope of a function whether they run or not. Programs referring to undefined names are
+/// left as they are so they fail the same way, which takes keeping the global scope between
+/// programs as the backends do.
+#[derive(Debug, Clone)]
+pub struct Optimizer {
+    resolver: Resolver,
+}
+
+impl Optimizer {
+    pub fn new() -> Self {
+        Optimizer {
+            resolver: Resolver::new(BuiltinFunctions::new().names()),
+        }
+    }
+
+    pub fn optimize(&mut self, program: Program) -> Program {
+        match self.resolver.resolve(&program) {
+            Ok(_) => block(program),
+            Err(_) => program,
+        }
+    }
+}
+
+impl Default for Optimizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn block(program: Program) -> Program {
+    let mut out = vec![];
+    let mut returned = false;
+    let len = program.len();
+    for (i, stmt) in program.into_iter().enumerate() {
+        if returned {
+            // unreachable
+            if declares(&stmt) {
+                out.push(stmt);
+            }
+            continue;
+        }
+        let last = i == len - 1;
+        match stmt {
+            Stmt::ExprStmt(expr) => match taken_branch(expr) {
+                // the statements of the branch run in this block as they would in their own,
+                // stopping at a return
+                Ok(branch) if !branch.is_empty() || !last => {
+                    returned = branch.iter().any(|s| matches!(s, Stmt::ReturnStmt(_)));
+                    out.extend(branch);
+                }
+                Ok(branch) => out.push(Stmt::ExprStmt(Expr::IfExpr {
+                    cond: Box::new(Expr::LiteralExpr(Literal::BoolLiteral(true))),
+                    consequence: branch,
+                    alternative: None,
+                })),
+                Err(expr) => out.push(Stmt::ExprStmt(expr)),
+            },
+            Stmt::LetStmt(ident, ty, expr) => out.push(Stmt::LetStmt(ident, ty, self::expr(expr))),
+            Stmt::ReturnStmt(expr) => {
+                out.push(Stmt::ReturnStmt(self::expr(expr)));
+                returned = true;
+            }
+            Stmt::ImportStmt(..) | Stmt::ExportStmt(_) => out.push(stmt),
+        }
+    }
+    out
+}
+
+/// The optimized statements of an `if` always taking its only branch, which `expr` turns
+/// the `if`s with a literal condition into. Any other expression is optimized and given back.
+fn taken_branch(expr: Expr) -> Result<Program, Expr> {
+    match self::expr(expr) {
+        Expr::IfExpr { cond, consequence, alternative: None }
+            if *cond == Expr::LiteralExpr(Literal::BoolLiteral(true)) => Ok(consequence),
+        expr => Err(expr),
+    }
+}
+
+fn expr(expr: Expr) -> Expr {
+    match expr {
+        Expr::IdentExpr(_) | Expr::LiteralExpr(_) => expr,
+        Expr::PrefixExpr(prefix, e) => match (prefix, self::expr(*e)) {
+            (prefix, Expr::LiteralExpr(l)) => match fold(operators::prefix(&prefix, object(&l))) {
+                Some(folded) => Expr::LiteralExpr(folded),
+                None => Expr::PrefixExpr(prefix, Box::new(Expr::LiteralExpr(l))),
+            },
+            (prefix, e) => Expr::PrefixExpr(prefix, Box::new(e)),
+        },
+        Expr::InfixExpr(infix, e1, e2) => match (self::expr(*e1), self::expr(*e2)) {
+            (Expr::LiteralExpr(l1), Expr::LiteralExpr(l2)) => match fold(operators::infix(&infix, object(&l1), object(&l2))) {
+                Some(folded) => Expr::LiteralExpr(folded),
+                None => Expr::InfixExpr(infix, Box::new(Expr::LiteralExpr(l1)), Box::new(Expr::LiteralExpr(l2))),
+            },
+            (e1, e2) => Expr::InfixExpr(infix, Box::new(e1), Box::new(e2)),
+        },
+        Expr::IfExpr { cond, consequence, alternative } => {
+            let cond = self::expr(*cond);
+            let consequence = block(consequence);
+            let alternative = alternative.map(block);
+            match cond {
+                Expr::LiteralExpr(Literal::BoolLiteral(b)) => {
+                    let (taken, dropped) = match (b, alternative) {
+                        (true, alternative) => (consequence, alternative),
+                        (false, alternative) => (alternative.unwrap_or_default(), Some(consequence)),
+                    };
+                    match dropped {
+                        // kept whole, as the branch not taken binds a name
+                        Some(dropped) if dropped.iter().any(declares) => {
+                            let (consequence, alternative) = if b { (taken, dropped) } else { (dropped, taken) };
+                            Expr::IfExpr {
+                                cond: Box::new(Expr::LiteralExpr(Literal::BoolLiteral(b))),
+                                consequence,
+                                alternative: Some(alternative),
+                            }
+                        }
+                        _ => Expr::IfExpr {
+                            cond: Box::new(Expr::LiteralExpr(Literal::BoolLiteral(true))),
+                            consequence: taken,
+                            alternative: None,
+                        },
+                    }
+                }
+                cond => Expr::IfExpr { cond: Box::new(cond), consequence, alternative },
+            }
+        }
+        Expr::FnExpr { params, signature, body } => {
+            let body = Rc::try_unwrap(body).unwrap_or_else(|body| (*body).clone());
+            Expr::FnExpr { params, signature, body: Rc::new(block(body)) }
+        }
+        Expr::CallExpr { function, arguments } => Expr::CallExpr {
+            function: Box::new(self::expr(*function)),
+            arguments: arguments.into_iter().map(self::expr).collect(),
+        },
+        Expr::ArrayExpr(exprs) => Expr::ArrayExpr(exprs.into_iter().map(self::expr).collect()),
+        Expr::HashExpr(pairs) => Expr::HashExpr(pairs.into_iter().map(|(l, e)| (l, self::expr(e))).collect()),
+        Expr::IndexExpr { array, index } => Expr::IndexExpr {
+            array: Box::new(self::expr(*array)),
+            index: Box::new(self::expr(*index)),
+        },
+        Expr::MemberExpr { module, name } => Expr::MemberExpr { module: Box::new(self::expr(*module)), name },
+    }
+}
+
+/// The literal an operation on literals evaluates to, computed by the operators the backends
+/// share. An error, such as an overflow, is left as the operation, which gives the same error
+/// value whether the program is optimized or not.
+fn fold(object: Object) -> Option<Literal> {
+    match object {
+        Object::Integer(i) => Some(Literal::IntLiteral(i)),
+        Object::Boolean(b) => Some(Literal::BoolLiteral(b)),
+        Object::String(s) => Some(Literal::StringLiteral(s)),
+        _ => None,
+    }
+}
+
+fn object(literal: &Literal) -> Object {
+    match literal {
+        Literal::IntLiteral(i) => Object::Integer(*i),
+        Literal::BoolLiteral(b) => Object::Boolean(*b),
+        Literal::StringLiteral(s) => Object::String(s.clone()),
+    }
+}
+
+/// whether a statement binds a name in the scope it runs in
+fn declares(stmt: &Stmt) -> bool {
+    match stmt {
+        Stmt::LetStmt(_, _, _) | Stmt::ImportStmt(..) => true,
+        Stmt::ExportStmt(_) => false,
+        Stmt::ReturnStmt(expr) | Stmt::ExprStmt(expr) => expr_declares(expr),
+    }
+}
+
+fn expr_declares(expr: &Expr) -> bool {
+    match expr {
+        Expr::IdentExpr(_) | Expr::LiteralExpr(_) | Expr::FnExpr { .. } => false,
+        Expr::PrefixExpr(_, e) => expr_declares(e),
+        Expr::InfixExpr(_, e1, e2) => expr_declares(e1) || expr_declares(e2),
+        Expr::IfExpr { cond, consequence, alternative } => {
+            expr_declares(cond)
+                || consequence.iter().any(declares)
+                || alternative.iter().flatten().any(declares)
+        }
+        Expr::CallExpr { function, arguments } => {
+            expr_declares(function) || arguments.iter().any(expr_declares)
+        }
+        Expr::ArrayExpr(exprs) => exprs.iter().any(expr_declares),
+        Expr::HashExpr(pairs) => pairs.iter().any(|(_, e)| expr_declares(e)),
+        Expr::IndexExpr { array, index } => expr_declares(array) || expr_declares(index),
+        Expr::MemberExpr { module, .. } => expr_declares(module),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{Backend, Engine};
+    use crate::lexer::token::Tokens;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn parse(input: &str) -> Program {
+        let (_, r) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+        let (_, program) = Parser::parse_tokens(Tokens::new(&r)).unwrap();
+        program
+    }
+
+    fn compare(input: &str, expected: &str) {
+        let optimized = Optimizer::new().optimize(parse(input));
+        assert_eq!(optimized, parse(expected), "optimizing {}", input);
+    }
+
+    #[test]
+    fn folding() {
+        compare("2 * 60 * 60", "7200");
+        compare("let x = (1 + 2) * -(3) + 10", "let x = 1");
+        compare("\"a\" + \"b\" == \"ab\"", "true");
+        compare("!(1 < 2) != !!false", "false");
+        compare("fn(x) { x * (2 + 3) }", "fn(x) { x * 5 }");
+        compare("[1 + 1, {\"k\": 2 * 2}[\"k\"]]", "[2, {\"k\": 4}[\"k\"]]");
+    }
+
+    #[test]
+    fn errors_are_kept() {
+        compare("10 / (5 - 5)", "10 / 0");
+        compare("9223372036854775807 + 1", "9223372036854775807 + 1");
+        compare("(1 + 1) * 9223372036854775807 * 2", "2 * 9223372036854775807 * 2");
+        compare("!5", "!5");
+        compare("\"a\" - 1", "\"a\" - 1");
+        compare("1 + true", "1 + true");
+    }
+
+    #[test]
+    fn same_errors() {
+        let overflow = Object::Error("integer overflow in addition".to_string());
+        let division = Object::Error("division by zero".to_string());
+        for (input, expected) in [("9223372036854775807 + 1", overflow), ("1 / 0", division)] {
+            for backend in [Backend::Eval, Backend::Vm] {
+                let plain = Engine::new(backend).eval_program(parse(input));
+                let optimized = Engine::new(backend).eval_program(Optimizer::new().optimize(parse(input)));
+                assert_eq!(plain, expected, "running {} on {:?}", input, backend);
+                assert_eq!(optimized, expected, "running {} optimized on {:?}", input, backend);
+            }
+        }
+    }
+
+    #[test]
+    fn dead_branches() {
+        compare("if (1 < 2) { 1 } else { 2 }", "1");
+        compare("if (false) { 1 }; 2", "2");
+        compare("if (false) { 1 }", "if (true) { }");
+        compare("let a = if (false) { 1 } else { 2 }", "let a = if (true) { 2 }");
+        compare("if (true) { let a = 1; return a; 3 }; 4", "let a = 1; return a;");
+        compare("let c = true; if (c) { 1 } else { 2 }", "let c = true; if (c) { 1 } else { 2 }");
+        compare("if (false) { let x = 1 } else { 2 }", "if (false) { let x = 1 } else { 2 }");
+    }
+
+    #[test]
+    fn after_return() {
+        compare("return 1; 2; let x = 3; x", "return 1; let x = 3;");
+        compare("fn(x) { return 1; if (x) { let y = 2 }; 3 }", "fn(x) { return 1; if (x) { let y = 2 }; }");
+    }
+
+    #[test]
+    fn undefined_names() {
+        compare("return 1; x", "return 1; x");
+        compare("if (false) { x } else { 1 + 1 }", "if (false) { x } else { 1 + 1 }");
+
+        let mut optimizer = Optimizer::new();
+        assert_eq!(optimizer.optimize(parse("let x = 1 + 1;")), parse("let x = 2;"));
+        assert_eq!(optimizer.optimize(parse("if (true) { x }")), parse("x"));
+    }
+}
@@ -1,11 +1,36 @@
+use std::rc::Rc;
+
 pub type Program = Vec<Stmt>;
 
 /// Statement
 #[derive(Debug, Clone, PartialEq)]
 pub enum Stmt {
-    LetStmt(Ident, Expr),
+    LetStmt(Ident, Option<Type>, Expr),
     ReturnStmt(Expr),
     ExprStmt(Expr),
+    /// binds a module, or the names it exports which are given, at the top level of a file
+    ImportStmt(String, Import),
+    /// the bindings of a file those importing it may use, at its top level
+    ExportStmt(Vec<Ident>),
+}
+
+/// what an import binds
+#[derive(Debug, Clone, PartialEq)]
+pub enum Import {
+    /// `import "lib.mk" as lib;`
+    Module(Ident),
+    /// `import { double, square } from "lib.mk";`
+    Names(Vec<Ident>),
+}
+
+impl Import {
+    /// the names bound
+    pub fn idents(&self) -> impl Iterator<Item = &Ident> {
+        match self {
+            Import::Module(ident) => std::slice::from_ref(ident).iter(),
+            Import::Names(idents) => idents.iter(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -21,7 +46,8 @@ pub enum Expr {
     },
     FnExpr {
         params: Vec<Ident>,
-        body: Program,
+        signature: Rc<Signature>,
+        body: Rc<Program>,
     },
     CallExpr {
         function: Box<Expr>,
@@ -32,7 +58,12 @@ pub enum Expr {
     IndexExpr {
         array: Box<Expr>,
         index: Box<Expr>,
-    }
+    },
+    /// a name a module exports, `lib.name`
+    MemberExpr {
+        module: Box<Expr>,
+        name: Ident,
+    },
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -46,6 +77,32 @@ pub enum Literal {
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Ident(pub String);
 
+/// Type annotation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Type {
+    Int,
+    Bool,
+    String,
+    Null,
+    Array(Box<Type>),
+    Hash(Box<Type>, Box<Type>),
+    Fn(Vec<Type>, Box<Type>),
+}
+
+/// The annotations of a function literal: the types of its parameters, none when no
+/// parameter has one, and the type of what it returns.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Signature {
+    pub params: Vec<Option<Type>>,
+    pub ret: Option<Type>,
+}
+
+impl Signature {
+    pub fn param(&self, index: usize) -> Option<&Type> {
+        self.params.get(index).and_then(Option::as_ref)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Prefix {
     PrefixPlus,
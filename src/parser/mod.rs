@@ -1,3 +1,5 @@
+use std::rc::Rc;
+
 use nom::branch::*;
 use nom::bytes::complete::take;
 use nom::combinator::{map, opt, verify};
@@ -7,9 +9,11 @@ use nom::sequence::*;
 use nom::{error_position, IResult};
 
 pub mod ast;
+pub mod printer;
 mod parse_util;
 use crate::lexer::token::{Token, Tokens};
-use crate::parser::ast::{Expr, Ident, Literal, Stmt};
+use crate::lexer::Lexer;
+use crate::parser::ast::{Expr, Ident, Import, Literal, Signature, Stmt, Type};
 use crate::parser::ast::{Infix, Precedence, Prefix, Program};
 use crate::parser::parse_util::{parse_ident, parse_literal};
 
@@ -32,6 +36,7 @@ tag_token!(rparen_tag, Token::RParen);
 tag_token!(lbracket_tag, Token::LBracket);
 tag_token!(rbracket_tag, Token::RBracket);
 tag_token!(comma_tag, Token::Comma);
+tag_token!(arrow_tag, Token::Arrow);
 tag_token!(colon_tag, Token::Colon);
 tag_token!(plus_tag, Token::Plus);
 tag_token!(minus_tag, Token::Minus);
@@ -39,6 +44,9 @@ tag_token!(not_tag, Token::Not);
 tag_token!(if_tag, Token::If);
 tag_token!(else_tag, Token::Else);
 tag_token!(function_tag, Token::Function);
+tag_token!(import_tag, Token::Import);
+tag_token!(export_tag, Token::Export);
+tag_token!(dot_tag, Token::Dot);
 tag_token!(eof_tag, Token::EOF);
 
 fn infix_op(t: &Token) -> (Precedence, Option<Infix>) {
@@ -54,7 +62,7 @@ fn infix_op(t: &Token) -> (Precedence, Option<Infix>) {
         Token::Multiply => (Precedence::PProduct, Some(Infix::Multiply)),
         Token::Divide => (Precedence::PProduct, Some(Infix::Divide)),
         Token::LParen => (Precedence::PCall, None),
-        Token::LBracket => (Precedence::PIndex, None),
+        Token::LBracket | Token::Dot => (Precedence::PIndex, None),
         _ => (Precedence::PLowest, None),
     }
 }
@@ -68,7 +76,43 @@ fn parse_expr(input: Tokens) -> IResult<Tokens, Expr> {
 }
 
 fn parse_stmt(input: Tokens) -> IResult<Tokens, Stmt> {
-    alt((parse_let_stmt, parse_return_stmt, parse_expr_stmt))(input)
+    alt((parse_let_stmt, parse_return_stmt, parse_import_stmt, parse_export_stmt, parse_expr_stmt))(input)
+}
+
+/// an identifier read as a word of the syntax where it is, such as the `as` of imports
+fn keyword(word: &'static str) -> impl Fn(Tokens) -> IResult<Tokens, Ident> {
+    move |input| verify(parse_ident, |Ident(name)| name == word)(input)
+}
+
+fn parse_idents(input: Tokens) -> IResult<Tokens, Vec<Ident>> {
+    map(
+        pair(parse_ident, many0(preceded(comma_tag, parse_ident))),
+        |(first, rest)| [vec![first], rest].concat(),
+    )(input)
+}
+
+fn parse_path(input: Tokens) -> IResult<Tokens, String> {
+    let (i1, literal) = parse_literal(input)?;
+    match literal {
+        Literal::StringLiteral(path) => Ok((i1, path)),
+        _ => Err(nom::Err::Error(error_position!(input, ErrorKind::Tag))),
+    }
+}
+
+fn parse_import_stmt(input: Tokens) -> IResult<Tokens, Stmt> {
+    let module = map(
+        tuple((import_tag, parse_path, keyword("as"), parse_ident)),
+        |(_, path, _, ident)| Stmt::ImportStmt(path, Import::Module(ident)),
+    );
+    let names = map(
+        tuple((import_tag, lbrace_tag, parse_idents, rbrace_tag, keyword("from"), parse_path)),
+        |(_, _, names, _, _, path)| Stmt::ImportStmt(path, Import::Names(names)),
+    );
+    terminated(alt((module, names)), opt(semicolon_tag))(input)
+}
+
+fn parse_export_stmt(input: Tokens) -> IResult<Tokens, Stmt> {
+    map(delimited(export_tag, parse_idents, opt(semicolon_tag)), Stmt::ExportStmt)(input)
 }
 
 fn parse_let_stmt(input: Tokens) -> IResult<Tokens, Stmt> {
@@ -76,11 +120,55 @@ fn parse_let_stmt(input: Tokens) -> IResult<Tokens, Stmt> {
         tuple((
             let_tag,
             parse_ident,
+            opt(preceded(colon_tag, parse_type)),
             assign_tag,
             parse_expr,
             opt(semicolon_tag),
         )),
-        |(_, ident, _, expr, _)| Stmt::LetStmt(ident, expr),
+        |(_, ident, ty, _, expr, _)| Stmt::LetStmt(ident, ty, expr),
+    )(input)
+}
+
+fn parse_type(input: Tokens) -> IResult<Tokens, Type> {
+    alt((parse_named_type, parse_array_type, parse_hash_type, parse_fn_type))(input)
+}
+
+fn parse_named_type(input: Tokens) -> IResult<Tokens, Type> {
+    let (i1, Ident(name)) = parse_ident(input)?;
+    match name.as_str() {
+        "int" => Ok((i1, Type::Int)),
+        "bool" => Ok((i1, Type::Bool)),
+        "string" => Ok((i1, Type::String)),
+        "null" => Ok((i1, Type::Null)),
+        _ => Err(nom::Err::Error(error_position!(input, ErrorKind::Tag))),
+    }
+}
+
+fn parse_array_type(input: Tokens) -> IResult<Tokens, Type> {
+    map(delimited(lbracket_tag, parse_type, rbracket_tag), |t| Type::Array(Box::new(t)))(input)
+}
+
+fn parse_hash_type(input: Tokens) -> IResult<Tokens, Type> {
+    map(
+        tuple((lbrace_tag, parse_type, colon_tag, parse_type, rbrace_tag)),
+        |(_, k, _, v, _)| Type::Hash(Box::new(k), Box::new(v)),
+    )(input)
+}
+
+fn parse_fn_type(input: Tokens) -> IResult<Tokens, Type> {
+    map(
+        tuple((
+            function_tag,
+            lparen_tag,
+            opt(pair(parse_type, many0(preceded(comma_tag, parse_type)))),
+            rparen_tag,
+            arrow_tag,
+            parse_type,
+        )),
+        |(_, _, params, _, _, ret)| {
+            let params = params.map_or(vec![], |(p, ps)| [vec![p], ps].concat());
+            Type::Fn(params, Box::new(ret))
+        },
     )(input)
 }
 
@@ -211,7 +299,10 @@ fn go_parse_pratt_expr(input: Tokens, precedence: Precedence, left: Expr) -> IRe
                 go_parse_pratt_expr(i2, precedence, left2)
             }
             (Precedence::PIndex, _) if precedence < Precedence::PIndex => {
-                let (i2, left2) = parse_index_expr(input, left)?;
+                let (i2, left2) = match preview {
+                    Token::Dot => parse_member_expr(input, left)?,
+                    _ => parse_index_expr(input, left)?,
+                };
                 go_parse_pratt_expr(i2, precedence, left2)
             }
             (ref peek_precedence, _) if precedence < *peek_precedence => {
@@ -259,6 +350,13 @@ fn parse_index_expr(input: Tokens, arr: Expr) -> IResult<Tokens, Expr> {
     })(input)
 }
 
+fn parse_member_expr(input: Tokens, module: Expr) -> IResult<Tokens, Expr> {
+    map(preceded(dot_tag, parse_ident), |name| Expr::MemberExpr {
+        module: Box::new(module.clone()),
+        name,
+    })(input)
+}
+
 fn parse_if_expr(input: Tokens) -> IResult<Tokens, Expr> {
     map(
         tuple((
@@ -281,7 +379,9 @@ fn parse_else_expr(input: Tokens) -> IResult<Tokens, Option<Program>> {
     opt(preceded(else_tag, parse_block_stmt))(input)
 }
 
-fn empty_params(input: Tokens) -> IResult<Tokens, Vec<Ident>> {
+type Param = (Ident, Option<Type>);
+
+fn empty_params(input: Tokens) -> IResult<Tokens, Vec<Param>> {
     Ok((input, vec![]))
 }
 
@@ -292,19 +392,41 @@ fn parse_fn_expr(input: Tokens) -> IResult<Tokens, Expr> {
             lparen_tag,
             alt((parse_params, empty_params)),
             rparen_tag,
+            opt(preceded(arrow_tag, parse_type)),
             parse_block_stmt,
         )),
-        |(_, _, p, _, b)| Expr::FnExpr { params: p, body: b },
+        |(_, _, p, _, ret, b)| {
+            let (params, types): (Vec<_>, Vec<_>) = p.into_iter().unzip();
+            let types = if types.iter().all(Option::is_none) { vec![] } else { types };
+            Expr::FnExpr {
+                params,
+                signature: Rc::new(Signature { params: types, ret }),
+                body: Rc::new(b),
+            }
+        },
     )(input)
 }
 
-fn parse_params(input: Tokens) -> IResult<Tokens, Vec<Ident>> {
+fn parse_param(input: Tokens) -> IResult<Tokens, Param> {
+    pair(parse_ident, opt(preceded(colon_tag, parse_type)))(input)
+}
+
+fn parse_params(input: Tokens) -> IResult<Tokens, Vec<Param>> {
     map(
-        pair(parse_ident, many0(preceded(comma_tag, parse_ident))),
+        pair(parse_param, many0(preceded(comma_tag, parse_param))),
         |(p, ps)| [&vec![p][..], &ps[..]].concat(),
     )(input)
 }
 
+/// the program of a source, or why there is none
+pub fn parse(source: &str) -> Result<Program, String> {
+    let (_, tokens) = Lexer::lex_tokens(source.as_bytes()).map_err(|_| "the code does not lex".to_string())?;
+    match Parser::parse_tokens(Tokens::new(&tokens)) {
+        Ok((_, program)) => Ok(program),
+        Err(_) => Err("the code does not parse".to_string()),
+    }
+}
+
 pub struct Parser;
 
 impl Parser {
@@ -316,7 +438,6 @@ impl Parser {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::lexer::*;
 
     fn assert_input_with_program(input: &[u8], expected_results: Program) {
         let (_, r) = Lexer::lex_tokens(input).unwrap();
@@ -354,18 +475,22 @@ mod tests {
         let program: Program = vec![
             Stmt::LetStmt(
                 Ident("x".to_owned()),
+                None,
                 Expr::LiteralExpr(Literal::IntLiteral(5)),
             ),
             Stmt::LetStmt(
                 Ident("y".to_owned()),
+                None,
                 Expr::LiteralExpr(Literal::IntLiteral(10)),
             ),
             Stmt::LetStmt(
                 Ident("foobar".to_owned()),
+                None,
                 Expr::LiteralExpr(Literal::IntLiteral(838383)),
             ),
             Stmt::LetStmt(
                 Ident("boo".to_owned()),
+                None,
                 Expr::LiteralExpr(Literal::BoolLiteral(true)),
             ),
         ];
@@ -405,12 +530,14 @@ mod tests {
         let program: Program = vec![
             Stmt::LetStmt(
                 Ident("x".to_owned()),
+                None,
                 Expr::LiteralExpr(Literal::IntLiteral(5)),
             ),
             Stmt::ReturnStmt(Expr::LiteralExpr(Literal::IntLiteral(10))),
             Stmt::ExprStmt(Expr::LiteralExpr(Literal::IntLiteral(15))),
             Stmt::LetStmt(
                 Ident("y".to_owned()),
+                None,
                 Expr::LiteralExpr(Literal::IntLiteral(20)),
             ),
             Stmt::ReturnStmt(Expr::LiteralExpr(Literal::BoolLiteral(false))),
@@ -650,11 +777,12 @@ mod tests {
 
         let program: Program = vec![Stmt::ExprStmt(Expr::FnExpr {
             params: vec![],
-            body: vec![Stmt::ReturnStmt(Expr::InfixExpr(
+            signature: Rc::default(),
+            body: Rc::new(vec![Stmt::ReturnStmt(Expr::InfixExpr(
                 Infix::Plus,
                 Box::new(Expr::IdentExpr(Ident("foobar".to_owned()))),
                 Box::new(Expr::IdentExpr(Ident("barfoo".to_owned()))),
-            ))],
+            ))]),
         })];
 
         assert_input_with_program(input, program);
@@ -667,11 +795,12 @@ mod tests {
 
         let program: Program = vec![Stmt::ExprStmt(Expr::FnExpr {
             params: vec![Ident("x".to_owned()), Ident("y".to_owned())],
-            body: vec![Stmt::ReturnStmt(Expr::InfixExpr(
+            signature: Rc::default(),
+            body: Rc::new(vec![Stmt::ReturnStmt(Expr::InfixExpr(
                 Infix::Plus,
                 Box::new(Expr::IdentExpr(Ident("x".to_owned()))),
                 Box::new(Expr::IdentExpr(Ident("y".to_owned()))),
-            ))],
+            ))]),
         })];
 
         assert_input_with_program(input, program);
@@ -684,24 +813,65 @@ mod tests {
 
         let program: Program = vec![Stmt::ExprStmt(Expr::FnExpr {
             params: vec![],
-            body: vec![Stmt::ReturnStmt(Expr::FnExpr {
+            signature: Rc::default(),
+            body: Rc::new(vec![Stmt::ReturnStmt(Expr::FnExpr {
                 params: vec![
                     Ident("x".to_owned()),
                     Ident("y".to_owned()),
                     Ident("z".to_owned()),
                     Ident("zz".to_owned()),
                 ],
-                body: vec![Stmt::ReturnStmt(Expr::InfixExpr(
+                signature: Rc::default(),
+                body: Rc::new(vec![Stmt::ReturnStmt(Expr::InfixExpr(
                     Infix::GreaterThanEqual,
                     Box::new(Expr::IdentExpr(Ident("x".to_owned()))),
                     Box::new(Expr::IdentExpr(Ident("y".to_owned()))),
-                ))],
-            })],
+                ))]),
+            })]),
         })];
 
         assert_input_with_program(input, program);
     }
 
+    #[test]
+    fn annotations() {
+        let input = "let x: [int] = [];\
+             fn(f: fn(int, string) -> {bool: null}, n) -> int { 1 }\
+            "
+        .as_bytes();
+
+        let program: Program = vec![
+            Stmt::LetStmt(
+                Ident("x".to_owned()),
+                Some(Type::Array(Box::new(Type::Int))),
+                Expr::ArrayExpr(vec![]),
+            ),
+            Stmt::ExprStmt(Expr::FnExpr {
+                params: vec![Ident("f".to_owned()), Ident("n".to_owned())],
+                signature: Rc::new(Signature {
+                    params: vec![
+                        Some(Type::Fn(
+                            vec![Type::Int, Type::String],
+                            Box::new(Type::Hash(Box::new(Type::Bool), Box::new(Type::Null))),
+                        )),
+                        None,
+                    ],
+                    ret: Some(Type::Int),
+                }),
+                body: Rc::new(vec![Stmt::ExprStmt(Expr::LiteralExpr(Literal::IntLiteral(1)))]),
+            }),
+        ];
+
+        assert_input_with_program(input, program);
+
+        // only types are annotations, and functions without any have an empty signature
+        let (_, r) = Lexer::lex_tokens(&b"let x: y = 1"[..]).unwrap();
+        assert!(Parser::parse_tokens(Tokens::new(&r)).is_err());
+        let (_, r) = Lexer::lex_tokens(&b"fn(a, b) { a }"[..]).unwrap();
+        let (_, program) = Parser::parse_tokens(Tokens::new(&r)).unwrap();
+        assert!(matches!(&program[0], Stmt::ExprStmt(Expr::FnExpr { signature, .. }) if **signature == Signature::default()));
+    }
+
     #[test]
     fn function_call_expr() {
         let input = "add(2, 3);\
@@ -753,11 +923,12 @@ mod tests {
             Stmt::ExprStmt(Expr::CallExpr {
                 function: Box::new(Expr::FnExpr {
                     params: vec![Ident("a".to_owned()), Ident("b".to_owned())],
-                    body: vec![Stmt::ReturnStmt(Expr::InfixExpr(
+                    signature: Rc::default(),
+                    body: Rc::new(vec![Stmt::ReturnStmt(Expr::InfixExpr(
                         Infix::Plus,
                         Box::new(Expr::IdentExpr(Ident("a".to_owned()))),
                         Box::new(Expr::IdentExpr(Ident("b".to_owned()))),
-                    ))],
+                    ))]),
                 }),
                 arguments: vec![
                     Expr::LiteralExpr(Literal::IntLiteral(1)),
@@ -953,4 +1124,26 @@ mod tests {
 
         assert_input_with_program(input, program);
     }
+
+    #[test]
+    fn modules() {
+        let input = "import \"lib/a.mk\" as a;\nimport { f, g } from \"b.mk\"\nexport f, as\na.f(1).g";
+        let ident = |name: &str| Ident(name.to_owned());
+        let member = |module: Expr, name: &str| Expr::MemberExpr { module: Box::new(module), name: ident(name) };
+        let program: Program = vec![
+            Stmt::ImportStmt("lib/a.mk".to_owned(), Import::Module(ident("a"))),
+            Stmt::ImportStmt("b.mk".to_owned(), Import::Names(vec![ident("f"), ident("g")])),
+            Stmt::ExportStmt(vec![ident("f"), ident("as")]),
+            Stmt::ExprStmt(member(
+                Expr::CallExpr {
+                    function: Box::new(member(Expr::IdentExpr(ident("a")), "f")),
+                    arguments: vec![Expr::LiteralExpr(Literal::IntLiteral(1))],
+                },
+                "g",
+            )),
+        ];
+        assert_input_with_program(input.as_bytes(), program);
+        assert!(parse("import a as b").is_err());
+        assert!(parse("import {} from \"a.mk\"").is_err());
+    }
 }
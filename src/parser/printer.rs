@@ -0,0 +1,472 @@
+//! Printing of syntax trees back to Monkey source.
+//!
+//! The output is canonical: the statements of a block go on lines of their own, indented by
+//! four spaces, and end with a semicolon except for an expression ending the block, which
+//! gives its value. Arrays, hashes and arguments going past `WIDTH` columns are written one
+//! item per line. Expressions are parenthesized only where the parser would read them
+//! differently otherwise, so parsing the output gives back the same tree.
+
+use std::fmt::{self, Formatter, Write};
+
+use crate::parser::ast::{Expr, Ident, Import, Infix, Literal, Prefix, Program, Stmt, Type};
+
+const INDENT: &str = "    ";
+
+/// the columns a line may take before its lists are broken up
+pub const WIDTH: usize = 100;
+
+// How tightly expressions bind, from the loosest. An operand binding more loosely than its
+// place requires is parenthesized.
+const LOWEST: u8 = 0;
+const EQUALS: u8 = 1;
+const LESS_GREATER: u8 = 2;
+const SUM: u8 = 3;
+const PRODUCT: u8 = 4;
+/// calls, index expressions and members of modules
+const POSTFIX: u8 = 5;
+/// Prefix operators take an atom, and a call or index expression following one applies to
+/// the whole prefix expression, so `-f(x)` calls `-f`.
+const PREFIX: u8 = 6;
+const ATOM: u8 = 7;
+
+/// the source of a program, ending with a newline unless it is empty
+pub fn print(program: &Program) -> String {
+    let mut printer = Printer::new(Some(WIDTH));
+    printer.stmts(program);
+    printer.out
+}
+
+/// Lists are first printed on the current line, as a trial abandoned as soon as its first
+/// line goes past the width or an item but the last takes several lines, and printed again
+/// one item per line then. Only the lists of the first line of a trial are tried within it.
+struct Printer {
+    out: String,
+    indent: usize,
+    /// the width lists are broken up past, if any
+    width: Option<usize>,
+    /// where the innermost trial started in `out`, if one is running
+    trial: Option<usize>,
+    /// whether what is printed must stay on the line, as in an item of a trial but its last
+    flat: bool,
+    /// whether the innermost trial failed, which stops printing until it is abandoned
+    failed: bool,
+}
+
+impl Printer {
+    fn new(width: Option<usize>) -> Self {
+        Printer { out: String::new(), indent: 0, width, trial: None, flat: false, failed: false }
+    }
+
+    /// fails the trial running when what it printed so far cannot be printed on its line
+    fn check(&mut self) {
+        if let (Some(start), Some(width)) = (self.trial, self.width) {
+            let line_start = self.out[..start].rfind('\n').map_or(0, |i| i + 1);
+            let line_end = self.out[start..].find('\n').map(|i| start + i);
+            let line = &self.out[line_start..line_end.unwrap_or(self.out.len())];
+            if (line_end.is_some() && self.flat) || line.chars().count() > width {
+                self.failed = true;
+            }
+        }
+    }
+
+    fn stmts(&mut self, program: &[Stmt]) {
+        for (i, stmt) in program.iter().enumerate() {
+            self.out.push_str(&INDENT.repeat(self.indent));
+            self.stmt(stmt);
+            if i + 1 < program.len() || !matches!(stmt, Stmt::ExprStmt(_)) {
+                self.out.push(';');
+            }
+            self.out.push('\n');
+        }
+    }
+
+    fn block(&mut self, program: &[Stmt]) {
+        if program.is_empty() {
+            self.out.push_str("{}");
+            return;
+        }
+        self.out.push('{');
+        self.check();
+        if self.flat || self.failed {
+            self.failed = true;
+            return;
+        }
+        self.out.push('\n');
+        self.indent += 1;
+        self.stmts(program);
+        self.indent -= 1;
+        self.out.push_str(&INDENT.repeat(self.indent));
+        self.out.push('}');
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::LetStmt(Ident(name), ty, expr) => {
+                let _ = match ty {
+                    Some(ty) => write!(self.out, "let {}: {} = ", name, ty),
+                    None => write!(self.out, "let {} = ", name),
+                };
+                self.expr(expr, LOWEST);
+            }
+            Stmt::ReturnStmt(expr) => {
+                self.out.push_str("return ");
+                self.expr(expr, LOWEST);
+            }
+            Stmt::ExprStmt(expr) => self.expr(expr, LOWEST),
+            Stmt::ImportStmt(path, import) => {
+                let path = Literal::StringLiteral(path.clone());
+                let _ = match import {
+                    Import::Module(Ident(name)) => write!(self.out, "import {} as {}", path, name),
+                    Import::Names(names) => write!(self.out, "import {{ {} }} from {}", names_list(names), path),
+                };
+            }
+            Stmt::ExportStmt(names) => {
+                let _ = write!(self.out, "export {}", names_list(names));
+            }
+        }
+    }
+
+    /// prints an expression, parenthesized if it binds more loosely than `min`
+    fn expr(&mut self, expr: &Expr, min: u8) {
+        if self.failed {
+            return;
+        }
+        if precedence(expr) < min {
+            self.out.push('(');
+            self.expr(expr, LOWEST);
+            self.out.push(')');
+            return;
+        }
+        match expr {
+            Expr::IdentExpr(Ident(name)) => self.out.push_str(name),
+            Expr::LiteralExpr(literal) => {
+                let _ = write!(self.out, "{}", literal);
+            }
+            Expr::PrefixExpr(prefix, e) => {
+                let _ = write!(self.out, "{}", prefix);
+                self.expr(e, PREFIX);
+            }
+            Expr::InfixExpr(infix, e1, e2) => {
+                let p = infix_precedence(infix);
+                self.expr(e1, p);
+                let _ = write!(self.out, " {} ", infix);
+                self.expr(e2, p + 1);
+            }
+            Expr::IfExpr { cond, consequence, alternative } => {
+                self.out.push_str("if (");
+                self.expr(cond, LOWEST);
+                self.out.push_str(") ");
+                self.block(consequence);
+                if let Some(alternative) = alternative {
+                    self.out.push_str(" else ");
+                    self.block(alternative);
+                }
+            }
+            Expr::FnExpr { params, signature, body } => {
+                let params = params
+                    .iter()
+                    .enumerate()
+                    .map(|(i, Ident(name))| match signature.param(i) {
+                        Some(ty) => format!("{}: {}", name, ty),
+                        None => name.clone(),
+                    })
+                    .collect::<Vec<_>>();
+                let _ = write!(self.out, "fn({}) ", params.join(", "));
+                if let Some(ty) = &signature.ret {
+                    let _ = write!(self.out, "-> {} ", ty);
+                }
+                self.block(body);
+            }
+            Expr::CallExpr { function, arguments } => {
+                self.expr(function, POSTFIX);
+                self.list('(', arguments, ')', |p, e| p.expr(e, LOWEST));
+            }
+            Expr::ArrayExpr(exprs) => self.list('[', exprs, ']', |p, e| p.expr(e, LOWEST)),
+            Expr::HashExpr(pairs) => self.list('{', pairs, '}', |p, (key, value)| {
+                let _ = write!(p.out, "{}: ", key);
+                p.expr(value, LOWEST);
+            }),
+            Expr::IndexExpr { array, index } => {
+                self.expr(array, POSTFIX);
+                self.out.push('[');
+                self.expr(index, LOWEST);
+                self.out.push(']');
+            }
+            Expr::MemberExpr { module, name: Ident(name) } => {
+                self.expr(module, POSTFIX);
+                self.out.push('.');
+                self.out.push_str(name);
+            }
+        }
+        self.check();
+    }
+
+    /// Prints items between delimiters on the current line when its first line fits in the
+    /// width and only the last item, such as a function, takes several lines, and one item
+    /// per line otherwise.
+    fn list<T>(&mut self, open: char, items: &[T], close: char, item: impl Fn(&mut Printer, &T)) {
+        if self.failed {
+            return;
+        }
+        if items.is_empty() {
+            self.out.push(open);
+            self.out.push(close);
+            return;
+        }
+        let (start, trial, flat) = (self.out.len(), self.trial, self.flat);
+        if self.width.is_some() {
+            self.trial = Some(start);
+        }
+        self.out.push(open);
+        for (i, it) in items.iter().enumerate() {
+            if i > 0 {
+                self.out.push_str(", ");
+            }
+            self.flat = flat || (self.width.is_some() && i + 1 < items.len());
+            item(self, it);
+            if self.failed {
+                break;
+            }
+        }
+        self.flat = flat;
+        if !self.failed {
+            self.out.push(close);
+            self.check();
+        }
+        self.trial = trial;
+        // a list which must stay on the line fails the trial around it
+        if !self.failed || flat {
+            return;
+        }
+        self.failed = false;
+        self.out.truncate(start);
+        self.out.push(open);
+        self.check();
+        if self.failed {
+            return;
+        }
+        self.out.push('\n');
+        self.indent += 1;
+        for (i, it) in items.iter().enumerate() {
+            self.out.push_str(&INDENT.repeat(self.indent));
+            item(self, it);
+            if i + 1 < items.len() {
+                self.out.push(',');
+            }
+            self.out.push('\n');
+        }
+        self.indent -= 1;
+        self.out.push_str(&INDENT.repeat(self.indent));
+        self.out.push(close);
+    }
+}
+
+fn names_list(names: &[Ident]) -> String {
+    names.iter().map(|Ident(name)| name.as_str()).collect::<Vec<_>>().join(", ")
+}
+
+fn precedence(expr: &Expr) -> u8 {
+    match expr {
+        Expr::InfixExpr(infix, _, _) => infix_precedence(infix),
+        Expr::CallExpr { .. } | Expr::IndexExpr { .. } | Expr::MemberExpr { .. } => POSTFIX,
+        // a negative integer, as the optimizer folds them, reads as a prefix expression
+        Expr::PrefixExpr(_, _) | Expr::LiteralExpr(Literal::IntLiteral(i64::MIN..=-1)) => PREFIX,
+        _ => ATOM,
+    }
+}
+
+fn infix_precedence(infix: &Infix) -> u8 {
+    match infix {
+        Infix::Equal | Infix::NotEqual => EQUALS,
+        Infix::GreaterThanEqual | Infix::LessThanEqual | Infix::GreaterThan | Infix::LessThan => LESS_GREATER,
+        Infix::Plus | Infix::Minus => SUM,
+        Infix::Multiply | Infix::Divide => PRODUCT,
+    }
+}
+
+/// Statements print without the semicolon ending them in a block, on several lines when they
+/// hold a block, and without breaking lists up.
+impl fmt::Display for Stmt {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut printer = Printer::new(None);
+        printer.stmt(self);
+        f.write_str(&printer.out)
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut printer = Printer::new(None);
+        printer.expr(self, LOWEST);
+        f.write_str(&printer.out)
+    }
+}
+
+impl fmt::Display for Literal {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Literal::IntLiteral(i) => write!(f, "{}", i),
+            Literal::BoolLiteral(b) => write!(f, "{}", b),
+            Literal::StringLiteral(s) => {
+                f.write_char('"')?;
+                for c in s.chars() {
+                    // a backslash makes the next character part of the string, whatever it is
+                    if c == '"' || c == '\\' {
+                        f.write_char('\\')?;
+                    }
+                    f.write_char(c)?;
+                }
+                f.write_char('"')
+            }
+        }
+    }
+}
+
+impl fmt::Display for Ident {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Int => f.write_str("int"),
+            Type::Bool => f.write_str("bool"),
+            Type::String => f.write_str("string"),
+            Type::Null => f.write_str("null"),
+            Type::Array(t) => write!(f, "[{}]", t),
+            Type::Hash(k, v) => write!(f, "{{{}: {}}}", k, v),
+            Type::Fn(params, ret) => {
+                let params = params.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+                write!(f, "fn({}) -> {}", params.join(", "), ret)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Prefix {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Prefix::PrefixPlus => "+",
+            Prefix::PrefixMinus => "-",
+            Prefix::Not => "!",
+        })
+    }
+}
+
+impl fmt::Display for Infix {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Infix::Plus => "+",
+            Infix::Minus => "-",
+            Infix::Divide => "/",
+            Infix::Multiply => "*",
+            Infix::Equal => "==",
+            Infix::NotEqual => "!=",
+            Infix::GreaterThanEqual => ">=",
+            Infix::LessThanEqual => "<=",
+            Infix::GreaterThan => ">",
+            Infix::LessThan => "<",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::token::Tokens;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn parse(input: &str) -> Program {
+        let (_, r) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+        let (_, program) = Parser::parse_tokens(Tokens::new(&r)).unwrap();
+        program
+    }
+
+    fn reprint(input: &str) -> String {
+        print(&parse(input))
+    }
+
+    #[test]
+    fn layout() {
+        assert_eq!(reprint(""), "");
+        assert_eq!(
+            reprint("let f=fn(x,y){if(x>y){return x}else{y;}};f(1,2)"),
+            "let f = fn(x, y) {\n    if (x > y) {\n        return x;\n    } else {\n        y\n    }\n};\nf(1, 2)\n"
+        );
+        assert_eq!(reprint("fn() {}; if (a) {} else {}; 1;"), "fn() {};\nif (a) {} else {};\n1\n");
+        assert_eq!(
+            reprint("import \"lib/a.mk\" as a\nimport {f,g} from \"b.mk\" export f ,h; a.x.y(1)"),
+            "import \"lib/a.mk\" as a;\nimport { f, g } from \"b.mk\";\nexport f, h;\na.x.y(1)\n"
+        );
+        assert_eq!(reprint("[1, \"a\\\"b\\\\\", {true: [], 2: {}}]"), "[1, \"a\\\"b\\\\\", {true: [], 2: {}}]\n");
+        assert_eq!(
+            reprint("let f:fn([int],string)->null=fn(a:[int],b)->{string:bool}{{}}"),
+            "let f: fn([int], string) -> null = fn(a: [int], b) -> {string: bool} {\n    {}\n};\n"
+        );
+    }
+
+    #[test]
+    fn width() {
+        let items = (0..30).map(|i| (i * 1000).to_string()).collect::<Vec<_>>();
+        let broken = format!("let xs = [\n    {}\n];\nf(xs, {{1: xs}})\n", items.join(",\n    "));
+        assert_eq!(reprint(&format!("let xs = [{}]; f(xs, {{1: xs}})", items.join(", "))), broken);
+        let long = "x".repeat(90);
+        assert_eq!(
+            reprint(&format!("f({}, fn(y) {{ y }}, 1)", long)),
+            format!("f(\n    {},\n    fn(y) {{\n        y\n    }},\n    1\n)\n", long)
+        );
+        assert_eq!(
+            reprint(&format!("map({}, fn(y) {{ y }})", &long[..80])),
+            format!("map({}, fn(y) {{\n    y\n}})\n", &long[..80])
+        );
+        assert_eq!(
+            reprint("[f(fn() { 1 }), 2]"),
+            "[\n    f(fn() {\n        1\n    }),\n    2\n]\n"
+        );
+        // trials stop at the width, so nesting does not multiply the work
+        let deep = (0..40).fold("1".to_string(), |list, i| format!("[{}, {}]", list, i));
+        assert_eq!(parse(&reprint(&deep)), parse(&deep));
+        assert!(reprint(&deep).starts_with("[\n    [\n"));
+        // expressions and statements print on a line whatever their width
+        let array = parse(&format!("[{}]", items.join(", ")));
+        assert_eq!(array[0].to_string(), format!("[{}]", items.join(", ")));
+        assert_eq!(parse("[fn() { 1 }, 2]")[0].to_string(), "[fn() {\n    1\n}, 2]");
+    }
+
+    #[test]
+    fn parentheses() {
+        let cases = [
+            ("(a + b) * c", "(a + b) * c"),
+            ("a + (b * c)", "a + b * c"),
+            ("(a - b) - c", "a - b - c"),
+            ("a - (b - c)", "a - (b - c)"),
+            ("(a == b) == (c < d)", "a == b == c < d"),
+            ("a == (b == c)", "a == (b == c)"),
+            ("-(a + b)", "-(a + b)"),
+            ("(-a) + b", "-a + b"),
+            ("!(f(x))", "!(f(x))"),
+            ("(!f)(x)", "!f(x)"),
+            ("- -a", "--a"),
+            ("(a + b)(c)[d]", "(a + b)(c)[d]"),
+            ("(-m).f(x)", "-m.f(x)"),
+            ("(a + b).c", "(a + b).c"),
+            ("(fn(x) { x })(1)", "fn(x) {\n    x\n}(1)"),
+            ("(if (a) { b } else { c }) + 1", "if (a) {\n    b\n} else {\n    c\n} + 1"),
+        ];
+        for (input, expected) in cases {
+            let program = parse(input);
+            assert_eq!(program[0].to_string(), expected, "{}", input);
+            assert_eq!(parse(expected), program, "{}", expected);
+        }
+        let folded = Expr::PrefixExpr(Prefix::Not, Box::new(Expr::LiteralExpr(Literal::IntLiteral(-1))));
+        assert_eq!(folded.to_string(), "!-1");
+        let folded = Expr::IndexExpr {
+            array: Box::new(Expr::LiteralExpr(Literal::IntLiteral(-1))),
+            index: Box::new(Expr::LiteralExpr(Literal::IntLiteral(0))),
+        };
+        assert_eq!(folded.to_string(), "-1[0]");
+    }
+}
@@ -0,0 +1,647 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::Formatter;
+use std::hash::{BuildHasherDefault, Hasher};
+use std::rc::{Rc, Weak};
+
+use crate::parser::ast::{Expr, Ident, Program, Stmt};
+
+/// Where a variable lives: `depth` scopes up from the current one, at `index` in that scope.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Slot {
+    pub depth: usize,
+    pub index: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResolveError {
+    UndefinedIdent(String),
+    DuplicateParam(String),
+    /// an `import` or `export` in a function
+    InFunction(&'static str),
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ResolveError::UndefinedIdent(name) => write!(f, "identifier not found: {}", name),
+            ResolveError::DuplicateParam(name) => write!(f, "duplicate parameter: {}", name),
+            ResolveError::InFunction(keyword) => write!(f, "`{}` is only allowed outside of functions", keyword),
+        }
+    }
+}
+
+/// The slots bound to the identifiers of a program, keyed by the address of each `Ident`
+/// node, and the layout of the scope of every function body.
+#[derive(Debug, Default)]
+pub struct Resolution {
+    slots: PtrMap<Ident, Slot>,
+    frames: PtrMap<Program, Rc<Vec<String>>>,
+    /// the function bodies which outlived their program, whose entries go once they are freed
+    kept: Vec<Kept>,
+}
+
+/// a function body still referenced when its program was forgotten, with the identifiers it
+/// holds outside of the functions in it
+#[derive(Debug)]
+struct Kept {
+    body: Weak<Program>,
+    idents: Vec<*const Ident>,
+}
+
+impl Resolution {
+    /// slot read by an identifier expression or written by a `let`
+    pub fn slot(&self, ident: &Ident) -> Option<Slot> {
+        self.slots.get(&(ident as *const Ident)).copied()
+    }
+
+    /// names of the slots of a function body, parameters first
+    pub fn frame(&self, body: &Program) -> Option<&Rc<Vec<String>>> {
+        self.frames.get(&(body as *const Program))
+    }
+
+    /// Adds the entries of another program, once those of the bodies freed since the last
+    /// time are gone, as the new nodes may be where they were.
+    pub fn extend(&mut self, other: Resolution) {
+        self.sweep();
+        self.slots.extend(other.slots);
+        self.frames.extend(other.frames);
+        self.kept.extend(other.kept);
+    }
+
+    /// Drops the entries of a program about to be freed. The bodies of functions still
+    /// referenced elsewhere outlive the program and keep their entries until they are freed.
+    pub fn forget(&mut self, program: &Program) {
+        let mut idents = vec![];
+        let mut bodies = vec![];
+        nodes(program, &mut idents, &mut bodies);
+        for ident in idents {
+            self.slots.remove(&ident);
+        }
+        for body in bodies {
+            if Rc::strong_count(body) == 1 {
+                self.frames.remove(&Rc::as_ptr(body));
+                self.forget(body);
+            } else {
+                self.keep(body);
+            }
+        }
+    }
+
+    /// Follows a body outliving its program, and the functions in it, which may outlive it.
+    fn keep(&mut self, body: &Rc<Program>) {
+        let mut idents = vec![];
+        let mut bodies = vec![];
+        nodes(body, &mut idents, &mut bodies);
+        self.kept.push(Kept { body: Rc::downgrade(body), idents });
+        for body in bodies {
+            self.keep(body);
+        }
+    }
+
+    /// drops the entries of the bodies kept which were freed since
+    fn sweep(&mut self) {
+        let (slots, frames) = (&mut self.slots, &mut self.frames);
+        self.kept.retain(|kept| {
+            let freed = kept.body.strong_count() == 0;
+            if freed {
+                for ident in &kept.idents {
+                    slots.remove(ident);
+                }
+                frames.remove(&kept.body.as_ptr());
+            }
+            !freed
+        });
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.slots.len() + self.frames.len()
+    }
+}
+
+/// the identifiers of a block and the bodies of the functions in it, not looking into them
+fn nodes<'a>(program: &'a Program, idents: &mut Vec<*const Ident>, bodies: &mut Vec<&'a Rc<Program>>) {
+    for stmt in program {
+        match stmt {
+            Stmt::LetStmt(ident, _, expr) => {
+                idents.push(ident);
+                expr_nodes(expr, idents, bodies);
+            }
+            Stmt::ReturnStmt(expr) | Stmt::ExprStmt(expr) => expr_nodes(expr, idents, bodies),
+            Stmt::ImportStmt(_, import) => idents.extend(import.idents().map(|ident| ident as *const Ident)),
+            Stmt::ExportStmt(exported) => idents.extend(exported.iter().map(|ident| ident as *const Ident)),
+        }
+    }
+}
+
+fn expr_nodes<'a>(expr: &'a Expr, idents: &mut Vec<*const Ident>, bodies: &mut Vec<&'a Rc<Program>>) {
+    match expr {
+        Expr::IdentExpr(ident) => idents.push(ident),
+        Expr::LiteralExpr(_) => {}
+        Expr::PrefixExpr(_, e) | Expr::MemberExpr { module: e, .. } => expr_nodes(e, idents, bodies),
+        Expr::InfixExpr(_, e1, e2) | Expr::IndexExpr { array: e1, index: e2 } => {
+            expr_nodes(e1, idents, bodies);
+            expr_nodes(e2, idents, bodies);
+        }
+        Expr::IfExpr { cond, consequence, alternative } => {
+            expr_nodes(cond, idents, bodies);
+            nodes(consequence, idents, bodies);
+            if let Some(alternative) = alternative {
+                nodes(alternative, idents, bodies);
+            }
+        }
+        Expr::FnExpr { body, .. } => bodies.push(body),
+        Expr::CallExpr { function, arguments } => {
+            expr_nodes(function, idents, bodies);
+            arguments.iter().for_each(|e| expr_nodes(e, idents, bodies));
+        }
+        Expr::ArrayExpr(exprs) => exprs.iter().for_each(|e| expr_nodes(e, idents, bodies)),
+        Expr::HashExpr(pairs) => pairs.iter().for_each(|(_, e)| expr_nodes(e, idents, bodies)),
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Scope {
+    names: Vec<String>,
+    index: HashMap<String, usize>,
+    /// whether the `let` of each slot was passed, where the slots of parameters always were
+    bound: Vec<bool>,
+}
+
+impl Scope {
+    fn new(names: Vec<String>) -> Self {
+        let mut scope = Scope::default();
+        for name in names {
+            scope.bind(&name);
+        }
+        scope
+    }
+
+    fn declare(&mut self, name: &str) -> usize {
+        match self.index.get(name) {
+            Some(&i) => i,
+            None => {
+                self.names.push(name.to_string());
+                self.bound.push(false);
+                self.index.insert(name.to_string(), self.names.len() - 1);
+                self.names.len() - 1
+            }
+        }
+    }
+
+    fn bind(&mut self, name: &str) -> usize {
+        let index = self.declare(name);
+        self.bound[index] = true;
+        index
+    }
+
+    fn get(&self, name: &str) -> Option<usize> {
+        self.index.get(name).copied()
+    }
+
+    /// the slot of a name whose `let` was passed
+    fn get_bound(&self, name: &str) -> Option<usize> {
+        self.get(name).filter(|&index| self.bound[index])
+    }
+}
+
+/// Static scope resolution.
+///
+/// Scopes are created by function bodies only; a `let` anywhere in a body, including inside
+/// an `if` block, binds in the body's scope. The functions of a body see its bindings
+/// whether they come before or after them, so that they can refer to bindings declared
+/// later, while the body itself reads an enclosing binding of the same name until it passed
+/// the `let`. The global scope persists between programs.
+#[derive(Debug, Clone, Default)]
+pub struct Resolver {
+    prelude: Scope,
+    globals: Scope,
+}
+
+impl Resolver {
+    pub fn new(prelude: Vec<String>) -> Self {
+        Resolver {
+            prelude: Scope::new(prelude),
+            globals: Scope::default(),
+        }
+    }
+
+    pub fn globals(&self) -> &[String] {
+        &self.globals.names
+    }
+
+    /// Resolves a program against the global scope, which keeps the program's bindings
+    /// only when no error is found.
+    pub fn resolve(&mut self, program: &Program) -> Result<Resolution, Vec<ResolveError>> {
+        let mut pass = Pass {
+            prelude: &self.prelude,
+            scopes: vec![self.globals.clone()],
+            resolution: Resolution::default(),
+            errors: vec![],
+        };
+        pass.resolve_block(program);
+        if pass.errors.is_empty() {
+            let Pass { mut scopes, resolution, .. } = pass;
+            self.globals = scopes.remove(0);
+            Ok(resolution)
+        } else {
+            Err(pass.errors)
+        }
+    }
+
+    /// Resolves a program in the scopes of the names given, outermost first from the global
+    /// scope, binding nothing in them.
+    pub fn resolve_in(&self, scopes: Vec<Vec<String>>, program: &Program) -> Result<Resolution, Vec<ResolveError>> {
+        let mut pass = Pass {
+            prelude: &self.prelude,
+            scopes: scopes.into_iter().map(Scope::new).collect(),
+            resolution: Resolution::default(),
+            errors: vec![],
+        };
+        pass.resolve_block(program);
+        match pass.errors.is_empty() {
+            true => Ok(pass.resolution),
+            false => Err(pass.errors),
+        }
+    }
+}
+
+struct Pass<'a> {
+    prelude: &'a Scope,
+    scopes: Vec<Scope>,
+    resolution: Resolution,
+    errors: Vec<ResolveError>,
+}
+
+impl<'a> Pass<'a> {
+    fn current(&mut self) -> &mut Scope {
+        self.scopes.last_mut().expect("no scope")
+    }
+
+    fn resolve_block(&mut self, program: &Program) {
+        self.hoist(program);
+        for stmt in program {
+            self.resolve_stmt(stmt);
+        }
+    }
+
+    fn hoist(&mut self, program: &[Stmt]) {
+        for stmt in program {
+            match stmt {
+                Stmt::LetStmt(Ident(name), _, expr) => {
+                    self.current().declare(name);
+                    self.hoist_expr(expr);
+                }
+                Stmt::ReturnStmt(expr) | Stmt::ExprStmt(expr) => self.hoist_expr(expr),
+                Stmt::ImportStmt(_, import) => {
+                    for Ident(name) in import.idents() {
+                        self.current().declare(name);
+                    }
+                }
+                Stmt::ExportStmt(_) => {}
+            }
+        }
+    }
+
+    fn hoist_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::IdentExpr(_) | Expr::LiteralExpr(_) | Expr::FnExpr { .. } => {}
+            Expr::PrefixExpr(_, e) => self.hoist_expr(e),
+            Expr::InfixExpr(_, e1, e2) => {
+                self.hoist_expr(e1);
+                self.hoist_expr(e2);
+            }
+            Expr::IfExpr { cond, consequence, alternative } => {
+                self.hoist_expr(cond);
+                self.hoist(consequence);
+                if let Some(alternative) = alternative {
+                    self.hoist(alternative);
+                }
+            }
+            Expr::CallExpr { function, arguments } => {
+                self.hoist_expr(function);
+                arguments.iter().for_each(|e| self.hoist_expr(e));
+            }
+            Expr::ArrayExpr(exprs) => exprs.iter().for_each(|e| self.hoist_expr(e)),
+            Expr::HashExpr(pairs) => pairs.iter().for_each(|(_, e)| self.hoist_expr(e)),
+            Expr::IndexExpr { array, index } => {
+                self.hoist_expr(array);
+                self.hoist_expr(index);
+            }
+            Expr::MemberExpr { module, .. } => self.hoist_expr(module),
+        }
+    }
+
+    fn resolve_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::LetStmt(ident, _, expr) => {
+                self.resolve_expr(expr);
+                let index = self.current().bind(&ident.0);
+                self.resolution
+                    .slots
+                    .insert(ident as *const Ident, Slot { depth: 0, index });
+            }
+            Stmt::ReturnStmt(expr) | Stmt::ExprStmt(expr) => self.resolve_expr(expr),
+            Stmt::ImportStmt(_, import) => {
+                self.top_level("import");
+                for ident in import.idents() {
+                    let index = self.current().bind(&ident.0);
+                    self.resolution.slots.insert(ident as *const Ident, Slot { depth: 0, index });
+                }
+            }
+            Stmt::ExportStmt(idents) => {
+                self.top_level("export");
+                idents.iter().for_each(|ident| self.resolve_ident(ident));
+            }
+        }
+    }
+
+    fn top_level(&mut self, keyword: &'static str) {
+        if self.scopes.len() > 1 {
+            self.errors.push(ResolveError::InFunction(keyword));
+        }
+    }
+
+    /// Binds an identifier to the innermost binding of its name, from the current scope only
+    /// once its `let` was passed. A name declared later in the current scope and nowhere
+    /// else still reads its slot, which is not set yet.
+    fn resolve_ident(&mut self, ident: &Ident) {
+        let Ident(name) = ident;
+        let top = self.scopes.len() - 1;
+        let slot = self
+            .scopes
+            .iter()
+            .rev()
+            .enumerate()
+            .find_map(|(depth, scope)| {
+                let index = if depth == 0 { scope.get_bound(name) } else { scope.get(name) };
+                index.map(|index| Slot { depth, index })
+            })
+            .or_else(|| {
+                self.prelude
+                    .get(name)
+                    .map(|index| Slot { depth: top + 1, index })
+            })
+            .or_else(|| self.scopes[top].get(name).map(|index| Slot { depth: 0, index }));
+        match slot {
+            Some(slot) => {
+                self.resolution.slots.insert(ident as *const Ident, slot);
+            }
+            None => self.errors.push(ResolveError::UndefinedIdent(name.clone())),
+        }
+    }
+
+    fn resolve_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::IdentExpr(ident) => self.resolve_ident(ident),
+            Expr::LiteralExpr(_) => {}
+            Expr::PrefixExpr(_, e) => self.resolve_expr(e),
+            Expr::InfixExpr(_, e1, e2) => {
+                self.resolve_expr(e1);
+                self.resolve_expr(e2);
+            }
+            Expr::IfExpr { cond, consequence, alternative } => {
+                self.resolve_expr(cond);
+                consequence.iter().for_each(|s| self.resolve_stmt(s));
+                if let Some(alternative) = alternative {
+                    alternative.iter().for_each(|s| self.resolve_stmt(s));
+                }
+            }
+            Expr::FnExpr { params, body, .. } => {
+                let mut scope = Scope::default();
+                for Ident(name) in params {
+                    if scope.get(name).is_some() {
+                        self.errors.push(ResolveError::DuplicateParam(name.clone()));
+                    }
+                    scope.bind(name);
+                }
+                self.scopes.push(scope);
+                self.resolve_block(body);
+                let scope = self.scopes.pop().expect("no scope");
+                self.resolution
+                    .frames
+                    .insert(Rc::as_ptr(body), Rc::new(scope.names));
+            }
+            Expr::CallExpr { function, arguments } => {
+                self.resolve_expr(function);
+                arguments.iter().for_each(|e| self.resolve_expr(e));
+            }
+            Expr::ArrayExpr(exprs) => exprs.iter().for_each(|e| self.resolve_expr(e)),
+            Expr::HashExpr(pairs) => pairs.iter().for_each(|(_, e)| self.resolve_expr(e)),
+            Expr::IndexExpr { array, index } => {
+                self.resolve_expr(array);
+                self.resolve_expr(index);
+            }
+            Expr::MemberExpr { module, .. } => self.resolve_expr(module),
+        }
+    }
+}
+
+type PtrMap<K, V> = HashMap<*const K, V, BuildHasherDefault<PtrHasher>>;
+
+/// Nodes are keyed by address, which needs no more than spreading the pointer bits.
+#[derive(Default)]
+struct PtrHasher(u64);
+
+impl Hasher for PtrHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 = (self.0 << 8 | b as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        }
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.0 = ((i as u64) >> 3).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::token::Tokens;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn parse(input: &str) -> Program {
+        let (_, r) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+        let (_, program) = Parser::parse_tokens(Tokens::new(&r)).unwrap();
+        program
+    }
+
+    fn resolve(input: &str) -> Result<(Program, Resolution), Vec<ResolveError>> {
+        let program = parse(input);
+        let mut resolver = Resolver::new(vec!["len".to_string()]);
+        resolver.resolve(&program).map(|resolution| (program, resolution))
+    }
+
+    fn first_use(program: &Program) -> &Ident {
+        match &program[program.len() - 1] {
+            Stmt::ExprStmt(Expr::IdentExpr(ident)) => ident,
+            Stmt::ExprStmt(Expr::CallExpr { function, .. }) => match &**function {
+                Expr::IdentExpr(ident) => ident,
+                _ => panic!("expected a call to an identifier"),
+            },
+            _ => panic!("expected an identifier"),
+        }
+    }
+
+    #[test]
+    fn globals_and_prelude() {
+        let (program, resolution) = resolve("let a = 1; let b = 2; b").unwrap();
+        assert_eq!(resolution.slot(first_use(&program)), Some(Slot { depth: 0, index: 1 }));
+
+        let (program, resolution) = resolve("len").unwrap();
+        assert_eq!(resolution.slot(first_use(&program)), Some(Slot { depth: 1, index: 0 }));
+    }
+
+    #[test]
+    fn function_scopes() {
+        let (program, resolution) =
+            resolve("let a = 1; let f = fn(x, y) { if (x) { let z = a; } fn() { z + y } }; f").unwrap();
+        let body = match &program[1] {
+            Stmt::LetStmt(_, _, Expr::FnExpr { body, .. }) => body,
+            _ => panic!("expected a function"),
+        };
+        assert_eq!(
+            **resolution.frame(body).unwrap(),
+            vec!["x".to_string(), "y".to_string(), "z".to_string()],
+        );
+        let inner = match &body[1] {
+            Stmt::ExprStmt(Expr::FnExpr { body, .. }) => body,
+            _ => panic!("expected a function"),
+        };
+        match &inner[0] {
+            Stmt::ExprStmt(Expr::InfixExpr(_, z, y)) => match (&**z, &**y) {
+                (Expr::IdentExpr(z), Expr::IdentExpr(y)) => {
+                    assert_eq!(resolution.slot(z), Some(Slot { depth: 1, index: 2 }));
+                    assert_eq!(resolution.slot(y), Some(Slot { depth: 1, index: 1 }));
+                }
+                _ => panic!("expected identifiers"),
+            },
+            _ => panic!("expected an infix expression"),
+        }
+    }
+
+    #[test]
+    fn kept_bodies() {
+        let (program, mut resolution) =
+            resolve("let a = 1; let f = fn(x) { let y = a; fn() { y } }; f").unwrap();
+        let (outer, inner) = match &program[1] {
+            Stmt::LetStmt(_, _, Expr::FnExpr { body, .. }) => match &body[1] {
+                Stmt::ExprStmt(Expr::FnExpr { body: inner, .. }) => (Rc::clone(body), Rc::clone(inner)),
+                _ => panic!("expected a function"),
+            },
+            _ => panic!("expected a function"),
+        };
+        let entries = resolution.len();
+        resolution.forget(&program);
+        drop(program);
+        // `y` and `a` in the outer body, `y` in the inner one, and their frames
+        assert_eq!(resolution.len(), 5);
+        assert!(entries > 5);
+
+        // the inner body outlives the outer one, as a closure would
+        drop(outer);
+        resolution.extend(Resolution::default());
+        assert_eq!(resolution.len(), 2);
+        assert!(resolution.frame(&inner).is_some());
+        drop(inner);
+        resolution.extend(Resolution::default());
+        assert_eq!(resolution.len(), 0);
+    }
+
+    #[test]
+    fn forward_references() {
+        assert!(resolve("let x = fn() { a }; let a = 10; x()").is_ok());
+        assert!(resolve("let f = fn(n) { f(n) }; f(1)").is_ok());
+    }
+
+    #[test]
+    fn shadowing() {
+        let (program, resolution) = resolve("let a = 1; let f = fn() { let b = a; let a = 2; [a, fn() { a }] }").unwrap();
+        let body = match &program[1] {
+            Stmt::LetStmt(_, _, Expr::FnExpr { body, .. }) => body,
+            _ => panic!("expected a function"),
+        };
+        let read = |expr: &Expr| match expr {
+            Expr::IdentExpr(ident) => resolution.slot(ident),
+            Expr::FnExpr { body, .. } => match &body[0] {
+                Stmt::ExprStmt(Expr::IdentExpr(ident)) => resolution.slot(ident),
+                _ => panic!("expected an identifier"),
+            },
+            _ => panic!("expected an identifier or a function"),
+        };
+        // the global until the `let`, the local after it and in the functions of the body
+        match (&body[0], &body[2]) {
+            (Stmt::LetStmt(_, _, before), Stmt::ExprStmt(Expr::ArrayExpr(after))) => {
+                assert_eq!(read(before), Some(Slot { depth: 1, index: 0 }));
+                assert_eq!(read(&after[0]), Some(Slot { depth: 0, index: 1 }));
+                assert_eq!(read(&after[1]), Some(Slot { depth: 1, index: 1 }));
+            }
+            _ => panic!("expected a let and an array"),
+        }
+        // the prelude until a global of the same name
+        let (program, resolution) = resolve("let n = len; let len = 1; len").unwrap();
+        match &program[0] {
+            Stmt::LetStmt(_, _, Expr::IdentExpr(ident)) => assert_eq!(resolution.slot(ident), Some(Slot { depth: 1, index: 0 })),
+            _ => panic!("expected a let"),
+        }
+        assert_eq!(resolution.slot(first_use(&program)), Some(Slot { depth: 0, index: 1 }));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            resolve("let f = fn(x) { y }; z").unwrap_err(),
+            vec![
+                ResolveError::UndefinedIdent("y".to_string()),
+                ResolveError::UndefinedIdent("z".to_string()),
+            ],
+        );
+        assert_eq!(
+            resolve("fn(a, b, a) { a }").unwrap_err(),
+            vec![ResolveError::DuplicateParam("a".to_string())],
+        );
+        assert_eq!(
+            resolve("fn() { let x = 1; }; x").unwrap_err(),
+            vec![ResolveError::UndefinedIdent("x".to_string())],
+        );
+        assert_eq!(
+            resolve("fn() { import \"a.mk\" as a; export a }; export b").unwrap_err(),
+            vec![
+                ResolveError::InFunction("import"),
+                ResolveError::InFunction("export"),
+                ResolveError::UndefinedIdent("b".to_string()),
+            ],
+        );
+    }
+
+    #[test]
+    fn modules() {
+        let (program, resolution) = resolve("export f, b; import { f, g } from \"a.mk\"; import \"b.mk\" as b; b.x").unwrap();
+        let slots = |stmt: &Stmt| match stmt {
+            Stmt::ImportStmt(_, import) => import.idents().map(|ident| resolution.slot(ident)).collect::<Vec<_>>(),
+            Stmt::ExportStmt(idents) => idents.iter().map(|ident| resolution.slot(ident)).collect(),
+            _ => panic!("expected an import or an export"),
+        };
+        let slot = |index| Some(Slot { depth: 0, index });
+        assert_eq!(slots(&program[0]), vec![slot(0), slot(2)]);
+        assert_eq!(slots(&program[1]), vec![slot(0), slot(1)]);
+        assert_eq!(slots(&program[2]), vec![slot(2)]);
+    }
+
+    #[test]
+    fn failed_programs_declare_nothing() {
+        let mut resolver = Resolver::new(vec![]);
+        assert!(resolver.resolve(&parse("let a = 1; b")).is_err());
+        assert!(resolver.globals().is_empty());
+        assert!(resolver.resolve(&parse("let a = 1;")).is_ok());
+        assert!(resolver.resolve(&parse("a")).is_ok());
+        assert_eq!(resolver.globals(), &["a".to_string()]);
+    }
+}
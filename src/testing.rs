@@ -0,0 +1,442 @@
+//! Running the tests of programs: the functions of `*_test.mk` files whose names start with
+//! `test_`, each called without arguments in a fresh evaluator after the rest of its file ran.
+//!
+//! A test fails at the first assertion failing, which stops it, or when it returns an error.
+//! What it prints is kept with its result rather than written out.
+
+use std::cell::RefCell;
+use std::fmt::{self, Formatter};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::rc::Rc;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use crate::evaluator::builtins::{redirect_print, ASSERTION_FAILED};
+use crate::evaluator::object::Object;
+use crate::evaluator::observer::EvalObserver;
+use crate::evaluator::Evaluator;
+use crate::parser::ast::{Expr, Ident, Program, Stmt};
+use crate::parser::parse;
+
+/// how the names of test files end
+pub const SUFFIX: &str = "_test.mk";
+
+/// how the results of the tests are written
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    /// a line for each test, the failures explained, then a summary
+    Text,
+    /// the Test Anything Protocol, version 13
+    Tap,
+    /// the JUnit XML report of CI services
+    Junit,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text),
+            "tap" => Ok(Format::Tap),
+            "junit" => Ok(Format::Junit),
+            _ => Err(format!("unknown format: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    Passed,
+    /// why the test failed
+    Failed(String),
+}
+
+/// the result of a test
+#[derive(Debug, Clone, PartialEq)]
+pub struct Case {
+    pub name: String,
+    pub outcome: Outcome,
+    /// the lines the test printed
+    pub output: Vec<String>,
+    pub time: Duration,
+}
+
+/// the results of the tests of a file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Suite {
+    pub path: String,
+    pub cases: Vec<Case>,
+    /// why none of its tests ran
+    pub error: Option<String>,
+}
+
+/// The test files of a path: the file itself, or those in the directory and its
+/// subdirectories, hidden ones aside, in the order of their paths.
+pub fn discover(path: &str) -> io::Result<Vec<String>> {
+    let mut files = vec![];
+    match Path::new(path).is_dir() {
+        true => walk(Path::new(path), &mut files)?,
+        false => files.push(fs::metadata(path).map(|_| path.to_string())?),
+    }
+    Ok(files)
+}
+
+fn walk(dir: &Path, files: &mut Vec<String>) -> io::Result<()> {
+    let mut entries = fs::read_dir(dir)?.map(|entry| entry.map(|entry| entry.path())).collect::<io::Result<Vec<_>>>()?;
+    entries.sort();
+    for path in entries {
+        let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        if name.starts_with('.') {
+            continue;
+        } else if path.is_dir() {
+            walk(&path, files)?;
+        } else if name.ends_with(SUFFIX) {
+            files.push(path.display().to_string());
+        }
+    }
+    Ok(())
+}
+
+/// the names of the tests of a program, in the order they are defined
+pub fn tests(program: &Program) -> Vec<String> {
+    let mut names: Vec<String> = vec![];
+    for stmt in program {
+        if let Stmt::LetStmt(Ident(name), _, Expr::FnExpr { .. }) = stmt {
+            if name.starts_with("test_") && !names.contains(name) {
+                names.push(name.clone());
+            }
+        }
+    }
+    names
+}
+
+/// Runs the tests of a file.
+pub fn run(path: &str, source: &str) -> Suite {
+    let (cases, error) = match parse(source) {
+        Ok(program) => (tests(&program).iter().map(|name| case(path, &program, name)).collect(), None),
+        Err(err) => (vec![], Some(err)),
+    };
+    Suite { path: path.to_string(), cases, error }
+}
+
+/// Keeps the first assertion failing, stopping the test there.
+struct Assertions(Rc<RefCell<Option<String>>>);
+
+impl EvalObserver for Assertions {
+    fn error(&mut self, evaluator: &mut Evaluator, message: &str) {
+        let mut failure = self.0.borrow_mut();
+        if failure.is_none() && message.starts_with(ASSERTION_FAILED) {
+            *failure = Some(message.to_string());
+            evaluator.interrupt_handle().interrupt();
+        }
+    }
+}
+
+fn case(path: &str, program: &Program, name: &str) -> Case {
+    let output = Rc::new(RefCell::new(vec![]));
+    let lines = Rc::clone(&output);
+    redirect_print(Some(Box::new(move |line| lines.borrow_mut().push(line.to_string()))));
+    let failure = Rc::new(RefCell::new(None));
+    let mut evaluator = Evaluator::new();
+    evaluator.set_file(Some(path));
+    evaluator.set_observer(Some(Box::new(Assertions(Rc::clone(&failure)))));
+    let mut program = program.clone();
+    let function = Box::new(Expr::IdentExpr(Ident(name.to_string())));
+    program.push(Stmt::ExprStmt(Expr::CallExpr { function, arguments: vec![] }));
+    let start = Instant::now();
+    let result = evaluator.eval_program(program);
+    let time = start.elapsed();
+    redirect_print(None);
+    let outcome = match (failure.take(), result) {
+        (Some(message), _) => Outcome::Failed(message),
+        (None, Object::Error(message)) => Outcome::Failed(format!("the test returned an error: {}", message)),
+        _ => Outcome::Passed,
+    };
+    Case { name: name.to_string(), outcome, output: output.take(), time }
+}
+
+/// the results of the tests of files
+pub struct Report {
+    pub suites: Vec<Suite>,
+}
+
+impl Report {
+    pub fn passed(&self) -> usize {
+        self.cases().filter(|case| case.outcome == Outcome::Passed).count()
+    }
+
+    pub fn failed(&self) -> usize {
+        self.cases().filter(|case| case.outcome != Outcome::Passed).count()
+    }
+
+    /// the number of files whose tests did not run
+    pub fn broken(&self) -> usize {
+        self.suites.iter().filter(|suite| suite.error.is_some()).count()
+    }
+
+    pub fn success(&self) -> bool {
+        self.failed() == 0 && self.broken() == 0
+    }
+
+    fn cases(&self) -> impl Iterator<Item = &Case> {
+        self.suites.iter().flat_map(|suite| &suite.cases)
+    }
+
+    /// the results as written in a format, ending with a newline
+    pub fn write(&self, format: Format) -> String {
+        match format {
+            Format::Text => format!("{}\n", self),
+            Format::Tap => self.tap(),
+            Format::Junit => self.junit(),
+        }
+    }
+
+    /// the results in the Test Anything Protocol, the printed lines as comments
+    pub fn tap(&self) -> String {
+        let mut out = format!("TAP version 13\n1..{}\n", self.passed() + self.failed() + self.broken());
+        let mut number = 0;
+        let mut point = |out: &mut String, name: String, failure: Option<&str>, output: &[String]| {
+            number += 1;
+            let ok = match failure {
+                Some(_) => "not ok",
+                None => "ok",
+            };
+            out.push_str(&format!("{} {} - {}\n", ok, number, name));
+            if let Some(failure) = failure {
+                out.push_str("  ---\n  message: |\n");
+                failure.lines().for_each(|line| out.push_str(&format!("    {}\n", line)));
+                out.push_str("  ...\n");
+            }
+            output.iter().for_each(|line| out.push_str(&format!("# {}\n", line)));
+        };
+        for suite in &self.suites {
+            if let Some(err) = &suite.error {
+                point(&mut out, suite.path.clone(), Some(err), &[]);
+            }
+            for case in &suite.cases {
+                let failure = match &case.outcome {
+                    Outcome::Passed => None,
+                    Outcome::Failed(message) => Some(message.as_str()),
+                };
+                point(&mut out, format!("{} {}", suite.path, case.name), failure, &case.output);
+            }
+        }
+        out
+    }
+
+    /// the results as a JUnit XML report, a test suite for each file
+    pub fn junit(&self) -> String {
+        let seconds = |time: Duration| format!("{:.6}", time.as_secs_f64());
+        let total = self.cases().map(|case| case.time).sum();
+        let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        out.push_str(&format!(
+            "<testsuites tests=\"{}\" failures=\"{}\" errors=\"{}\" time=\"{}\">\n",
+            self.passed() + self.failed() + self.broken(),
+            self.failed(),
+            self.broken(),
+            seconds(total)
+        ));
+        for suite in &self.suites {
+            let path = escape(&suite.path);
+            let failures = suite.cases.iter().filter(|case| case.outcome != Outcome::Passed).count();
+            let errors = suite.error.iter().count();
+            out.push_str(&format!(
+                "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" errors=\"{}\" time=\"{}\">\n",
+                path,
+                suite.cases.len() + errors,
+                failures,
+                errors,
+                seconds(suite.cases.iter().map(|case| case.time).sum())
+            ));
+            if let Some(err) = &suite.error {
+                out.push_str(&format!("    <testcase name=\"{}\" classname=\"{}\">\n", path, path));
+                out.push_str(&format!("      <error message=\"{}\"/>\n    </testcase>\n", escape(err)));
+            }
+            for case in &suite.cases {
+                let testcase = format!("<testcase name=\"{}\" classname=\"{}\" time=\"{}\"", escape(&case.name), path, seconds(case.time));
+                if case.outcome == Outcome::Passed && case.output.is_empty() {
+                    out.push_str(&format!("    {}/>\n", testcase));
+                    continue;
+                }
+                out.push_str(&format!("    {}>\n", testcase));
+                if let Outcome::Failed(message) = &case.outcome {
+                    let summary = message.lines().next().unwrap_or_default();
+                    out.push_str(&format!("      <failure message=\"{}\">{}</failure>\n", escape(summary), escape(message)));
+                }
+                if !case.output.is_empty() {
+                    let output = case.output.iter().map(|line| format!("{}\n", line)).collect::<String>();
+                    out.push_str(&format!("      <system-out>{}</system-out>\n", escape(&output)));
+                }
+                out.push_str("    </testcase>\n");
+            }
+            out.push_str("  </testsuite>\n");
+        }
+        out.push_str("</testsuites>\n");
+        out
+    }
+}
+
+/// text as written in XML, in elements or in attributes of a line
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// indents the lines of a text
+fn indent(text: &str, by: &str) -> String {
+    text.lines().map(|line| format!("{}{}\n", by, line)).collect()
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for suite in &self.suites {
+            if let Some(err) = &suite.error {
+                write!(f, "FAIL {}\n{}", suite.path, indent(err, "  "))?;
+            }
+            for case in &suite.cases {
+                match &case.outcome {
+                    Outcome::Passed => writeln!(f, "PASS {} {}", suite.path, case.name)?,
+                    Outcome::Failed(message) => {
+                        write!(f, "FAIL {} {}\n{}", suite.path, case.name, indent(message, "  "))?;
+                        if !case.output.is_empty() {
+                            write!(f, "  printed:\n{}", indent(&case.output.join("\n"), "    "))?;
+                        }
+                    }
+                }
+            }
+        }
+        write!(f, "{} passed, {} failed", self.passed(), self.failed())?;
+        match self.broken() {
+            0 => Ok(()),
+            1 => write!(f, ", 1 file not run"),
+            broken => write!(f, ", {} files not run", broken),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILE: &str = "let double = fn(x) { x * 2 };
+let test_double = fn() { assert_eq(double(2), 4) };
+let test_lists = fn() {
+  print(\"comparing\");
+  assert_eq([double(1), double(2)], [2, 5]);
+  print(\"stopped before\")
+};
+let test_errors = fn() { assert_error(double(true)); assert(1 < 2); double(\"x\") };
+let helper = fn() { assert(false) };";
+
+    fn report() -> Report {
+        let suites = vec![run("math_test.mk", FILE), run("broken_test.mk", "let = 1;")];
+        Report { suites }
+    }
+
+    #[test]
+    fn cases() {
+        let suite = run("math_test.mk", FILE);
+        let names = suite.cases.iter().map(|case| case.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, vec!["test_double", "test_lists", "test_errors"]);
+        assert_eq!(suite.cases[0].outcome, Outcome::Passed);
+        assert_eq!(
+            suite.cases[1].outcome,
+            Outcome::Failed("assertion failed: the values differ\n  left:  [2, 4]\n  right: [2, 5]\n  at [1]: 4 is not 5".to_string())
+        );
+        assert_eq!(suite.cases[1].output, vec!["comparing"]);
+        assert_eq!(
+            suite.cases[2].outcome,
+            Outcome::Failed("the test returned an error: x is not an integer".to_string())
+        );
+        assert_eq!(run("broken_test.mk", "let = 1;").error, Some("the code does not parse".to_string()));
+    }
+
+    #[test]
+    fn formats() {
+        let report = report();
+        assert!(!report.success());
+        assert_eq!(
+            report.to_string(),
+            "PASS math_test.mk test_double
+FAIL math_test.mk test_lists
+  assertion failed: the values differ
+    left:  [2, 4]
+    right: [2, 5]
+    at [1]: 4 is not 5
+  printed:
+    comparing
+FAIL math_test.mk test_errors
+  the test returned an error: x is not an integer
+FAIL broken_test.mk
+  the code does not parse
+1 passed, 2 failed, 1 file not run"
+        );
+        assert_eq!(
+            report.tap(),
+            "TAP version 13
+1..4
+ok 1 - math_test.mk test_double
+not ok 2 - math_test.mk test_lists
+  ---
+  message: |
+    assertion failed: the values differ
+      left:  [2, 4]
+      right: [2, 5]
+      at [1]: 4 is not 5
+  ...
+# comparing
+not ok 3 - math_test.mk test_errors
+  ---
+  message: |
+    the test returned an error: x is not an integer
+  ...
+not ok 4 - broken_test.mk
+  ---
+  message: |
+    the code does not parse
+  ...
+"
+        );
+        let junit = report.junit();
+        let times = junit.split("time=\"").skip(1).map(|rest| &rest[..rest.find('"').unwrap()]);
+        let junit = times.fold(junit.clone(), |junit, time| junit.replacen(&format!("time=\"{}\"", time), "time=\"t\"", 1));
+        assert_eq!(
+            junit,
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<testsuites tests=\"4\" failures=\"2\" errors=\"1\" time=\"t\">
+  <testsuite name=\"math_test.mk\" tests=\"3\" failures=\"2\" errors=\"0\" time=\"t\">
+    <testcase name=\"test_double\" classname=\"math_test.mk\" time=\"t\"/>
+    <testcase name=\"test_lists\" classname=\"math_test.mk\" time=\"t\">
+      <failure message=\"assertion failed: the values differ\">assertion failed: the values differ
+  left:  [2, 4]
+  right: [2, 5]
+  at [1]: 4 is not 5</failure>
+      <system-out>comparing
+</system-out>
+    </testcase>
+    <testcase name=\"test_errors\" classname=\"math_test.mk\" time=\"t\">
+      <failure message=\"the test returned an error: x is not an integer\">the test returned an error: x is not an integer</failure>
+    </testcase>
+  </testsuite>
+  <testsuite name=\"broken_test.mk\" tests=\"1\" failures=\"0\" errors=\"1\" time=\"t\">
+    <testcase name=\"broken_test.mk\" classname=\"broken_test.mk\">
+      <error message=\"the code does not parse\"/>
+    </testcase>
+  </testsuite>
+</testsuites>
+"
+        );
+    }
+}
@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+
+use crate::evaluator::object::Object;
+use crate::vm::Scope;
+
+const MIN_THRESHOLD: usize = 128;
+
+/// Cycle collector for the scopes of the `Vm`, working like the one of the `Evaluator`.
+///
+/// Only scopes still referenced when their call returns are tracked, as any other scope is
+/// freed right away. Parents are always older than their children, so every cycle goes
+/// through a slot, and clearing the slots of unreachable scopes is enough to break them.
+#[derive(Debug, Default)]
+pub struct Collector {
+    scopes: Vec<Weak<Scope>>,
+    threshold: usize,
+}
+
+impl Collector {
+    pub fn new() -> Self {
+        Collector {
+            scopes: vec![],
+            threshold: MIN_THRESHOLD,
+        }
+    }
+
+    pub fn track(&mut self, scope: &Rc<Scope>) {
+        self.scopes.push(Rc::downgrade(scope));
+    }
+
+    pub fn should_collect(&self) -> bool {
+        self.scopes.len() >= self.threshold
+    }
+
+    pub fn live(&self) -> usize {
+        self.scopes.iter().filter(|scope| scope.strong_count() > 0).count()
+    }
+
+    /// clears unreachable scopes and returns how many were freed
+    pub fn collect(&mut self) -> usize {
+        let scopes = self
+            .scopes
+            .iter()
+            .filter_map(Weak::upgrade)
+            .collect::<Vec<_>>();
+        let index = scopes
+            .iter()
+            .enumerate()
+            .map(|(i, scope)| (Rc::as_ptr(scope), i))
+            .collect::<HashMap<_, _>>();
+
+        let mut edges = vec![vec![]; scopes.len()];
+        let mut internal = vec![0; scopes.len()];
+        for (i, scope) in scopes.iter().enumerate() {
+            let mut refs = vec![];
+            if let Some(parent) = &scope.parent {
+                refs.push(Rc::as_ptr(parent));
+            }
+            for object in scope.slots.borrow().iter().flatten() {
+                scope_refs(object, &mut refs);
+            }
+            for ptr in refs {
+                if let Some(&j) = index.get(&ptr) {
+                    internal[j] += 1;
+                    edges[i].push(j);
+                }
+            }
+        }
+        // one strong reference is held by `scopes` itself
+        let mut roots = (0..scopes.len())
+            .filter(|&i| Rc::strong_count(&scopes[i]) - 1 > internal[i])
+            .collect::<Vec<_>>();
+
+        let mut marked = vec![false; scopes.len()];
+        while let Some(i) = roots.pop() {
+            if !marked[i] {
+                marked[i] = true;
+                roots.extend(edges[i].iter().copied());
+            }
+        }
+
+        let garbage = scopes
+            .iter()
+            .zip(&marked)
+            .filter(|(_, marked)| !**marked)
+            .map(|(scope, _)| std::mem::take(&mut *scope.slots.borrow_mut()))
+            .collect::<Vec<_>>();
+        let freed = garbage.len();
+        drop(garbage);
+        drop(scopes);
+
+        self.scopes.retain(|scope| scope.strong_count() > 0);
+        self.threshold = MIN_THRESHOLD.max(self.scopes.len() * 2);
+        freed
+    }
+}
+
+fn scope_refs(object: &Object, refs: &mut Vec<*const Scope>) {
+    match object {
+        Object::Closure(closure) => {
+            if let Some(scope) = &closure.scope {
+                refs.push(Rc::as_ptr(scope));
+            }
+        }
+        Object::Array(arr) => arr.iter().for_each(|o| scope_refs(o, refs)),
+        Object::Hash(hash) => hash.values().for_each(|o| scope_refs(o, refs)),
+        Object::ReturnValue(o) => scope_refs(o, refs),
+        _ => {}
+    }
+}
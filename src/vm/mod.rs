@@ -0,0 +1,509 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::compiler::code::{read_u16, read_u8, Op};
+use crate::compiler::{Bytecode, CompiledFunction, Constant};
+use crate::evaluator::builtins::BuiltinFunctions;
+use crate::evaluator::object::Object;
+use crate::evaluator::operators;
+use crate::evaluator::InterruptHandle;
+use crate::parser::ast::{Infix, Prefix};
+use crate::vm::gc::Collector;
+
+mod gc;
+
+/// The variables of a function call. Closures keep the scope they were created in, so
+/// they see later assignments to it as they do in the `Evaluator`.
+#[derive(Debug)]
+pub struct Scope {
+    slots: RefCell<Vec<Option<Object>>>,
+    names: Rc<Vec<String>>,
+    parent: Option<Rc<Scope>>,
+}
+
+impl Scope {
+    fn get(&self, index: usize) -> Object {
+        match &self.slots.borrow()[index] {
+            Some(object) => object.clone(),
+            None => Object::Error(format!("identifier not found: {}", self.names[index])),
+        }
+    }
+
+    fn ancestor(self: &Rc<Scope>, depth: usize) -> &Rc<Scope> {
+        (0..depth).fold(self, |scope, _| scope.parent.as_ref().expect("no parent scope"))
+    }
+}
+
+/// A compiled function with the scope it was created in. Functions of the top level
+/// capture nothing, as globals are not kept in scopes.
+#[derive(Debug, Clone)]
+pub struct Closure {
+    pub function: Rc<CompiledFunction>,
+    scope: Option<Rc<Scope>>,
+}
+
+impl PartialEq for Closure {
+    fn eq(&self, other: &Self) -> bool {
+        let same_scope = match (&self.scope, &other.scope) {
+            (Some(s1), Some(s2)) => Rc::ptr_eq(s1, s2),
+            (s1, s2) => s1.is_none() && s2.is_none(),
+        };
+        // like functions of the `Evaluator`, closures compare their code and their scope
+        self.function == other.function && same_scope
+    }
+}
+
+struct Frame {
+    function: Rc<CompiledFunction>,
+    ip: usize,
+    scope: Option<Rc<Scope>>,
+}
+
+/// Stack machine running the bytecode of the `Compiler`, giving the same results as the
+/// `Evaluator`. Globals are kept between programs.
+pub struct Vm {
+    globals: Vec<Option<Object>>,
+    global_names: Vec<String>,
+    builtins: Vec<Object>,
+    stack: Vec<Object>,
+    interrupt: InterruptHandle,
+    collector: Collector,
+}
+
+impl Vm {
+    pub fn new() -> Self {
+        let builtins = BuiltinFunctions::new()
+            .get_builtins()
+            .into_iter()
+            .map(|(_, object)| object)
+            .collect();
+        Vm {
+            globals: vec![],
+            global_names: vec![],
+            builtins,
+            stack: vec![],
+            interrupt: InterruptHandle::new(),
+            collector: Collector::new(),
+        }
+    }
+
+    /// Returns a handle that cancels the program currently running on this machine.
+    /// The interrupted program evaluates to an error and the globals are kept.
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.interrupt.clone()
+    }
+
+    /// frees the scopes only kept alive by reference cycles, returning how many were freed
+    pub fn collect_garbage(&mut self) -> usize {
+        self.collector.collect()
+    }
+
+    /// number of scopes outliving their call that are still alive
+    pub fn live_scopes(&self) -> usize {
+        self.collector.live()
+    }
+
+    pub fn run(&mut self, bytecode: &Bytecode) -> Object {
+        self.globals.resize(bytecode.globals.len(), None);
+        self.global_names = bytecode.globals.clone();
+        let frame = Frame {
+            function: Rc::clone(&bytecode.main),
+            ip: 0,
+            scope: None,
+        };
+        let object = match self.execute(frame) {
+            Some(object) => object,
+            None => {
+                self.interrupt.reset();
+                Object::Error("evaluation interrupted".to_string())
+            }
+        };
+        self.stack.clear();
+        object
+    }
+
+    fn push(&mut self, object: Object) {
+        self.stack.push(object);
+    }
+
+    fn pop(&mut self) -> Object {
+        self.stack.pop().expect("stack underflow")
+    }
+
+    fn top(&self) -> &Object {
+        self.stack.last().expect("stack underflow")
+    }
+
+    /// runs until the top level leaves, or returns `None` when interrupted
+    fn execute(&mut self, mut frame: Frame) -> Option<Object> {
+        let mut frames: Vec<Frame> = vec![];
+        loop {
+            let code = &frame.function.instructions;
+            let op = Op::from_byte(code[frame.ip]).expect("invalid opcode");
+            let operands = frame.ip + 1;
+            frame.ip += op.width();
+            match op {
+                Op::Constant => {
+                    let object = match &frame.function.constants[read_u16(code, operands)] {
+                        Constant::Integer(i) => Object::Integer(*i),
+                        Constant::String(s) => Object::String(s.clone()),
+                        Constant::Function(_) | Constant::Type(_) => Object::Error("invalid constant".to_string()),
+                    };
+                    self.push(object);
+                }
+                Op::Null => self.push(Object::Null),
+                Op::True => self.push(Object::Boolean(true)),
+                Op::False => self.push(Object::Boolean(false)),
+                Op::Add
+                | Op::Sub
+                | Op::Mul
+                | Op::Div
+                | Op::Equal
+                | Op::NotEqual
+                | Op::GreaterThanEqual
+                | Op::LessThanEqual
+                | Op::GreaterThan
+                | Op::LessThan => {
+                    let infix = match op {
+                        Op::Add => Infix::Plus,
+                        Op::Sub => Infix::Minus,
+                        Op::Mul => Infix::Multiply,
+                        Op::Div => Infix::Divide,
+                        Op::Equal => Infix::Equal,
+                        Op::NotEqual => Infix::NotEqual,
+                        Op::GreaterThanEqual => Infix::GreaterThanEqual,
+                        Op::LessThanEqual => Infix::LessThanEqual,
+                        Op::GreaterThan => Infix::GreaterThan,
+                        _ => Infix::LessThan,
+                    };
+                    let object2 = self.pop();
+                    let object1 = self.pop();
+                    self.push(operators::infix(&infix, object1, object2));
+                }
+                Op::Plus | Op::Minus | Op::Not => {
+                    let prefix = match op {
+                        Op::Plus => Prefix::PrefixPlus,
+                        Op::Minus => Prefix::PrefixMinus,
+                        _ => Prefix::Not,
+                    };
+                    let object = self.pop();
+                    self.push(operators::prefix(&prefix, object));
+                }
+                Op::GetGlobal => {
+                    let index = read_u16(code, operands);
+                    let object = match &self.globals[index] {
+                        Some(object) => object.clone(),
+                        None => Object::Error(format!("identifier not found: {}", self.global_names[index])),
+                    };
+                    self.push(object);
+                }
+                Op::SetGlobal => {
+                    let index = read_u16(code, operands);
+                    self.globals[index] = Some(self.top().clone());
+                }
+                Op::GetLocal => {
+                    let scope = frame.scope.as_ref().expect("no scope");
+                    let object = scope.get(read_u16(code, operands));
+                    self.push(object);
+                }
+                Op::SetLocal => {
+                    let scope = frame.scope.as_ref().expect("no scope");
+                    scope.slots.borrow_mut()[read_u16(code, operands)] = Some(self.top().clone());
+                }
+                Op::GetFree => {
+                    let scope = frame.scope.as_ref().expect("no scope");
+                    let object = scope
+                        .ancestor(read_u8(code, operands))
+                        .get(read_u16(code, operands + 1));
+                    self.push(object);
+                }
+                Op::GetBuiltin => {
+                    let object = self.builtins[read_u8(code, operands)].clone();
+                    self.push(object);
+                }
+                Op::Array => {
+                    let len = self.stack.len() - read_u16(code, operands);
+                    let elements = self.stack.split_off(len);
+                    self.push(Object::Array(elements));
+                }
+                Op::Hash => {
+                    let len = self.stack.len() - 2 * read_u16(code, operands);
+                    let mut pairs = self.stack.split_off(len).into_iter();
+                    #[allow(clippy::mutable_key_type)]
+                    let mut hash = HashMap::new();
+                    while let (Some(key), Some(value)) = (pairs.next(), pairs.next()) {
+                        hash.insert(operators::to_hashable(key), value);
+                    }
+                    self.push(Object::Hash(hash));
+                }
+                Op::Index => {
+                    let index = self.pop();
+                    let target = self.pop();
+                    self.push(operators::index(target, index));
+                }
+                Op::Return => {
+                    let object = self.pop();
+                    self.push(Object::ReturnValue(Box::new(object)));
+                }
+                Op::Closure => {
+                    let object = match &frame.function.constants[read_u16(code, operands)] {
+                        Constant::Function(function) => Object::Closure(Closure {
+                            function: Rc::clone(function),
+                            scope: frame.scope.clone(),
+                        }),
+                        _ => Object::Error("invalid constant".to_string()),
+                    };
+                    self.push(object);
+                }
+                Op::Jump => frame.ip = read_u16(code, operands),
+                Op::Branch => match operators::to_bool(self.pop()) {
+                    Ok(true) => {}
+                    Ok(false) => frame.ip = read_u16(code, operands),
+                    Err(err) => {
+                        frame.ip = read_u16(code, operands + 2);
+                        self.push(err);
+                    }
+                },
+                Op::JumpIfReturned => {
+                    if self.top().is_returned() {
+                        frame.ip = read_u16(code, operands);
+                    } else {
+                        self.pop();
+                    }
+                }
+                Op::PrepareCall => {
+                    if self.interrupt.is_interrupted() {
+                        return None;
+                    }
+                    let argc = read_u8(code, operands);
+                    let error = match self.top() {
+                        Object::Closure(closure) if closure.function.num_params == argc => None,
+                        Object::Builtin(_, num_params, _) if *num_params == argc => None,
+                        Object::Closure(closure) => Some(operators::arity_error(closure.function.num_params, argc)),
+                        Object::Builtin(_, num_params, _) => Some(operators::arity_error(*num_params, argc)),
+                        o => Some(operators::call_error(o.clone())),
+                    };
+                    if let Some(error) = error {
+                        frame.ip = read_u16(code, operands + 1);
+                        self.pop();
+                        self.push(error);
+                    }
+                }
+                Op::CheckArg => {
+                    let index = read_u8(code, operands);
+                    let callee = self.stack.len() - 2 - index;
+                    let ty = match &self.stack[callee] {
+                        Object::Closure(closure) => closure.function.signature.param(index).map(|ty| {
+                            (closure.function.locals[index].clone(), ty.clone())
+                        }),
+                        _ => None,
+                    };
+                    if let Some((name, ty)) = ty {
+                        let object = self.pop();
+                        match operators::annotated(&name, &ty, object) {
+                            Object::Error(err) => {
+                                self.stack.truncate(callee);
+                                self.push(Object::Error(err));
+                                frame.ip = read_u16(code, operands + 1);
+                            }
+                            object => self.push(object),
+                        }
+                    }
+                }
+                Op::Check => {
+                    let constants = &frame.function.constants;
+                    if let (Constant::String(name), Constant::Type(ty)) =
+                        (&constants[read_u16(code, operands)], &constants[read_u16(code, operands + 2)])
+                    {
+                        let object = self.pop();
+                        self.push(operators::annotated(name, ty, object));
+                    }
+                }
+                Op::Call => {
+                    let argc = read_u8(code, operands);
+                    let args = self.stack.split_off(self.stack.len() - argc);
+                    match self.pop() {
+                        Object::Closure(closure) => {
+                            if self.collector.should_collect() {
+                                self.collector.collect();
+                            }
+                            let function = &closure.function;
+                            // parameters take the first slots of the body's scope
+                            let mut slots = args.into_iter().map(Some).collect::<Vec<_>>();
+                            slots.resize(function.locals.len(), None);
+                            let scope = Scope {
+                                slots: RefCell::new(slots),
+                                names: Rc::clone(&function.locals),
+                                parent: closure.scope.clone(),
+                            };
+                            let callee = Frame {
+                                function: Rc::clone(function),
+                                ip: 0,
+                                scope: Some(Rc::new(scope)),
+                            };
+                            frames.push(std::mem::replace(&mut frame, callee));
+                        }
+                        Object::Builtin(_, _, builtin_fn) => {
+                            let object = builtin_fn(args).unwrap_or_else(Object::Error);
+                            self.push(object);
+                        }
+                        _ => unreachable!("callee checked by PrepareCall"),
+                    }
+                }
+                Op::Leave => {
+                    let object = self.pop().returned();
+                    let object = match &frame.function.signature.ret {
+                        Some(ty) => operators::annotated("the returned value", ty, object),
+                        None => object,
+                    };
+                    if let Some(scope) = &frame.scope {
+                        // the scope outlives the call when a closure captured it
+                        if Rc::strong_count(scope) > 1 {
+                            self.collector.track(scope);
+                        }
+                    }
+                    match frames.pop() {
+                        Some(caller) => {
+                            frame = caller;
+                            self.push(object);
+                        }
+                        None => return Some(object),
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Default for Vm {
+    fn default() -> Self {
+        Vm::new()
+    }
+}
+
+impl Drop for Vm {
+    fn drop(&mut self) {
+        self.globals.clear();
+        self.collector.collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::Compiler;
+    use crate::evaluator::Evaluator;
+    use crate::lexer::token::Tokens;
+    use crate::lexer::Lexer;
+    use crate::parser::ast::Program;
+    use crate::parser::Parser;
+
+    fn parse(input: &str) -> Program {
+        let (_, r) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+        let (_, program) = Parser::parse_tokens(Tokens::new(&r)).unwrap();
+        program
+    }
+
+    fn run_with(compiler: &mut Compiler, vm: &mut Vm, input: &str) -> Object {
+        match compiler.compile(&parse(input)) {
+            Ok(bytecode) => vm.run(&bytecode),
+            Err(err) => Object::Error(err.to_string()),
+        }
+    }
+
+    /// runs the lines one after the other on both backends, which must agree on each
+    fn agree(lines: &[&str]) {
+        let mut evaluator = Evaluator::new();
+        let mut compiler = Compiler::new();
+        let mut vm = Vm::new();
+        for line in lines {
+            let expected = evaluator.eval_program(parse(line));
+            let object = run_with(&mut compiler, &mut vm, line);
+            assert_eq!(object.to_string(), expected.to_string(), "running {}", line);
+        }
+    }
+
+    #[test]
+    fn expressions() {
+        agree(&["1 + 2 * 3 - 4 / 2", "-(5) + +3", "!true", "!5", "1 < 2 == true", "10 / 0"]);
+        agree(&["\"foo\" + \"bar\"", "\"foo\" + 1", "true + false", "-true"]);
+        agree(&["[1, 2 + 3, \"a\"][1]", "[1, 2][5]", "{\"a\": 1, 2: true}[2]", "{1: 2}[[]]", "5[0]"]);
+        agree(&["if (true) { 10 }", "if (false) { 10 }", "if (1) { 10 } else { 20 }", "if (1 > 2) { 1 } else { 2 }"]);
+    }
+
+    #[test]
+    fn statements() {
+        agree(&["", "let a = 5; a", "let a = 5;", "return 10; 9", "9; return 2 * 5; 9"]);
+        agree(&["if (10 > 1) { if (10 > 1) { return 10; } return 1; }", "if (true) { 1; } else { 2; }; 3"]);
+        agree(&["let x = if (true) { return 1 }; 2", "1 + if (true) { return 2 }", "[if (true) { return 3 }]"]);
+        agree(&["let a = 1; a", "let b = a + 1; b", "foobar", "a + b", "c; let c = 1", "c"]);
+    }
+
+    #[test]
+    fn annotations() {
+        agree(&["let x: int = true; x", "x", "let y: [int] = [1, 2]; y", "fn(a: int) -> string { a }(1)"]);
+        agree(&["let f = fn(a: int, b: bool) { b }; [f(1, true), f(true, 1), f(1, 2), f(foo, 1)]"]);
+        agree(&["let g: fn(int) -> int = fn(a) { a }; [g(1), len(g), fn(f: fn(int) -> int) { f }(len)]"]);
+        agree(&["let h = fn(a: int) -> int { return a; 1 }; [h(2), h(\"2\"), fn() -> null { if (true) { return 1 } }()]"]);
+    }
+
+    #[test]
+    fn functions() {
+        agree(&[
+            "let identity = fn(x) { x; }; identity(5);",
+            "let double = fn(x) { return x * 2; 0 }; double(5)",
+            "fn(x, y) { x + y }(1, 2)",
+            "fn() { }()",
+            "identity(1, 2)",
+            "5(1)",
+            "undefined(1)",
+            "fn(x) { x }",
+            "len(\"four\") + len([1, 2])",
+            "len(1, 2)",
+            "len(1)",
+            "head(tail(cons(1, [2, 3])))",
+            "let f = fn(n) { if (n) { 1 } }; f(1)",
+        ]);
+    }
+
+    #[test]
+    fn closures() {
+        agree(&[
+            "let adder = fn(x) { fn(y) { x + y } }; adder(2)(3)",
+            "let a = 1; let f = fn() { a }; let a = 2; f()",
+            "let counter = fn(x) { let g = fn() { x + y }; let y = 10; g() }; counter(1)",
+            "let deep = fn(a) { fn(b) { fn(c) { a + b + c } } }; deep(1)(2)(3)",
+            "let early = fn() { let g = fn() { later }; g() }; early()",
+            "let fib = fn(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } }; fib(15)",
+            "let rec = fn(n) { let inner = fn(m) { if (m == 0) { 0 } else { inner(m - 1) } }; inner(n) }; rec(10)",
+            "let map = fn(arr, f) { if (len(arr) == 0) { [] } else { cons(f(head(arr)), map(tail(arr), f)) } }; map([1, 2, 3], fn(x) { x * x })",
+        ]);
+    }
+
+    #[test]
+    fn interrupt() {
+        let mut compiler = Compiler::new();
+        let mut vm = Vm::new();
+        run_with(&mut compiler, &mut vm, "let a = 1; let loop = fn(n) { loop(n + 1) };");
+        vm.interrupt_handle().interrupt();
+        assert_eq!(
+            run_with(&mut compiler, &mut vm, "loop(0)"),
+            Object::Error("evaluation interrupted".to_string()),
+        );
+        assert_eq!(run_with(&mut compiler, &mut vm, "a"), Object::Integer(1));
+    }
+
+    #[test]
+    fn cycle_collection() {
+        let mut compiler = Compiler::new();
+        let mut vm = Vm::new();
+        run_with(&mut compiler, &mut vm, "let f = fn(n) { let g = fn() { g }; n }; let keep = fn() { let h = fn() { h }; h }();");
+        for _ in 0..300 {
+            run_with(&mut compiler, &mut vm, "f(1)");
+        }
+        vm.collect_garbage();
+        assert_eq!(vm.live_scopes(), 1);
+        assert_eq!(run_with(&mut compiler, &mut vm, "keep()()").to_string(), "[function]");
+    }
+}
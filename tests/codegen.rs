@@ -0,0 +1,254 @@
+//! Translates programs to the languages of `codegen` and, where a runtime for the language is
+//! installed, runs them, comparing their output with the result of the evaluator.
+//!
+//! The translations of the programs of `tests/golden/<language>` must also match the files
+//! next to them, which are written again when `UPDATE_GOLDEN` is set.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+
+use monkey_lang_lib::codegen::{CodegenError, Target};
+use monkey_lang_lib::evaluator::Evaluator;
+use monkey_lang_lib::lexer::token::Tokens;
+use monkey_lang_lib::lexer::Lexer;
+use monkey_lang_lib::parser::ast::Program;
+use monkey_lang_lib::parser::Parser;
+
+/// programs of integers, booleans and functions, which every target supports
+const PROGRAMS: &[&str] = &[
+    "let fib = fn(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } }; fib(20)",
+    "let fact = fn(n) { if (n < 2) { 1 } else { n * fact(n - 1) } }; fact(20)",
+    "let gcd = fn(a, b) { if (b == 0) { a } else { gcd(b, a - (a / b) * b) } }; gcd(1071, 462)",
+    "let sum = fn(i, n, acc) { if (i > n) { return acc; }; sum(i + 1, n, acc + i) }; sum(1, 100, 0)",
+    "let even = fn(n) { if (n == 0) { true } else { odd(n - 1) } };
+     let odd = fn(n) { if (n == 0) { false } else { even(n - 1) } };
+     even(10) == odd(7)",
+    "let base = 10; let scale = fn(x) { x * base }; let r = scale(4); r + 1",
+    "let sign = fn(b) { if (!b) { -1 } else { +1 } }; sign(false) * 3",
+    "let abs = fn(x) { if (x > 0) { let y = x; y } else { let y = 0 - x; y } }; abs(5) + abs(-3)",
+    "let same = fn(a, b) { a == b }; same(true, 1 < 2) != same(false, 2 <= 1)",
+    "let clamp = fn(x) { if (x >= 10) { return 10; }; x }; clamp(3) + clamp(30)",
+    "let f = fn() { return 1 > 2; 3 }; f()",
+];
+
+/// programs whose errors every value of the language may give, which only C supports
+const C_PROGRAMS: &[&str] = &[
+    "let f = fn(a, b) { a + b }; [f(1), f(1, true), 5(1), -f, f + 1, f == f, len, len == len, \"a\" + 1]",
+    "let h = {\"k\": [1, \"two\"], true: fn(x) { x }}; [h[\"k\"], h[true](3), h[[]], 1[0], [1][-1]]",
+    "let s = \"a??/b\\\"c\"; [s, len(s), print(s)]",
+    "let big = 9223372036854775807; big + 1",
+    "let fill = fn(n, acc) { if (n == 0) { acc } else { fill(n - 1, cons(n, acc)) } }; len(fill(200, []))",
+    "[assert(1 < 2), assert(2 < 1), assert(3), assert_eq([1, [2]], [1, [2]]), assert_error(1 + true), assert_error(4)]",
+];
+
+/// programs returning from the top level, which a JavaScript script cannot do
+const TOP_LEVEL_RETURNS: &[&str] = &["return 1 > 2; 3"];
+
+/// programs of integers past 2^53, which JavaScript numbers do not all represent
+const LARGE_INTEGERS: &[&str] = &["let big = 9223372036854775807; if (big > 0) { let half = big / 2; half } else { 0 }"];
+
+/// programs using what JavaScript supports beyond the other targets
+const JS_PROGRAMS: &[&str] = &[
+    "let map = fn(xs, f) { if (len(xs) == 0) { [] } else { cons(f(head(xs)), map(tail(xs), f)) } };
+     map([1, 2, 3], fn(x) { x * x })",
+    "let make = fn(n) { let k = n + 1; fn(x) { x + k } }; let add3 = make(2); [add3(1), make(0)(0)]",
+    "let h = {\"a\": [1, 2], 2: {true: \"yes\"}}; [h[\"a\"][1], h[2][true], h[\"b\"], [1, [2]] == [1, [2]]]",
+    "let f = fn(x) { let y = x; if (x > 0) { let y = x * 2 }; y }; f(2) + f(-1)",
+    "let s = \"héllo\"; [len(s), s + \"!\", s == \"héllo\"]",
+    "[assert(1 < 2), assert_eq([1, {\"a\": 2}], [1, {\"a\": 2}])]",
+    "assert_eq([1, {\"a\": [2, \"x\\\"y\"], \"b\": 1}, [3, 4, 5]], [1, {\"a\": [3], \"c\": true}, [4]])",
+    "assert(\"yes\")",
+];
+
+fn parse(input: &str) -> Program {
+    let (_, r) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+    let (_, program) = Parser::parse_tokens(Tokens::new(&r)).unwrap();
+    program
+}
+
+fn installed(program: &str) -> bool {
+    Command::new(program)
+        .arg("--version")
+        .output()
+        .is_ok_and(|output| output.status.success())
+}
+
+fn temp_file(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("monkey-{}-{}", process::id(), name))
+}
+
+fn expected(program: &Program) -> String {
+    Evaluator::new().eval_program(program.clone()).to_string()
+}
+
+/// the programs of the files of `tests/corpus`, each run by itself
+fn corpus() -> Vec<String> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/corpus");
+    let mut paths = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "mk"))
+        .collect::<Vec<_>>();
+    paths.sort();
+    let mut programs = vec![];
+    for path in paths {
+        let source = fs::read_to_string(&path).unwrap();
+        programs.extend(source.split("\n---\n").map(str::to_string));
+    }
+    programs
+}
+
+/// the last lines of an output, as many as the result of a program has
+fn last_lines(output: &str, result: &str) -> String {
+    let lines = output.lines().collect::<Vec<_>>();
+    lines[lines.len().saturating_sub(result.lines().count().max(1))..].join("\n")
+}
+
+/// The last lines written by a JavaScript translation, run for its completion value, which is
+/// displayed as Monkey displays values, or for the error it throws, as many as `expected` has.
+fn run_js(js: &str, expected: &str) -> String {
+    let path = temp_file("script.js");
+    fs::write(&path, js).unwrap();
+    let script = "const fs = require('fs');
+        const vm = require('vm');
+        const show = (value) => {
+          if (value === null || value === undefined) {
+            return 'null';
+          } else if (Array.isArray(value)) {
+            return '[' + value.map(show).join(', ') + ']';
+          } else if (value instanceof Map) {
+            return '{' + Array.from(value, ([k, v]) => show(k) + ': ' + show(v)).join(', ') + '}';
+          } else if (typeof value === 'function') {
+            return '[function]';
+          }
+          return String(value);
+        };
+        try {
+          console.log(show(vm.runInThisContext(fs.readFileSync(process.argv[1], 'utf8'))));
+        } catch (error) {
+          console.log('Error: ' + error.message);
+        }";
+    let output = Command::new("node").arg("-e").arg(script).arg(&path).output().unwrap();
+    fs::remove_file(&path).unwrap();
+    assert!(output.status.success(), "{}\n{}", String::from_utf8_lossy(&output.stderr), js);
+    last_lines(&String::from_utf8_lossy(&output.stdout), expected)
+}
+
+/// the last lines printed by a C translation, compiled with `cc`, as many as `expected` has
+fn run_c(c: &str, expected: &str) -> String {
+    let source = temp_file("program.c");
+    let binary = temp_file("program");
+    fs::write(&source, c).unwrap();
+    let output = Command::new("cc").arg("-std=c99").arg("-o").arg(&binary).arg(&source).output().unwrap();
+    fs::remove_file(&source).unwrap();
+    assert!(output.status.success(), "{}\n{}", String::from_utf8_lossy(&output.stderr), c);
+    let output = Command::new(&binary).output().unwrap();
+    fs::remove_file(&binary).unwrap();
+    assert!(output.status.success(), "{}\n{}", String::from_utf8_lossy(&output.stderr), c);
+    last_lines(&String::from_utf8_lossy(&output.stdout), expected)
+}
+
+/// checks the translations of the programs of a directory of `tests/golden`, returning them
+fn golden(target: Target, dir: &str, extension: &str) -> Vec<(PathBuf, Program, String)> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden").join(dir);
+    let mut paths = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "mk"))
+        .collect::<Vec<_>>();
+    paths.sort();
+    assert!(!paths.is_empty(), "no programs in {}", dir.display());
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+    let mut translations = vec![];
+    for path in paths {
+        let program = parse(&fs::read_to_string(&path).unwrap());
+        let code = target.emit(&program).unwrap_or_else(|err| panic!("{}: {}", path.display(), err));
+        let golden = path.with_extension(extension);
+        if update {
+            fs::write(&golden, &code).unwrap();
+        } else {
+            let expected = fs::read_to_string(&golden).unwrap_or_default();
+            assert!(code == expected, "{} differs from {}:\n{}", path.display(), golden.display(), code);
+        }
+        translations.push((path, program, code));
+    }
+    translations
+}
+
+#[test]
+fn wat() {
+    let node = installed("node");
+    if !node {
+        eprintln!("node is not installed, WebAssembly modules are assembled but not run");
+    }
+    for (i, input) in PROGRAMS.iter().chain(TOP_LEVEL_RETURNS).chain(LARGE_INTEGERS).enumerate() {
+        let program = parse(input);
+        let wat = Target::Wat.emit(&program).unwrap_or_else(|err| panic!("{}: {}", input, err));
+        let wasm = wat::parse_str(&wat).unwrap_or_else(|err| panic!("{}: {}\n{}", input, err, wat));
+        if !node {
+            continue;
+        }
+        let path = temp_file(&format!("{}.wasm", i));
+        fs::write(&path, wasm).unwrap();
+        // i64 results are BigInts, and booleans are i32 ones and zeros
+        let script = "const fs = require('fs');
+            const module = new WebAssembly.Module(fs.readFileSync(process.argv[1]));
+            const result = new WebAssembly.Instance(module).exports.main();
+            console.log(String(result));";
+        let output = Command::new("node").arg("-e").arg(script).arg(&path).output().unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(output.status.success(), "{}: {}\n{}", input, String::from_utf8_lossy(&output.stderr), wat);
+        let expected = match expected(&program).as_str() {
+            "true" => "1".to_string(),
+            "false" => "0".to_string(),
+            result => result.to_string(),
+        };
+        assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), expected, "{}\n{}", input, wat);
+    }
+}
+
+#[test]
+fn js() {
+    let node = installed("node");
+    if !node {
+        eprintln!("node is not installed, JavaScript translations are not run");
+    }
+    for (path, program, js) in golden(Target::Js, "js", "js") {
+        if node {
+            let expected = expected(&program);
+            assert_eq!(run_js(&js, &expected), expected, "{}", path.display());
+        }
+    }
+    for input in PROGRAMS.iter().chain(JS_PROGRAMS) {
+        let program = parse(input);
+        let js = Target::Js.emit(&program).unwrap_or_else(|err| panic!("{}: {}", input, err));
+        if node {
+            let expected = expected(&program);
+            assert_eq!(run_js(&js, &expected), expected, "{}\n{}", input, js);
+        }
+    }
+    for input in TOP_LEVEL_RETURNS {
+        let err = Target::Js.emit(&parse(input)).unwrap_err();
+        assert_eq!(err.to_string(), "not supported by the target: return outside of functions");
+    }
+}
+
+#[test]
+fn c() {
+    if !installed("cc") {
+        eprintln!("cc is not installed, C translations are not compiled");
+        return;
+    }
+    let programs = PROGRAMS.iter().chain(TOP_LEVEL_RETURNS).chain(LARGE_INTEGERS).chain(JS_PROGRAMS).chain(C_PROGRAMS);
+    for input in programs.map(|input| input.to_string()).chain(corpus()) {
+        let program = parse(&input);
+        let expected = expected(&program);
+        match Target::C.emit(&program) {
+            Ok(c) => assert_eq!(run_c(&c, &expected), expected, "{}", input),
+            // only the evaluator and the vm check annotations
+            Err(CodegenError::Unsupported(what)) if what == "type annotations" => {}
+            Err(err) => assert_eq!(format!("Error: {}", err), expected, "{}", input),
+        }
+    }
+}
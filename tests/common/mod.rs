@@ -0,0 +1,250 @@
+//! What the integration tests share: parsing, the programs of `tests/corpus`, and a
+//! generator of random programs.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use monkey_lang_lib::lexer::token::Tokens;
+use monkey_lang_lib::lexer::Lexer;
+use monkey_lang_lib::parser::ast::{Expr, Ident, Infix, Literal, Prefix, Program, Signature, Stmt, Type};
+use monkey_lang_lib::parser::Parser;
+
+pub fn parse(input: &str) -> Program {
+    let (_, r) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+    let (_, program) = Parser::parse_tokens(Tokens::new(&r)).unwrap();
+    program
+}
+
+/// The files of `tests/corpus` and their programs: lines holding only `---` split a file into
+/// programs run one after the other on the same engine.
+pub fn corpus() -> Vec<(PathBuf, Vec<String>)> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/corpus");
+    let mut paths = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "mk"))
+        .collect::<Vec<_>>();
+    paths.sort();
+    assert!(!paths.is_empty(), "no programs in {}", dir.display());
+    let mut files = vec![];
+    for path in paths {
+        let source = fs::read_to_string(&path).unwrap();
+        let mut programs = vec![String::new()];
+        for line in source.lines() {
+            if line.trim() == "---" {
+                programs.push(String::new());
+            } else {
+                let program = programs.last_mut().unwrap();
+                program.push_str(line);
+                program.push('\n');
+            }
+        }
+        files.push((path, programs));
+    }
+    files
+}
+
+/// xorshift64*, enough to vary the programs without a dependency
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn chance(&mut self, percent: usize) -> bool {
+        self.below(100) < percent
+    }
+}
+
+#[derive(Default)]
+struct Scope {
+    /// every name usable in the scope
+    names: Vec<String>,
+    /// names bound by a `let` to a function literal
+    functions: Vec<String>,
+}
+
+/// Generates programs that always terminate: only builtins, function literals and names
+/// bound to a function literal before the caller was written are ever called, so no
+/// function can reach itself. Bindings get fresh names, and some programs refer to names
+/// that are unbound or not set yet.
+pub struct Generator {
+    rng: Rng,
+    scopes: Vec<Scope>,
+    fresh: usize,
+}
+
+impl Generator {
+    pub fn new(seed: u64) -> Self {
+        Generator {
+            rng: Rng(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1),
+            scopes: vec![Scope::default()],
+            fresh: 0,
+        }
+    }
+
+    fn fresh(&mut self, prefix: &str) -> String {
+        self.fresh += 1;
+        format!("{}{}", prefix, self.fresh)
+    }
+
+    pub fn program(&mut self) -> Program {
+        let len = 1 + self.rng.below(5);
+        (0..len).map(|_| self.stmt(3)).collect()
+    }
+
+    fn block(&mut self, depth: usize) -> Program {
+        let len = self.rng.below(3);
+        (0..len).map(|_| self.stmt(depth)).collect()
+    }
+
+    fn stmt(&mut self, depth: usize) -> Stmt {
+        match self.rng.below(10) {
+            0..=3 => {
+                let name = self.fresh("v");
+                let expr = self.expr(depth);
+                let scope = self.scopes.last_mut().unwrap();
+                if let Expr::FnExpr { .. } = expr {
+                    scope.functions.push(name.clone());
+                }
+                scope.names.push(name.clone());
+                let ty = self.annotation();
+                Stmt::LetStmt(Ident(name), ty, expr)
+            }
+            4 => Stmt::ReturnStmt(self.expr(depth)),
+            _ => Stmt::ExprStmt(self.expr(depth)),
+        }
+    }
+
+    fn ident(&mut self) -> Expr {
+        let names = self
+            .scopes
+            .iter()
+            .flat_map(|scope| scope.names.iter())
+            .collect::<Vec<_>>();
+        let name = if names.is_empty() || self.rng.chance(5) {
+            ["len", "head", "tail", "cons", "unbound"][self.rng.below(5)].to_string()
+        } else {
+            names[self.rng.below(names.len())].clone()
+        };
+        Expr::IdentExpr(Ident(name))
+    }
+
+    fn literal(&mut self) -> Literal {
+        match self.rng.below(3) {
+            0 if self.rng.chance(20) => Literal::IntLiteral(i64::MAX),
+            0 => Literal::IntLiteral(self.rng.below(10) as i64),
+            1 => Literal::BoolLiteral(self.rng.chance(50)),
+            _ => Literal::StringLiteral(["", "a", "bc"][self.rng.below(3)].to_string()),
+        }
+    }
+
+    fn callee(&mut self, depth: usize) -> Expr {
+        let functions = self
+            .scopes
+            .iter()
+            .flat_map(|scope| scope.functions.iter())
+            .collect::<Vec<_>>();
+        match self.rng.below(4) {
+            0 if !functions.is_empty() => {
+                Expr::IdentExpr(Ident(functions[self.rng.below(functions.len())].clone()))
+            }
+            1 => Expr::IdentExpr(Ident(["len", "head", "tail", "cons"][self.rng.below(4)].to_string())),
+            2 => Expr::LiteralExpr(self.literal()),
+            _ => self.function(depth),
+        }
+    }
+
+    fn function(&mut self, depth: usize) -> Expr {
+        let params = (0..self.rng.below(3))
+            .map(|_| Ident(self.fresh("p")))
+            .collect::<Vec<_>>();
+        self.scopes.push(Scope {
+            names: params.iter().map(|Ident(name)| name.clone()).collect(),
+            functions: vec![],
+        });
+        let body = self.block(depth.saturating_sub(1));
+        self.scopes.pop();
+        let mut types = params.iter().map(|_| self.annotation()).collect::<Vec<_>>();
+        if types.iter().all(Option::is_none) {
+            types.clear();
+        }
+        let signature = Signature { params: types, ret: self.annotation() };
+        Expr::FnExpr { params, signature: signature.into(), body: body.into() }
+    }
+
+    /// a type now and then, so that some values are checked against it
+    fn annotation(&mut self) -> Option<Type> {
+        self.rng.chance(25).then(|| self.ty(2))
+    }
+
+    fn ty(&mut self, depth: usize) -> Type {
+        match self.rng.below(if depth == 0 { 4 } else { 7 }) {
+            0 => Type::Int,
+            1 => Type::Bool,
+            2 => Type::String,
+            3 => Type::Null,
+            4 => Type::Array(Box::new(self.ty(depth - 1))),
+            5 => Type::Hash(Box::new(self.ty(depth - 1)), Box::new(self.ty(depth - 1))),
+            _ => Type::Fn((0..self.rng.below(3)).map(|_| self.ty(depth - 1)).collect(), Box::new(self.ty(depth - 1))),
+        }
+    }
+
+    fn expr(&mut self, depth: usize) -> Expr {
+        if depth == 0 || self.rng.chance(30) {
+            return match self.rng.below(2) {
+                0 => self.ident(),
+                _ => Expr::LiteralExpr(self.literal()),
+            };
+        }
+        let depth = depth - 1;
+        match self.rng.below(8) {
+            0 => {
+                let prefix = [Prefix::PrefixPlus, Prefix::PrefixMinus, Prefix::Not][self.rng.below(3)].clone();
+                Expr::PrefixExpr(prefix, Box::new(self.expr(depth)))
+            }
+            1 => {
+                let infix = [
+                    Infix::Plus,
+                    Infix::Minus,
+                    Infix::Divide,
+                    Infix::Multiply,
+                    Infix::Equal,
+                    Infix::NotEqual,
+                    Infix::GreaterThanEqual,
+                    Infix::LessThanEqual,
+                    Infix::GreaterThan,
+                    Infix::LessThan,
+                ][self.rng.below(10)]
+                .clone();
+                Expr::InfixExpr(infix, Box::new(self.expr(depth)), Box::new(self.expr(depth)))
+            }
+            2 => Expr::IfExpr {
+                cond: Box::new(self.expr(depth)),
+                consequence: self.block(depth),
+                alternative: if self.rng.chance(50) { Some(self.block(depth)) } else { None },
+            },
+            3 => self.function(depth),
+            4 => {
+                let function = self.callee(depth);
+                let arguments = (0..self.rng.below(3)).map(|_| self.expr(depth)).collect();
+                Expr::CallExpr { function: Box::new(function), arguments }
+            }
+            5 => Expr::ArrayExpr((0..self.rng.below(4)).map(|_| self.expr(depth)).collect()),
+            // a single pair, as the order in which hashes are displayed in errors varies
+            6 => Expr::HashExpr((0..self.rng.below(2)).map(|_| (self.literal(), self.expr(depth))).collect()),
+            _ => Expr::IndexExpr {
+                array: Box::new(self.expr(depth)),
+                index: Box::new(self.expr(depth)),
+            },
+        }
+    }
+}
@@ -0,0 +1,8 @@
+let n: int = 1;
+let s: string = n;
+let f = fn(a: int, b: [bool]) -> int { if (a > 0) { return a; } len(b) };
+let g: fn(int, [bool]) -> int = f;
+[n, s, f(2, []), f(0, [true, false]), f(true, []), f(1, [1]), g(0, []), fn() -> bool { 1 }()]
+---
+let h: {string: fn(int) -> int} = {"id": fn(x: int) -> int { x }};
+[h["id"](3), h["id"]("3"), fn(x) -> null { x }(n)]
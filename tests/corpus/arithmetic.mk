@@ -0,0 +1,11 @@
+let a = 5 + 5 * 2 - 10 / 2;
+let b = -a + +3;
+let c = (a + b) * (a - b);
+[a, b, c, a < b, a >= b, a == 10, b != -7, !(a > b), 7 / 2, -7 / 2, 10 / 0, 1 + true, -false, !3]
+---
+let seconds = 2 * 60 * 60;
+let f = fn(x) { if (1 > 2) { x } else { x * (3 - 1) } };
+let g = fn() { return seconds; let late = 1; late };
+[seconds, f(4), g(), 1 / (2 - 2), !(1 == 1), "a" + "b", if (true) { }, if (false) { 1 }]
+---
+if (true) { let inner = 5; }; inner + late
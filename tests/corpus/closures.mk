@@ -0,0 +1,7 @@
+let adder = fn(x) { fn(y) { x + y } };
+let add2 = adder(2);
+let compose = fn(f, g) { fn(x) { g(f(x)) } };
+let twice = fn(f) { compose(f, f) };
+let counter = fn(start) { let step = 10; let next = fn() { start + step + later }; let later = 100; next };
+let deep = fn(a) { fn(b) { fn(c) { fn(d) { a * 1000 + b * 100 + c * 10 + d } } } };
+[add2(3), twice(add2)(0), twice(twice(add2))(1), counter(1)(), deep(1)(2)(3)(4), adder(1) == adder(1), add2 == add2]
@@ -0,0 +1,7 @@
+let arr = [1, 2 + 3, "x", true, [4, 5]];
+let h = {"one": 1, 2: "two", true: [3]};
+[arr[1], arr[4][0], arr[10], arr[-1], h["one"], h[2], h[true][0], h["missing"], h[[]], 5[0], head(arr), tail([1, 2, 3]), cons(0, [1]), head([]), tail(1), len(arr)]
+---
+let inc = fn(x) { x + 1 };
+let fns = [inc, fn(x) { x * 2 }];
+[fns[0](1), fns[1](5), {"f": inc}["f"](9)]
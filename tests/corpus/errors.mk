@@ -0,0 +1,19 @@
+let add = fn(a, b) { a + b };
+let e1 = add(1);
+let e2 = add(1, 2, 3);
+let e3 = 5(1);
+let e4 = "f"();
+let e5 = if (1) { 2 };
+let e6 = if (add(1, true)) { 2 } else { 3 };
+let e7 = len();
+let e8 = cons(1, 2);
+let e9 = -add;
+let e10 = add + 1;
+[e1, e2, e3, e4, e5, e6, e7, e8, e9, e10, later]
+---
+let later = 1;
+undefined_name + later
+---
+fn(a, a) { a }
+---
+later + 1
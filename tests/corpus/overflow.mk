@@ -0,0 +1,20 @@
+let big = 9223372036854775807;
+[big - 1, -big, big * 1, big / -1]
+---
+let f = fn(n) { n + big };
+f(1)
+---
+let small = -big - 1;
+small - 1
+---
+-small
+---
+big * 2
+---
+small / -1
+---
+let g = fn(n) { if (n < big) { n + 1 } else { n } };
+[g(big - 1), g(big), f(-1)]
+---
+let e = f(1);
+[e, big]
@@ -0,0 +1,8 @@
+let fib = fn(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } };
+let map = fn(arr, f) { if (len(arr) == 0) { [] } else { cons(f(head(arr)), map(tail(arr), f)) } };
+let reduce = fn(arr, acc, f) { if (len(arr) == 0) { acc } else { reduce(tail(arr), f(acc, head(arr)), f) } };
+let range = fn(n) { if (n == 0) { [] } else { cons(n, range(n - 1)) } };
+let even = fn(n) { if (n == 0) { true } else { odd(n - 1) } };
+let odd = fn(n) { if (n == 0) { false } else { even(n - 1) } };
+let loop = fn(n) { let inner = fn(m) { if (m == 0) { n } else { inner(m - 1) } }; inner(n) };
+[fib(15), map(range(5), fn(x) { x * x }), reduce(range(100), 0, fn(a, b) { a + b }), even(10), odd(7), loop(20)]
@@ -0,0 +1,20 @@
+let f = fn(x) {
+    if (x > 10) {
+        if (x > 100) {
+            return "huge";
+        }
+        return "big";
+    }
+    let y = if (x < 0) { return "negative" } else { x * 2 };
+    y
+};
+let g = fn() { return 1; 2 };
+let h = fn() { let x = if (true) { return 3 }; 4 };
+let k = fn() { [if (true) { return 5 }] };
+let m = fn() { 1 + if (true) { return 6 } };
+let n = fn() { };
+[f(1000), f(50), f(-1), f(3), g(), h(), k(), m(), n()]
+---
+if (1 > 2) { 1 } else { return 2; 3 }; 4
+---
+let r = fn() { return 1 }; return r(); 2
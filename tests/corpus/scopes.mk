@@ -0,0 +1,16 @@
+let x = 1;
+let shadow = fn(x) { let y = x * 2; fn(x) { x + y } };
+let early = fn() { missing };
+let missing = "found";
+let conditional = fn(b) { if (b) { let v = "set" } ; v };
+[shadow(10)(1), early(), conditional(true), conditional(false), x]
+---
+let x = x + 1;
+let f = fn() { x };
+let x = 10;
+[x, f()]
+---
+let a = 1;
+let f = fn() { let b = a; let a = 2; [b, a] };
+let g = fn() { let h = fn() { a }; let a = 3; h() };
+[f(), g(), a]
@@ -0,0 +1,3 @@
+let greet = fn(name) { "hello, " + name + "!" };
+let s = greet("monkey");
+[s, len(s), len(""), "a" == "a", "a" != "b", "a" + 1, "a" - "b", "abc"[0], len(1)]
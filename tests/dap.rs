@@ -0,0 +1,256 @@
+//! Debugs programs through the debug adapter binary, over its standard input and output, as
+//! editors do.
+
+use std::collections::VecDeque;
+use std::fs;
+use std::io::BufReader;
+use std::path::PathBuf;
+use std::process::{self, Child, ChildStdin, ChildStdout, Command, Stdio};
+
+use monkey_lang_lib::json::Json;
+use monkey_lang_lib::lsp::{read_message, write_message};
+
+const PROGRAM: &str = "let double = fn(x) {
+  let y = x * 2;
+  y
+};
+let values = {\"a\": [1, 2]};
+let total = double(3) + double(4);
+print(total);
+total";
+
+struct Client {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    seq: usize,
+    /// the events received while waiting for responses
+    events: VecDeque<Json>,
+    path: PathBuf,
+}
+
+impl Client {
+    /// Starts the adapter and launches a program, stopped after `configure` sets it up.
+    fn launch(name: &str, source: &str, stop_on_entry: bool, configure: impl FnOnce(&mut Client)) -> Self {
+        let path = std::env::temp_dir().join(format!("monkey-{}-{}.mk", process::id(), name));
+        fs::write(&path, source).unwrap();
+        let mut child = Command::new(env!("CARGO_BIN_EXE_monkey_dap"))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let stdin = child.stdin.take().unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        let mut client = Client { child, stdin, stdout, seq: 0, events: VecDeque::new(), path };
+
+        let capabilities = client.request("initialize", Json::object([("adapterID", Json::string("monkey"))]));
+        assert_eq!(capabilities.get("supportsConditionalBreakpoints"), &Json::Bool(true));
+        client.event("initialized");
+        let program = Json::string(client.path.to_str().unwrap());
+        client.request("launch", Json::object([("program", program), ("stopOnEntry", stop_on_entry.into())]));
+        configure(&mut client);
+        client.request("configurationDone", Json::Null);
+        client
+    }
+
+    fn receive(&mut self) -> Json {
+        read_message(&mut self.stdout).unwrap().expect("the adapter closed its output")
+    }
+
+    /// the response to a request, whose success must be `success`
+    fn respond(&mut self, command: &str, arguments: Json, success: bool) -> Json {
+        self.seq += 1;
+        let request = Json::object([
+            ("seq", self.seq.into()),
+            ("type", Json::string("request")),
+            ("command", Json::string(command)),
+            ("arguments", arguments),
+        ]);
+        write_message(&mut self.stdin, &request).unwrap();
+        loop {
+            let message = self.receive();
+            if message.get("type") == &Json::string("event") {
+                self.events.push_back(message);
+                continue;
+            }
+            assert_eq!(message.get("request_seq").as_usize(), Some(self.seq), "{}", message);
+            assert_eq!(message.get("command"), &Json::string(command));
+            assert_eq!(message.get("success"), &Json::Bool(success), "{}", message);
+            return message;
+        }
+    }
+
+    fn request(&mut self, command: &str, arguments: Json) -> Json {
+        self.respond(command, arguments, true).get("body").clone()
+    }
+
+    /// the body of the next event, which must be `event`
+    fn event(&mut self, event: &str) -> Json {
+        let message = self.events.pop_front().unwrap_or_else(|| self.receive());
+        assert_eq!(message.get("event"), &Json::string(event), "{}", message);
+        message.get("body").clone()
+    }
+
+    fn set_breakpoints(&mut self, breakpoints: &[(usize, Option<&str>)]) -> Vec<bool> {
+        let breakpoints = breakpoints
+            .iter()
+            .map(|&(line, condition)| {
+                let mut breakpoint = vec![("line".to_string(), line.into())];
+                breakpoint.extend(condition.map(|c| ("condition".to_string(), Json::string(c))));
+                Json::Object(breakpoint)
+            })
+            .collect();
+        let source = Json::object([("path", Json::string(self.path.to_str().unwrap()))]);
+        let body = self.request("setBreakpoints", Json::object([("source", source), ("breakpoints", Json::Array(breakpoints))]));
+        body.get("breakpoints").as_array().unwrap().iter().map(|b| b.get("verified") == &Json::Bool(true)).collect()
+    }
+
+    /// Waits for the program to stop, giving why and the name and line of each frame.
+    fn stopped(&mut self) -> (String, Vec<(String, usize)>) {
+        let reason = self.event("stopped").get("reason").as_str().unwrap().to_string();
+        let trace = self.request("stackTrace", Json::object([("threadId", 1.into())]));
+        let frames = trace
+            .get("stackFrames")
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|f| (f.get("name").as_str().unwrap().to_string(), f.get("line").as_usize().unwrap()))
+            .collect();
+        (reason, frames)
+    }
+
+    fn resume(&mut self, command: &str) {
+        self.request(command, Json::object([("threadId", 1.into())]));
+    }
+
+    /// the value of an expression in a frame of the stack trace, counted from the top
+    fn evaluate(&mut self, expression: &str, frame: usize) -> Json {
+        let trace = self.request("stackTrace", Json::object([("threadId", 1.into())]));
+        let id = trace.get("stackFrames").as_array().unwrap()[frame].get("id").clone();
+        self.request("evaluate", Json::object([("expression", Json::string(expression)), ("frameId", id)]))
+    }
+
+    /// the (name, value) of the variables of a reference
+    fn variables(&mut self, reference: &Json) -> Vec<(String, String)> {
+        let body = self.request("variables", Json::object([("variablesReference", reference.clone())]));
+        body.get("variables")
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|v| (v.get("name").as_str().unwrap().to_string(), v.get("value").as_str().unwrap().to_string()))
+            .collect()
+    }
+
+    /// Runs the program to its end, giving what it wrote and its exit code.
+    fn finish(mut self) -> (Vec<(String, String)>, usize) {
+        let mut output = vec![];
+        loop {
+            let body = self.events.pop_front().unwrap_or_else(|| self.receive());
+            match body.get("event").as_str() {
+                Some("output") => {
+                    let body = body.get("body");
+                    let category = body.get("category").as_str().unwrap().to_string();
+                    output.push((category, body.get("output").as_str().unwrap().to_string()));
+                }
+                Some("exited") => {
+                    let code = body.get("body").get("exitCode").as_usize().unwrap();
+                    self.event("terminated");
+                    self.request("disconnect", Json::Null);
+                    assert_eq!(self.child.wait().unwrap().code(), Some(0));
+                    fs::remove_file(&self.path).unwrap();
+                    return (output, code);
+                }
+                _ => panic!("unexpected message: {}", body),
+            }
+        }
+    }
+}
+
+fn frames(frames: &[(&str, usize)]) -> Vec<(String, usize)> {
+    frames.iter().map(|&(name, line)| (name.to_string(), line)).collect()
+}
+
+fn lines(lines: &[(&str, &str)]) -> Vec<(String, String)> {
+    lines.iter().map(|&(a, b)| (a.to_string(), b.to_string())).collect()
+}
+
+#[test]
+fn breakpoints() {
+    let mut client = Client::launch("breakpoints", PROGRAM, false, |client| {
+        // only lines where statements start
+        assert_eq!(client.set_breakpoints(&[(2, None), (4, None)]), vec![true, false]);
+    });
+    assert_eq!(client.stopped(), ("breakpoint".to_string(), frames(&[("double", 2), ("main", 6)])));
+    let threads = client.request("threads", Json::Null);
+    assert_eq!(threads.get("threads").as_array().unwrap().len(), 1);
+
+    let scopes = client.request("scopes", Json::object([("frameId", 2.into())]));
+    let scopes = scopes.get("scopes").as_array().unwrap().to_vec();
+    let names = scopes.iter().map(|s| s.get("name").as_str().unwrap()).collect::<Vec<_>>();
+    assert_eq!(names, vec!["Locals", "Globals"]);
+    assert_eq!(client.variables(scopes[0].get("variablesReference")), lines(&[("x", "3")]));
+    let globals = client.variables(scopes[1].get("variablesReference"));
+    assert_eq!(globals[1], ("values".to_string(), "{\"a\": [1, 2]}".to_string()));
+    assert_eq!(globals.len(), 2);
+
+    assert_eq!(client.evaluate("x * 10", 0).get("result"), &Json::string("30"));
+    let values = client.evaluate("values", 1);
+    assert_eq!(values.get("type"), &Json::string("hash"));
+    let items = client.variables(values.get("variablesReference"));
+    assert_eq!(items, lines(&[("\"a\"", "[1, 2]")]));
+    assert_eq!(client.evaluate("x", 1).get("result"), &Json::string("Error: identifier not found: x"));
+    client.respond("evaluate", Json::object([("expression", Json::string("let"))]), false);
+
+    client.resume("continue");
+    assert_eq!(client.stopped().1, frames(&[("double", 2), ("main", 6)]));
+    assert_eq!(client.evaluate("x", 0).get("result"), &Json::string("4"));
+    assert_eq!(client.set_breakpoints(&[(7, None)]), vec![true]);
+    client.resume("continue");
+    assert_eq!(client.stopped().1, frames(&[("main", 7)]));
+    client.resume("continue");
+    let (output, code) = client.finish();
+    assert_eq!(output, lines(&[("stdout", "14\n"), ("stdout", "14\n")]));
+    assert_eq!(code, 0);
+}
+
+#[test]
+fn conditions() {
+    let mut client = Client::launch("conditions", PROGRAM, false, |client| {
+        assert_eq!(client.set_breakpoints(&[(2, Some("x == 4")), (3, Some("y +"))]), vec![true, false]);
+    });
+    assert_eq!(client.stopped().0, "breakpoint");
+    assert_eq!(client.evaluate("x", 0).get("result"), &Json::string("4"));
+    client.resume("continue");
+    assert_eq!(client.finish().1, 0);
+}
+
+#[test]
+fn stepping() {
+    let mut client = Client::launch("stepping", PROGRAM, true, |_| {});
+    assert_eq!(client.stopped(), ("entry".to_string(), frames(&[("main", 1)])));
+    client.resume("next");
+    assert_eq!(client.stopped(), ("step".to_string(), frames(&[("main", 5)])));
+    client.resume("next");
+    assert_eq!(client.stopped().1, frames(&[("main", 6)]));
+    client.resume("stepIn");
+    assert_eq!(client.stopped().1, frames(&[("double", 2), ("main", 6)]));
+    client.resume("next");
+    assert_eq!(client.stopped().1, frames(&[("double", 3), ("main", 6)]));
+    // out of both calls of the line
+    client.resume("stepOut");
+    assert_eq!(client.stopped().1, frames(&[("main", 7)]));
+    // over a call of a builtin
+    client.resume("next");
+    assert_eq!(client.event("output").get("output"), &Json::string("14\n"));
+    assert_eq!(client.stopped().1, frames(&[("main", 8)]));
+    client.resume("next");
+    assert_eq!(client.finish(), (lines(&[("stdout", "14\n")]), 0));
+}
+
+#[test]
+fn errors() {
+    let source = "let f = fn(n) {\n  n + true\n};\nf(1)";
+    let mut client = Client::launch("errors", source, false, |_| {});
+    client.respond("stackTrace", Json::object([("threadId", 1.into())]), false);
+    assert_eq!(client.finish(), (lines(&[("stderr", "Error: 1 and true are not addable\n")]), 1));
+}
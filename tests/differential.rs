@@ -0,0 +1,75 @@
+//! Runs the same programs on the tree-walking evaluator and on every other execution path,
+//! which must give the same results and errors.
+//!
+//! Programs come from the files of `tests/corpus`, the programs of a file running one after
+//! the other on the same engine, and from syntax trees generated at random.
+
+use monkey_lang_lib::engine::{Backend, Engine};
+use monkey_lang_lib::evaluator::object::Object;
+use monkey_lang_lib::optimizer::Optimizer;
+use monkey_lang_lib::parser::ast::Program;
+
+use common::{parse, Generator};
+
+mod common;
+
+/// The paths checked against `Backend::Eval`: a backend, and whether programs are optimized
+/// before running.
+const PATHS: &[(Backend, bool)] = &[(Backend::Vm, false), (Backend::Eval, true), (Backend::Vm, true)];
+
+const GENERATED_PROGRAMS: u64 = 400;
+
+/// Results are compared by value. Functions differ in representation between backends and
+/// are only checked to be functions, and hashes are compared regardless of their order.
+fn same(object1: &Object, object2: &Object) -> bool {
+    match (object1, object2) {
+        (Object::Function(..) | Object::Closure(_), Object::Function(..) | Object::Closure(_)) => true,
+        (Object::Array(arr1), Object::Array(arr2)) => {
+            arr1.len() == arr2.len() && arr1.iter().zip(arr2).all(|(o1, o2)| same(o1, o2))
+        }
+        (Object::Hash(hash1), Object::Hash(hash2)) => {
+            hash1.len() == hash2.len()
+                && hash1
+                    .iter()
+                    .all(|(k, v1)| hash2.get(k).is_some_and(|v2| same(v1, v2)))
+        }
+        (Object::ReturnValue(o1), Object::ReturnValue(o2)) => same(o1, o2),
+        (o1, o2) => o1 == o2,
+    }
+}
+
+/// runs the programs in order on each backend, comparing every result to the evaluator's
+fn check_session(name: &str, programs: &[Program]) {
+    let mut expected = Engine::new(Backend::Eval);
+    let results = programs.iter().map(|program| expected.eval_program(program.clone())).collect::<Vec<_>>();
+    for &(backend, optimized) in PATHS {
+        let mut engine = Engine::new(backend);
+        let mut optimizer = Optimizer::new();
+        for (i, (program, expected)) in programs.iter().zip(&results).enumerate() {
+            let program = if optimized { optimizer.optimize(program.clone()) } else { program.clone() };
+            let result = engine.eval_program(program.clone());
+            assert!(
+                same(&result, expected),
+                "{}, program {} on {:?}, optimized: {}: got {:?}, expected {:?}\n{:#?}",
+                name, i, backend, optimized, result, expected, program,
+            );
+        }
+    }
+}
+
+#[test]
+fn corpus() {
+    for (path, programs) in common::corpus() {
+        let programs = programs.iter().map(|p| parse(p)).collect::<Vec<_>>();
+        check_session(&path.display().to_string(), &programs);
+    }
+}
+
+#[test]
+fn generated() {
+    for seed in 1..=GENERATED_PROGRAMS {
+        let mut generator = Generator::new(seed);
+        let programs = (0..3).map(|_| generator.program()).collect::<Vec<_>>();
+        check_session(&format!("seed {}", seed), &programs);
+    }
+}
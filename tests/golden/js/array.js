@@ -0,0 +1,16 @@
+"use strict";
+
+const len = (value) => (typeof value === "string" ? unescape(encodeURIComponent(value)) : value).length;
+
+const cons = (value, array) => [value].concat(array);
+
+const empty = [];
+const nested = [1, [2, [3]], "four", (x) => x];
+const range = (n) => {
+  if (n === 0) {
+    return [];
+  } else {
+    return cons(n, range(n - 1));
+  }
+};
+[len(empty), len(nested), range(4)];
@@ -0,0 +1,4 @@
+let empty = [];
+let nested = [1, [2, [3]], "four", fn(x) { x }];
+let range = fn(n) { if (n == 0) { [] } else { cons(n, range(n - 1)) } };
+[len(empty), len(nested), range(4)]
@@ -0,0 +1,40 @@
+"use strict";
+
+const $show = (value) => {
+  if (value === null || value === undefined) {
+    return "null";
+  } else if (Array.isArray(value)) {
+    return "[" + value.map($show).join(", ") + "]";
+  } else if (value instanceof Map) {
+    return "{" + Array.from(value, ([k, v]) => $show(k) + ": " + $show(v)).join(", ") + "}";
+  } else if (typeof value === "function") {
+    return "[function]";
+  }
+  return String(value);
+};
+
+const print = (value) => {
+  console.log($show(value));
+  return null;
+};
+
+const head = (array) => {
+  if (array.length === 0) {
+    throw new Error("empty array");
+  }
+  return array[0];
+};
+
+const tail = (array) => {
+  if (array.length === 0) {
+    throw new Error("empty array");
+  }
+  return array.slice(1);
+};
+
+const cons = (value, array) => [value].concat(array);
+
+const apply = (f, args) => f(head(args), head(tail(args)));
+const pair = (a, b) => cons(a, [b]);
+print(pair(1, 2));
+apply(pair, [3, 4]);
@@ -0,0 +1,4 @@
+let apply = fn(f, args) { f(head(args), head(tail(args))) };
+let pair = fn(a, b) { cons(a, [b]) };
+print(pair(1, 2));
+apply(pair, [3, 4])
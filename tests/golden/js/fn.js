@@ -0,0 +1,10 @@
+"use strict";
+
+const add = (a, b) => a + b;
+const counter = (start) => {
+  const step = 2;
+  return () => start + step;
+};
+const twice = (f, x) => f(f(x));
+const noop = () => null;
+[add(1, 2), counter(5)(), twice((x) => x * 3, 2), noop()];
@@ -0,0 +1,8 @@
+let add = fn(a, b) { a + b };
+let counter = fn(start) {
+  let step = 2;
+  fn() { start + step }
+};
+let twice = fn(f, x) { f(f(x)) };
+let noop = fn() { };
+[add(1, 2), counter(5)(), twice(fn(x) { x * 3 }, 2), noop()]
@@ -0,0 +1,13 @@
+"use strict";
+
+const $index = (target, key) => {
+  if (target instanceof Map) {
+    return target.has(key) ? target.get(key) : null;
+  }
+  return key >= 0 && key < target.length ? target[key] : null;
+};
+
+const empty = new Map();
+const person = new Map([["name", "Ada"], ["age", 36], [true, [1, 2]]]);
+const older = (p) => new Map([["name", $index(p, "name")], ["age", $index(p, "age") + 1]]);
+[empty, $index(older(person), "age"), $index(person, true), $index(person, "missing")];
@@ -0,0 +1,4 @@
+let empty = {};
+let person = {"name": "Ada", "age": 36, true: [1, 2]};
+let older = fn(p) { {"name": p["name"], "age": p["age"] + 1} };
+[empty, older(person)["age"], person[true], person["missing"]]
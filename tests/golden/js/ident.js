@@ -0,0 +1,13 @@
+"use strict";
+
+const head = (array) => {
+  if (array.length === 0) {
+    throw new Error("empty array");
+  }
+  return array[0];
+};
+
+const answer = 42;
+const new_ = answer;
+const first = head;
+first([new_, answer]);
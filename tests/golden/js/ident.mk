@@ -0,0 +1,4 @@
+let answer = 42;
+let new = answer;
+let first = head;
+first([new, answer])
@@ -0,0 +1,43 @@
+"use strict";
+
+const len = (value) => (typeof value === "string" ? unescape(encodeURIComponent(value)) : value).length;
+
+const classify = (n) => {
+  if (n < 0) {
+    return "negative";
+  } else if (n === 0) {
+    return "zero";
+  } else {
+    return "positive";
+  }
+};
+const clamp = (n) => {
+  let z;
+  if (n > 10) {
+    return 10;
+  }
+  let m;
+  if (n < 0) {
+    z = 0;
+    m = z;
+  } else {
+    m = n;
+  }
+  return m;
+};
+const pick = (c) => [c ? 1 : 2, c ? 3 : null];
+const inline = (c) => {
+  let q;
+  return 1 + (() => {
+    if (c) {
+      q = 2;
+      return q * 2;
+    } else {
+      return 3;
+    }
+  })();
+};
+const sum = [inline(true), classify(-3), classify(0), classify(8), clamp(20) + clamp(-4) + clamp(5), pick(false)];
+if (len(sum) > 3) {
+  sum;
+}
@@ -0,0 +1,16 @@
+let classify = fn(n) {
+  if (n < 0) {
+    "negative"
+  } else {
+    if (n == 0) { "zero" } else { "positive" }
+  }
+};
+let clamp = fn(n) {
+  if (n > 10) { return 10; };
+  let m = if (n < 0) { let z = 0; z } else { n };
+  m
+};
+let pick = fn(c) { [if (c) { 1 } else { 2 }, if (c) { 3 }] };
+let inline = fn(c) { 1 + if (c) { let q = 2; q * 2 } else { 3 } };
+let sum = [inline(true), classify(-3), classify(0), classify(8), clamp(20) + clamp(-4) + clamp(5), pick(false)];
+if (len(sum) > 3) { sum }
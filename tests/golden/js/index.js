@@ -0,0 +1,12 @@
+"use strict";
+
+const $index = (target, key) => {
+  if (target instanceof Map) {
+    return target.has(key) ? target.get(key) : null;
+  }
+  return key >= 0 && key < target.length ? target[key] : null;
+};
+
+const xs = [10, 20, 30];
+const h = new Map([[1, "one"], ["k", xs]]);
+[$index(xs, 0), $index(xs, 1 + 1), $index(xs, 3), $index(xs, -1), $index(h, 1), $index($index(h, "k"), 2), $index($index([[1, 2], [3]], 1), 0)];
@@ -0,0 +1,3 @@
+let xs = [10, 20, 30];
+let h = {1: "one", "k": xs};
+[xs[0], xs[1 + 1], xs[3], xs[-1], h[1], h["k"][2], [[1, 2], [3]][1][0]]
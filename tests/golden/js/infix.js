@@ -0,0 +1,15 @@
+"use strict";
+
+const $equal = (a, b) => {
+  if (Array.isArray(a) && Array.isArray(b)) {
+    return a.length === b.length && a.every((x, i) => $equal(x, b[i]));
+  } else if (a instanceof Map && b instanceof Map) {
+    return a.size === b.size && Array.from(a).every(([k, v]) => b.has(k) && $equal(v, b.get(k)));
+  }
+  return a === b;
+};
+
+const a = 7;
+const b = 2;
+const same = (x, y) => $equal(x, y);
+[a + b * 3, (a + b) * 3, a - (b - 1), Math.trunc(a / b), Math.trunc(-a / b), Math.trunc(a * b / 3), a < b, a >= b, a === 7, !$equal(a, b), same([a], [7]), "a" + "b"];
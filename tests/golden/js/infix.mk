@@ -0,0 +1,4 @@
+let a = 7;
+let b = 2;
+let same = fn(x, y) { x == y };
+[a + b * 3, (a + b) * 3, a - (b - 1), a / b, -a / b, a * b / 3, a < b, a >= b, a == 7, a != b, same([a], [7]), "a" + "b"]
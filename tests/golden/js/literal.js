@@ -0,0 +1,10 @@
+"use strict";
+
+const n = 9007199254740991;
+const t = true;
+const s = "say \"hi\"\\ to\ttabs";
+if (t) {
+  s;
+} else {
+  n;
+}
@@ -0,0 +1,4 @@
+let n = 9007199254740991;
+let t = true;
+let s = "say \"hi\"\\ to	tabs";
+if (t) { s } else { n }
@@ -0,0 +1,5 @@
+"use strict";
+
+const n = 5;
+const flip = (b) => !b;
+[-n, +n, -(-n), !flip(false)];
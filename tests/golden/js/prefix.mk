@@ -0,0 +1,3 @@
+let n = 5;
+let flip = fn(b) { !b };
+[-n, +n, - -n, !(flip(false))]
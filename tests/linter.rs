@@ -0,0 +1,57 @@
+//! Checks programs with the linter, inferring their types too, whose undefined names must be
+//! those the resolver rejects, each spanning its name in the source.
+
+use monkey_lang_lib::evaluator::builtins::BuiltinFunctions;
+use monkey_lang_lib::linter::{check, Rule};
+use monkey_lang_lib::parser::printer::print;
+use monkey_lang_lib::resolver::{ResolveError, Resolver};
+
+use common::{parse, Generator};
+
+mod common;
+
+const GENERATED_PROGRAMS: u64 = 1000;
+
+fn undefined_names(name: &str, source: &str) {
+    let lines = source.lines().collect::<Vec<_>>();
+    let diagnostics = check(source, true).unwrap_or_else(|err| panic!("{}: {}", name, err));
+    let mut found = vec![];
+    for d in diagnostics.iter().filter(|d| d.rule == Rule::UndefinedName) {
+        let (start, end) = (d.span.start, d.span.end);
+        assert_eq!(start.line, end.line, "{}: {}", name, d);
+        let line = lines[start.line - 1].chars().skip(start.column - 1);
+        found.push(line.take(end.column - start.column).collect::<String>());
+    }
+
+    let resolved = Resolver::new(BuiltinFunctions::new().names()).resolve(&parse(source));
+    let expected = match resolved {
+        Ok(_) => vec![],
+        Err(errors) => errors
+            .into_iter()
+            .filter_map(|err| match err {
+                ResolveError::UndefinedIdent(name) => Some(name),
+                ResolveError::DuplicateParam(_) | ResolveError::InFunction(_) => None,
+            })
+            .collect(),
+    };
+    assert_eq!(found, expected, "{}:\n{}", name, source);
+}
+
+#[test]
+fn corpus() {
+    for (path, programs) in common::corpus() {
+        for (i, program) in programs.iter().enumerate() {
+            undefined_names(&format!("{}, program {}", path.display(), i), program);
+        }
+    }
+}
+
+#[test]
+fn generated() {
+    for seed in 1..=GENERATED_PROGRAMS {
+        let mut generator = Generator::new(seed);
+        for _ in 0..3 {
+            undefined_names(&format!("seed {}", seed), &print(&generator.program()));
+        }
+    }
+}
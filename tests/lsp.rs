@@ -0,0 +1,256 @@
+//! Drives the language server binary over its standard input and output, as editors do.
+
+use std::io::{BufReader, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+use monkey_lang_lib::json::Json;
+use monkey_lang_lib::lsp::{read_message, write_message};
+
+const URI: &str = "file:///tmp/main.mk";
+
+struct Client {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    id: usize,
+}
+
+impl Client {
+    fn start() -> Self {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_monkey_lsp"))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let stdin = child.stdin.take().unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        let mut client = Client { child, stdin, stdout, id: 0 };
+        let result = client.request("initialize", Json::object([("capabilities", Json::object([]))]));
+        assert_eq!(result.get("serverInfo").get("name"), &Json::string("monkey_lsp"));
+        assert_eq!(result.get("capabilities").get("hoverProvider"), &Json::Bool(true));
+        client.notify("initialized", Json::object([]));
+        client
+    }
+
+    fn send(&mut self, message: Json) {
+        write_message(&mut self.stdin, &message).unwrap();
+    }
+
+    fn receive(&mut self) -> Json {
+        read_message(&mut self.stdout).unwrap().expect("the server closed its output")
+    }
+
+    fn notify(&mut self, method: &str, params: Json) {
+        self.send(Json::object([
+            ("jsonrpc", Json::string("2.0")),
+            ("method", Json::string(method)),
+            ("params", params),
+        ]));
+    }
+
+    /// the response to a request, which must not be an error
+    fn request(&mut self, method: &str, params: Json) -> Json {
+        let response = self.try_request(method, params);
+        assert_eq!(response.get("error"), &Json::Null, "{}", response);
+        response.get("result").clone()
+    }
+
+    fn try_request(&mut self, method: &str, params: Json) -> Json {
+        self.id += 1;
+        self.send(Json::object([
+            ("jsonrpc", Json::string("2.0")),
+            ("id", self.id.into()),
+            ("method", Json::string(method)),
+            ("params", params),
+        ]));
+        let response = self.receive();
+        assert_eq!(response.get("id").as_usize(), Some(self.id), "{}", response);
+        response
+    }
+
+    /// Opens or changes the document, giving its diagnostics as (line, character, code, message).
+    fn open(&mut self, text: &str) -> Vec<(usize, usize, String, String)> {
+        let document = Json::object([
+            ("uri", Json::string(URI)),
+            ("languageId", Json::string("monkey")),
+            ("version", 1.into()),
+            ("text", Json::string(text)),
+        ]);
+        self.notify("textDocument/didOpen", Json::object([("textDocument", document)]));
+        self.diagnostics()
+    }
+
+    fn change(&mut self, text: &str) -> Vec<(usize, usize, String, String)> {
+        let document = Json::object([("uri", Json::string(URI)), ("version", 2.into())]);
+        let changes = Json::Array(vec![Json::object([("text", Json::string(text))])]);
+        self.notify("textDocument/didChange", Json::object([("textDocument", document), ("contentChanges", changes)]));
+        self.diagnostics()
+    }
+
+    fn diagnostics(&mut self) -> Vec<(usize, usize, String, String)> {
+        let notification = self.receive();
+        assert_eq!(notification.get("method"), &Json::string("textDocument/publishDiagnostics"));
+        assert_eq!(notification.get("params").get("uri"), &Json::string(URI));
+        let diagnostics = notification.get("params").get("diagnostics").as_array().unwrap().to_vec();
+        diagnostics
+            .iter()
+            .map(|d| {
+                let start = d.get("range").get("start");
+                let code = d.get("code").as_str().unwrap_or_default().to_string();
+                let message = d.get("message").as_str().unwrap().to_string();
+                (start.get("line").as_usize().unwrap(), start.get("character").as_usize().unwrap(), code, message)
+            })
+            .collect()
+    }
+
+    /// the response to a request about a position of the document
+    fn at(&mut self, method: &str, line: usize, character: usize) -> Json {
+        let position = Json::object([("line", line.into()), ("character", character.into())]);
+        let mut params = vec![
+            ("textDocument".to_string(), Json::object([("uri", Json::string(URI))])),
+            ("position".to_string(), position),
+        ];
+        if method == "textDocument/references" {
+            params.push(("context".to_string(), Json::object([("includeDeclaration", true.into())])));
+        }
+        self.request(method, Json::Object(params))
+    }
+
+    /// Shuts the server down, giving its exit status.
+    fn stop(mut self) -> Option<i32> {
+        assert_eq!(self.request("shutdown", Json::Null), Json::Null);
+        self.notify("exit", Json::Null);
+        self.stdin.flush().unwrap();
+        self.child.wait().unwrap().code()
+    }
+}
+
+/// the (line, character) of the start of each range of locations
+fn starts(locations: &Json) -> Vec<(usize, usize)> {
+    let locations = match locations {
+        Json::Array(locations) => locations.clone(),
+        location => vec![location.clone()],
+    };
+    locations
+        .iter()
+        .map(|location| {
+            assert_eq!(location.get("uri"), &Json::string(URI));
+            let start = location.get("range").get("start");
+            (start.get("line").as_usize().unwrap(), start.get("character").as_usize().unwrap())
+        })
+        .collect()
+}
+
+const SOURCE: &str = "let total = 2 * 3;\nlet add = fn(a, b) { a * b };\nadd(total, unknown)";
+
+#[test]
+fn diagnostics() {
+    let mut client = Client::start();
+    assert_eq!(
+        client.open(SOURCE),
+        vec![(2, 11, "undefined-name".to_string(), "identifier not found: unknown".to_string())]
+    );
+    assert_eq!(
+        client.change("let x = 1;\nlet y = \"a\" + x"),
+        vec![
+            (1, 4, "unused-variable".to_string(), "variable `y` is never used".to_string()),
+            (1, 14, "type-mismatch".to_string(), "mismatched types: expected string, found int".to_string()),
+        ]
+    );
+    assert_eq!(
+        client.change("let x = 1;\nlet = 2"),
+        vec![(1, 0, String::new(), "the code does not parse from line 2, column 1".to_string())]
+    );
+    assert_eq!(client.change("1"), vec![]);
+    assert_eq!(client.stop(), Some(0));
+}
+
+#[test]
+fn names() {
+    let mut client = Client::start();
+    client.open(SOURCE);
+
+    // from a reference or the binding itself
+    assert_eq!(starts(&client.at("textDocument/definition", 2, 6)), vec![(0, 4)]);
+    assert_eq!(starts(&client.at("textDocument/definition", 1, 22)), vec![(1, 13)]);
+    assert_eq!(starts(&client.at("textDocument/definition", 0, 4)), vec![(0, 4)]);
+    assert_eq!(client.at("textDocument/definition", 2, 14), Json::Null);
+    assert_eq!(starts(&client.at("textDocument/references", 0, 6)), vec![(0, 4), (2, 4)]);
+    assert_eq!(starts(&client.at("textDocument/references", 1, 16)), vec![(1, 16), (1, 25)]);
+
+    let hover = |client: &mut Client, line, character| {
+        let hover = client.at("textDocument/hover", line, character);
+        hover.get("contents").get("value").as_str().map(str::to_string)
+    };
+    assert_eq!(hover(&mut client, 2, 5).as_deref(), Some("```monkey\nlet total: int = 6\n```"));
+    assert_eq!(hover(&mut client, 2, 1).as_deref(), Some("```monkey\nlet add: fn(int, int) -> int\n```"));
+    assert_eq!(hover(&mut client, 1, 13).as_deref(), Some("```monkey\na: int\n```"));
+    assert_eq!(hover(&mut client, 2, 10), None);
+    assert_eq!(client.stop(), Some(0));
+}
+
+#[test]
+fn completion_and_formatting() {
+    let mut client = Client::start();
+    let document = Json::object([("textDocument", Json::object([("uri", Json::string(URI))]))]);
+    let labels = |items: &Json| -> Vec<(String, String)> {
+        items
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| {
+                let detail = item.get("detail").as_str().unwrap_or_default();
+                (item.get("label").as_str().unwrap().to_string(), detail.to_string())
+            })
+            .collect()
+    };
+    let builtins = ["print", "len", "head", "tail", "cons", "assert", "assert_eq", "assert_error"];
+
+    client.open("let len2 = fn(xs) { len(xs) };\nlen2(\"ab\")");
+    let items = labels(&client.request("textDocument/completion", document.clone()));
+    let mut names = items.iter().map(|(label, _)| label.as_str()).collect::<Vec<_>>();
+    names.sort();
+    let mut expected = builtins.to_vec();
+    expected.extend(["len2", "xs"]);
+    expected.sort();
+    assert_eq!(names, expected);
+    assert!(items.contains(&("len2".to_string(), "fn(a) -> int".to_string())));
+    assert!(items.contains(&("len".to_string(), "built-in function".to_string())));
+
+    // the identifiers written, while the document does not parse
+    client.change("let count = 1;\ncount +");
+    let items = labels(&client.request("textDocument/completion", document.clone()));
+    assert!(items.contains(&("count".to_string(), String::new())));
+    assert_eq!(client.request("textDocument/formatting", document.clone()), Json::Null);
+
+    client.change("let x=fn(a){a};\n// keep\nx(1)");
+    let edits = client.request("textDocument/formatting", document.clone());
+    let edit = &edits.as_array().unwrap()[0];
+    assert_eq!(edit.get("newText"), &Json::string("let x = fn(a) {\n    a\n};\n// keep\nx(1)\n"));
+    let end = edit.get("range").get("end");
+    assert_eq!((end.get("line").as_usize(), end.get("character").as_usize()), (Some(2), Some(4)));
+    client.change("x\n");
+    assert_eq!(client.request("textDocument/formatting", document), Json::Array(vec![]));
+    assert_eq!(client.stop(), Some(0));
+}
+
+#[test]
+fn protocol() {
+    let mut client = Client::start();
+    let response = client.try_request("textDocument/unknown", Json::Null);
+    assert_eq!(response.get("error").get("code"), &Json::Number(-32601.0));
+    let response = client.try_request("textDocument/hover", Json::object([]));
+    assert_eq!(response.get("error").get("code"), &Json::Number(-32602.0));
+
+    // a body which is not JSON is answered, and the server keeps running
+    write!(client.stdin, "Content-Length: 3\r\n\r\n{{1}}").unwrap();
+    let response = client.receive();
+    assert_eq!(response.get("id"), &Json::Null);
+    assert_eq!(response.get("error").get("code"), &Json::Number(-32700.0));
+    assert_eq!(client.stop(), Some(0));
+
+    // exiting without shutting down is a failure
+    let mut client = Client::start();
+    client.notify("exit", Json::Null);
+    assert_eq!(client.child.wait().unwrap().code(), Some(1));
+}
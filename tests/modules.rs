@@ -0,0 +1,106 @@
+//! Runs programs importing files written to a temporary directory.
+
+use std::cell::RefCell;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::rc::Rc;
+
+use monkey_lang_lib::evaluator::builtins::redirect_print;
+use monkey_lang_lib::evaluator::Evaluator;
+use monkey_lang_lib::parser::parse;
+
+/// Writes the files of a program, each a path relative to a directory of its own and its
+/// source, giving the directory.
+fn write(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("monkey-{}-{}", process::id(), name));
+    for (path, source) in files {
+        let path = dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, source).unwrap();
+    }
+    dir
+}
+
+/// what the file at `path` evaluates to, run as that file, with the paths it shows relative to
+/// the directory
+fn run(dir: &Path, path: &str) -> String {
+    let path = dir.join(path);
+    let source = fs::read_to_string(&path).unwrap();
+    let mut evaluator = Evaluator::new();
+    evaluator.set_file(Some(path.to_str().unwrap()));
+    let result = evaluator.eval_program(parse(&source).unwrap()).to_string();
+    result.replace(&format!("{}/", dir.display()), "")
+}
+
+#[test]
+fn imports() {
+    let dir = write(
+        "imports",
+        &[
+            (
+                "main.mk",
+                "import \"lib/math.mk\" as math;
+                import { double, total } from \"lib/math.mk\"
+                [math.double(21), double(2), total, math.inc(1)]",
+            ),
+            (
+                "lib/math.mk",
+                "import { inc } from \"util/inc.mk\";
+                let double = fn(x) { x * 2 };
+                let total = inc(double(3));
+                export double, total",
+            ),
+            ("lib/util/inc.mk", "export inc; let inc = fn(n) { n + 1 };"),
+        ],
+    );
+    assert_eq!(run(&dir, "main.mk"), "[42, 4, 7, Error: lib/math.mk does not export inc]");
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn once() {
+    let dir = write(
+        "once",
+        &[
+            ("main.mk", "import \"a.mk\" as a; import \"lib/../a.mk\" as b; import \"lib/c.mk\" as c; [a.n, b.n, c.n]"),
+            ("a.mk", "print(\"ran\"); let n = 1; export n"),
+            ("lib/c.mk", "import { n } from \"../a.mk\"; export n"),
+        ],
+    );
+    let printed = Rc::new(RefCell::new(vec![]));
+    let lines = Rc::clone(&printed);
+    redirect_print(Some(Box::new(move |line| lines.borrow_mut().push(line.to_string()))));
+    let result = run(&dir, "main.mk");
+    redirect_print(None);
+    assert_eq!(result, "[1, 1, 1]");
+    assert_eq!(*printed.borrow(), vec!["ran"]);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn errors() {
+    let dir = write(
+        "errors",
+        &[
+            ("cycle.mk", "import \"a.mk\" as a; a"),
+            ("a.mk", "import \"b.mk\" as b; let x = 1; export x"),
+            ("b.mk", "import { x } from \"a.mk\"; export x"),
+            ("missing.mk", "import { y } from \"lib.mk\"; y"),
+            ("lib.mk", "let x = 1; export x"),
+            ("unresolved.mk", "import \"undefined.mk\" as u; u"),
+            ("undefined.mk", "export nothing"),
+            ("nested.mk", "let f = fn() { import \"lib.mk\" as lib; lib }; f"),
+            ("value.mk", "let lib = [1]; lib.x"),
+            ("absent.mk", "import \"nowhere.mk\" as nowhere; nowhere"),
+        ],
+    );
+    assert_eq!(run(&dir, "cycle.mk"), "Error: import cycle: a.mk -> b.mk -> a.mk");
+    assert_eq!(run(&dir, "missing.mk"), "Error: lib.mk does not export y");
+    assert_eq!(run(&dir, "unresolved.mk"), "Error: undefined.mk: identifier not found: nothing");
+    assert_eq!(run(&dir, "nested.mk"), "Error: `import` is only allowed outside of functions");
+    assert_eq!(run(&dir, "value.mk"), "Error: [1] is not a module");
+    let absent = run(&dir, "absent.mk");
+    assert!(absent.starts_with("Error: cannot import nowhere.mk: "), "{}", absent);
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,42 @@
+//! Prints programs back to source, which must parse to the same syntax trees, print the same
+//! again, and be left as they are by the formatter.
+
+use monkey_lang_lib::formatter::format;
+use monkey_lang_lib::parser::ast::Program;
+use monkey_lang_lib::parser::printer::print;
+
+use common::{parse, Generator};
+
+mod common;
+
+const GENERATED_PROGRAMS: u64 = 1000;
+
+fn round_trip(name: &str, program: &Program) {
+    let source = print(program);
+    let reparsed = parse(&source);
+    assert!(reparsed == *program, "{} prints as\n{}\nwhich parses as\n{:#?}", name, source, reparsed);
+    assert_eq!(print(&reparsed), source, "{}", name);
+    assert_eq!(format(&source).as_ref(), Ok(&source), "{}", name);
+}
+
+#[test]
+fn corpus() {
+    for (path, programs) in common::corpus() {
+        for (i, program) in programs.iter().enumerate() {
+            let name = format!("{}, program {}", path.display(), i);
+            round_trip(&name, &parse(program));
+            let formatted = format(program).unwrap_or_else(|err| panic!("{}: {}", name, err));
+            assert!(parse(&formatted) == parse(program), "{} formats as\n{}", name, formatted);
+        }
+    }
+}
+
+#[test]
+fn generated() {
+    for seed in 1..=GENERATED_PROGRAMS {
+        let mut generator = Generator::new(seed);
+        for _ in 0..3 {
+            round_trip(&format!("seed {}", seed), &generator.program());
+        }
+    }
+}